@@ -0,0 +1,14 @@
+-- a random, stable id for each user, used in api responses instead of `id`.
+-- sqlite cannot add a `NOT NULL` column without a default, so it is always set on insert instead.
+ALTER TABLE users ADD COLUMN public_id TEXT;
+
+-- backfill existing users with random (v4) uuids.
+UPDATE users SET public_id = lower(
+    hex(randomblob(4)) || '-' ||
+    hex(randomblob(2)) || '-' ||
+    '4' || substr(hex(randomblob(2)), 2) || '-' ||
+    substr('89ab', 1 + (abs(random()) % 4), 1) || substr(hex(randomblob(2)), 2) || '-' ||
+    hex(randomblob(6))
+);
+
+CREATE UNIQUE INDEX users_public_id_idx ON users (public_id);
@@ -0,0 +1,10 @@
+CREATE TABLE login_attempts (
+    -- what is being throttled, `user:<username>` or `ip:<address>`.
+    key TEXT PRIMARY KEY NOT NULL,
+    -- failed attempts since the last successful login, or since the counter expired.
+    failures INTEGER NOT NULL,
+    -- stored as seconds after the unix epoch.
+    last_failure INTEGER NOT NULL,
+    -- stored as seconds after the unix epoch. no attempts are allowed before this.
+    locked_until INTEGER NOT NULL
+);
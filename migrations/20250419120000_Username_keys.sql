@@ -0,0 +1,24 @@
+-- the normalized, lowercased username. usernames must be unique by it, so "Alice" and "alice" are the same user.
+-- `lower` only folds ascii, new users' keys are normalized fully when they sign up.
+ALTER TABLE users ADD COLUMN username_key TEXT;
+
+-- existing users whose usernames collide with an older user's, to be resolved by hand.
+-- they are given a placeholder key (with a `#`, which usernames cannot contain) until then.
+CREATE TABLE username_collisions (
+    user_id INTEGER PRIMARY KEY NOT NULL,
+    username TEXT NOT NULL,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+INSERT INTO username_collisions(user_id, username)
+SELECT id, username FROM users AS newer
+WHERE EXISTS (
+    SELECT 1 FROM users AS older
+    WHERE lower(trim(older.username)) = lower(trim(newer.username)) AND older.id < newer.id
+);
+
+UPDATE users SET username_key = lower(trim(username));
+UPDATE users SET username_key = username_key || '#' || id
+WHERE id IN (SELECT user_id FROM username_collisions);
+
+CREATE UNIQUE INDEX users_username_key_idx ON users (username_key);
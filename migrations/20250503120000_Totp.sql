@@ -0,0 +1,28 @@
+CREATE TABLE totp (
+    user_id INTEGER PRIMARY KEY NOT NULL,
+    -- base32 encoded, without padding.
+    secret TEXT NOT NULL,
+    -- 0 until the user confirms enrolling with a code, then 1. only confirmed totp is required to log in.
+    confirmed INTEGER NOT NULL,
+    -- the last time step a code was accepted for, so codes cannot be used twice.
+    last_step INTEGER NOT NULL,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE TABLE recovery_codes (
+    user_id INTEGER NOT NULL,
+    -- hex encoded sha-256 of the code. codes are random, so they do not need a slow hash.
+    code_hash TEXT NOT NULL,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX recovery_codes_idx ON recovery_codes (user_id);
+
+-- logins that verified a password, and are waiting on a second factor.
+CREATE TABLE login_challenges (
+    id TEXT PRIMARY KEY NOT NULL,
+    user_id INTEGER NOT NULL,
+    -- stored as seconds after the unix epoch.
+    created_at INTEGER NOT NULL,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
@@ -0,0 +1,16 @@
+CREATE TABLE api_tokens (
+    -- the public id tokens are listed and revoked by.
+    id TEXT PRIMARY KEY NOT NULL,
+    user_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    -- `read_usage` or `sync`.
+    scope TEXT NOT NULL,
+    -- hex encoded sha-256 of the token. tokens are random, so they do not need a slow hash.
+    token_hash TEXT NOT NULL UNIQUE,
+    -- stored as seconds after the unix epoch.
+    created_at INTEGER NOT NULL,
+    -- stored as seconds after the unix epoch, null if never used.
+    last_used INTEGER,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE,
+    UNIQUE(user_id, name)
+);
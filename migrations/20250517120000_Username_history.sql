@@ -0,0 +1,11 @@
+-- usernames users changed away from. they stay reserved for their old owner for a while, so they cannot be squatted.
+CREATE TABLE username_history (
+    -- the old username's key, see `users.username_key`.
+    username_key TEXT NOT NULL,
+    user_id INTEGER NOT NULL,
+    -- stored as seconds after the unix epoch.
+    released_at INTEGER NOT NULL,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX username_history_idx ON username_history (username_key);
@@ -0,0 +1,3 @@
+-- stored as seconds after the unix epoch, null unless the user asked to delete their account.
+-- the account is purged once the grace period after it passes, logging in before then restores it.
+ALTER TABLE users ADD COLUMN deletion_requested_at INTEGER;
@@ -0,0 +1,13 @@
+-- the usage users synced, by day. one row per app, per day.
+CREATE TABLE usage_history (
+    user_id INTEGER NOT NULL,
+    app_name TEXT NOT NULL,
+    -- the utc date synced on, as `YYYY-MM-DD`.
+    day TEXT NOT NULL,
+    -- stored as seconds, the most synced that day.
+    app_usage INTEGER NOT NULL,
+    -- stored as seconds, the last synced that day.
+    app_limit INTEGER NOT NULL,
+    PRIMARY KEY(user_id, app_name, day),
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
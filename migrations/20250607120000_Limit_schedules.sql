@@ -0,0 +1,36 @@
+-- limits replacing an app's `app_info.app_limit` on a day of the week.
+CREATE TABLE weekday_limits (
+    user_id INTEGER NOT NULL,
+    app_name TEXT NOT NULL,
+    -- 0 is monday.
+    weekday INTEGER NOT NULL CHECK(weekday BETWEEN 0 AND 6),
+    -- stored as seconds
+    app_limit INTEGER NOT NULL,
+    PRIMARY KEY(user_id, app_name, weekday),
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+-- times of day an app is not allowed.
+CREATE TABLE blocked_windows (
+    user_id INTEGER NOT NULL,
+    app_name TEXT NOT NULL,
+    -- minutes after midnight, in the user's timezone. an end before the start wraps past midnight.
+    start_minute INTEGER NOT NULL CHECK(start_minute BETWEEN 0 AND 1439),
+    end_minute INTEGER NOT NULL CHECK(end_minute BETWEEN 0 AND 1439),
+    -- the days the window starts on, bit 0 is monday. none disables the window.
+    weekdays INTEGER NOT NULL,
+    PRIMARY KEY(user_id, app_name, start_minute, end_minute),
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+-- temporary limits replacing all of an app's others.
+CREATE TABLE limit_overrides (
+    user_id INTEGER NOT NULL,
+    app_name TEXT NOT NULL,
+    -- stored as seconds
+    app_limit INTEGER NOT NULL,
+    -- stored as seconds after the unix epoch.
+    expires_at INTEGER NOT NULL,
+    PRIMARY KEY(user_id, app_name),
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
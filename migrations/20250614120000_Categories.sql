@@ -0,0 +1,18 @@
+-- groups of apps sharing a limit.
+CREATE TABLE categories (
+    user_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    -- stored as seconds, across all the category's apps. 0 is no limit.
+    category_limit INTEGER NOT NULL,
+    PRIMARY KEY(user_id, name),
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+-- the category of an app, at most one per app.
+CREATE TABLE category_apps (
+    user_id INTEGER NOT NULL,
+    category TEXT NOT NULL,
+    app_name TEXT NOT NULL,
+    PRIMARY KEY(user_id, app_name),
+    FOREIGN KEY(user_id, category) REFERENCES categories(user_id, name) ON DELETE CASCADE
+);
@@ -0,0 +1,8 @@
+-- the canonical app of an app name, e.g. "chrome.exe" is "Google Chrome".
+CREATE TABLE app_aliases (
+    user_id INTEGER NOT NULL,
+    alias TEXT NOT NULL,
+    app_name TEXT NOT NULL,
+    PRIMARY KEY(user_id, alias),
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
@@ -0,0 +1,35 @@
+-- the devices users log in and sync from.
+CREATE TABLE devices (
+    id INTEGER PRIMARY KEY NOT NULL,
+    user_id INTEGER NOT NULL,
+    -- chosen by the client, unique per user.
+    name TEXT NOT NULL,
+    -- e.g. `windows` or `android`, as the client reports it.
+    platform TEXT NOT NULL,
+    -- stored as seconds after the unix epoch, the last login or sync.
+    last_seen INTEGER NOT NULL,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE,
+    UNIQUE(user_id, name)
+);
+
+-- usage is recorded per device. sqlite cannot change a primary key, so the table is rebuilt.
+-- 0 is no device, as for usage synced before devices, or without one.
+CREATE TABLE usage_history_by_device (
+    user_id INTEGER NOT NULL,
+    device_id INTEGER NOT NULL DEFAULT 0,
+    app_name TEXT NOT NULL,
+    -- the utc date synced on, as `YYYY-MM-DD`.
+    day TEXT NOT NULL,
+    -- stored as seconds, the most synced that day.
+    app_usage INTEGER NOT NULL,
+    -- stored as seconds, the last synced that day.
+    app_limit INTEGER NOT NULL,
+    PRIMARY KEY(user_id, device_id, app_name, day),
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+INSERT INTO usage_history_by_device(user_id, app_name, day, app_usage, app_limit)
+SELECT user_id, app_name, day, app_usage, app_limit FROM usage_history;
+
+DROP TABLE usage_history;
+ALTER TABLE usage_history_by_device RENAME TO usage_history;
@@ -0,0 +1,13 @@
+-- rules of when clients remind users, e.g. at 80% of a limit, or to take a break.
+CREATE TABLE reminders (
+    user_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    -- `limit_percent` or `break`.
+    kind TEXT NOT NULL,
+    -- an app or a category, null for all of them.
+    target TEXT,
+    -- the percent of the limit for `limit_percent`, minutes of usage between breaks for `break`.
+    threshold INTEGER NOT NULL,
+    PRIMARY KEY(user_id, name),
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
@@ -0,0 +1,21 @@
+-- notifications waiting to be delivered to the configured sinks.
+CREATE TABLE notification_outbox (
+    id INTEGER PRIMARY KEY NOT NULL,
+    user_id INTEGER NOT NULL,
+    -- what the notification is about, e.g. `app_limit_exceeded:2025-07-12:firefox`. emitted once per user.
+    event_key TEXT NOT NULL,
+    -- the event, as json.
+    payload TEXT NOT NULL,
+    -- stored as seconds after the unix epoch.
+    created INTEGER NOT NULL,
+    -- failed deliveries so far.
+    attempts INTEGER NOT NULL DEFAULT 0,
+    -- when to next try delivering, NULL once delivered or given up on.
+    next_attempt INTEGER,
+    delivered INTEGER,
+    last_error TEXT,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE,
+    UNIQUE(user_id, event_key)
+);
+
+CREATE INDEX notification_outbox_next_attempt ON notification_outbox(next_attempt);
@@ -0,0 +1,37 @@
+-- webhooks users registered to receive their events.
+CREATE TABLE webhooks (
+    id TEXT PRIMARY KEY NOT NULL,
+    user_id INTEGER NOT NULL,
+    -- an http url.
+    url TEXT NOT NULL,
+    -- what payloads are signed with, with hmac-sha256.
+    secret TEXT NOT NULL,
+    -- the event kinds delivered, comma separated. empty for all.
+    events TEXT NOT NULL,
+    -- stored as seconds after the unix epoch.
+    created_at INTEGER NOT NULL,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+-- deliveries of events to webhooks, retried until delivered or given up on, and kept as a log.
+CREATE TABLE webhook_deliveries (
+    id INTEGER PRIMARY KEY NOT NULL,
+    webhook_id TEXT NOT NULL,
+    -- the event kind, e.g. `app_limit_exceeded`.
+    event TEXT NOT NULL,
+    -- the notification, as json.
+    payload TEXT NOT NULL,
+    -- stored as seconds after the unix epoch.
+    created INTEGER NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    -- when to next try delivering, NULL once delivered or given up on.
+    next_attempt INTEGER,
+    delivered INTEGER,
+    -- the status of the last response, NULL if there was none.
+    status INTEGER,
+    last_error TEXT,
+    FOREIGN KEY(webhook_id) REFERENCES webhooks(id) ON DELETE CASCADE
+);
+
+CREATE INDEX webhook_deliveries_next_attempt ON webhook_deliveries(next_attempt);
+CREATE INDEX webhook_deliveries_webhook_id ON webhook_deliveries(webhook_id);
@@ -0,0 +1,7 @@
+-- the timezone a user's weekday limits and blocked windows are in. utc if none.
+CREATE TABLE user_timezones (
+    user_id INTEGER PRIMARY KEY NOT NULL,
+    -- an iana name, e.g. "Europe/Berlin".
+    timezone TEXT NOT NULL,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
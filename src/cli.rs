@@ -0,0 +1,48 @@
+use std::path::{Path, PathBuf};
+
+use clap::{Parser, Subcommand};
+use sqlx::{Pool, Sqlite};
+
+use crate::routes::import::import_for_username;
+
+/// The pcup backend.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// What to do, serving the api if not given.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Default, Subcommand)]
+pub enum Command {
+    /// Serve the api.
+    #[default]
+    Serve,
+    /// Load an export, from `/export` on this or another server, into a user's account.
+    ///
+    /// Only usage data and usage history are imported, merged as `/sync` would.
+    Import {
+        /// The user to import into.
+        username: String,
+        /// The export, as JSON or ZIP.
+        file: PathBuf,
+    },
+}
+
+/// Import `file` into `username`'s account, printing what was imported.
+pub async fn import(db: &Pool<Sqlite>, username: &str, file: &Path) -> Result<(), String> {
+    let archive = std::fs::read(file).map_err(|err| format!("{}: {err}", file.display()))?;
+
+    let summary = import_for_username(db, username, &archive)
+        .await
+        .map_err(|err| format!("{err:?}"))?;
+    println!(
+        "imported into {username}, now storing {} apps and {} debug entries, {} failed",
+        summary.sync.data.app_usage.len(),
+        summary.sync.data.debug.len(),
+        summary.sync.failed
+    );
+
+    Ok(())
+}
@@ -0,0 +1,222 @@
+use std::path::PathBuf;
+
+use argon2::{Algorithm, Argon2, Params, PasswordHash, Version};
+use serde::Deserialize;
+
+/// Our settings, read from `Rocket.toml` (or `ROCKET_` environment variables) alongside Rocket's own.
+///
+/// Every section is optional, a missing one uses its [`Default`].
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct AppConfig {
+    pub argon2: HashConfig,
+    pub password_policy: PasswordPolicy,
+    pub account_deletion: DeletionConfig,
+    pub usage_limits: LimitConfig,
+    pub notifications: NotificationConfig,
+}
+
+/// The costs used to hash passwords with Argon2id, under `argon2`.
+///
+/// Raising them makes existing hashes get re-hashed on their user's next login.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct HashConfig {
+    /// Memory used, in KiB.
+    pub memory_cost: u32,
+    /// Number of passes over the memory.
+    pub time_cost: u32,
+    /// Number of lanes.
+    pub parallelism: u32,
+}
+
+impl Default for HashConfig {
+    fn default() -> Self {
+        Self {
+            memory_cost: Params::DEFAULT_M_COST,
+            time_cost: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+impl HashConfig {
+    /// Create an [`Argon2`] hashing with these costs.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the costs are out of the ranges Argon2 allows.
+    pub fn hasher(&self) -> Result<Argon2<'static>, argon2::Error> {
+        let params = Params::new(self.memory_cost, self.time_cost, self.parallelism, None)?;
+        Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+    }
+
+    /// Return `true` if `hash` was made with another algorithm or version, or any cost weaker than ours.
+    #[must_use]
+    pub fn needs_rehash(&self, hash: &PasswordHash) -> bool {
+        if Algorithm::try_from(hash.algorithm).ok() != Some(Algorithm::Argon2id)
+            || hash.version != Some(Version::V0x13.into())
+        {
+            return true;
+        }
+
+        match Params::try_from(hash) {
+            Ok(params) => {
+                params.m_cost() < self.memory_cost
+                    || params.t_cost() < self.time_cost
+                    || params.p_cost() < self.parallelism
+            }
+            // we cannot tell, so replace it with one we can.
+            Err(_) => true,
+        }
+    }
+}
+
+/// What new passwords must satisfy, under `password_policy`.
+///
+/// Lengths are counted in graphemes, what a user would see as characters.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub max_length: usize,
+    /// Reject passwords in the bundled list of common passwords.
+    pub reject_common: bool,
+    /// Reject passwords equal to, or containing, the username.
+    pub reject_username: bool,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            min_length: 8,
+            max_length: 64,
+            reject_common: true,
+            reject_username: true,
+        }
+    }
+}
+
+/// How deleting accounts is delayed, under `account_deletion`.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct DeletionConfig {
+    /// Seconds after asking to be deleted that an account is purged. Logging in before then restores it.
+    pub grace_period: i64,
+    /// Seconds between purges of accounts whose grace period passed.
+    pub purge_interval: u64,
+}
+
+impl Default for DeletionConfig {
+    fn default() -> Self {
+        Self {
+            grace_period: 7 * 24 * 60 * 60,
+            purge_interval: 60 * 60,
+        }
+    }
+}
+
+/// How app limits are evaluated for clients, under `usage_limits`.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct LimitConfig {
+    /// The percentage of a limit used after which an app is warned about.
+    pub warning_percent: u32,
+}
+
+impl Default for LimitConfig {
+    fn default() -> Self {
+        Self {
+            warning_percent: 90,
+        }
+    }
+}
+
+/// How notifications are delivered, under `notifications`.
+///
+/// Without a `webhook_url` or `file`, notifications are dropped once due.
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct NotificationConfig {
+    /// Seconds between deliveries of due notifications.
+    pub interval: u64,
+    /// The most notifications delivered each interval.
+    pub batch_size: u32,
+    /// Failed deliveries after which a notification is given up on.
+    pub max_attempts: u32,
+    /// Seconds before retrying a failed delivery, doubling with each failure after the first.
+    pub backoff: i64,
+    /// Seconds that delivered, or given up on, notifications are kept for.
+    pub retention: i64,
+    /// An `http` or `https` url notifications are posted to, as json.
+    pub webhook_url: Option<String>,
+    /// A file notifications are appended to, as json lines.
+    pub file: Option<PathBuf>,
+    /// Whether users' webhooks can be loopback, private or other local addresses. Only for testing against local
+    /// servers, as it lets users make requests into the server's network.
+    pub allow_local_webhooks: bool,
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        Self {
+            interval: 10,
+            batch_size: 100,
+            max_attempts: 8,
+            backoff: 30,
+            retention: 7 * 24 * 60 * 60,
+            webhook_url: None,
+            file: None,
+            allow_local_webhooks: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use argon2::{
+        Algorithm, Argon2, Params, PasswordHash, PasswordHasher, Version,
+        password_hash::{SaltString, rand_core::OsRng},
+    };
+
+    use super::HashConfig;
+
+    fn hash_with(argon2: &Argon2) -> String {
+        let salt = SaltString::generate(&mut OsRng);
+        argon2
+            .hash_password(b"password", &salt)
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn needs_rehash() {
+        let config = HashConfig::default();
+
+        let same = hash_with(&config.hasher().unwrap());
+        assert!(!config.needs_rehash(&PasswordHash::new(&same).unwrap()));
+
+        let weaker_params = Params::new(config.memory_cost / 2, 1, 1, None).unwrap();
+        let weaker = hash_with(&Argon2::new(
+            Algorithm::Argon2id,
+            Version::V0x13,
+            weaker_params,
+        ));
+        assert!(config.needs_rehash(&PasswordHash::new(&weaker).unwrap()));
+
+        let other_algorithm = hash_with(&Argon2::new(
+            Algorithm::Argon2i,
+            Version::V0x13,
+            Params::default(),
+        ));
+        assert!(config.needs_rehash(&PasswordHash::new(&other_algorithm).unwrap()));
+
+        // stronger than ours is left alone.
+        let stronger = HashConfig {
+            time_cost: config.time_cost + 1,
+            ..config
+        };
+        let stronger = hash_with(&stronger.hasher().unwrap());
+        assert!(!config.needs_rehash(&PasswordHash::new(&stronger).unwrap()));
+    }
+}
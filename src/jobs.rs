@@ -0,0 +1,102 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use rocket::{fairing::AdHoc, tokio};
+use sqlx::{Pool, Sqlite};
+
+use crate::{
+    config::{DeletionConfig, NotificationConfig},
+    notify::{
+        self,
+        sink::{HttpClient, Sink},
+        webhooks,
+    },
+    repo::{OutboxRepository, WebhookRepository},
+    routes::delete_account::purge,
+};
+
+/// Purge deleted accounts every [`DeletionConfig::purge_interval`], once Rocket lifts off.
+pub(crate) fn purge_deleted_accounts() -> AdHoc {
+    AdHoc::on_liftoff("Purge deleted accounts", |rocket| {
+        Box::pin(async move {
+            let (Some(db), Some(config)) = (
+                rocket.state::<Pool<Sqlite>>(),
+                rocket.state::<DeletionConfig>(),
+            ) else {
+                tracing::error!("no db or deletion config managed, not purging deleted accounts");
+                return;
+            };
+            let db = db.clone();
+            let config = *config;
+
+            tokio::spawn(async move {
+                // `interval` panics on zero.
+                let period = Duration::from_secs(config.purge_interval.max(1));
+                let mut interval = tokio::time::interval(period);
+
+                loop {
+                    interval.tick().await;
+                    if let Err(err) = purge(&db, &config, Utc::now().timestamp()).await {
+                        tracing::error!("failed to purge deleted accounts: {err:?}");
+                    }
+                }
+            });
+        })
+    })
+}
+
+/// Deliver due notifications to the configured sinks, and to users' webhooks, every
+/// [`NotificationConfig::interval`], once Rocket lifts off.
+///
+/// Notifications and webhook deliveries older than [`NotificationConfig::retention`] that are delivered, or
+/// given up on, are purged as well.
+pub(crate) fn deliver_notifications() -> AdHoc {
+    AdHoc::on_liftoff("Deliver notifications", |rocket| {
+        Box::pin(async move {
+            let (Some(db), Some(config), Some(client)) = (
+                rocket.state::<Pool<Sqlite>>(),
+                rocket.state::<NotificationConfig>(),
+                rocket.state::<HttpClient>(),
+            ) else {
+                tracing::error!(
+                    "no db, notification config or http client managed, not delivering notifications"
+                );
+                return;
+            };
+            let (db, config, client) = (db.clone(), config.clone(), client.clone());
+            let sinks = match Sink::from_config(&config) {
+                Ok(sinks) => sinks,
+                Err(err) => {
+                    tracing::error!(
+                        "invalid notification sinks, not delivering notifications: {err}"
+                    );
+                    return;
+                }
+            };
+
+            tokio::spawn(async move {
+                // `interval` panics on zero.
+                let period = Duration::from_secs(config.interval.max(1));
+                let mut interval = tokio::time::interval(period);
+
+                loop {
+                    interval.tick().await;
+                    let now = Utc::now().timestamp();
+                    if let Err(err) = notify::deliver_due(&db, sinks.as_slice(), &config, now).await
+                    {
+                        tracing::error!("failed to deliver notifications: {err:?}");
+                    }
+                    if let Err(err) = webhooks::deliver_due(&db, &client, &config, now).await {
+                        tracing::error!("failed to deliver to webhooks: {err:?}");
+                    }
+                    if let Err(err) = db.purge_notifications(now - config.retention).await {
+                        tracing::error!("failed to purge notifications: {err:?}");
+                    }
+                    if let Err(err) = db.purge_webhook_deliveries(now - config.retention).await {
+                        tracing::error!("failed to purge webhook deliveries: {err:?}");
+                    }
+                }
+            });
+        })
+    })
+}
@@ -60,6 +60,29 @@ where
     }
 }
 
+/// A type that is fetchable to a [`Vec`] of [`Self`], filterable by `F` from a database of type [`Self::DB`].
+///
+/// Unlike [`Fetchable`], for types that are only fetched all together, e.g. all of a user's rows of a table.
+///
+/// Only one implementation with `F` and [`Self::DB`] is allowed per type.
+#[allow(async_fn_in_trait)]
+pub trait FetchableAll<'a, F>: Sized
+where
+    F: Encode<'a, Self::DB> + Type<Self::DB> + 'a,
+{
+    /// The database the implementor is [`FetchableAll`] for.
+    type DB: Database;
+
+    /// Fetch all Self from the [`Self::DB`] database, using `filter` to filter.
+    ///
+    /// # Errors
+    ///
+    /// See [`sqlx::Error`].
+    async fn fetch_all<E>(filter: F, executor: E) -> Result<Vec<Self>, sqlx::Error>
+    where
+        E: Executor<'a, Database = Self::DB> + Copy;
+}
+
 #[derive(Error, Debug, Serialize, Deserialize)]
 pub enum DBErrorKind {
     #[error("InsertError")]
@@ -1,14 +1,39 @@
+/// The command line interface, see [`cli::Cli`].
+mod cli;
+/// Our settings, see [`config::AppConfig`].
+mod config;
+/// Background work, started when Rocket lifts off.
+mod jobs;
+/// Notifying other channels of events, through an outbox.
+mod notify;
+/// Storage of users, sessions and user data, independent of the database used.
+mod repo;
 pub mod routes;
 /// Test the database schema.
 #[cfg(test)]
 mod schema_test;
 mod util;
 
+use clap::Parser;
+use cli::{Cli, Command};
+use config::AppConfig;
 use console_subscriber::Server;
 use rocket::{Build, Rocket, get, routes};
 use routes::{
-    auth::authenticate, delete_account::delete_account, reset_session::reset_session, sync::sync,
+    auth::{login, login_totp, signup},
+    change_username::change_username,
+    delete_account::delete_account,
+    devices::{delete_device, list_devices},
+    export::export_data,
+    import::import_data,
+    reminders::due_reminders,
+    reset_session::reset_session,
+    sync::{events::SyncEvents, sync, sync_events},
+    tokens::{create_token, list_tokens, revoke_token},
+    totp::{confirm_totp, enroll_totp},
+    usage::{usage_catalog, usage_csv, usage_limits, usage_stats},
     validate_session::validate_session,
+    webhooks::{create_webhook, delete_webhook, list_webhooks, test_webhook, webhook_deliveries},
 };
 use sqlx::{Pool, Sqlite, migrate, pool::PoolOptions, sqlite::SqliteConnectOptions};
 use tracing::level_filters::LevelFilter;
@@ -32,10 +57,24 @@ static GLOBAL: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
 static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
 
 #[rocket::main]
+// `rocket::Error` is large, but only returned once, on exit.
+#[allow(clippy::result_large_err)]
 async fn main() -> Result<(), rocket::Error> {
+    let cli = Cli::parse();
     init_loggers();
 
-    rocket(None).await.launch().await.unwrap();
+    match cli.command.unwrap_or_default() {
+        Command::Serve => {
+            rocket(None).await.launch().await.unwrap();
+        }
+        Command::Import { username, file } => {
+            let db_pool = get_migrated_db_pool(None).await;
+            if let Err(err) = cli::import(&db_pool, &username, &file).await {
+                eprintln!("import failed: {err}");
+                std::process::exit(1);
+            }
+        }
+    }
 
     Ok(())
 }
@@ -83,6 +122,29 @@ async fn get_db_pool(name: Option<&str>) -> Pool<Sqlite> {
         .expect("could not open db")
 }
 
+/// Create a [`Pool<Sqlite>`] with [`get_db_pool`], and run migrations on it.
+///
+/// # Panics
+///
+/// Will panic if db cannot be opened, or migrated.
+async fn get_migrated_db_pool(name: Option<&str>) -> Pool<Sqlite> {
+    let db_pool = get_db_pool(name).await;
+    tracing::debug!("created db pool");
+
+    // do db migrations. (from the `./migrations` dir)
+    tracing::debug!("running migrations..");
+    let migrator = migrate!();
+    migrator
+        .run(&db_pool)
+        .await
+        .expect("could not run migrations");
+    util::username::backfill_username_keys(&db_pool)
+        .await
+        .expect("could not backfill username keys");
+
+    db_pool
+}
+
 /// Test a Rocket!
 ///
 /// `name` is the test's name.
@@ -109,27 +171,60 @@ pub(crate) fn test_rocket(name: &str) -> Rocket<Build> {
 ///
 /// if `db_name` is [`None`], we use the [`DB_PATH`] const.
 async fn rocket(db_name: Option<&str>) -> Rocket<Build> {
-    let db_pool = get_db_pool(db_name).await;
-    tracing::debug!("created db pool");
-
-    // do db migrations. (from the `./migrations` dir)
-    tracing::debug!("running migrations..");
-    let migrator = migrate!();
-    migrator
-        .run(&db_pool)
-        .await
-        .expect("could not run migrations");
+    let db_pool = get_migrated_db_pool(db_name).await;
 
     let routes = routes![
         index,
-        authenticate,
+        login,
+        login_totp,
+        signup,
+        enroll_totp,
+        confirm_totp,
+        create_token,
+        list_tokens,
+        revoke_token,
+        change_username,
         delete_account,
+        list_devices,
+        delete_device,
+        export_data,
+        import_data,
+        usage_csv,
+        usage_stats,
+        usage_limits,
+        usage_catalog,
+        due_reminders,
+        create_webhook,
+        list_webhooks,
+        delete_webhook,
+        webhook_deliveries,
+        test_webhook,
         validate_session,
         reset_session,
         sync,
+        sync_events,
     ];
 
-    rocket::build().manage(db_pool).mount("/", routes)
+    let rocket = rocket::build();
+    let config: AppConfig = rocket.figment().extract().expect("invalid config");
+    tracing::debug!("using {config:?}");
+    let hasher = config.argon2.hasher().expect("invalid [argon2] config");
+    routes::auth::dummy_hash(&hasher).expect("could not hash with the [argon2] config");
+
+    rocket
+        .manage(db_pool)
+        .manage(config.argon2)
+        .manage(config.password_policy)
+        .manage(config.account_deletion)
+        .manage(config.usage_limits)
+        .manage(notify::sink::http_client(
+            config.notifications.allow_local_webhooks,
+        ))
+        .manage(config.notifications)
+        .manage(SyncEvents::default())
+        .attach(jobs::purge_deleted_accounts())
+        .attach(jobs::deliver_notifications())
+        .mount("/", routes)
 }
 
 /// this is our default fmt.
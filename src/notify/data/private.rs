@@ -0,0 +1,20 @@
+use sqlx::FromRow;
+
+/// A notification of the outbox, see the `notification_outbox` table.
+#[derive(Debug, FromRow, PartialEq, Eq, Clone)]
+pub struct DBNotification {
+    pub id: u32,
+    pub user_id: u32,
+    /// See [`Event::key`](super::public::Event::key).
+    pub event_key: String,
+    /// The [`Event`](super::public::Event), as json.
+    pub payload: String,
+    /// Stored as seconds since the unix epoch.
+    pub created: i64,
+    /// Failed deliveries so far.
+    pub attempts: u32,
+    /// When to next try delivering, [`None`] once delivered or given up on.
+    pub next_attempt: Option<i64>,
+    pub delivered: Option<i64>,
+    pub last_error: Option<String>,
+}
@@ -0,0 +1,136 @@
+use serde::{Deserialize, Serialize};
+
+/// What a [`Notification`] is about.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// An app was used past its own limit, on `day`.
+    AppLimitExceeded {
+        app: String,
+        /// The date in the user's timezone, as `YYYY-MM-DD`.
+        day: String,
+        /// In seconds.
+        usage: u32,
+        /// In seconds.
+        limit: u32,
+    },
+    /// The apps of a category were used past the category's limit, on `day`.
+    CategoryLimitExceeded {
+        category: String,
+        /// The date in the user's timezone, as `YYYY-MM-DD`.
+        day: String,
+        /// In seconds.
+        usage: u32,
+        /// In seconds.
+        limit: u32,
+    },
+    /// The usage of a past `day`, as synced by the first sync after it.
+    DailySummary {
+        /// The date in the user's timezone, as `YYYY-MM-DD`.
+        day: String,
+        /// Of all apps, in seconds.
+        usage: u32,
+        /// The most used first.
+        apps: Vec<AppUsage>,
+    },
+    /// The user signed up.
+    ///
+    /// Accounts are created before they can register webhooks, so webhooks receiving it are sent it when they are
+    /// registered instead.
+    AccountCreated,
+    /// Sent to a webhook on request, see [`crate::routes::webhooks`].
+    WebhookTest {
+        /// The webhook's id.
+        webhook: String,
+    },
+}
+
+impl Event {
+    /// What the event is unique by, per user. It is only notified about once.
+    #[must_use]
+    pub fn key(&self) -> String {
+        match self {
+            Self::AppLimitExceeded { app, day, .. } => format!("app_limit_exceeded:{day}:{app}"),
+            Self::CategoryLimitExceeded { category, day, .. } => {
+                format!("category_limit_exceeded:{day}:{category}")
+            }
+            Self::DailySummary { day, .. } => format!("daily_summary:{day}"),
+            Self::AccountCreated => "account_created".to_string(),
+            Self::WebhookTest { webhook } => format!("webhook_test:{webhook}"),
+        }
+    }
+
+    /// The kind webhooks subscribe to the event by, [`None`] if webhooks never receive it.
+    ///
+    /// Tests are sent regardless.
+    #[must_use]
+    pub fn kind(&self) -> Option<EventKind> {
+        match self {
+            Self::AppLimitExceeded { .. } => Some(EventKind::AppLimitExceeded),
+            Self::CategoryLimitExceeded { .. } => Some(EventKind::CategoryLimitExceeded),
+            Self::DailySummary { .. } => Some(EventKind::DailySummary),
+            Self::AccountCreated => Some(EventKind::AccountCreated),
+            Self::WebhookTest { .. } => None,
+        }
+    }
+}
+
+/// The [`Event`]s a webhook can subscribe to.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    AppLimitExceeded,
+    CategoryLimitExceeded,
+    DailySummary,
+    AccountCreated,
+}
+
+impl EventKind {
+    /// As serialized, e.g. `app_limit_exceeded`.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::AppLimitExceeded => "app_limit_exceeded",
+            Self::CategoryLimitExceeded => "category_limit_exceeded",
+            Self::DailySummary => "daily_summary",
+            Self::AccountCreated => "account_created",
+        }
+    }
+
+    /// Parse a [`name`](Self::name).
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            Self::AppLimitExceeded,
+            Self::CategoryLimitExceeded,
+            Self::DailySummary,
+            Self::AccountCreated,
+        ]
+        .into_iter()
+        .find(|k| k.name() == name)
+    }
+}
+
+/// An app's usage of a [`Event::DailySummary`].
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct AppUsage {
+    /// The canonical name, see [`AppAlias`](crate::routes::sync::data::public::AppAlias).
+    pub app: String,
+    /// In seconds.
+    pub usage: u32,
+}
+
+/// An [`Event`] of a user, as delivered to sinks.
+///
+/// Deliveries are retried until they succeed, so the same notification may be received more than once.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct Notification {
+    /// Unique per notification, the same on every delivery of it. `0` for webhook tests.
+    pub id: u32,
+    /// The user's public id.
+    pub user: String,
+    /// When the event happened. Seconds since the unix epoch.
+    pub created: i64,
+    #[serde(flatten)]
+    pub event: Event,
+}
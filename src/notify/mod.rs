@@ -0,0 +1,251 @@
+/// Data structs of notifications.
+pub mod data;
+/// Which addresses webhooks can be delivered to.
+pub(crate) mod net;
+/// Where notifications are delivered to, see [`sink::NotificationSink`].
+pub mod sink;
+/// Delivering notifications to users' own webhooks, see [`crate::routes::webhooks`].
+pub(crate) mod webhooks;
+
+#[cfg(test)]
+pub(crate) mod tests;
+
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use data::public::{AppUsage, Event, Notification};
+use rocket::serde::json;
+use sink::NotificationSink;
+
+use crate::{
+    config::{LimitConfig, NotificationConfig},
+    repo::{OutboxRepository, UsageRepository, UserRepository, WebhookRepository},
+    routes::usage::{self, aliases::Aliases, data::private::DAY_FORMAT},
+};
+
+/// The longest wait before retrying a delivery, in seconds.
+const MAX_BACKOFF: i64 = 24 * 60 * 60;
+
+/// Queue a notification of `event` for the user with the id `user_id`, and its deliveries to their webhooks,
+/// returning `false` if it was already.
+///
+/// # Errors
+///
+/// See [`sqlx::Error`].
+pub(crate) async fn emit(
+    repo: &(impl OutboxRepository + WebhookRepository + UserRepository),
+    user_id: u32,
+    event: &Event,
+    now: i64,
+) -> Result<bool, sqlx::Error> {
+    let payload = json::to_string(event).map_err(|err| sqlx::Error::Encode(err.into()))?;
+    let Some(id) = repo
+        .enqueue_notification(user_id, &event.key(), &payload, now)
+        .await?
+    else {
+        return Ok(false);
+    };
+
+    if event.kind().is_some() {
+        let notification = Notification {
+            id,
+            user: repo.fetch_user_by_id(user_id).await?.public_id,
+            created: now,
+            event: event.clone(),
+        };
+        webhooks::fan_out(repo, user_id, &notification, now).await?;
+    }
+    Ok(true)
+}
+
+/// [`emit`] `event`, logging failures instead of returning them, so they do not fail what caused the event.
+pub(crate) async fn emit_logged(
+    repo: &(impl OutboxRepository + WebhookRepository + UserRepository),
+    user_id: u32,
+    event: &Event,
+    now: i64,
+) {
+    match emit(repo, user_id, event, now).await {
+        Ok(true) => tracing::info!("emitted {} for user {user_id}", event.key()),
+        Ok(false) => {}
+        Err(err) => tracing::error!("could not emit {} for user {user_id}: {err:?}", event.key()),
+    }
+}
+
+/// Emit an event for each app and category of the user with the id `user_id` whose limit is exceeded at
+/// `now`.
+///
+/// Failures are logged, not returned, see [`emit_logged`].
+pub(crate) async fn emit_limits_exceeded(
+    repo: &(impl UsageRepository + OutboxRepository + WebhookRepository + UserRepository),
+    user_id: u32,
+    now: DateTime<Utc>,
+) {
+    // warnings are not notified about, so the warning percentage does not matter.
+    let limits = match usage::limits_of(repo, &LimitConfig::default(), user_id, now).await {
+        Ok(limits) => limits,
+        Err(err) => {
+            tracing::error!("could not evaluate limits of user {user_id}: {err:?}");
+            return;
+        }
+    };
+    // the day the limits were evaluated for.
+    let Some(day) = local_day(repo, user_id, now).await else {
+        return;
+    };
+    let day = day.format(DAY_FORMAT).to_string();
+
+    let apps = limits
+        .apps
+        .into_iter()
+        .filter(|a| usage::limit_exceeded(a.limit, a.usage))
+        .map(|a| Event::AppLimitExceeded {
+            app: a.app,
+            day: day.clone(),
+            usage: a.usage,
+            limit: a.limit,
+        });
+    let categories = limits
+        .categories
+        .into_iter()
+        .filter(|c| usage::limit_exceeded(c.limit, c.usage))
+        .map(|c| Event::CategoryLimitExceeded {
+            category: c.name,
+            day: day.clone(),
+            usage: c.usage,
+            limit: c.limit,
+        });
+
+    for event in apps.chain(categories) {
+        emit_logged(repo, user_id, &event, now.timestamp()).await;
+    }
+}
+
+/// Emit a summary of yesterday's usage of the user with the id `user_id`, in their timezone, if they had any, as of
+/// `now`.
+///
+/// Only the usage synced by then is summarized, it is emitted once.
+pub(crate) async fn emit_daily_summary(
+    repo: &(impl UsageRepository + OutboxRepository + WebhookRepository + UserRepository),
+    user_id: u32,
+    now: DateTime<Utc>,
+) {
+    let Some(yesterday) = local_day(repo, user_id, now)
+        .await
+        .and_then(|today| today.checked_sub_days(Days::new(1)))
+    else {
+        return;
+    };
+    let usage = async {
+        let aliases = Aliases::new(&repo.fetch_app_aliases(user_id).await?);
+        let history = repo
+            .fetch_usage_history(user_id, None, Some(yesterday), Some(yesterday))
+            .await?;
+        Ok::<_, sqlx::Error>(aliases.aggregate(history))
+    };
+    let mut apps: Vec<AppUsage> = match usage.await {
+        Ok(usage) => usage
+            .into_iter()
+            .map(|u| AppUsage {
+                app: u.app_name,
+                usage: u.app_usage,
+            })
+            .collect(),
+        Err(err) => {
+            tracing::error!("could not fetch the usage of user {user_id}: {err:?}");
+            return;
+        }
+    };
+    if apps.is_empty() {
+        return;
+    }
+    apps.sort_by(|a, b| b.usage.cmp(&a.usage).then_with(|| a.app.cmp(&b.app)));
+
+    let event = Event::DailySummary {
+        day: yesterday.format(DAY_FORMAT).to_string(),
+        usage: apps.iter().map(|a| a.usage).sum(),
+        apps,
+    };
+    emit_logged(repo, user_id, &event, now.timestamp()).await;
+}
+
+/// The date of `now` in the timezone of the user with the id `user_id`, see [`usage::local_time`].
+///
+/// Failures are logged, not returned.
+async fn local_day(
+    repo: &impl UsageRepository,
+    user_id: u32,
+    now: DateTime<Utc>,
+) -> Option<NaiveDate> {
+    match usage::timezone_of(repo, user_id).await {
+        Ok(timezone) => Some(usage::local_time(now, timezone).date_naive()),
+        Err(err) => {
+            tracing::error!("could not fetch the timezone of user {user_id}: {err:?}");
+            None
+        }
+    }
+}
+
+/// When to retry a notification after its `attempts`th failed delivery at `now`, [`None`] to give up.
+fn retry_at(config: &NotificationConfig, attempts: u32, now: i64) -> Option<i64> {
+    if attempts >= config.max_attempts {
+        return None;
+    }
+    let backoff = config
+        .backoff
+        .saturating_mul(1 << attempts.saturating_sub(1).min(32))
+        .min(MAX_BACKOFF);
+    Some(now + backoff)
+}
+
+/// Deliver up to [`NotificationConfig::batch_size`] notifications due at `now` to `sink`, returning how many
+/// were delivered.
+///
+/// Failed deliveries are retried with an exponential backoff, up to [`NotificationConfig::max_attempts`].
+///
+/// # Errors
+///
+/// See [`sqlx::Error`].
+pub(crate) async fn deliver_due(
+    repo: &(impl OutboxRepository + UserRepository),
+    sink: &(impl NotificationSink + ?Sized),
+    config: &NotificationConfig,
+    now: i64,
+) -> Result<usize, sqlx::Error> {
+    let mut delivered = 0;
+
+    for entry in repo.fetch_due_notifications(now, config.batch_size).await? {
+        let user = repo.fetch_user_by_id(entry.user_id).await?;
+        let event: Event = match json::from_str(&entry.payload) {
+            Ok(event) => event,
+            Err(err) => {
+                // it will never parse, retrying is pointless.
+                tracing::error!("notification {} has an invalid payload: {err}", entry.id);
+                repo.mark_failed(entry.id, &err.to_string(), None).await?;
+                continue;
+            }
+        };
+        let notification = Notification {
+            id: entry.id,
+            user: user.public_id,
+            created: entry.created,
+            event,
+        };
+
+        match sink.deliver(&notification).await {
+            Ok(()) => {
+                repo.mark_delivered(entry.id, now).await?;
+                delivered += 1;
+            }
+            Err(err) => {
+                let next_attempt = retry_at(config, entry.attempts + 1, now);
+                tracing::warn!(
+                    "could not deliver notification {}, next attempt at {next_attempt:?}: {err}",
+                    entry.id
+                );
+                repo.mark_failed(entry.id, &err.to_string(), next_attempt)
+                    .await?;
+            }
+        }
+    }
+
+    Ok(delivered)
+}
@@ -0,0 +1,155 @@
+use std::{
+    future::Future,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    pin::Pin,
+    task::{Context, Poll},
+    vec,
+};
+
+use hyper::Uri;
+use hyper_util::client::legacy::connect::dns::Name;
+use rocket::tokio::net::lookup_host;
+use tower_service::Service;
+
+use super::sink::SinkError;
+
+/// Return `true` if `ip` is reachable on the internet, so not loopback, private, link-local, or otherwise reserved.
+///
+/// [`IpAddr::is_global`] is not stable yet, this follows it.
+pub(crate) fn is_global(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_global_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(mapped) => is_global_v4(mapped),
+            None => is_global_v6(ip),
+        },
+    }
+}
+
+fn is_global_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        // "this network".
+        || a == 0
+        // shared address space.
+        || (a == 100 && (b & 0b1100_0000) == 64)
+        // protocol assignments.
+        || (a == 192 && b == 0 && c == 0)
+        // benchmarking.
+        || (a == 198 && (b & 0b1111_1110) == 18)
+        // reserved.
+        || a >= 240)
+}
+
+fn is_global_v6(ip: Ipv6Addr) -> bool {
+    let segments = ip.segments();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // unique local.
+        || (segments[0] & 0xfe00) == 0xfc00
+        // link-local.
+        || (segments[0] & 0xffc0) == 0xfe80
+        // documentation.
+        || (segments[0] == 0x2001 && segments[1] == 0x0db8)
+        // ipv4 compatible, nat64 and discard-only.
+        || (segments[..6] == [0; 6])
+        || (segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0])
+        || (segments[..4] == [0x100, 0, 0, 0]))
+}
+
+/// Check that every address the host of `url` resolves to is global, see [`is_global`], unless `allow_local`.
+///
+/// # Errors
+///
+/// Errors if the host does not resolve, or resolves to an address that is not allowed.
+pub(crate) async fn check_destination(url: &Uri, allow_local: bool) -> Result<(), SinkError> {
+    let host = url.host().unwrap_or_default();
+    let port = url.port_u16().unwrap_or(0);
+    let addresses: Vec<SocketAddr> = lookup_host((host.trim_matches(['[', ']']), port))
+        .await
+        .map_err(|err| SinkError::Request(err.to_string()))?
+        .collect();
+
+    if addresses.is_empty() {
+        return Err(SinkError::Request(format!("{host} did not resolve")));
+    }
+    if !allow_local && addresses.iter().any(|a| !is_global(a.ip())) {
+        return Err(SinkError::Forbidden(host.to_string()));
+    }
+    Ok(())
+}
+
+/// Resolves hosts as [`GaiResolver`](hyper_util::client::legacy::connect::dns::GaiResolver) does, dropping addresses
+/// that are not global unless `allow_local`.
+///
+/// Checking as the connection is made means hosts cannot resolve to a global address when the webhook is created,
+/// then a local one when it is delivered to.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct GlobalResolver {
+    pub allow_local: bool,
+}
+
+impl Service<Name> for GlobalResolver {
+    type Response = vec::IntoIter<SocketAddr>;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        let allow_local = self.allow_local;
+        Box::pin(async move {
+            let addresses: Vec<SocketAddr> = lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|a| allow_local || is_global(a.ip()))
+                .collect();
+            if addresses.is_empty() {
+                return Err(io::Error::other(format!(
+                    "{name} has no address that can be delivered to"
+                )));
+            }
+            Ok(addresses.into_iter())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use super::is_global;
+
+    #[test]
+    fn global_addresses() {
+        for ip in ["1.1.1.1", "93.184.215.14", "2606:4700:4700::1111"] {
+            assert!(is_global(ip.parse::<IpAddr>().unwrap()), "{ip}");
+        }
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "255.255.255.255",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!is_global(ip.parse::<IpAddr>().unwrap()), "{ip}");
+        }
+    }
+}
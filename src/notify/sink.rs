@@ -0,0 +1,246 @@
+use std::{net::IpAddr, path::PathBuf, time::Duration};
+
+use http_body_util::Full;
+use hyper::{Request, Uri, body::Bytes, header::CONTENT_TYPE};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use hyper_util::{
+    client::legacy::{Client, connect::HttpConnector},
+    rt::TokioExecutor,
+};
+use rocket::{
+    serde::json,
+    tokio::{self, fs::OpenOptions, io::AsyncWriteExt},
+};
+use thiserror::Error;
+
+use crate::config::NotificationConfig;
+
+use super::{
+    data::public::Notification,
+    net::{GlobalResolver, is_global},
+};
+
+/// How long webhooks have to respond, including connecting.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Somewhere notifications are delivered to.
+#[allow(async_fn_in_trait)]
+pub trait NotificationSink {
+    /// Deliver `notification`, once.
+    ///
+    /// # Errors
+    ///
+    /// Errors if it was not delivered, it is retried later.
+    async fn deliver(&self, notification: &Notification) -> Result<(), SinkError>;
+}
+
+#[derive(Error, Debug)]
+pub enum SinkError {
+    #[error("invalid webhook url {0}, only http and https urls are supported")]
+    InvalidUrl(String),
+    #[error("{0} is not a public address")]
+    Forbidden(String),
+    #[error("request failed: {0}")]
+    Request(String),
+    #[error("responded with status {0}")]
+    Status(u16),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not serialize: {0}")]
+    Serialize(#[from] json::serde_json::Error),
+}
+
+/// The client webhooks are posted with, over `http` or `https`.
+///
+/// Unless it allows local addresses, it only connects to global ones, see [`is_global`].
+#[derive(Debug, Clone)]
+pub(crate) struct HttpClient {
+    client: Client<HttpsConnector<HttpConnector<GlobalResolver>>, Full<Bytes>>,
+    allow_local: bool,
+}
+
+impl HttpClient {
+    /// Whether the client connects to local addresses, see [`NotificationConfig::allow_local_webhooks`].
+    pub fn allows_local(&self) -> bool {
+        self.allow_local
+    }
+}
+
+/// Create an [`HttpClient`], connecting to local addresses too if `allow_local`.
+pub(crate) fn http_client(allow_local: bool) -> HttpClient {
+    let mut http = HttpConnector::new_with_resolver(GlobalResolver { allow_local });
+    http.enforce_http(false);
+    let https = HttpsConnectorBuilder::new()
+        .with_webpki_roots()
+        .https_or_http()
+        .enable_http1()
+        .wrap_connector(http);
+
+    HttpClient {
+        client: Client::builder(TokioExecutor::new()).build(https),
+        allow_local,
+    }
+}
+
+/// Parse `url`, which must be an `http` or `https` url.
+///
+/// # Errors
+///
+/// Errors if it is not.
+pub(crate) fn parse_url(url: &str) -> Result<Uri, SinkError> {
+    let invalid = || SinkError::InvalidUrl(url.to_string());
+    let parsed: Uri = url.parse().map_err(|_| invalid())?;
+    if !matches!(parsed.scheme_str(), Some("http" | "https")) || parsed.host().is_none() {
+        return Err(invalid());
+    }
+    Ok(parsed)
+}
+
+/// Post `body`, json, to `url` with `headers`, returning the response's status.
+///
+/// # Errors
+///
+/// Errors if there was no response, or its status is not `2xx`.
+pub(crate) async fn post_json(
+    client: &HttpClient,
+    url: &Uri,
+    body: String,
+    headers: &[(&str, String)],
+) -> Result<u16, SinkError> {
+    // addresses are connected to without resolving, so are not checked by the resolver.
+    let host = url.host().unwrap_or_default();
+    if let Ok(ip) = host.trim_matches(['[', ']']).parse::<IpAddr>()
+        && !client.allow_local
+        && !is_global(ip)
+    {
+        return Err(SinkError::Forbidden(host.to_string()));
+    }
+
+    let mut request = Request::post(url).header(CONTENT_TYPE, "application/json");
+    for (name, value) in headers {
+        request = request.header(*name, value);
+    }
+    let request = request
+        .body(Full::from(body))
+        .map_err(|err| SinkError::Request(err.to_string()))?;
+
+    let response = tokio::time::timeout(REQUEST_TIMEOUT, client.client.request(request))
+        .await
+        .map_err(|_| SinkError::Request("timed out".to_string()))?
+        .map_err(|err| SinkError::Request(err.to_string()))?;
+
+    let status = response.status();
+    if status.is_success() {
+        Ok(status.as_u16())
+    } else {
+        Err(SinkError::Status(status.as_u16()))
+    }
+}
+
+/// Posts notifications as json to a url, a `2xx` response is a delivery.
+///
+/// The url is configured, so can be a local address.
+#[derive(Debug, Clone)]
+pub struct WebhookSink {
+    url: Uri,
+    client: HttpClient,
+}
+
+impl WebhookSink {
+    /// Create a [`WebhookSink`] posting to `url`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if `url` is not an `http` or `https` url.
+    pub fn new(url: &str) -> Result<Self, SinkError> {
+        Ok(Self {
+            url: parse_url(url)?,
+            client: http_client(true),
+        })
+    }
+}
+
+impl NotificationSink for WebhookSink {
+    async fn deliver(&self, notification: &Notification) -> Result<(), SinkError> {
+        post_json(&self.client, &self.url, json::to_string(notification)?, &[]).await?;
+        Ok(())
+    }
+}
+
+/// Appends notifications to a file, one json object per line.
+#[derive(Debug, Clone)]
+pub struct FileSink {
+    path: PathBuf,
+}
+
+impl FileSink {
+    /// Create a [`FileSink`] appending to `path`, created if missing.
+    #[must_use]
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+impl NotificationSink for FileSink {
+    async fn deliver(&self, notification: &Notification) -> Result<(), SinkError> {
+        let mut line = json::to_string(notification)?;
+        line.push('\n');
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+        file.write_all(line.as_bytes()).await?;
+        // tokio writes in the background, make sure it is done.
+        file.flush().await?;
+        Ok(())
+    }
+}
+
+/// One of the sinks that can be configured, see [`NotificationConfig`].
+#[derive(Debug, Clone)]
+pub enum Sink {
+    Webhook(Box<WebhookSink>),
+    File(FileSink),
+}
+
+impl Sink {
+    /// Create the sinks `config` sets.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the webhook url is invalid.
+    pub fn from_config(config: &NotificationConfig) -> Result<Vec<Self>, SinkError> {
+        let mut sinks = Vec::new();
+        if let Some(url) = &config.webhook_url {
+            sinks.push(Self::Webhook(Box::new(WebhookSink::new(url)?)));
+        }
+        if let Some(path) = &config.file {
+            sinks.push(Self::File(FileSink::new(path.clone())));
+        }
+        Ok(sinks)
+    }
+}
+
+impl NotificationSink for Sink {
+    async fn deliver(&self, notification: &Notification) -> Result<(), SinkError> {
+        match self {
+            Self::Webhook(sink) => sink.deliver(notification).await,
+            Self::File(sink) => sink.deliver(notification).await,
+        }
+    }
+}
+
+/// Delivers to every sink. If any fails, all are retried.
+impl<S: NotificationSink> NotificationSink for [S] {
+    async fn deliver(&self, notification: &Notification) -> Result<(), SinkError> {
+        let mut result = Ok(());
+        for sink in self {
+            if let Err(err) = sink.deliver(notification).await {
+                result = Err(err);
+            }
+        }
+        result
+    }
+}
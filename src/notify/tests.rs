@@ -0,0 +1,342 @@
+use std::sync::{
+    Mutex,
+    atomic::{AtomicU32, Ordering},
+};
+
+use chrono::{Days, Utc};
+use chrono_tz::Tz;
+use rocket::{
+    serde::json,
+    tokio::{
+        self,
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    },
+};
+
+use crate::{
+    config::NotificationConfig,
+    repo::{OutboxRepository, UsageRepository, memory::MemoryRepository},
+    routes::{
+        auth::signed_up,
+        sync::{
+            data::public::{AppInfo, UserData},
+            events::SyncEvents,
+            sync_data,
+        },
+        usage::data::private::{DAY_FORMAT, DBUsageDay},
+    },
+};
+
+use super::{
+    data::public::{Event, Notification},
+    deliver_due,
+    sink::{FileSink, NotificationSink, SinkError, WebhookSink},
+};
+
+/// Fails its first `failures` deliveries, then keeps what it is delivered.
+#[derive(Default)]
+struct StubSink {
+    failures: AtomicU32,
+    delivered: Mutex<Vec<Notification>>,
+}
+
+impl NotificationSink for StubSink {
+    async fn deliver(&self, notification: &Notification) -> Result<(), SinkError> {
+        let failed = self
+            .failures
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |f| f.checked_sub(1));
+        if failed.is_ok() {
+            return Err(SinkError::Status(503));
+        }
+        self.delivered.lock().unwrap().push(notification.clone());
+        Ok(())
+    }
+}
+
+fn notification() -> Notification {
+    Notification {
+        id: 1,
+        user: "user".to_string(),
+        created: 0,
+        event: Event::AppLimitExceeded {
+            app: "io1".to_string(),
+            day: "2025-07-12".to_string(),
+            usage: 60,
+            limit: 30,
+        },
+    }
+}
+
+#[rocket::async_test]
+async fn outbox_retries() {
+    let repo = MemoryRepository::default();
+    let (_, session) = signed_up(&repo).await;
+    let config = NotificationConfig {
+        max_attempts: 3,
+        backoff: 10,
+        ..Default::default()
+    };
+    let now = Utc::now().timestamp();
+
+    // signing up is notified about.
+    let sink = StubSink::default();
+    assert_eq!(deliver_due(&repo, &sink, &config, now).await.unwrap(), 1);
+    assert_eq!(
+        sink.delivered.lock().unwrap()[0].event,
+        Event::AccountCreated
+    );
+
+    let events = SyncEvents::default();
+    let sync = |usage| {
+        let data = UserData {
+            app_usage: vec![AppInfo::new("io1", usage, 30), AppInfo::new("io2", 10, 30)],
+            ..Default::default()
+        };
+        sync_data(&repo, &events, &session.id, None, Some(data))
+    };
+    sync(60).await.unwrap();
+    // exceeding it again the same day is not notified about again.
+    sync(90).await.unwrap();
+    let now = Utc::now().timestamp();
+
+    let sink = StubSink {
+        failures: AtomicU32::new(2),
+        ..Default::default()
+    };
+
+    assert_eq!(deliver_due(&repo, &sink, &config, now).await.unwrap(), 0);
+    // not due again before the backoff..
+    assert!(
+        repo.fetch_due_notifications(now + 9, 10)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    assert_eq!(
+        deliver_due(&repo, &sink, &config, now + 10).await.unwrap(),
+        0
+    );
+    // ..which doubles.
+    assert!(
+        repo.fetch_due_notifications(now + 29, 10)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    assert_eq!(
+        deliver_due(&repo, &sink, &config, now + 30).await.unwrap(),
+        1
+    );
+
+    let delivered = sink.delivered.lock().unwrap().clone();
+    assert_eq!(delivered.len(), 1);
+    assert_eq!(delivered[0].user, session.user_id);
+    assert_eq!(
+        delivered[0].event,
+        Event::AppLimitExceeded {
+            app: "io1".to_string(),
+            day: Utc::now().date_naive().format("%Y-%m-%d").to_string(),
+            usage: 60,
+            limit: 30,
+        }
+    );
+
+    // delivered ones are purged after the retention.
+    assert_eq!(repo.purge_notifications(now - 60).await.unwrap(), 0);
+    assert_eq!(repo.purge_notifications(now + 1).await.unwrap(), 2);
+}
+
+#[rocket::async_test]
+async fn events_on_local_day() {
+    let repo = MemoryRepository::default();
+    let (user, session) = signed_up(&repo).await;
+    let user_id = user.id;
+    let timezone = Tz::Pacific__Kiritimati;
+    let today = Utc::now().with_timezone(&timezone).date_naive();
+    let yesterday = today - Days::new(1);
+    repo.record_usage(&DBUsageDay {
+        user_id,
+        device_id: 0,
+        app_name: "io1".to_string(),
+        day: yesterday.format(DAY_FORMAT).to_string(),
+        app_usage: 10,
+        app_limit: 30,
+    })
+    .await
+    .unwrap();
+
+    let data = UserData {
+        app_usage: vec![AppInfo::new("io1", 60, 30)],
+        timezone: Some(timezone.name().to_string()),
+        ..Default::default()
+    };
+    sync_data(&repo, &SyncEvents::default(), &session.id, None, Some(data))
+        .await
+        .unwrap();
+
+    let events: Vec<Event> = repo
+        .fetch_due_notifications(Utc::now().timestamp(), 10)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|n| json::from_str(&n.payload).unwrap())
+        .collect();
+    assert!(events.contains(&Event::AppLimitExceeded {
+        app: "io1".to_string(),
+        day: today.format(DAY_FORMAT).to_string(),
+        usage: 60,
+        limit: 30,
+    }));
+    let yesterday = yesterday.format(DAY_FORMAT).to_string();
+    assert!(
+        events
+            .iter()
+            .any(|e| matches!(e, Event::DailySummary { day, usage: 10, .. } if *day == yesterday))
+    );
+}
+
+#[rocket::async_test]
+async fn outbox_gives_up() {
+    let repo = MemoryRepository::default();
+    let (_, session) = signed_up(&repo).await;
+    let data = UserData {
+        app_usage: vec![AppInfo::new("io1", 60, 30)],
+        ..Default::default()
+    };
+    sync_data(&repo, &SyncEvents::default(), &session.id, None, Some(data))
+        .await
+        .unwrap();
+
+    let config = NotificationConfig {
+        max_attempts: 2,
+        ..Default::default()
+    };
+    let sink = StubSink {
+        failures: AtomicU32::new(u32::MAX),
+        ..Default::default()
+    };
+    let later = i64::MAX / 2;
+    deliver_due(&repo, &sink, &config, Utc::now().timestamp())
+        .await
+        .unwrap();
+    deliver_due(&repo, &sink, &config, later).await.unwrap();
+
+    assert!(
+        repo.fetch_due_notifications(later, 10)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    assert!(sink.delivered.lock().unwrap().is_empty());
+}
+
+/// A request received by [`serve_once`].
+pub(crate) struct StubRequest {
+    /// The request line and headers.
+    pub head: String,
+    pub body: String,
+}
+
+impl StubRequest {
+    /// The value of the header `name`, matched case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.head.lines().find_map(|l| {
+            let (key, value) = l.split_once(':')?;
+            key.eq_ignore_ascii_case(name).then(|| value.trim())
+        })
+    }
+}
+
+/// Serve one request on `listener`, a stub of an http server, responding with `status`.
+pub(crate) async fn serve_once(listener: &TcpListener, status: &str) -> StubRequest {
+    let (stream, request) = receive(listener).await;
+    respond(stream, status).await;
+    request
+}
+
+/// Receive one request on `listener`, to [`respond`] to.
+pub(crate) async fn receive(listener: &TcpListener) -> (TcpStream, StubRequest) {
+    let (mut stream, _) = listener.accept().await.unwrap();
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    let body_start = loop {
+        let read = stream.read(&mut buf).await.unwrap();
+        request.extend_from_slice(&buf[..read]);
+        if let Some(end) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+            break end + 4;
+        }
+    };
+    let head = String::from_utf8(request[..body_start].to_vec()).unwrap();
+    let mut stub_request = StubRequest {
+        head,
+        body: String::new(),
+    };
+    let length: usize = stub_request
+        .header("content-length")
+        .unwrap()
+        .parse()
+        .unwrap();
+    while request.len() < body_start + length {
+        let read = stream.read(&mut buf).await.unwrap();
+        request.extend_from_slice(&buf[..read]);
+    }
+
+    stub_request.body = String::from_utf8(request[body_start..].to_vec()).unwrap();
+    (stream, stub_request)
+}
+
+/// Respond to a request from [`receive`] with `status`.
+pub(crate) async fn respond(mut stream: TcpStream, status: &str) {
+    stream
+        .write_all(
+            format!("HTTP/1.1 {status}\r\nconnection: close\r\ncontent-length: 0\r\n\r\n")
+                .as_bytes(),
+        )
+        .await
+        .unwrap();
+}
+
+#[rocket::async_test]
+async fn webhook_sink() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let sink =
+        WebhookSink::new(&format!("http://{}/hook", listener.local_addr().unwrap())).unwrap();
+
+    let server = tokio::spawn(async move {
+        let ok = serve_once(&listener, "200 OK").await;
+        let failed = serve_once(&listener, "500 Internal Server Error").await;
+        (ok, failed)
+    });
+    sink.deliver(&notification()).await.unwrap();
+    assert!(matches!(
+        sink.deliver(&notification()).await.unwrap_err(),
+        SinkError::Status(500)
+    ));
+
+    let (ok, _) = server.await.unwrap();
+    assert_eq!(
+        json::from_str::<Notification>(&ok.body).unwrap(),
+        notification()
+    );
+
+    assert!(WebhookSink::new("https://example.com").is_ok());
+    assert!(WebhookSink::new("ftp://example.com").is_err());
+    assert!(WebhookSink::new("not a url").is_err());
+}
+
+#[rocket::async_test]
+async fn file_sink() {
+    let path = std::env::temp_dir().join(format!("{}.jsonl", uuid::Uuid::new_v4()));
+    let sink = FileSink::new(path.clone());
+    sink.deliver(&notification()).await.unwrap();
+    sink.deliver(&notification()).await.unwrap();
+
+    let lines: Vec<Notification> = std::fs::read_to_string(&path)
+        .unwrap()
+        .lines()
+        .map(|l| json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(lines, [notification(), notification()]);
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,198 @@
+use rocket::{
+    futures::{StreamExt, stream},
+    serde::json,
+};
+
+use crate::{
+    config::NotificationConfig,
+    repo::{OutboxRepository, UserRepository, WebhookRepository},
+    routes::webhooks::data::private::{DBWebhook, DBWebhookDelivery},
+    util::digest::hmac_sha256_hex,
+};
+
+use super::{
+    data::public::{Event, EventKind, Notification},
+    retry_at,
+    sink::{HttpClient, SinkError, parse_url, post_json},
+};
+
+/// The most deliveries sent at once, so one slow webhook does not hold up the others.
+const CONCURRENT_DELIVERIES: usize = 16;
+
+/// The header with the hex encoded hmac-sha256 of the timestamp, a `.` and the body, prefixed by `sha256=`.
+pub(crate) const SIGNATURE_HEADER: &str = "X-Pcup-Signature";
+/// The header with when the delivery was sent, in seconds since the unix epoch.
+pub(crate) const TIMESTAMP_HEADER: &str = "X-Pcup-Timestamp";
+/// The header with the event's kind.
+pub(crate) const EVENT_HEADER: &str = "X-Pcup-Event";
+/// The header with the delivery's id, as in the delivery log.
+pub(crate) const DELIVERY_HEADER: &str = "X-Pcup-Delivery";
+
+/// Sign `body`, sent at `timestamp`, with `secret`.
+pub(crate) fn signature(secret: &str, timestamp: i64, body: &str) -> String {
+    let signed = format!("{timestamp}.{body}");
+    format!(
+        "sha256={}",
+        hmac_sha256_hex(secret.as_bytes(), signed.as_bytes())
+    )
+}
+
+/// Queue a delivery of `notification` to each of the user with the id `user_id`'s webhooks that receive it.
+///
+/// # Errors
+///
+/// See [`sqlx::Error`].
+pub(crate) async fn fan_out(
+    repo: &impl WebhookRepository,
+    user_id: u32,
+    notification: &Notification,
+    now: i64,
+) -> Result<(), sqlx::Error> {
+    let Some(kind) = notification.event.kind() else {
+        return Ok(());
+    };
+    let payload = json::to_string(notification).map_err(|err| sqlx::Error::Encode(err.into()))?;
+
+    for webhook in repo.fetch_webhooks(user_id).await? {
+        if webhook.receives(kind) {
+            repo.enqueue_webhook_delivery(&webhook.id, kind.name(), &payload, now)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Queue a delivery of the user's [`Event::AccountCreated`] notification to `webhook`, just registered, if it
+/// receives it.
+///
+/// The notification's `id` is `0`, and `created` is `now`, if it was purged from the outbox already.
+///
+/// # Errors
+///
+/// See [`sqlx::Error`].
+pub(crate) async fn replay_account_created(
+    repo: &(impl OutboxRepository + WebhookRepository + UserRepository),
+    webhook: &DBWebhook,
+    now: i64,
+) -> Result<(), sqlx::Error> {
+    let kind = EventKind::AccountCreated;
+    if !webhook.receives(kind) {
+        return Ok(());
+    }
+
+    let event = Event::AccountCreated;
+    let stored = repo
+        .fetch_notification(webhook.user_id, &event.key())
+        .await?;
+    let notification = Notification {
+        id: stored.as_ref().map_or(0, |n| n.id),
+        user: repo.fetch_user_by_id(webhook.user_id).await?.public_id,
+        created: stored.as_ref().map_or(now, |n| n.created),
+        event,
+    };
+    let payload = json::to_string(&notification).map_err(|err| sqlx::Error::Encode(err.into()))?;
+    repo.enqueue_webhook_delivery(&webhook.id, kind.name(), &payload, now)
+        .await?;
+    Ok(())
+}
+
+/// Try to deliver `delivery` to `webhook` at `now`, storing the outcome.
+///
+/// A failed delivery is retried as `config` sets, or given up on right away without one.
+///
+/// # Errors
+///
+/// See [`sqlx::Error`].
+pub(crate) async fn attempt(
+    repo: &impl WebhookRepository,
+    client: &HttpClient,
+    webhook: &DBWebhook,
+    delivery: &mut DBWebhookDelivery,
+    config: Option<&NotificationConfig>,
+    now: i64,
+) -> Result<(), sqlx::Error> {
+    let headers = [
+        (
+            SIGNATURE_HEADER,
+            signature(&webhook.secret, now, &delivery.payload),
+        ),
+        (TIMESTAMP_HEADER, now.to_string()),
+        (EVENT_HEADER, delivery.event.clone()),
+        (DELIVERY_HEADER, delivery.id.to_string()),
+    ];
+    let result = match parse_url(&webhook.url) {
+        Ok(url) => post_json(client, &url, delivery.payload.clone(), &headers).await,
+        Err(err) => Err(err),
+    };
+
+    match result {
+        Ok(status) => {
+            delivery.status = Some(status);
+            delivery.delivered = Some(now);
+            delivery.next_attempt = None;
+        }
+        Err(err) => {
+            delivery.status = match err {
+                SinkError::Status(status) => Some(status),
+                _ => None,
+            };
+            delivery.attempts += 1;
+            delivery.last_error = Some(err.to_string());
+            delivery.next_attempt = config.and_then(|c| retry_at(c, delivery.attempts, now));
+            tracing::info!(
+                "could not deliver {} to webhook {}, next attempt at {:?}: {err}",
+                delivery.id,
+                webhook.id,
+                delivery.next_attempt
+            );
+        }
+    }
+    repo.update_webhook_delivery(delivery).await
+}
+
+/// Deliver up to [`NotificationConfig::batch_size`] deliveries due at `now`, [`CONCURRENT_DELIVERIES`] at a time,
+/// returning how many succeeded.
+///
+/// Failing to store how a delivery went is logged, and does not stop the others.
+///
+/// # Errors
+///
+/// See [`sqlx::Error`].
+pub(crate) async fn deliver_due(
+    repo: &impl WebhookRepository,
+    client: &HttpClient,
+    config: &NotificationConfig,
+    now: i64,
+) -> Result<usize, sqlx::Error> {
+    let due = repo
+        .fetch_due_webhook_deliveries(now, config.batch_size)
+        .await?;
+
+    let delivered = stream::iter(due)
+        .map(|mut delivery| async move {
+            let webhook = match repo.fetch_webhook(&delivery.webhook_id).await {
+                Ok(webhook) => webhook,
+                Err(sqlx::Error::RowNotFound) => {
+                    // deleted since its deliveries were fetched, they are gone with it.
+                    tracing::info!("webhook {} was deleted, skipping", delivery.webhook_id);
+                    return false;
+                }
+                Err(err) => {
+                    tracing::error!("could not fetch webhook {}: {err:?}", delivery.webhook_id);
+                    return false;
+                }
+            };
+            if let Err(err) =
+                attempt(repo, client, &webhook, &mut delivery, Some(config), now).await
+            {
+                tracing::error!("could not store delivery {}: {err:?}", delivery.id);
+            }
+            delivery.delivered.is_some()
+        })
+        .buffer_unordered(CONCURRENT_DELIVERIES)
+        .filter(|delivered| std::future::ready(*delivered))
+        .count()
+        .await;
+
+    Ok(delivered)
+}
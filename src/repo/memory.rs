@@ -0,0 +1,1200 @@
+use std::{
+    borrow::Cow,
+    cmp::Reverse,
+    error::Error,
+    fmt::{self, Display},
+    sync::{Mutex, MutexGuard},
+};
+
+use chrono::NaiveDate;
+use sqlx::error::{DatabaseError, ErrorKind};
+
+use crate::{
+    notify::data::private::DBNotification,
+    routes::{
+        auth::data::private::{DBLoginAttempts, DBUser, DBUserSession, DBUsernameChange},
+        devices::data::private::DBDevice,
+        sync::data::{
+            private::{
+                DBAppAlias, DBAppInfo, DBBlockedWindow, DBCategory, DBCategoryApp, DBLimitOverride,
+                DBReminder, DBUserDebug, DBWeekdayLimit,
+            },
+            public::ScheduleKey,
+        },
+        tokens::data::private::DBApiToken,
+        totp::data::private::{DBLoginChallenge, DBTotp},
+        usage::data::private::{DAY_FORMAT, DBUsageDay},
+        webhooks::data::private::{DBWebhook, DBWebhookDelivery},
+    },
+};
+
+use crate::util::{throttle, username::username_key};
+
+use super::{
+    AttemptRepository, DeviceRepository, OutboxRepository, SessionRepository, TokenRepository,
+    TotpRepository, UsageRepository, UserRepository, WebhookRepository,
+};
+
+/// The stored rows, one [`Vec`] per table.
+#[derive(Debug, Default)]
+struct Tables {
+    users: Vec<DBUser>,
+    sessions: Vec<DBUserSession>,
+    login_attempts: Vec<DBLoginAttempts>,
+    totp: Vec<DBTotp>,
+    /// `(user_id, code_hash)`
+    recovery_codes: Vec<(u32, String)>,
+    login_challenges: Vec<DBLoginChallenge>,
+    api_tokens: Vec<DBApiToken>,
+    username_history: Vec<DBUsernameChange>,
+    devices: Vec<DBDevice>,
+    app_info: Vec<DBAppInfo>,
+    debug: Vec<DBUserDebug>,
+    weekday_limits: Vec<DBWeekdayLimit>,
+    blocked_windows: Vec<DBBlockedWindow>,
+    limit_overrides: Vec<DBLimitOverride>,
+    categories: Vec<DBCategory>,
+    category_apps: Vec<DBCategoryApp>,
+    app_aliases: Vec<DBAppAlias>,
+    reminders: Vec<DBReminder>,
+    /// `(user_id, timezone)`
+    timezones: Vec<(u32, String)>,
+    usage_history: Vec<DBUsageDay>,
+    notification_outbox: Vec<DBNotification>,
+    webhooks: Vec<DBWebhook>,
+    webhook_deliveries: Vec<DBWebhookDelivery>,
+}
+
+impl Tables {
+    /// Enforce the foreign key on `users(id)`.
+    fn user_exists(&self, user_id: u32) -> Result<(), sqlx::Error> {
+        if self.users.iter().any(|u| u.id == user_id) {
+            Ok(())
+        } else {
+            Err(MemoryDBError::ForeignKey.into())
+        }
+    }
+
+    /// Delete the user with the id `id`, cascading like `ON DELETE CASCADE`.
+    fn delete_user(&mut self, id: u32) {
+        self.users.retain(|u| u.id != id);
+        self.sessions.retain(|s| s.user_id != id);
+        self.totp.retain(|t| t.user_id != id);
+        self.recovery_codes.retain(|(user_id, _)| *user_id != id);
+        self.login_challenges.retain(|c| c.user_id != id);
+        self.api_tokens.retain(|t| t.user_id != id);
+        self.username_history.retain(|c| c.user_id != id);
+        self.devices.retain(|d| d.user_id != id);
+        self.app_info.retain(|a| a.user_id != id);
+        self.debug.retain(|d| d.user_id != id);
+        self.weekday_limits.retain(|l| l.user_id != id);
+        self.blocked_windows.retain(|w| w.user_id != id);
+        self.limit_overrides.retain(|o| o.user_id != id);
+        self.categories.retain(|c| c.user_id != id);
+        self.category_apps.retain(|a| a.user_id != id);
+        self.app_aliases.retain(|a| a.user_id != id);
+        self.reminders.retain(|r| r.user_id != id);
+        self.timezones.retain(|(user_id, _)| *user_id != id);
+        self.usage_history.retain(|u| u.user_id != id);
+        self.notification_outbox.retain(|n| n.user_id != id);
+        let webhooks: Vec<String> = self
+            .webhooks
+            .iter()
+            .filter(|w| w.user_id == id)
+            .map(|w| w.id.clone())
+            .collect();
+        self.webhooks.retain(|w| w.user_id != id);
+        self.webhook_deliveries
+            .retain(|d| !webhooks.contains(&d.webhook_id));
+    }
+}
+
+/// A repository that keeps everything in memory, mirroring the constraints of the `SQLite` schema.
+#[derive(Debug, Default)]
+pub(crate) struct MemoryRepository {
+    tables: Mutex<Tables>,
+}
+
+impl MemoryRepository {
+    fn tables(&self) -> MutexGuard<'_, Tables> {
+        // a panicking test poisons the lock, the data is still fine to use.
+        self.tables
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+impl UserRepository for MemoryRepository {
+    async fn fetch_user_by_name(&self, username: &str) -> Result<DBUser, sqlx::Error> {
+        let key = username_key(username);
+        self.tables()
+            .users
+            .iter()
+            .find(|u| u.username_key == key)
+            .cloned()
+            .ok_or(sqlx::Error::RowNotFound)
+    }
+
+    async fn fetch_user_by_id(&self, id: u32) -> Result<DBUser, sqlx::Error> {
+        self.tables()
+            .users
+            .iter()
+            .find(|u| u.id == id)
+            .cloned()
+            .ok_or(sqlx::Error::RowNotFound)
+    }
+
+    async fn insert_user(
+        &self,
+        username: &str,
+        password_hash: &str,
+    ) -> Result<(DBUser, DBUserSession), sqlx::Error> {
+        let mut tables = self.tables();
+
+        let id = tables.users.iter().map(|u| u.id).max().unwrap_or(0) + 1;
+        let user = DBUser::new_raw(id, username, password_hash);
+
+        if tables
+            .users
+            .iter()
+            .any(|u| u.username_key == user.username_key)
+        {
+            return Err(MemoryDBError::Unique("users.username_key").into());
+        }
+        let session = DBUserSession::generate(id);
+        tables.users.push(user.clone());
+        tables.sessions.push(session.clone());
+
+        Ok((user, session))
+    }
+
+    async fn rename_user(&self, id: u32, username: &str, now: i64) -> Result<(), sqlx::Error> {
+        let mut tables = self.tables();
+        let new_key = username_key(username);
+
+        if tables
+            .users
+            .iter()
+            .any(|u| u.id != id && u.username_key == new_key)
+        {
+            return Err(MemoryDBError::Unique("users.username_key").into());
+        }
+
+        let user = tables
+            .users
+            .iter_mut()
+            .find(|u| u.id == id)
+            .ok_or(sqlx::Error::RowNotFound)?;
+        let old_key = std::mem::replace(&mut user.username_key, new_key);
+        user.username = username.to_string();
+
+        if old_key != user.username_key {
+            tables.username_history.push(DBUsernameChange {
+                username_key: old_key,
+                user_id: id,
+                released_at: now,
+            });
+        }
+        Ok(())
+    }
+
+    async fn fetch_username_holder(
+        &self,
+        username: &str,
+        released_after: i64,
+    ) -> Result<Option<u32>, sqlx::Error> {
+        let key = username_key(username);
+        Ok(self
+            .tables()
+            .username_history
+            .iter()
+            .filter(|c| c.username_key == key && c.released_at >= released_after)
+            .max_by_key(|c| c.released_at)
+            .map(|c| c.user_id))
+    }
+
+    async fn fetch_username_history(
+        &self,
+        user_id: u32,
+    ) -> Result<Vec<DBUsernameChange>, sqlx::Error> {
+        let mut history: Vec<DBUsernameChange> = self
+            .tables()
+            .username_history
+            .iter()
+            .filter(|c| c.user_id == user_id)
+            .cloned()
+            .collect();
+        history.sort_by_key(|c| c.released_at);
+        Ok(history)
+    }
+
+    async fn mark_for_deletion(
+        &self,
+        id: u32,
+        requested_at: Option<i64>,
+    ) -> Result<(), sqlx::Error> {
+        let mut tables = self.tables();
+
+        if let Some(user) = tables.users.iter_mut().find(|u| u.id == id) {
+            user.deletion_requested_at = requested_at;
+        }
+        if requested_at.is_some() {
+            tables.sessions.retain(|s| s.user_id != id);
+        }
+        Ok(())
+    }
+
+    async fn purge_users(&self, requested_before: i64) -> Result<u64, sqlx::Error> {
+        let mut tables = self.tables();
+        let purged: Vec<u32> = tables
+            .users
+            .iter()
+            .filter(|u| {
+                u.deletion_requested_at
+                    .is_some_and(|at| at <= requested_before)
+            })
+            .map(|u| u.id)
+            .collect();
+
+        for id in &purged {
+            tables.delete_user(*id);
+        }
+        Ok(purged.len() as u64)
+    }
+
+    async fn update_password_hash(&self, id: u32, password_hash: &str) -> Result<(), sqlx::Error> {
+        if let Some(user) = self.tables().users.iter_mut().find(|u| u.id == id) {
+            user.password_hash = password_hash.to_string();
+        }
+        Ok(())
+    }
+}
+
+impl SessionRepository for MemoryRepository {
+    async fn fetch_session(&self, session_id: &str) -> Result<DBUserSession, sqlx::Error> {
+        self.tables()
+            .sessions
+            .iter()
+            .find(|s| s.id == session_id)
+            .cloned()
+            .ok_or(sqlx::Error::RowNotFound)
+    }
+
+    async fn fetch_user_session(&self, user_id: u32) -> Result<DBUserSession, sqlx::Error> {
+        self.tables()
+            .sessions
+            .iter()
+            .find(|s| s.user_id == user_id)
+            .cloned()
+            .ok_or(sqlx::Error::RowNotFound)
+    }
+
+    async fn store_session(&self, session: &DBUserSession) -> Result<(), sqlx::Error> {
+        let mut tables = self.tables();
+        tables.user_exists(session.user_id)?;
+
+        // `INSERT OR REPLACE`, `user_id` is the primary key.
+        tables.sessions.retain(|s| s.user_id != session.user_id);
+        tables.sessions.push(session.clone());
+
+        Ok(())
+    }
+}
+
+impl AttemptRepository for MemoryRepository {
+    async fn fetch_attempts(&self, key: &str) -> Result<DBLoginAttempts, sqlx::Error> {
+        Ok(self
+            .tables()
+            .login_attempts
+            .iter()
+            .find(|a| a.key == key)
+            .cloned()
+            .unwrap_or_else(|| DBLoginAttempts::new(key)))
+    }
+
+    async fn record_failure(&self, key: &str, now: i64) -> Result<DBLoginAttempts, sqlx::Error> {
+        // incremented under the lock, as the upsert does.
+        let mut tables = self.tables();
+        let index = match tables.login_attempts.iter().position(|a| a.key == key) {
+            Some(index) => index,
+            None => {
+                tables.login_attempts.push(DBLoginAttempts::new(key));
+                tables.login_attempts.len() - 1
+            }
+        };
+        let attempts = &mut tables.login_attempts[index];
+        throttle::record_failure(attempts, now);
+        Ok(attempts.clone())
+    }
+
+    async fn clear_attempts(&self, key: &str) -> Result<(), sqlx::Error> {
+        self.tables().login_attempts.retain(|a| a.key != key);
+        Ok(())
+    }
+}
+
+impl TotpRepository for MemoryRepository {
+    async fn fetch_totp(&self, user_id: u32) -> Result<DBTotp, sqlx::Error> {
+        self.tables()
+            .totp
+            .iter()
+            .find(|t| t.user_id == user_id)
+            .cloned()
+            .ok_or(sqlx::Error::RowNotFound)
+    }
+
+    async fn store_totp(&self, totp: &DBTotp) -> Result<(), sqlx::Error> {
+        let mut tables = self.tables();
+        tables.user_exists(totp.user_id)?;
+
+        // `INSERT OR REPLACE`, `user_id` is the primary key.
+        tables.totp.retain(|t| t.user_id != totp.user_id);
+        tables.totp.push(totp.clone());
+        Ok(())
+    }
+
+    async fn replace_recovery_codes(
+        &self,
+        user_id: u32,
+        code_hashes: &[String],
+    ) -> Result<(), sqlx::Error> {
+        let mut tables = self.tables();
+        tables.user_exists(user_id)?;
+
+        tables.recovery_codes.retain(|(id, _)| *id != user_id);
+        tables
+            .recovery_codes
+            .extend(code_hashes.iter().map(|hash| (user_id, hash.clone())));
+        Ok(())
+    }
+
+    async fn use_recovery_code(&self, user_id: u32, code_hash: &str) -> Result<bool, sqlx::Error> {
+        let mut tables = self.tables();
+        let before = tables.recovery_codes.len();
+        tables
+            .recovery_codes
+            .retain(|(id, hash)| !(*id == user_id && hash == code_hash));
+        Ok(tables.recovery_codes.len() < before)
+    }
+
+    async fn fetch_challenge(&self, challenge_id: &str) -> Result<DBLoginChallenge, sqlx::Error> {
+        self.tables()
+            .login_challenges
+            .iter()
+            .find(|c| c.id == challenge_id)
+            .cloned()
+            .ok_or(sqlx::Error::RowNotFound)
+    }
+
+    async fn store_challenge(&self, challenge: &DBLoginChallenge) -> Result<(), sqlx::Error> {
+        let mut tables = self.tables();
+        tables.user_exists(challenge.user_id)?;
+        tables.login_challenges.push(challenge.clone());
+        Ok(())
+    }
+
+    async fn delete_challenge(&self, challenge_id: &str) -> Result<(), sqlx::Error> {
+        self.tables()
+            .login_challenges
+            .retain(|c| c.id != challenge_id);
+        Ok(())
+    }
+}
+
+impl OutboxRepository for MemoryRepository {
+    async fn enqueue_notification(
+        &self,
+        user_id: u32,
+        event_key: &str,
+        payload: &str,
+        now: i64,
+    ) -> Result<Option<u32>, sqlx::Error> {
+        let mut tables = self.tables();
+        tables.user_exists(user_id)?;
+
+        if tables
+            .notification_outbox
+            .iter()
+            .any(|n| n.user_id == user_id && n.event_key == event_key)
+        {
+            return Ok(None);
+        }
+        let id = tables
+            .notification_outbox
+            .iter()
+            .map(|n| n.id)
+            .max()
+            .unwrap_or(0)
+            + 1;
+        tables.notification_outbox.push(DBNotification {
+            id,
+            user_id,
+            event_key: event_key.to_string(),
+            payload: payload.to_string(),
+            created: now,
+            attempts: 0,
+            next_attempt: Some(now),
+            delivered: None,
+            last_error: None,
+        });
+        Ok(Some(id))
+    }
+
+    async fn fetch_notification(
+        &self,
+        user_id: u32,
+        event_key: &str,
+    ) -> Result<Option<DBNotification>, sqlx::Error> {
+        Ok(self
+            .tables()
+            .notification_outbox
+            .iter()
+            .find(|n| n.user_id == user_id && n.event_key == event_key)
+            .cloned())
+    }
+
+    async fn fetch_due_notifications(
+        &self,
+        now: i64,
+        limit: u32,
+    ) -> Result<Vec<DBNotification>, sqlx::Error> {
+        let mut due: Vec<DBNotification> = self
+            .tables()
+            .notification_outbox
+            .iter()
+            .filter(|n| n.next_attempt.is_some_and(|at| at <= now))
+            .cloned()
+            .collect();
+        due.sort_by_key(|n| (n.next_attempt, n.id));
+        due.truncate(limit as usize);
+        Ok(due)
+    }
+
+    async fn mark_delivered(&self, id: u32, now: i64) -> Result<(), sqlx::Error> {
+        if let Some(notification) = self
+            .tables()
+            .notification_outbox
+            .iter_mut()
+            .find(|n| n.id == id)
+        {
+            notification.delivered = Some(now);
+            notification.next_attempt = None;
+        }
+        Ok(())
+    }
+
+    async fn mark_failed(
+        &self,
+        id: u32,
+        error: &str,
+        next_attempt: Option<i64>,
+    ) -> Result<(), sqlx::Error> {
+        if let Some(notification) = self
+            .tables()
+            .notification_outbox
+            .iter_mut()
+            .find(|n| n.id == id)
+        {
+            notification.attempts += 1;
+            notification.last_error = Some(error.to_string());
+            notification.next_attempt = next_attempt;
+        }
+        Ok(())
+    }
+
+    async fn purge_notifications(&self, created_before: i64) -> Result<u64, sqlx::Error> {
+        let mut tables = self.tables();
+        let before = tables.notification_outbox.len();
+        tables
+            .notification_outbox
+            .retain(|n| n.next_attempt.is_some() || n.created >= created_before);
+        Ok((before - tables.notification_outbox.len()) as u64)
+    }
+}
+
+impl WebhookRepository for MemoryRepository {
+    async fn fetch_webhook(&self, id: &str) -> Result<DBWebhook, sqlx::Error> {
+        self.tables()
+            .webhooks
+            .iter()
+            .find(|w| w.id == id)
+            .cloned()
+            .ok_or(sqlx::Error::RowNotFound)
+    }
+
+    async fn fetch_webhooks(&self, user_id: u32) -> Result<Vec<DBWebhook>, sqlx::Error> {
+        let mut webhooks: Vec<DBWebhook> = self
+            .tables()
+            .webhooks
+            .iter()
+            .filter(|w| w.user_id == user_id)
+            .cloned()
+            .collect();
+        webhooks.sort_by(|a, b| (a.created_at, &a.id).cmp(&(b.created_at, &b.id)));
+        Ok(webhooks)
+    }
+
+    async fn insert_webhook(&self, webhook: &DBWebhook) -> Result<(), sqlx::Error> {
+        let mut tables = self.tables();
+        tables.user_exists(webhook.user_id)?;
+
+        if tables.webhooks.iter().any(|w| w.id == webhook.id) {
+            return Err(MemoryDBError::Unique("webhooks.id").into());
+        }
+        tables.webhooks.push(webhook.clone());
+        Ok(())
+    }
+
+    async fn delete_webhook(&self, user_id: u32, id: &str) -> Result<bool, sqlx::Error> {
+        let mut tables = self.tables();
+        let before = tables.webhooks.len();
+        tables
+            .webhooks
+            .retain(|w| !(w.user_id == user_id && w.id == id));
+        let deleted = tables.webhooks.len() < before;
+        if deleted {
+            tables.webhook_deliveries.retain(|d| d.webhook_id != id);
+        }
+        Ok(deleted)
+    }
+
+    async fn enqueue_webhook_delivery(
+        &self,
+        webhook_id: &str,
+        event: &str,
+        payload: &str,
+        now: i64,
+    ) -> Result<DBWebhookDelivery, sqlx::Error> {
+        let mut tables = self.tables();
+        if !tables.webhooks.iter().any(|w| w.id == webhook_id) {
+            return Err(MemoryDBError::ForeignKey.into());
+        }
+
+        let id = tables
+            .webhook_deliveries
+            .iter()
+            .map(|d| d.id)
+            .max()
+            .unwrap_or(0)
+            + 1;
+        let delivery = DBWebhookDelivery {
+            id,
+            webhook_id: webhook_id.to_string(),
+            event: event.to_string(),
+            payload: payload.to_string(),
+            created: now,
+            attempts: 0,
+            next_attempt: Some(now),
+            delivered: None,
+            status: None,
+            last_error: None,
+        };
+        tables.webhook_deliveries.push(delivery.clone());
+        Ok(delivery)
+    }
+
+    async fn fetch_due_webhook_deliveries(
+        &self,
+        now: i64,
+        limit: u32,
+    ) -> Result<Vec<DBWebhookDelivery>, sqlx::Error> {
+        let mut due: Vec<DBWebhookDelivery> = self
+            .tables()
+            .webhook_deliveries
+            .iter()
+            .filter(|d| d.next_attempt.is_some_and(|at| at <= now))
+            .cloned()
+            .collect();
+        due.sort_by_key(|d| (d.next_attempt, d.id));
+        due.truncate(limit as usize);
+        Ok(due)
+    }
+
+    async fn fetch_webhook_deliveries(
+        &self,
+        webhook_id: &str,
+        limit: u32,
+    ) -> Result<Vec<DBWebhookDelivery>, sqlx::Error> {
+        let mut deliveries: Vec<DBWebhookDelivery> = self
+            .tables()
+            .webhook_deliveries
+            .iter()
+            .filter(|d| d.webhook_id == webhook_id)
+            .cloned()
+            .collect();
+        deliveries.sort_by_key(|d| Reverse(d.id));
+        deliveries.truncate(limit as usize);
+        Ok(deliveries)
+    }
+
+    async fn update_webhook_delivery(
+        &self,
+        delivery: &DBWebhookDelivery,
+    ) -> Result<(), sqlx::Error> {
+        if let Some(stored) = self
+            .tables()
+            .webhook_deliveries
+            .iter_mut()
+            .find(|d| d.id == delivery.id)
+        {
+            stored.attempts = delivery.attempts;
+            stored.next_attempt = delivery.next_attempt;
+            stored.delivered = delivery.delivered;
+            stored.status = delivery.status;
+            stored.last_error.clone_from(&delivery.last_error);
+        }
+        Ok(())
+    }
+
+    async fn purge_webhook_deliveries(&self, created_before: i64) -> Result<u64, sqlx::Error> {
+        let mut tables = self.tables();
+        let before = tables.webhook_deliveries.len();
+        tables
+            .webhook_deliveries
+            .retain(|d| d.next_attempt.is_some() || d.created >= created_before);
+        Ok((before - tables.webhook_deliveries.len()) as u64)
+    }
+}
+
+impl DeviceRepository for MemoryRepository {
+    async fn register_device(
+        &self,
+        user_id: u32,
+        name: &str,
+        platform: &str,
+        now: i64,
+    ) -> Result<DBDevice, sqlx::Error> {
+        let mut tables = self.tables();
+        tables.user_exists(user_id)?;
+
+        let next_id = tables.devices.iter().map(|d| d.id).max().unwrap_or(0) + 1;
+        if let Some(device) = tables
+            .devices
+            .iter_mut()
+            .find(|d| d.user_id == user_id && d.name == name)
+        {
+            device.platform = platform.to_string();
+            device.last_seen = now;
+            return Ok(device.clone());
+        }
+
+        let device = DBDevice {
+            id: next_id,
+            user_id,
+            name: name.to_string(),
+            platform: platform.to_string(),
+            last_seen: now,
+        };
+        tables.devices.push(device.clone());
+        Ok(device)
+    }
+
+    async fn fetch_devices(&self, user_id: u32) -> Result<Vec<DBDevice>, sqlx::Error> {
+        let mut devices: Vec<DBDevice> = self
+            .tables()
+            .devices
+            .iter()
+            .filter(|d| d.user_id == user_id)
+            .cloned()
+            .collect();
+        devices.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(devices)
+    }
+
+    async fn touch_device(&self, user_id: u32, id: u32, now: i64) -> Result<bool, sqlx::Error> {
+        let mut tables = self.tables();
+        let device = tables
+            .devices
+            .iter_mut()
+            .find(|d| d.user_id == user_id && d.id == id);
+        Ok(device.map(|d| d.last_seen = now).is_some())
+    }
+
+    async fn delete_device(
+        &self,
+        user_id: u32,
+        id: u32,
+        keep_usage: bool,
+    ) -> Result<bool, sqlx::Error> {
+        let mut tables = self.tables();
+        let before = tables.devices.len();
+        tables
+            .devices
+            .retain(|d| !(d.user_id == user_id && d.id == id));
+        if tables.devices.len() == before {
+            return Ok(false);
+        }
+
+        let (usage, mut kept): (Vec<_>, Vec<_>) = tables
+            .usage_history
+            .drain(..)
+            .partition(|u| u.user_id == user_id && u.device_id == id);
+        if keep_usage {
+            for usage in usage {
+                match kept.iter_mut().find(|u| {
+                    (u.user_id, u.device_id, &u.app_name, &u.day)
+                        == (user_id, 0, &usage.app_name, &usage.day)
+                }) {
+                    Some(stored) => {
+                        stored.app_usage = stored.app_usage.saturating_add(usage.app_usage);
+                        stored.app_limit = stored.app_limit.max(usage.app_limit);
+                    }
+                    None => kept.push(DBUsageDay {
+                        device_id: 0,
+                        ..usage
+                    }),
+                }
+            }
+        }
+        tables.usage_history = kept;
+        Ok(true)
+    }
+}
+
+impl TokenRepository for MemoryRepository {
+    async fn fetch_token(&self, token_hash: &str) -> Result<DBApiToken, sqlx::Error> {
+        let tables = self.tables();
+        tables
+            .api_tokens
+            .iter()
+            .find(|t| {
+                t.token_hash == token_hash
+                    && tables
+                        .users
+                        .iter()
+                        .any(|u| u.id == t.user_id && u.deletion_requested_at.is_none())
+            })
+            .cloned()
+            .ok_or(sqlx::Error::RowNotFound)
+    }
+
+    async fn fetch_tokens(&self, user_id: u32) -> Result<Vec<DBApiToken>, sqlx::Error> {
+        let mut tokens: Vec<DBApiToken> = self
+            .tables()
+            .api_tokens
+            .iter()
+            .filter(|t| t.user_id == user_id)
+            .cloned()
+            .collect();
+        tokens.sort_by_key(|t| t.created_at);
+        Ok(tokens)
+    }
+
+    async fn insert_token(&self, token: &DBApiToken) -> Result<(), sqlx::Error> {
+        let mut tables = self.tables();
+        tables.user_exists(token.user_id)?;
+
+        if tables
+            .api_tokens
+            .iter()
+            .any(|t| t.token_hash == token.token_hash)
+        {
+            return Err(MemoryDBError::Unique("api_tokens.token_hash").into());
+        }
+        if tables
+            .api_tokens
+            .iter()
+            .any(|t| t.user_id == token.user_id && t.name == token.name)
+        {
+            return Err(MemoryDBError::Unique("api_tokens.user_id, api_tokens.name").into());
+        }
+
+        tables.api_tokens.push(token.clone());
+        Ok(())
+    }
+
+    async fn touch_token(&self, id: &str, now: i64) -> Result<(), sqlx::Error> {
+        if let Some(token) = self.tables().api_tokens.iter_mut().find(|t| t.id == id) {
+            token.last_used = Some(now);
+        }
+        Ok(())
+    }
+
+    async fn delete_token(&self, user_id: u32, id: &str) -> Result<bool, sqlx::Error> {
+        let mut tables = self.tables();
+        let before = tables.api_tokens.len();
+        tables
+            .api_tokens
+            .retain(|t| !(t.user_id == user_id && t.id == id));
+        Ok(tables.api_tokens.len() < before)
+    }
+}
+
+impl UsageRepository for MemoryRepository {
+    async fn fetch_app_info(&self, user_id: u32) -> Result<Vec<DBAppInfo>, sqlx::Error> {
+        Ok(self
+            .tables()
+            .app_info
+            .iter()
+            .filter(|a| a.user_id == user_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn store_app_info(&self, app_info: &DBAppInfo) -> Result<(), sqlx::Error> {
+        let mut tables = self.tables();
+        tables.user_exists(app_info.user_id)?;
+        tables.app_info.push(app_info.clone());
+        Ok(())
+    }
+
+    async fn fetch_debug(&self, user_id: u32) -> Result<Vec<DBUserDebug>, sqlx::Error> {
+        Ok(self
+            .tables()
+            .debug
+            .iter()
+            .filter(|d| d.user_id == user_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn store_debug(&self, debug: &DBUserDebug) -> Result<(), sqlx::Error> {
+        let mut tables = self.tables();
+        tables.user_exists(debug.user_id)?;
+        tables.debug.push(debug.clone());
+        Ok(())
+    }
+
+    async fn fetch_weekday_limits(&self, user_id: u32) -> Result<Vec<DBWeekdayLimit>, sqlx::Error> {
+        let mut limits: Vec<DBWeekdayLimit> = self
+            .tables()
+            .weekday_limits
+            .iter()
+            .filter(|l| l.user_id == user_id)
+            .cloned()
+            .collect();
+        limits.sort_by(|a, b| (&a.app_name, a.weekday).cmp(&(&b.app_name, b.weekday)));
+        Ok(limits)
+    }
+
+    async fn store_weekday_limit(&self, limit: &DBWeekdayLimit) -> Result<(), sqlx::Error> {
+        let mut tables = self.tables();
+        tables.user_exists(limit.user_id)?;
+        tables.weekday_limits.retain(|l| {
+            (l.user_id, &l.app_name, l.weekday) != (limit.user_id, &limit.app_name, limit.weekday)
+        });
+        tables.weekday_limits.push(limit.clone());
+        Ok(())
+    }
+
+    async fn fetch_blocked_windows(
+        &self,
+        user_id: u32,
+    ) -> Result<Vec<DBBlockedWindow>, sqlx::Error> {
+        let mut windows: Vec<DBBlockedWindow> = self
+            .tables()
+            .blocked_windows
+            .iter()
+            .filter(|w| w.user_id == user_id)
+            .cloned()
+            .collect();
+        windows.sort_by(|a, b| {
+            (&a.app_name, a.start_minute, a.end_minute).cmp(&(
+                &b.app_name,
+                b.start_minute,
+                b.end_minute,
+            ))
+        });
+        Ok(windows)
+    }
+
+    async fn store_blocked_window(&self, window: &DBBlockedWindow) -> Result<(), sqlx::Error> {
+        let mut tables = self.tables();
+        tables.user_exists(window.user_id)?;
+        tables.blocked_windows.retain(|w| {
+            (w.user_id, &w.app_name, w.start_minute, w.end_minute)
+                != (
+                    window.user_id,
+                    &window.app_name,
+                    window.start_minute,
+                    window.end_minute,
+                )
+        });
+        tables.blocked_windows.push(window.clone());
+        Ok(())
+    }
+
+    async fn fetch_limit_overrides(
+        &self,
+        user_id: u32,
+    ) -> Result<Vec<DBLimitOverride>, sqlx::Error> {
+        let mut overrides: Vec<DBLimitOverride> = self
+            .tables()
+            .limit_overrides
+            .iter()
+            .filter(|o| o.user_id == user_id)
+            .cloned()
+            .collect();
+        overrides.sort_by(|a, b| a.app_name.cmp(&b.app_name));
+        Ok(overrides)
+    }
+
+    async fn store_limit_override(&self, limit: &DBLimitOverride) -> Result<(), sqlx::Error> {
+        let mut tables = self.tables();
+        tables.user_exists(limit.user_id)?;
+        tables
+            .limit_overrides
+            .retain(|o| (o.user_id, &o.app_name) != (limit.user_id, &limit.app_name));
+        tables.limit_overrides.push(limit.clone());
+        Ok(())
+    }
+
+    async fn fetch_categories(&self, user_id: u32) -> Result<Vec<DBCategory>, sqlx::Error> {
+        let mut categories: Vec<DBCategory> = self
+            .tables()
+            .categories
+            .iter()
+            .filter(|c| c.user_id == user_id)
+            .cloned()
+            .collect();
+        categories.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(categories)
+    }
+
+    async fn fetch_category_apps(&self, user_id: u32) -> Result<Vec<DBCategoryApp>, sqlx::Error> {
+        let mut apps: Vec<DBCategoryApp> = self
+            .tables()
+            .category_apps
+            .iter()
+            .filter(|a| a.user_id == user_id)
+            .cloned()
+            .collect();
+        apps.sort_by(|a, b| (&a.category, &a.app_name).cmp(&(&b.category, &b.app_name)));
+        Ok(apps)
+    }
+
+    async fn replace_category(
+        &self,
+        category: &DBCategory,
+        apps: &[String],
+    ) -> Result<(), sqlx::Error> {
+        let mut tables = self.tables();
+        tables.user_exists(category.user_id)?;
+
+        let user_id = category.user_id;
+        tables
+            .categories
+            .retain(|c| (c.user_id, &c.name) != (user_id, &category.name));
+        tables.categories.push(category.clone());
+        tables.category_apps.retain(|a| {
+            a.user_id != user_id || (a.category != category.name && !apps.contains(&a.app_name))
+        });
+        tables
+            .category_apps
+            .extend(apps.iter().map(|app_name| DBCategoryApp {
+                user_id,
+                category: category.name.clone(),
+                app_name: app_name.clone(),
+            }));
+        Ok(())
+    }
+
+    async fn fetch_app_aliases(&self, user_id: u32) -> Result<Vec<DBAppAlias>, sqlx::Error> {
+        let mut aliases: Vec<DBAppAlias> = self
+            .tables()
+            .app_aliases
+            .iter()
+            .filter(|a| a.user_id == user_id)
+            .cloned()
+            .collect();
+        aliases.sort_by(|a, b| a.alias.cmp(&b.alias));
+        Ok(aliases)
+    }
+
+    async fn store_app_alias(&self, alias: &DBAppAlias) -> Result<(), sqlx::Error> {
+        let mut tables = self.tables();
+        tables.user_exists(alias.user_id)?;
+        tables
+            .app_aliases
+            .retain(|a| (a.user_id, &a.alias) != (alias.user_id, &alias.alias));
+        tables.app_aliases.push(alias.clone());
+        Ok(())
+    }
+
+    async fn fetch_reminders(&self, user_id: u32) -> Result<Vec<DBReminder>, sqlx::Error> {
+        let mut reminders: Vec<DBReminder> = self
+            .tables()
+            .reminders
+            .iter()
+            .filter(|r| r.user_id == user_id)
+            .cloned()
+            .collect();
+        reminders.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(reminders)
+    }
+
+    async fn store_reminder(&self, reminder: &DBReminder) -> Result<(), sqlx::Error> {
+        let mut tables = self.tables();
+        tables.user_exists(reminder.user_id)?;
+        tables
+            .reminders
+            .retain(|r| (r.user_id, &r.name) != (reminder.user_id, &reminder.name));
+        tables.reminders.push(reminder.clone());
+        Ok(())
+    }
+
+    async fn fetch_timezone(&self, user_id: u32) -> Result<Option<String>, sqlx::Error> {
+        Ok(self
+            .tables()
+            .timezones
+            .iter()
+            .find(|(id, _)| *id == user_id)
+            .map(|(_, timezone)| timezone.clone()))
+    }
+
+    async fn store_timezone(&self, user_id: u32, timezone: &str) -> Result<(), sqlx::Error> {
+        let mut tables = self.tables();
+        tables.user_exists(user_id)?;
+        tables.timezones.retain(|(id, _)| *id != user_id);
+        tables.timezones.push((user_id, timezone.to_string()));
+        Ok(())
+    }
+
+    async fn delete_schedule(&self, user_id: u32, key: &ScheduleKey) -> Result<bool, sqlx::Error> {
+        let mut tables = self.tables();
+        let tables = &mut *tables;
+        let (before, after) = match key {
+            ScheduleKey::WeekdayLimit { app, weekday } => {
+                let before = tables.weekday_limits.len();
+                tables.weekday_limits.retain(|l| {
+                    (l.user_id, &l.app_name, l.weekday) != (user_id, app, weekday.index())
+                });
+                (before, tables.weekday_limits.len())
+            }
+            ScheduleKey::BlockedWindow { app, start, end } => {
+                let before = tables.blocked_windows.len();
+                tables.blocked_windows.retain(|w| {
+                    (w.user_id, &w.app_name, w.start_minute, w.end_minute)
+                        != (user_id, app, *start, *end)
+                });
+                (before, tables.blocked_windows.len())
+            }
+            ScheduleKey::LimitOverride { app } => {
+                let before = tables.limit_overrides.len();
+                tables
+                    .limit_overrides
+                    .retain(|o| (o.user_id, &o.app_name) != (user_id, app));
+                (before, tables.limit_overrides.len())
+            }
+            ScheduleKey::Category { name } => {
+                let before = tables.categories.len();
+                tables
+                    .categories
+                    .retain(|c| (c.user_id, &c.name) != (user_id, name));
+                tables
+                    .category_apps
+                    .retain(|a| (a.user_id, &a.category) != (user_id, name));
+                (before, tables.categories.len())
+            }
+            ScheduleKey::AppAlias { alias } => {
+                let before = tables.app_aliases.len();
+                tables
+                    .app_aliases
+                    .retain(|a| (a.user_id, &a.alias) != (user_id, alias));
+                (before, tables.app_aliases.len())
+            }
+            ScheduleKey::Reminder { name } => {
+                let before = tables.reminders.len();
+                tables
+                    .reminders
+                    .retain(|r| (r.user_id, &r.name) != (user_id, name));
+                (before, tables.reminders.len())
+            }
+        };
+        Ok(after < before)
+    }
+
+    async fn record_usage(&self, usage: &DBUsageDay) -> Result<(), sqlx::Error> {
+        let mut tables = self.tables();
+        tables.user_exists(usage.user_id)?;
+        match tables.usage_history.iter_mut().find(|u| {
+            (u.user_id, u.device_id, &u.app_name, &u.day)
+                == (usage.user_id, usage.device_id, &usage.app_name, &usage.day)
+        }) {
+            Some(stored) => {
+                stored.app_usage = stored.app_usage.max(usage.app_usage);
+                stored.app_limit = usage.app_limit;
+            }
+            None => tables.usage_history.push(usage.clone()),
+        }
+        Ok(())
+    }
+
+    async fn fetch_usage_history(
+        &self,
+        user_id: u32,
+        device: Option<u32>,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) -> Result<Vec<DBUsageDay>, sqlx::Error> {
+        let from = from.map(|day| day.format(DAY_FORMAT).to_string());
+        let to = to.map(|day| day.format(DAY_FORMAT).to_string());
+
+        let mut history: Vec<DBUsageDay> = self
+            .tables()
+            .usage_history
+            .iter()
+            .filter(|u| u.user_id == user_id)
+            .filter(|u| device.is_none_or(|device| u.device_id == device))
+            .filter(|u| from.as_ref().is_none_or(|from| &u.day >= from))
+            .filter(|u| to.as_ref().is_none_or(|to| &u.day <= to))
+            .cloned()
+            .collect();
+        history.sort_by(|a, b| {
+            (&a.day, &a.app_name, a.device_id).cmp(&(&b.day, &b.app_name, b.device_id))
+        });
+        Ok(history)
+    }
+}
+
+/// A constraint violation, reported like the `SQLite` driver would.
+#[derive(Debug)]
+enum MemoryDBError {
+    /// The `UNIQUE` constraint on the contained column failed.
+    Unique(&'static str),
+    /// A `FOREIGN KEY` constraint failed.
+    ForeignKey,
+}
+
+impl Display for MemoryDBError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unique(column) => write!(f, "UNIQUE constraint failed: {column}"),
+            Self::ForeignKey => f.write_str("FOREIGN KEY constraint failed"),
+        }
+    }
+}
+
+impl Error for MemoryDBError {}
+
+impl DatabaseError for MemoryDBError {
+    fn message(&self) -> &str {
+        match self {
+            Self::Unique(_) => "UNIQUE constraint failed",
+            Self::ForeignKey => "FOREIGN KEY constraint failed",
+        }
+    }
+
+    fn code(&self) -> Option<Cow<'_, str>> {
+        None
+    }
+
+    fn as_error(&self) -> &(dyn Error + Send + Sync + 'static) {
+        self
+    }
+
+    fn as_error_mut(&mut self) -> &mut (dyn Error + Send + Sync + 'static) {
+        self
+    }
+
+    fn into_error(self: Box<Self>) -> Box<dyn Error + Send + Sync + 'static> {
+        self
+    }
+
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Unique(_) => ErrorKind::UniqueViolation,
+            Self::ForeignKey => ErrorKind::ForeignKeyViolation,
+        }
+    }
+}
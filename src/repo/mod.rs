@@ -0,0 +1,450 @@
+/// The in-memory implementation, used to test route logic without a database.
+#[cfg(test)]
+pub(crate) mod memory;
+/// The `SQLite` implementation, on [`sqlx::Pool<sqlx::Sqlite>`].
+mod sqlite;
+
+use chrono::{NaiveDate, Utc};
+
+use crate::{
+    notify::data::private::DBNotification,
+    routes::{
+        auth::data::private::{DBLoginAttempts, DBUser, DBUserSession, DBUsernameChange},
+        devices::data::private::DBDevice,
+        sync::data::{
+            private::{
+                DBAppAlias, DBAppInfo, DBBlockedWindow, DBCategory, DBCategoryApp, DBLimitOverride,
+                DBReminder, DBUserDebug, DBWeekdayLimit,
+            },
+            public::{Category, ScheduleKey, UserData},
+        },
+        tokens::data::private::DBApiToken,
+        totp::data::private::{DBLoginChallenge, DBTotp},
+        usage::data::private::DBUsageDay,
+        webhooks::data::private::{DBWebhook, DBWebhookDelivery},
+    },
+};
+
+/// Access to stored users.
+///
+/// Lookups that find nothing return [`sqlx::Error::RowNotFound`].
+#[allow(async_fn_in_trait)]
+pub trait UserRepository {
+    /// Fetch the user with the username `username`, compared by [`username_key`](crate::util::username::username_key).
+    async fn fetch_user_by_name(&self, username: &str) -> Result<DBUser, sqlx::Error>;
+
+    /// Fetch the user with the id `id`.
+    async fn fetch_user_by_id(&self, id: u32) -> Result<DBUser, sqlx::Error>;
+
+    /// Store a new user, allocating it a new id, and its first session, together.
+    ///
+    /// Returns the stored user and session.
+    async fn insert_user(
+        &self,
+        username: &str,
+        password_hash: &str,
+    ) -> Result<(DBUser, DBUserSession), sqlx::Error>;
+
+    /// Change the username of the user with the id `id` to `username`, recording their old one in the username history.
+    ///
+    /// Errors with a unique violation if `username` is taken.
+    async fn rename_user(&self, id: u32, username: &str, now: i64) -> Result<(), sqlx::Error>;
+
+    /// Fetch the id of the user who changed away from `username` at or after `released_after`, if anyone did.
+    async fn fetch_username_holder(
+        &self,
+        username: &str,
+        released_after: i64,
+    ) -> Result<Option<u32>, sqlx::Error>;
+
+    /// Fetch the usernames the user with the id `user_id` changed away from, oldest first.
+    async fn fetch_username_history(
+        &self,
+        user_id: u32,
+    ) -> Result<Vec<DBUsernameChange>, sqlx::Error>;
+
+    /// Mark the user with the id `id` as asking to be deleted at `requested_at`, or as not if [`None`].
+    ///
+    /// Marking also deletes the user's session and stops their api tokens working, so they are logged out everywhere.
+    async fn mark_for_deletion(
+        &self,
+        id: u32,
+        requested_at: Option<i64>,
+    ) -> Result<(), sqlx::Error>;
+
+    /// Delete every user who asked to be deleted at or before `requested_before`, and everything stored for them.
+    ///
+    /// Returns how many were deleted.
+    async fn purge_users(&self, requested_before: i64) -> Result<u64, sqlx::Error>;
+
+    /// Replace the password hash of the user with the id `id`.
+    async fn update_password_hash(&self, id: u32, password_hash: &str) -> Result<(), sqlx::Error>;
+}
+
+/// Access to stored user sessions.
+///
+/// Lookups that find nothing return [`sqlx::Error::RowNotFound`].
+#[allow(async_fn_in_trait)]
+pub trait SessionRepository {
+    /// Fetch the session with the session id `session_id`.
+    async fn fetch_session(&self, session_id: &str) -> Result<DBUserSession, sqlx::Error>;
+
+    /// Fetch the session of the user with the id `user_id`.
+    async fn fetch_user_session(&self, user_id: u32) -> Result<DBUserSession, sqlx::Error>;
+
+    /// Store `session`, replacing the user's existing session if there is one.
+    async fn store_session(&self, session: &DBUserSession) -> Result<(), sqlx::Error>;
+}
+
+/// Access to stored failed login attempts.
+#[allow(async_fn_in_trait)]
+pub trait AttemptRepository {
+    /// Fetch the failed attempts for `key`, or no attempts if there are none stored.
+    async fn fetch_attempts(&self, key: &str) -> Result<DBLoginAttempts, sqlx::Error>;
+
+    /// Record a failed attempt for `key` at `now`, as [`record_failure`](crate::util::throttle::record_failure) does,
+    /// returning the attempts after it.
+    ///
+    /// Concurrent failures for the same key are all counted.
+    async fn record_failure(&self, key: &str, now: i64) -> Result<DBLoginAttempts, sqlx::Error>;
+
+    /// Forget the failed attempts for `key`.
+    async fn clear_attempts(&self, key: &str) -> Result<(), sqlx::Error>;
+}
+
+/// Access to stored second factors, and logins waiting on them.
+///
+/// Lookups that find nothing return [`sqlx::Error::RowNotFound`].
+#[allow(async_fn_in_trait)]
+pub trait TotpRepository {
+    /// Fetch the totp secret of the user with the id `user_id`.
+    async fn fetch_totp(&self, user_id: u32) -> Result<DBTotp, sqlx::Error>;
+
+    /// Store `totp`, replacing the user's existing secret if there is one.
+    async fn store_totp(&self, totp: &DBTotp) -> Result<(), sqlx::Error>;
+
+    /// Replace the recovery codes of the user with the id `user_id` with `code_hashes`.
+    async fn replace_recovery_codes(
+        &self,
+        user_id: u32,
+        code_hashes: &[String],
+    ) -> Result<(), sqlx::Error>;
+
+    /// Delete the recovery code hashed as `code_hash`, returning `true` if the user had it.
+    async fn use_recovery_code(&self, user_id: u32, code_hash: &str) -> Result<bool, sqlx::Error>;
+
+    /// Fetch the login challenge with the id `challenge_id`.
+    async fn fetch_challenge(&self, challenge_id: &str) -> Result<DBLoginChallenge, sqlx::Error>;
+
+    /// Store `challenge`.
+    async fn store_challenge(&self, challenge: &DBLoginChallenge) -> Result<(), sqlx::Error>;
+
+    /// Delete the login challenge with the id `challenge_id`.
+    async fn delete_challenge(&self, challenge_id: &str) -> Result<(), sqlx::Error>;
+}
+
+/// Access to stored api tokens.
+///
+/// Lookups that find nothing return [`sqlx::Error::RowNotFound`].
+#[allow(async_fn_in_trait)]
+pub trait TokenRepository {
+    /// Fetch the token hashed as `token_hash`, unless its user is marked for deletion.
+    async fn fetch_token(&self, token_hash: &str) -> Result<DBApiToken, sqlx::Error>;
+
+    /// Fetch all the tokens of the user with the id `user_id`, oldest first.
+    async fn fetch_tokens(&self, user_id: u32) -> Result<Vec<DBApiToken>, sqlx::Error>;
+
+    /// Store a new `token`. Names are unique per user.
+    async fn insert_token(&self, token: &DBApiToken) -> Result<(), sqlx::Error>;
+
+    /// Record the token with the id `id` as used at `now`.
+    async fn touch_token(&self, id: &str, now: i64) -> Result<(), sqlx::Error>;
+
+    /// Delete the token with the id `id` of the user with the id `user_id`, returning `true` if they had it.
+    async fn delete_token(&self, user_id: u32, id: &str) -> Result<bool, sqlx::Error>;
+}
+
+/// Access to the devices users log in and sync from.
+#[allow(async_fn_in_trait)]
+pub trait DeviceRepository {
+    /// Store the device `name` of the user with the id `user_id`, seen at `now`, returning it.
+    ///
+    /// A device with the same name is updated with `platform` and `now`, and keeps its id.
+    async fn register_device(
+        &self,
+        user_id: u32,
+        name: &str,
+        platform: &str,
+        now: i64,
+    ) -> Result<DBDevice, sqlx::Error>;
+
+    /// Fetch all the devices of the user with the id `user_id`, by name.
+    async fn fetch_devices(&self, user_id: u32) -> Result<Vec<DBDevice>, sqlx::Error>;
+
+    /// Record the device with the id `id` as seen at `now`, returning `true` if it is the user's with the id
+    /// `user_id`.
+    async fn touch_device(&self, user_id: u32, id: u32, now: i64) -> Result<bool, sqlx::Error>;
+
+    /// Delete the device with the id `id`, returning `true` if it was the user's with the id `user_id`.
+    ///
+    /// If `keep_usage`, its usage is added to the usage synced without a device, else it is deleted too.
+    async fn delete_device(
+        &self,
+        user_id: u32,
+        id: u32,
+        keep_usage: bool,
+    ) -> Result<bool, sqlx::Error>;
+}
+
+/// Access to the outbox of notifications waiting to be delivered.
+#[allow(async_fn_in_trait)]
+pub trait OutboxRepository {
+    /// Store a notification of the user with the id `user_id`, created at `now` and due right away, returning
+    /// its id if they had none with `event_key` yet.
+    async fn enqueue_notification(
+        &self,
+        user_id: u32,
+        event_key: &str,
+        payload: &str,
+        now: i64,
+    ) -> Result<Option<u32>, sqlx::Error>;
+
+    /// Fetch the notification of the user with the id `user_id` with `event_key`, if it was not purged.
+    async fn fetch_notification(
+        &self,
+        user_id: u32,
+        event_key: &str,
+    ) -> Result<Option<DBNotification>, sqlx::Error>;
+
+    /// Fetch at most `limit` notifications due at `now`, the longest due first.
+    async fn fetch_due_notifications(
+        &self,
+        now: i64,
+        limit: u32,
+    ) -> Result<Vec<DBNotification>, sqlx::Error>;
+
+    /// Record the notification with the id `id` as delivered at `now`.
+    async fn mark_delivered(&self, id: u32, now: i64) -> Result<(), sqlx::Error>;
+
+    /// Record a failed delivery of the notification with the id `id`, to be retried at `next_attempt`, or
+    /// given up on if [`None`].
+    async fn mark_failed(
+        &self,
+        id: u32,
+        error: &str,
+        next_attempt: Option<i64>,
+    ) -> Result<(), sqlx::Error>;
+
+    /// Delete the notifications created before `created_before` that are delivered or given up on, returning
+    /// how many.
+    async fn purge_notifications(&self, created_before: i64) -> Result<u64, sqlx::Error>;
+}
+
+/// Access to users' webhooks, and the deliveries of events to them.
+#[allow(async_fn_in_trait)]
+pub trait WebhookRepository {
+    /// Fetch the webhook with the id `id`.
+    async fn fetch_webhook(&self, id: &str) -> Result<DBWebhook, sqlx::Error>;
+
+    /// Fetch all the webhooks of the user with the id `user_id`, oldest first.
+    async fn fetch_webhooks(&self, user_id: u32) -> Result<Vec<DBWebhook>, sqlx::Error>;
+
+    /// Store a new `webhook`.
+    async fn insert_webhook(&self, webhook: &DBWebhook) -> Result<(), sqlx::Error>;
+
+    /// Delete the webhook with the id `id`, and its deliveries, returning `true` if it was the user's with the
+    /// id `user_id`.
+    async fn delete_webhook(&self, user_id: u32, id: &str) -> Result<bool, sqlx::Error>;
+
+    /// Store a delivery of `payload` to the webhook with the id `webhook_id`, created at `now` and due right
+    /// away, returning it.
+    async fn enqueue_webhook_delivery(
+        &self,
+        webhook_id: &str,
+        event: &str,
+        payload: &str,
+        now: i64,
+    ) -> Result<DBWebhookDelivery, sqlx::Error>;
+
+    /// Fetch at most `limit` deliveries due at `now`, the longest due first.
+    async fn fetch_due_webhook_deliveries(
+        &self,
+        now: i64,
+        limit: u32,
+    ) -> Result<Vec<DBWebhookDelivery>, sqlx::Error>;
+
+    /// Fetch the last `limit` deliveries to the webhook with the id `webhook_id`, newest first.
+    async fn fetch_webhook_deliveries(
+        &self,
+        webhook_id: &str,
+        limit: u32,
+    ) -> Result<Vec<DBWebhookDelivery>, sqlx::Error>;
+
+    /// Store the attempts of `delivery`, found by its id.
+    async fn update_webhook_delivery(
+        &self,
+        delivery: &DBWebhookDelivery,
+    ) -> Result<(), sqlx::Error>;
+
+    /// Delete the deliveries created before `created_before` that are delivered or given up on, returning how
+    /// many.
+    async fn purge_webhook_deliveries(&self, created_before: i64) -> Result<u64, sqlx::Error>;
+}
+
+/// Access to stored user data.
+#[allow(async_fn_in_trait)]
+pub trait UsageRepository {
+    /// Fetch all the app info stored for the user with the id `user_id`.
+    async fn fetch_app_info(&self, user_id: u32) -> Result<Vec<DBAppInfo>, sqlx::Error>;
+
+    /// Store `app_info`.
+    async fn store_app_info(&self, app_info: &DBAppInfo) -> Result<(), sqlx::Error>;
+
+    /// Fetch all the debug info stored for the user with the id `user_id`.
+    async fn fetch_debug(&self, user_id: u32) -> Result<Vec<DBUserDebug>, sqlx::Error>;
+
+    /// Store `debug`.
+    async fn store_debug(&self, debug: &DBUserDebug) -> Result<(), sqlx::Error>;
+
+    /// Fetch the weekday limits of the user with the id `user_id`.
+    async fn fetch_weekday_limits(&self, user_id: u32) -> Result<Vec<DBWeekdayLimit>, sqlx::Error>;
+
+    /// Store `limit`, replacing the app's limit on the same weekday.
+    async fn store_weekday_limit(&self, limit: &DBWeekdayLimit) -> Result<(), sqlx::Error>;
+
+    /// Fetch the blocked windows of the user with the id `user_id`.
+    async fn fetch_blocked_windows(
+        &self,
+        user_id: u32,
+    ) -> Result<Vec<DBBlockedWindow>, sqlx::Error>;
+
+    /// Store `window`, replacing the weekdays of the app's window with the same times.
+    async fn store_blocked_window(&self, window: &DBBlockedWindow) -> Result<(), sqlx::Error>;
+
+    /// Fetch the limit overrides of the user with the id `user_id`, expired or not.
+    async fn fetch_limit_overrides(
+        &self,
+        user_id: u32,
+    ) -> Result<Vec<DBLimitOverride>, sqlx::Error>;
+
+    /// Store `limit`, replacing the app's override.
+    async fn store_limit_override(&self, limit: &DBLimitOverride) -> Result<(), sqlx::Error>;
+
+    /// Fetch the categories of the user with the id `user_id`.
+    async fn fetch_categories(&self, user_id: u32) -> Result<Vec<DBCategory>, sqlx::Error>;
+
+    /// Fetch the apps in the categories of the user with the id `user_id`.
+    async fn fetch_category_apps(&self, user_id: u32) -> Result<Vec<DBCategoryApp>, sqlx::Error>;
+
+    /// Store `category`, replacing the one with the same name, and make `apps` its only apps.
+    ///
+    /// Apps in another of the user's categories are moved.
+    async fn replace_category(
+        &self,
+        category: &DBCategory,
+        apps: &[String],
+    ) -> Result<(), sqlx::Error>;
+
+    /// Fetch the app aliases of the user with the id `user_id`.
+    async fn fetch_app_aliases(&self, user_id: u32) -> Result<Vec<DBAppAlias>, sqlx::Error>;
+
+    /// Store `alias`, replacing the app of the same alias.
+    async fn store_app_alias(&self, alias: &DBAppAlias) -> Result<(), sqlx::Error>;
+
+    /// Fetch the reminders of the user with the id `user_id`, by name.
+    async fn fetch_reminders(&self, user_id: u32) -> Result<Vec<DBReminder>, sqlx::Error>;
+
+    /// Store `reminder`, replacing the one with the same name.
+    async fn store_reminder(&self, reminder: &DBReminder) -> Result<(), sqlx::Error>;
+
+    /// Fetch the timezone of the user with the id `user_id`, [`None`] if they have not set one.
+    async fn fetch_timezone(&self, user_id: u32) -> Result<Option<String>, sqlx::Error>;
+
+    /// Store `timezone` as the timezone of the user with the id `user_id`.
+    async fn store_timezone(&self, user_id: u32, timezone: &str) -> Result<(), sqlx::Error>;
+
+    /// Delete the entry of the user with the id `user_id` with `key`, returning `true` if there was one.
+    async fn delete_schedule(&self, user_id: u32, key: &ScheduleKey) -> Result<bool, sqlx::Error>;
+
+    /// Store `usage`. If its app already has usage for the day on the same device, keep the larger usage and
+    /// the new limit.
+    async fn record_usage(&self, usage: &DBUsageDay) -> Result<(), sqlx::Error>;
+
+    /// Fetch the usage recorded for the user with the id `user_id`, from `from` to `to` inclusive,
+    /// ordered by day then app. Only `device`'s, if given.
+    async fn fetch_usage_history(
+        &self,
+        user_id: u32,
+        device: Option<u32>,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) -> Result<Vec<DBUsageDay>, sqlx::Error>;
+
+    /// Aggregate everything stored for the user with the id `user_id` in a [`UserData`].
+    async fn fetch_user_data(&self, user_id: u32) -> Result<UserData, sqlx::Error> {
+        let app_usage = self
+            .fetch_app_info(user_id)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect();
+        let debug = self
+            .fetch_debug(user_id)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect();
+
+        let weekday_limits = self
+            .fetch_weekday_limits(user_id)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect();
+        let blocked_windows = self
+            .fetch_blocked_windows(user_id)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect();
+        let now = Utc::now().timestamp();
+        let limit_overrides = self
+            .fetch_limit_overrides(user_id)
+            .await?
+            .into_iter()
+            .filter(|o| o.is_active(now))
+            .map(Into::into)
+            .collect();
+
+        let categories = Category::collect(
+            self.fetch_categories(user_id).await?,
+            self.fetch_category_apps(user_id).await?,
+        );
+        let aliases = self
+            .fetch_app_aliases(user_id)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect();
+        let reminders = self
+            .fetch_reminders(user_id)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect();
+        let timezone = self.fetch_timezone(user_id).await?;
+
+        Ok(UserData {
+            app_usage,
+            debug,
+            weekday_limits,
+            blocked_windows,
+            limit_overrides,
+            categories,
+            aliases,
+            reminders,
+            timezone,
+            removed: Vec::new(),
+        })
+    }
+}
@@ -0,0 +1,826 @@
+use chrono::NaiveDate;
+use pcupback::{Fetchable, FetchableAll, Storable};
+use sqlx::{Pool, Sqlite};
+
+use crate::{
+    notify::data::private::DBNotification,
+    routes::{
+        auth::data::private::{DBLoginAttempts, DBUser, DBUserSession, DBUsernameChange},
+        devices::data::private::DBDevice,
+        sync::data::{
+            private::{
+                DBAppAlias, DBAppInfo, DBBlockedWindow, DBCategory, DBCategoryApp, DBLimitOverride,
+                DBReminder, DBUserDebug, DBWeekdayLimit,
+            },
+            public::ScheduleKey,
+        },
+        tokens::data::private::DBApiToken,
+        totp::data::private::{DBLoginChallenge, DBTotp},
+        usage::data::private::{DAY_FORMAT, DBUsageDay},
+        webhooks::data::private::{DBWebhook, DBWebhookDelivery},
+    },
+};
+
+use crate::util::{throttle, username::username_key};
+
+use super::{
+    AttemptRepository, DeviceRepository, OutboxRepository, SessionRepository, TokenRepository,
+    TotpRepository, UsageRepository, UserRepository, WebhookRepository,
+};
+
+impl UserRepository for Pool<Sqlite> {
+    async fn fetch_user_by_name(&self, username: &str) -> Result<DBUser, sqlx::Error> {
+        DBUser::fetch_one(username, self).await
+    }
+
+    async fn insert_user(
+        &self,
+        username: &str,
+        password_hash: &str,
+    ) -> Result<(DBUser, DBUserSession), sqlx::Error> {
+        let mut user = DBUser::new_raw(0, username, password_hash);
+        let mut transaction = self.begin().await?;
+
+        // let the database allocate the id, so concurrent inserts cannot pick the same one.
+        user.id = sqlx::query_scalar(
+            "INSERT INTO users(username, password_hash, public_id, username_key) VALUES(?, ?, ?, ?) RETURNING id",
+        )
+        .bind(&user.username)
+        .bind(&user.password_hash)
+        .bind(&user.public_id)
+        .bind(&user.username_key)
+        .fetch_one(&mut *transaction)
+        .await?;
+        let session = DBUserSession::generate(user.id);
+        session.store(&mut *transaction).await?;
+
+        transaction.commit().await?;
+        Ok((user, session))
+    }
+
+    async fn fetch_user_by_id(&self, id: u32) -> Result<DBUser, sqlx::Error> {
+        DBUser::fetch_one(id, self).await
+    }
+
+    async fn rename_user(&self, id: u32, username: &str, now: i64) -> Result<(), sqlx::Error> {
+        let new_key = username_key(username);
+        let mut transaction = self.begin().await?;
+
+        let old_key = sqlx::query_scalar!("SELECT username_key FROM users WHERE id = ?", id)
+            .fetch_one(&mut *transaction)
+            .await?;
+
+        // only a different key frees the old username, not changing its case.
+        if old_key.as_deref() != Some(new_key.as_str()) {
+            sqlx::query!(
+                "INSERT INTO username_history(username_key, user_id, released_at) VALUES(?, ?, ?)",
+                old_key,
+                id,
+                now
+            )
+            .execute(&mut *transaction)
+            .await?;
+        }
+
+        sqlx::query!(
+            "UPDATE users SET username = ?, username_key = ? WHERE id = ?",
+            username,
+            new_key,
+            id
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        transaction.commit().await
+    }
+
+    async fn fetch_username_holder(
+        &self,
+        username: &str,
+        released_after: i64,
+    ) -> Result<Option<u32>, sqlx::Error> {
+        sqlx::query_scalar(
+            "SELECT user_id FROM username_history WHERE username_key = ? AND released_at >= ? ORDER BY released_at DESC",
+        )
+        .bind(username_key(username))
+        .bind(released_after)
+        .fetch_optional(self)
+        .await
+    }
+
+    async fn fetch_username_history(
+        &self,
+        user_id: u32,
+    ) -> Result<Vec<DBUsernameChange>, sqlx::Error> {
+        sqlx::query_as("SELECT * FROM username_history WHERE user_id = ? ORDER BY released_at")
+            .bind(user_id)
+            .fetch_all(self)
+            .await
+    }
+
+    async fn mark_for_deletion(
+        &self,
+        id: u32,
+        requested_at: Option<i64>,
+    ) -> Result<(), sqlx::Error> {
+        let mut transaction = self.begin().await?;
+
+        sqlx::query!(
+            "UPDATE users SET deletion_requested_at = ? WHERE id = ?",
+            requested_at,
+            id
+        )
+        .execute(&mut *transaction)
+        .await?;
+        if requested_at.is_some() {
+            sqlx::query!("DELETE FROM sessions WHERE user_id = ?", id)
+                .execute(&mut *transaction)
+                .await?;
+        }
+
+        transaction.commit().await
+    }
+
+    async fn purge_users(&self, requested_before: i64) -> Result<u64, sqlx::Error> {
+        // the users' sessions and data are deleted by the `ON DELETE CASCADE`s.
+        let deleted = sqlx::query!(
+            "DELETE FROM users WHERE deletion_requested_at <= ?",
+            requested_before
+        )
+        .execute(self)
+        .await?;
+        Ok(deleted.rows_affected())
+    }
+
+    async fn update_password_hash(&self, id: u32, password_hash: &str) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "UPDATE users SET password_hash = ? WHERE id = ?",
+            password_hash,
+            id
+        )
+        .execute(self)
+        .await?;
+        Ok(())
+    }
+}
+
+impl SessionRepository for Pool<Sqlite> {
+    async fn fetch_session(&self, session_id: &str) -> Result<DBUserSession, sqlx::Error> {
+        DBUserSession::fetch_one(session_id, self).await
+    }
+
+    async fn fetch_user_session(&self, user_id: u32) -> Result<DBUserSession, sqlx::Error> {
+        sqlx::query_as("SELECT * FROM sessions WHERE user_id = ?")
+            .bind(user_id)
+            .fetch_one(self)
+            .await
+    }
+
+    async fn store_session(&self, session: &DBUserSession) -> Result<(), sqlx::Error> {
+        session.store(self).await?;
+        Ok(())
+    }
+}
+
+impl AttemptRepository for Pool<Sqlite> {
+    async fn fetch_attempts(&self, key: &str) -> Result<DBLoginAttempts, sqlx::Error> {
+        match DBLoginAttempts::fetch_one(key, self).await {
+            Err(sqlx::Error::RowNotFound) => Ok(DBLoginAttempts::new(key)),
+            attempts => attempts,
+        }
+    }
+
+    async fn record_failure(&self, key: &str, now: i64) -> Result<DBLoginAttempts, sqlx::Error> {
+        // one statement, so concurrent failures cannot overwrite each other's.
+        // `?3` is the failures after this one, as in `throttle::record_failure`.
+        sqlx::query_as(
+            "INSERT INTO login_attempts(key, failures, last_failure, locked_until) VALUES(?1, 1, ?2, 0)
+            ON CONFLICT(key) DO UPDATE SET
+                failures = CASE WHEN ?2 - last_failure > ?3 THEN 1 ELSE failures + 1 END,
+                last_failure = ?2,
+                locked_until = CASE
+                    WHEN (CASE WHEN ?2 - last_failure > ?3 THEN 1 ELSE failures + 1 END) >= ?4
+                    THEN ?2 + min(?5 << min((CASE WHEN ?2 - last_failure > ?3 THEN 1 ELSE failures + 1 END) - ?4, 32), ?6)
+                    ELSE locked_until
+                END
+            RETURNING *",
+        )
+        .bind(key)
+        .bind(now)
+        .bind(throttle::FAILURE_EXPIRY)
+        .bind(throttle::FREE_ATTEMPTS)
+        .bind(throttle::BASE_LOCKOUT)
+        .bind(throttle::MAX_LOCKOUT)
+        .fetch_one(self)
+        .await
+    }
+
+    async fn clear_attempts(&self, key: &str) -> Result<(), sqlx::Error> {
+        sqlx::query!("DELETE FROM login_attempts WHERE key = ?", key)
+            .execute(self)
+            .await?;
+        Ok(())
+    }
+}
+
+impl TotpRepository for Pool<Sqlite> {
+    async fn fetch_totp(&self, user_id: u32) -> Result<DBTotp, sqlx::Error> {
+        DBTotp::fetch_one(user_id, self).await
+    }
+
+    async fn store_totp(&self, totp: &DBTotp) -> Result<(), sqlx::Error> {
+        totp.store(self).await?;
+        Ok(())
+    }
+
+    async fn replace_recovery_codes(
+        &self,
+        user_id: u32,
+        code_hashes: &[String],
+    ) -> Result<(), sqlx::Error> {
+        let mut transaction = self.begin().await?;
+
+        sqlx::query!("DELETE FROM recovery_codes WHERE user_id = ?", user_id)
+            .execute(&mut *transaction)
+            .await?;
+        for code_hash in code_hashes {
+            sqlx::query!(
+                "INSERT INTO recovery_codes(user_id, code_hash) VALUES(?, ?)",
+                user_id,
+                code_hash
+            )
+            .execute(&mut *transaction)
+            .await?;
+        }
+
+        transaction.commit().await
+    }
+
+    async fn use_recovery_code(&self, user_id: u32, code_hash: &str) -> Result<bool, sqlx::Error> {
+        let deleted = sqlx::query!(
+            "DELETE FROM recovery_codes WHERE user_id = ? AND code_hash = ?",
+            user_id,
+            code_hash
+        )
+        .execute(self)
+        .await?;
+        Ok(deleted.rows_affected() > 0)
+    }
+
+    async fn fetch_challenge(&self, challenge_id: &str) -> Result<DBLoginChallenge, sqlx::Error> {
+        DBLoginChallenge::fetch_one(challenge_id, self).await
+    }
+
+    async fn store_challenge(&self, challenge: &DBLoginChallenge) -> Result<(), sqlx::Error> {
+        challenge.store(self).await?;
+        Ok(())
+    }
+
+    async fn delete_challenge(&self, challenge_id: &str) -> Result<(), sqlx::Error> {
+        sqlx::query!("DELETE FROM login_challenges WHERE id = ?", challenge_id)
+            .execute(self)
+            .await?;
+        Ok(())
+    }
+}
+
+impl DeviceRepository for Pool<Sqlite> {
+    async fn register_device(
+        &self,
+        user_id: u32,
+        name: &str,
+        platform: &str,
+        now: i64,
+    ) -> Result<DBDevice, sqlx::Error> {
+        sqlx::query_as(
+            "INSERT INTO devices(user_id, name, platform, last_seen) VALUES(?, ?, ?, ?)
+            ON CONFLICT(user_id, name) DO UPDATE SET platform = excluded.platform, last_seen = excluded.last_seen
+            RETURNING *",
+        )
+        .bind(user_id)
+        .bind(name)
+        .bind(platform)
+        .bind(now)
+        .fetch_one(self)
+        .await
+    }
+
+    async fn fetch_devices(&self, user_id: u32) -> Result<Vec<DBDevice>, sqlx::Error> {
+        DBDevice::fetch_all(user_id, self).await
+    }
+
+    async fn touch_device(&self, user_id: u32, id: u32, now: i64) -> Result<bool, sqlx::Error> {
+        let touched = sqlx::query!(
+            "UPDATE devices SET last_seen = ? WHERE user_id = ? AND id = ?",
+            now,
+            user_id,
+            id
+        )
+        .execute(self)
+        .await?;
+        Ok(touched.rows_affected() > 0)
+    }
+
+    async fn delete_device(
+        &self,
+        user_id: u32,
+        id: u32,
+        keep_usage: bool,
+    ) -> Result<bool, sqlx::Error> {
+        let mut transaction = self.begin().await?;
+
+        let deleted = sqlx::query!(
+            "DELETE FROM devices WHERE user_id = ? AND id = ?",
+            user_id,
+            id
+        )
+        .execute(&mut *transaction)
+        .await?;
+        if deleted.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        if keep_usage {
+            // both were used that day, so their usage adds up.
+            sqlx::query!(
+                "INSERT INTO usage_history(user_id, device_id, app_name, day, app_usage, app_limit)
+                SELECT user_id, 0, app_name, day, app_usage, app_limit FROM usage_history
+                WHERE user_id = ? AND device_id = ?
+                ON CONFLICT(user_id, device_id, app_name, day) DO UPDATE
+                SET app_usage = app_usage + excluded.app_usage, app_limit = MAX(app_limit, excluded.app_limit)",
+                user_id,
+                id
+            )
+            .execute(&mut *transaction)
+            .await?;
+        }
+        sqlx::query!(
+            "DELETE FROM usage_history WHERE user_id = ? AND device_id = ?",
+            user_id,
+            id
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        transaction.commit().await?;
+        Ok(true)
+    }
+}
+
+impl OutboxRepository for Pool<Sqlite> {
+    async fn enqueue_notification(
+        &self,
+        user_id: u32,
+        event_key: &str,
+        payload: &str,
+        now: i64,
+    ) -> Result<Option<u32>, sqlx::Error> {
+        sqlx::query_scalar(
+            "INSERT INTO notification_outbox(user_id, event_key, payload, created, next_attempt)
+            VALUES(?, ?, ?, ?, ?)
+            ON CONFLICT(user_id, event_key) DO NOTHING
+            RETURNING id",
+        )
+        .bind(user_id)
+        .bind(event_key)
+        .bind(payload)
+        .bind(now)
+        .bind(now)
+        .fetch_optional(self)
+        .await
+    }
+
+    async fn fetch_notification(
+        &self,
+        user_id: u32,
+        event_key: &str,
+    ) -> Result<Option<DBNotification>, sqlx::Error> {
+        sqlx::query_as("SELECT * FROM notification_outbox WHERE user_id = ? AND event_key = ?")
+            .bind(user_id)
+            .bind(event_key)
+            .fetch_optional(self)
+            .await
+    }
+
+    async fn fetch_due_notifications(
+        &self,
+        now: i64,
+        limit: u32,
+    ) -> Result<Vec<DBNotification>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT * FROM notification_outbox WHERE next_attempt <= ? ORDER BY next_attempt, id LIMIT ?",
+        )
+        .bind(now)
+        .bind(limit)
+        .fetch_all(self)
+        .await
+    }
+
+    async fn mark_delivered(&self, id: u32, now: i64) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "UPDATE notification_outbox SET delivered = ?, next_attempt = NULL WHERE id = ?",
+            now,
+            id
+        )
+        .execute(self)
+        .await?;
+        Ok(())
+    }
+
+    async fn mark_failed(
+        &self,
+        id: u32,
+        error: &str,
+        next_attempt: Option<i64>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "UPDATE notification_outbox SET attempts = attempts + 1, last_error = ?, next_attempt = ?
+            WHERE id = ?",
+            error,
+            next_attempt,
+            id
+        )
+        .execute(self)
+        .await?;
+        Ok(())
+    }
+
+    async fn purge_notifications(&self, created_before: i64) -> Result<u64, sqlx::Error> {
+        let deleted = sqlx::query!(
+            "DELETE FROM notification_outbox WHERE next_attempt IS NULL AND created < ?",
+            created_before
+        )
+        .execute(self)
+        .await?;
+        Ok(deleted.rows_affected())
+    }
+}
+
+impl WebhookRepository for Pool<Sqlite> {
+    async fn fetch_webhook(&self, id: &str) -> Result<DBWebhook, sqlx::Error> {
+        DBWebhook::fetch_one(id, self).await
+    }
+
+    async fn fetch_webhooks(&self, user_id: u32) -> Result<Vec<DBWebhook>, sqlx::Error> {
+        <DBWebhook as FetchableAll<u32>>::fetch_all(user_id, self).await
+    }
+
+    async fn insert_webhook(&self, webhook: &DBWebhook) -> Result<(), sqlx::Error> {
+        webhook.store(self).await?;
+        Ok(())
+    }
+
+    async fn delete_webhook(&self, user_id: u32, id: &str) -> Result<bool, sqlx::Error> {
+        // its deliveries are deleted by the `ON DELETE CASCADE`.
+        let deleted = sqlx::query!(
+            "DELETE FROM webhooks WHERE user_id = ? AND id = ?",
+            user_id,
+            id
+        )
+        .execute(self)
+        .await?;
+        Ok(deleted.rows_affected() > 0)
+    }
+
+    async fn enqueue_webhook_delivery(
+        &self,
+        webhook_id: &str,
+        event: &str,
+        payload: &str,
+        now: i64,
+    ) -> Result<DBWebhookDelivery, sqlx::Error> {
+        sqlx::query_as(
+            "INSERT INTO webhook_deliveries(webhook_id, event, payload, created, next_attempt)
+            VALUES(?, ?, ?, ?, ?)
+            RETURNING *",
+        )
+        .bind(webhook_id)
+        .bind(event)
+        .bind(payload)
+        .bind(now)
+        .bind(now)
+        .fetch_one(self)
+        .await
+    }
+
+    async fn fetch_due_webhook_deliveries(
+        &self,
+        now: i64,
+        limit: u32,
+    ) -> Result<Vec<DBWebhookDelivery>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT * FROM webhook_deliveries WHERE next_attempt <= ? ORDER BY next_attempt, id LIMIT ?",
+        )
+        .bind(now)
+        .bind(limit)
+        .fetch_all(self)
+        .await
+    }
+
+    async fn fetch_webhook_deliveries(
+        &self,
+        webhook_id: &str,
+        limit: u32,
+    ) -> Result<Vec<DBWebhookDelivery>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT * FROM webhook_deliveries WHERE webhook_id = ? ORDER BY id DESC LIMIT ?",
+        )
+        .bind(webhook_id)
+        .bind(limit)
+        .fetch_all(self)
+        .await
+    }
+
+    async fn update_webhook_delivery(
+        &self,
+        delivery: &DBWebhookDelivery,
+    ) -> Result<(), sqlx::Error> {
+        delivery.store(self).await?;
+        Ok(())
+    }
+
+    async fn purge_webhook_deliveries(&self, created_before: i64) -> Result<u64, sqlx::Error> {
+        let deleted = sqlx::query!(
+            "DELETE FROM webhook_deliveries WHERE next_attempt IS NULL AND created < ?",
+            created_before
+        )
+        .execute(self)
+        .await?;
+        Ok(deleted.rows_affected())
+    }
+}
+
+impl TokenRepository for Pool<Sqlite> {
+    async fn fetch_token(&self, token_hash: &str) -> Result<DBApiToken, sqlx::Error> {
+        DBApiToken::fetch_one(token_hash, self).await
+    }
+
+    async fn fetch_tokens(&self, user_id: u32) -> Result<Vec<DBApiToken>, sqlx::Error> {
+        <DBApiToken as FetchableAll<u32>>::fetch_all(user_id, self).await
+    }
+
+    async fn insert_token(&self, token: &DBApiToken) -> Result<(), sqlx::Error> {
+        token.store(self).await?;
+        Ok(())
+    }
+
+    async fn touch_token(&self, id: &str, now: i64) -> Result<(), sqlx::Error> {
+        sqlx::query!("UPDATE api_tokens SET last_used = ? WHERE id = ?", now, id)
+            .execute(self)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_token(&self, user_id: u32, id: &str) -> Result<bool, sqlx::Error> {
+        let deleted = sqlx::query!(
+            "DELETE FROM api_tokens WHERE user_id = ? AND id = ?",
+            user_id,
+            id
+        )
+        .execute(self)
+        .await?;
+        Ok(deleted.rows_affected() > 0)
+    }
+}
+
+impl UsageRepository for Pool<Sqlite> {
+    async fn fetch_app_info(&self, user_id: u32) -> Result<Vec<DBAppInfo>, sqlx::Error> {
+        DBAppInfo::fetch_all(user_id, self).await
+    }
+
+    async fn store_app_info(&self, app_info: &DBAppInfo) -> Result<(), sqlx::Error> {
+        app_info.store(self).await?;
+        Ok(())
+    }
+
+    async fn fetch_debug(&self, user_id: u32) -> Result<Vec<DBUserDebug>, sqlx::Error> {
+        DBUserDebug::fetch_all(user_id, self).await
+    }
+
+    async fn store_debug(&self, debug: &DBUserDebug) -> Result<(), sqlx::Error> {
+        debug.store(self).await?;
+        Ok(())
+    }
+
+    async fn fetch_weekday_limits(&self, user_id: u32) -> Result<Vec<DBWeekdayLimit>, sqlx::Error> {
+        DBWeekdayLimit::fetch_all(user_id, self).await
+    }
+
+    async fn store_weekday_limit(&self, limit: &DBWeekdayLimit) -> Result<(), sqlx::Error> {
+        limit.store(self).await?;
+        Ok(())
+    }
+
+    async fn fetch_blocked_windows(
+        &self,
+        user_id: u32,
+    ) -> Result<Vec<DBBlockedWindow>, sqlx::Error> {
+        DBBlockedWindow::fetch_all(user_id, self).await
+    }
+
+    async fn store_blocked_window(&self, window: &DBBlockedWindow) -> Result<(), sqlx::Error> {
+        window.store(self).await?;
+        Ok(())
+    }
+
+    async fn fetch_limit_overrides(
+        &self,
+        user_id: u32,
+    ) -> Result<Vec<DBLimitOverride>, sqlx::Error> {
+        DBLimitOverride::fetch_all(user_id, self).await
+    }
+
+    async fn store_limit_override(&self, limit: &DBLimitOverride) -> Result<(), sqlx::Error> {
+        limit.store(self).await?;
+        Ok(())
+    }
+
+    async fn fetch_categories(&self, user_id: u32) -> Result<Vec<DBCategory>, sqlx::Error> {
+        DBCategory::fetch_all(user_id, self).await
+    }
+
+    async fn fetch_category_apps(&self, user_id: u32) -> Result<Vec<DBCategoryApp>, sqlx::Error> {
+        DBCategoryApp::fetch_all(user_id, self).await
+    }
+
+    async fn replace_category(
+        &self,
+        category: &DBCategory,
+        apps: &[String],
+    ) -> Result<(), sqlx::Error> {
+        let mut transaction = self.begin().await?;
+
+        sqlx::query!(
+            "INSERT INTO categories(user_id, name, category_limit) VALUES(?, ?, ?)
+            ON CONFLICT(user_id, name) DO UPDATE SET category_limit = excluded.category_limit",
+            category.user_id,
+            category.name,
+            category.category_limit
+        )
+        .execute(&mut *transaction)
+        .await?;
+        sqlx::query!(
+            "DELETE FROM category_apps WHERE user_id = ? AND category = ?",
+            category.user_id,
+            category.name
+        )
+        .execute(&mut *transaction)
+        .await?;
+        for app_name in apps {
+            sqlx::query!(
+                "INSERT INTO category_apps(user_id, category, app_name) VALUES(?, ?, ?)
+                ON CONFLICT(user_id, app_name) DO UPDATE SET category = excluded.category",
+                category.user_id,
+                category.name,
+                app_name
+            )
+            .execute(&mut *transaction)
+            .await?;
+        }
+
+        transaction.commit().await
+    }
+
+    async fn fetch_app_aliases(&self, user_id: u32) -> Result<Vec<DBAppAlias>, sqlx::Error> {
+        DBAppAlias::fetch_all(user_id, self).await
+    }
+
+    async fn store_app_alias(&self, alias: &DBAppAlias) -> Result<(), sqlx::Error> {
+        alias.store(self).await?;
+        Ok(())
+    }
+
+    async fn fetch_reminders(&self, user_id: u32) -> Result<Vec<DBReminder>, sqlx::Error> {
+        DBReminder::fetch_all(user_id, self).await
+    }
+
+    async fn store_reminder(&self, reminder: &DBReminder) -> Result<(), sqlx::Error> {
+        reminder.store(self).await?;
+        Ok(())
+    }
+
+    async fn fetch_timezone(&self, user_id: u32) -> Result<Option<String>, sqlx::Error> {
+        sqlx::query_scalar!(
+            "SELECT timezone FROM user_timezones WHERE user_id = ?",
+            user_id
+        )
+        .fetch_optional(self)
+        .await
+    }
+
+    async fn store_timezone(&self, user_id: u32, timezone: &str) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "INSERT INTO user_timezones(user_id, timezone) VALUES(?, ?)
+            ON CONFLICT(user_id) DO UPDATE SET timezone = excluded.timezone",
+            user_id,
+            timezone
+        )
+        .execute(self)
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_schedule(&self, user_id: u32, key: &ScheduleKey) -> Result<bool, sqlx::Error> {
+        let result = match key {
+            ScheduleKey::WeekdayLimit { app, weekday } => {
+                let weekday = weekday.index();
+                sqlx::query!(
+                    "DELETE FROM weekday_limits WHERE user_id = ? AND app_name = ? AND weekday = ?",
+                    user_id,
+                    app,
+                    weekday
+                )
+                .execute(self)
+                .await?
+            }
+            ScheduleKey::BlockedWindow { app, start, end } => {
+                sqlx::query!(
+                    "DELETE FROM blocked_windows
+                    WHERE user_id = ? AND app_name = ? AND start_minute = ? AND end_minute = ?",
+                    user_id,
+                    app,
+                    start,
+                    end
+                )
+                .execute(self)
+                .await?
+            }
+            ScheduleKey::LimitOverride { app } => {
+                sqlx::query!(
+                    "DELETE FROM limit_overrides WHERE user_id = ? AND app_name = ?",
+                    user_id,
+                    app
+                )
+                .execute(self)
+                .await?
+            }
+            // its apps are deleted with it.
+            ScheduleKey::Category { name } => {
+                sqlx::query!(
+                    "DELETE FROM categories WHERE user_id = ? AND name = ?",
+                    user_id,
+                    name
+                )
+                .execute(self)
+                .await?
+            }
+            ScheduleKey::AppAlias { alias } => {
+                sqlx::query!(
+                    "DELETE FROM app_aliases WHERE user_id = ? AND alias = ?",
+                    user_id,
+                    alias
+                )
+                .execute(self)
+                .await?
+            }
+            ScheduleKey::Reminder { name } => {
+                sqlx::query!(
+                    "DELETE FROM reminders WHERE user_id = ? AND name = ?",
+                    user_id,
+                    name
+                )
+                .execute(self)
+                .await?
+            }
+        };
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn record_usage(&self, usage: &DBUsageDay) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "INSERT INTO usage_history(user_id, device_id, app_name, day, app_usage, app_limit) VALUES(?, ?, ?, ?, ?, ?)
+            ON CONFLICT(user_id, device_id, app_name, day) DO UPDATE
+            SET app_usage = MAX(app_usage, excluded.app_usage), app_limit = excluded.app_limit",
+            usage.user_id,
+            usage.device_id,
+            usage.app_name,
+            usage.day,
+            usage.app_usage,
+            usage.app_limit
+        )
+        .execute(self)
+        .await?;
+        Ok(())
+    }
+
+    async fn fetch_usage_history(
+        &self,
+        user_id: u32,
+        device: Option<u32>,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) -> Result<Vec<DBUsageDay>, sqlx::Error> {
+        // days sort as text, see `DAY_FORMAT`.
+        sqlx::query_as(
+            "SELECT * FROM usage_history WHERE user_id = ?1 AND (?4 IS NULL OR device_id = ?4)
+            AND (?2 IS NULL OR day >= ?2) AND (?3 IS NULL OR day <= ?3)
+            ORDER BY day, app_name, device_id",
+        )
+        .bind(user_id)
+        .bind(from.map(|day| day.format(DAY_FORMAT).to_string()))
+        .bind(to.map(|day| day.format(DAY_FORMAT).to_string()))
+        .bind(device)
+        .fetch_all(self)
+        .await
+    }
+}
@@ -7,19 +7,30 @@ use pcupback::{Fetchable, Storable};
 use sqlx::{Executor, FromRow, Sqlite, sqlite::SqliteQueryResult};
 use uuid::Uuid;
 
+use crate::{config::HashConfig, util::username::username_key};
+
 use super::public::HashErrorKind::{self, CreateError};
 
-#[derive(Debug, FromRow, PartialEq, Eq)]
+#[derive(Debug, FromRow, PartialEq, Eq, Clone)]
 pub struct DBUser {
+    /// Internal only, never exposed to api consumers.
     pub id: u32,
     pub username: String,
     pub password_hash: String,
+    /// The random, stable id exposed to api consumers.
+    pub public_id: String,
+    /// What `username` is unique by, see [`username_key`].
+    pub username_key: String,
+    /// When the user asked to delete their account, see [`DeletionConfig`](crate::config::DeletionConfig).
+    ///
+    /// Stored as seconds since the unix epoch.
+    pub deletion_requested_at: Option<i64>,
 }
 
 impl DBUser {
     /// Create a new user to be stored in db.
     ///
-    /// Hashes the given password.
+    /// Hashes the given password with the default [`HashConfig`].
     ///
     /// # Errors
     ///
@@ -29,29 +40,56 @@ impl DBUser {
         username: impl Into<String>,
         password: impl AsRef<str>,
     ) -> Result<Self, HashErrorKind> {
-        let salt = SaltString::generate(&mut OsRng);
-        let password_hash = Argon2::default()
-            .hash_password(password.as_ref().as_bytes(), &salt)
-            .map_err(|e| CreateError(e.to_string()))?
-            .to_string();
-
-        Ok(Self {
+        Ok(Self::new_raw(
             id,
-            username: username.into(),
-            password_hash,
-        })
+            username,
+            hash_password(password, &HashConfig::default())?,
+        ))
     }
 
-    #[cfg(test)]
+    /// Create a user from an already hashed password.
+    ///
+    /// `public_id` is [`Uuid::new_v4`].
+    #[must_use]
     pub fn new_raw(id: u32, username: impl Into<String>, password: impl Into<String>) -> Self {
+        let username = username.into();
         Self {
             id,
-            username: username.into(),
+            username_key: username_key(&username),
+            username,
             password_hash: password.into(),
+            public_id: Uuid::new_v4().to_string(),
+            deletion_requested_at: None,
         }
     }
 }
 
+/// Hash `password` with a new random salt and the costs in `config`, to be stored as a user's `password_hash`.
+///
+/// # Errors
+///
+/// On error, return a [`HashErrorKind`], caused by [`argon2::password_hash::errors::Error`].
+pub fn hash_password(
+    password: impl AsRef<str>,
+    config: &HashConfig,
+) -> Result<String, HashErrorKind> {
+    let hasher = config.hasher().map_err(|e| CreateError(e.to_string()))?;
+    hash_with(password, &hasher)
+}
+
+/// Hash `password` with a new random salt and `hasher`, see [`hash_password`].
+///
+/// # Errors
+///
+/// On error, return a [`HashErrorKind`], caused by [`argon2::password_hash::errors::Error`].
+pub fn hash_with(password: impl AsRef<str>, hasher: &Argon2) -> Result<String, HashErrorKind> {
+    let salt = SaltString::generate(&mut OsRng);
+    hasher
+        .hash_password(password.as_ref().as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| CreateError(e.to_string()))
+}
+
 impl<'a> Storable<'a> for DBUser {
     type DB = Sqlite;
 
@@ -60,12 +98,13 @@ impl<'a> Storable<'a> for DBUser {
         E: Executor<'a, Database = Self::DB>,
     {
         sqlx::query!(
-            "INSERT INTO users(id, username, password_hash) VALUES(?, ?, ?)",
+            "INSERT INTO users(id, username, password_hash, public_id, username_key) VALUES(?, ?, ?, ?, ?)",
             self.id,
             self.username,
-            self.password_hash
+            self.password_hash,
+            self.public_id,
+            self.username_key
         )
-        // .bind(self.id)
         .execute(executor)
         .await
     }
@@ -74,15 +113,23 @@ impl<'a> Storable<'a> for DBUser {
 impl<'a> Fetchable<'a, &'a str> for DBUser {
     type DB = Sqlite;
 
-    /// username filter
+    /// username filter, compared by [`username_key`].
+    ///
+    /// Users in `username_collisions`, whose keys could not be set, are matched by their exact username first.
     async fn fetch_one<E>(filter: &str, executor: E) -> Result<Self, sqlx::Error>
     where
         E: Executor<'a, Database = Self::DB>,
     {
-        sqlx::query_as("SELECT * FROM users WHERE username = ?")
-            .bind(filter)
-            .fetch_one(executor)
-            .await
+        sqlx::query_as(
+            "SELECT users.* FROM users LEFT JOIN username_collisions ON username_collisions.user_id = users.id
+            WHERE users.username_key = ? OR username_collisions.username = ?
+            ORDER BY username_collisions.username = ? DESC LIMIT 1",
+        )
+        .bind(username_key(filter))
+        .bind(filter.trim())
+        .bind(filter.trim())
+        .fetch_one(executor)
+        .await
     }
 }
 
@@ -101,7 +148,17 @@ impl<'a> Fetchable<'a, u32> for DBUser {
     }
 }
 
-#[derive(Debug, FromRow, PartialEq, Eq)]
+/// A username a user changed away from, see the `username_history` table.
+#[derive(Debug, FromRow, PartialEq, Eq, Clone)]
+pub struct DBUsernameChange {
+    /// The old username's key, see [`username_key`].
+    pub username_key: String,
+    pub user_id: u32,
+    /// Stored as seconds since the unix epoch.
+    pub released_at: i64,
+}
+
+#[derive(Debug, FromRow, PartialEq, Eq, Clone)]
 pub struct DBUserSession {
     pub user_id: u32,
     pub id: String,
@@ -163,6 +220,44 @@ impl<'a> Storable<'a> for DBUserSession {
     }
 }
 
+/// Failed login attempts for a `key`, see the `login_attempts` table.
+#[derive(Debug, FromRow, PartialEq, Eq, Clone)]
+pub struct DBLoginAttempts {
+    pub key: String,
+    pub failures: u32,
+    /// Stored as seconds since the unix epoch.
+    pub last_failure: i64,
+    /// Stored as seconds since the unix epoch.
+    pub locked_until: i64,
+}
+
+impl DBLoginAttempts {
+    /// No failed attempts for `key` yet.
+    #[must_use]
+    pub fn new(key: impl Into<String>) -> Self {
+        Self {
+            key: key.into(),
+            failures: 0,
+            last_failure: 0,
+            locked_until: 0,
+        }
+    }
+}
+
+impl<'a> Fetchable<'a, &'a str> for DBLoginAttempts {
+    type DB = Sqlite;
+
+    async fn fetch_one<E>(filter: &'a str, executor: E) -> Result<Self, sqlx::Error>
+    where
+        E: Executor<'a, Database = Self::DB>,
+    {
+        sqlx::query_as("SELECT * FROM login_attempts WHERE key = ?")
+            .bind(filter)
+            .fetch_one(executor)
+            .await
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use pcupback::Fetchable;
@@ -3,31 +3,65 @@ use thiserror::Error;
 
 use pcupback::DBErrorKind;
 
-use super::private::DBUserSession;
+use crate::routes::devices::data::public::DeviceInfo;
+
+use super::private::{DBUser, DBUserSession};
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
 pub struct UserSession {
-    pub user_id: u32,
+    /// The user's public id, see [`DBUser::public_id`].
+    pub user_id: String,
     pub id: String,
+    /// The id of the device logged in from, if one was registered, see [`DeviceInfo`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_id: Option<u32>,
 }
 
-impl From<DBUserSession> for UserSession {
-    fn from(value: DBUserSession) -> Self {
+impl UserSession {
+    /// Create the api representation of `user`'s `session`.
+    #[must_use]
+    pub fn new(user: &DBUser, session: DBUserSession) -> Self {
         Self {
-            user_id: value.user_id,
-            id: value.id,
+            user_id: user.public_id.clone(),
+            id: session.id,
+            device_id: None,
         }
     }
 }
 
+/// The result of a verified password.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum LoginResponse {
+    /// The user is logged in.
+    Session(UserSession),
+    /// The user has totp enabled, answer `challenge` at `/auth/login/totp` to get a session.
+    TotpRequired { challenge: String },
+}
+
 #[derive(Error, Debug, Serialize, Deserialize)]
 pub enum AuthError {
     #[error("EmptyUsername")]
     EmptyUsername,
+    #[error("InvalidUsername")]
+    InvalidUsername(#[from] InvalidUsernameKind),
     #[error("InvalidPassword")]
     InvalidPassword(#[from] InvalidPasswordKind),
-    #[error("WrongPassword")]
-    WrongPassword,
+    /// The username does not exist, or the password was wrong.
+    #[error("InvalidCredentials")]
+    InvalidCredentials,
+    #[error("UsernameTaken")]
+    UsernameTaken,
+    #[error("InvalidSession")]
+    InvalidSession,
+    /// The totp login challenge does not exist, or timed out.
+    #[error("InvalidChallenge")]
+    InvalidChallenge,
+    /// Too many failed logins for the username or from the client's address.
+    #[error("TooManyAttempts")]
+    TooManyAttempts {
+        /// Seconds until another login is allowed.
+        retry_after: i64,
+    },
     #[error("HashError")]
     HashError(#[from] HashErrorKind),
     #[error("db error")]
@@ -42,6 +76,27 @@ pub enum InvalidPasswordKind {
     TooFewChars,
     #[error("TooManyChars")]
     TooManyChars,
+    /// The password is in the list of common passwords.
+    #[error("Common")]
+    Common,
+    #[error("EqualsUsername")]
+    EqualsUsername,
+    #[error("ContainsUsername")]
+    ContainsUsername,
+}
+
+#[derive(Error, Debug, Serialize, Deserialize)]
+pub enum InvalidUsernameKind {
+    #[error("TooShort")]
+    TooShort,
+    #[error("TooLong")]
+    TooLong,
+    /// The username contains a character that is not a letter, digit, `_`, `-` or `.`.
+    #[error("InvalidChar")]
+    InvalidChar(char),
+    /// The username is reserved, and cannot be signed up with.
+    #[error("Reserved")]
+    Reserved,
 }
 
 #[derive(Error, Debug, Serialize, Deserialize)]
@@ -56,14 +111,24 @@ pub enum HashErrorKind {
 pub struct AuthRequest {
     pub username: String,
     pub password: String,
+    /// The device logging in, registered as one of the user's.
+    #[serde(default)]
+    pub device: Option<DeviceInfo>,
 }
 
 impl AuthRequest {
     #[cfg(test)]
     pub fn random_valid() -> Self {
         Self {
-            username: uuid::Uuid::new_v4().to_string(),
-            password: "12345678".to_string(),
+            username: Self::random_username(),
+            password: "correct horse battery staple".to_string(),
+            device: None,
         }
     }
+
+    /// A random, valid username.
+    #[cfg(test)]
+    pub fn random_username() -> String {
+        format!("user_{}", &uuid::Uuid::new_v4().simple().to_string()[..16])
+    }
 }
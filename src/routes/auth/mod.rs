@@ -4,10 +4,13 @@ pub mod data;
 #[cfg(test)]
 mod tests;
 
+use std::{net::IpAddr, sync::OnceLock};
+
 use argon2::{Argon2, PasswordHash, PasswordVerifier, password_hash};
+use chrono::Utc;
 use data::{
-    private::{DBUser, DBUserSession},
-    public::{AuthError, AuthRequest, UserSession},
+    private::{DBUser, hash_password, hash_with},
+    public::{AuthError, AuthRequest, HashErrorKind, LoginResponse, UserSession},
 };
 use rocket::{
     State, post,
@@ -16,171 +19,530 @@ use rocket::{
 use sqlx::{Pool, Sqlite};
 use tracing::instrument;
 
-use pcupback::{
-    DBErrorKind::{InsertError, OtherError},
-    Fetchable, Storable,
-};
+use pcupback::DBErrorKind::{DeleteError, InsertError, SelectError};
 
-use crate::util::{
-    auth::{generate_store_session, validate_session},
-    db::PoolStateExt,
+use crate::{
+    config::{HashConfig, PasswordPolicy},
+    notify::{self, data::public::Event},
+    repo::{
+        AttemptRepository, DeviceRepository, OutboxRepository, SessionRepository, TotpRepository,
+        UserRepository, WebhookRepository,
+    },
+    routes::{
+        devices::{self, data::public::DeviceInfo},
+        totp::data::{private::DBLoginChallenge, public::TotpLoginRequest},
+    },
+    util::{
+        auth::validate_session,
+        db::PoolStateExt,
+        password::check_password,
+        throttle,
+        totp::{self, CHALLENGE_TIMEOUT},
+        username::{USERNAME_HOLD, validate_username},
+    },
 };
 
 pub type AuthResult = Result<UserSession, AuthError>;
+pub type LoginResult = Result<LoginResponse, AuthError>;
+
+/// A hash of no user's password, verified against when logging in as a user that does not exist.
+///
+/// This makes logging in as an unknown user take as long as a wrong password for a known one.
+///
+/// Hashed with the first hasher used, the config does not change while running.
+static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+
+/// Get [`DUMMY_HASH`], hashing it with `hasher` if it has not been yet.
+///
+/// Called with the validated [`HashConfig`] when Rocket is built, so it is ready before the first login.
+///
+/// # Errors
+///
+/// Errors if it was not hashed yet, and could not be.
+pub(crate) fn dummy_hash(hasher: &Argon2) -> Result<&'static str, HashErrorKind> {
+    if let Some(hash) = DUMMY_HASH.get() {
+        return Ok(hash);
+    }
+    let hash = hash_with("not anyone's password", hasher)?;
+    Ok(DUMMY_HASH.get_or_init(|| hash))
+}
 
 #[instrument(skip_all)]
-#[post("/auth", data = "<request>")]
-pub async fn authenticate(
+#[post("/auth/login", data = "<request>")]
+pub async fn login(
     state: &State<Pool<Sqlite>>,
+    hash_config: &State<HashConfig>,
+    client_ip: Option<IpAddr>,
     request: Json<AuthRequest>,
+) -> Json<LoginResult> {
+    let response = try_login(state.to_db(), hash_config, &request, client_ip).await;
+
+    tracing::debug!(
+        "json response: {}",
+        json::to_pretty_string(&response).unwrap()
+    );
+    Json(response)
+}
+
+#[instrument(skip_all)]
+#[post("/auth/login/totp", data = "<request>")]
+pub async fn login_totp(
+    state: &State<Pool<Sqlite>>,
+    client_ip: Option<IpAddr>,
+    request: Json<TotpLoginRequest>,
 ) -> Json<AuthResult> {
-    use AuthError::{
-        DBError, EmptyUsername, HashError, InternalError, InvalidPassword, WrongPassword,
-    };
-    use data::public::{
-        HashErrorKind::ParseError, InvalidPasswordKind::TooFewChars,
-        InvalidPasswordKind::TooManyChars,
-    };
-    use pcupback::DBErrorKind::SelectError;
+    let session = try_login_totp(state.to_db(), &request, client_ip).await;
 
-    let db = state.to_db();
+    tracing::debug!(
+        "json response: {}",
+        json::to_pretty_string(&session).unwrap()
+    );
+    Json(session)
+}
+
+#[instrument(skip_all)]
+#[post("/auth/signup", data = "<request>")]
+pub async fn signup(
+    state: &State<Pool<Sqlite>>,
+    hash_config: &State<HashConfig>,
+    password_policy: &State<PasswordPolicy>,
+    request: Json<AuthRequest>,
+) -> Json<AuthResult> {
+    let session = try_signup(state.to_db(), hash_config, password_policy, &request).await;
+
+    tracing::debug!(
+        "json response: {}",
+        json::to_pretty_string(&session).unwrap()
+    );
+    Json(session)
+}
+
+/// Log in as the user requested, returning their session.
+///
+/// Users with totp enabled get a challenge instead, answered with [`try_login_totp`].
+///
+/// Unknown usernames and wrong passwords both fail with [`AuthError::InvalidCredentials`].
+///
+/// Failed attempts are counted for both the username and `client_ip`, see [`throttle`].
+///
+/// If the user's password was hashed weaker than `hash_config`, it is re-hashed. The requested device is
+/// registered once logged in, see [`devices::register`].
+pub(crate) async fn try_login(
+    repo: &(
+         impl UserRepository + SessionRepository + AttemptRepository + TotpRepository + DeviceRepository
+     ),
+    hash_config: &HashConfig,
+    request: &AuthRequest,
+    client_ip: Option<IpAddr>,
+) -> LoginResult {
+    use AuthError::{DBError, EmptyUsername, InvalidCredentials, TooManyAttempts};
 
     let req_username = request.username.trim();
 
     if req_username.is_empty() {
-        return Json(Err(EmptyUsername));
+        return Err(EmptyUsername);
     }
 
-    // check the database for a user with the same username requested.
-    // get it if it exists.
-    let existing_user: Result<DBUser, _> = DBUser::fetch_one(req_username, db).await;
-
-    let session: AuthResult = match existing_user {
-        // the user requested exists, lets check if the request password hash matches:
-        Ok(existing_user) => {
-            tracing::info!("got auth request for existing user {req_username}.");
-
-            let parse_existing_hash = PasswordHash::new(&existing_user.password_hash);
-            let parse_and_validate = parse_existing_hash
-                .map(|e_h| Argon2::default().verify_password(request.password.as_bytes(), &e_h));
-            tracing::debug!("hashed password");
-
-            match parse_and_validate {
-                // the stored password parsed successfully and the request password matched!
-                Ok(Ok(())) => {
-                    // lets now provide them a session id.
-                    tracing::info!("getting session from db for user {}", existing_user.id);
-
-                    let last_set: Result<DBUserSession, _> =
-                        sqlx::query_as("SELECT * FROM sessions WHERE user_id = ?")
-                            .bind(existing_user.id)
-                            .fetch_one(db)
-                            .await;
-
-                    validate_session(db, last_set, existing_user.id)
+    let now = Utc::now().timestamp();
+
+    let attempt_keys = throttle::attempt_keys(req_username, client_ip);
+    let attempts = throttle::fetch_attempts(repo, &attempt_keys)
+        .await
+        .map_err(|err| DBError(SelectError(err.to_string())))?;
+
+    // the longest lockout of the username and address wins.
+    if let Some(retry_after) = throttle::locked_out(&attempts, now) {
+        tracing::info!("login for {req_username} locked out for {retry_after}s");
+        return Err(TooManyAttempts { retry_after });
+    }
+
+    let verified = verify_login(repo, hash_config, req_username, &request.password).await;
+
+    match verified {
+        Ok(user) => {
+            match repo.fetch_totp(user.id).await {
+                Ok(stored) if stored.confirmed => {
+                    // the username's failures are only cleared once the second factor is verified too,
+                    // so knowing the password does not allow guessing codes unthrottled.
+                    let challenge = DBLoginChallenge::generate(user.id);
+                    repo.store_challenge(&challenge)
                         .await
-                        .map_err(|err| DBError(InsertError(err.to_string())))
-                }
-                // stored password was parsed, but didnt match.
-                Ok(Err(err)) => match err {
-                    password_hash::Error::Password => {
-                        tracing::info!("mismatched password");
-                        Err(WrongPassword)
-                    }
-                    err => {
-                        tracing::error!("got error {err:?} when validating password");
-                        Err(InternalError(err.to_string()))
-                    }
-                },
-                // failed to parse stored password.
-                Err(err) => {
-                    tracing::error!("got error {err:?} when parsing stored password");
-                    Err(HashError(ParseError(err.to_string())))
+                        .map_err(|err| DBError(InsertError(err.to_string())))?;
+                    tracing::info!("user {} needs a totp code", user.id);
+
+                    return Ok(LoginResponse::TotpRequired {
+                        challenge: challenge.id,
+                    });
                 }
+                Ok(_) | Err(sqlx::Error::RowNotFound) => {}
+                Err(err) => return Err(DBError(SelectError(err.to_string()))),
             }
+
+            // the username is free again, the address's failures still count.
+            repo.clear_attempts(&attempt_keys[0])
+                .await
+                .map_err(|err| DBError(InsertError(err.to_string())))?;
+
+            complete_login(repo, &user, request.device.as_ref())
+                .await
+                .map(LoginResponse::Session)
         }
-        // the requested user doesnt exist. lets try to create a new account:
-        Err(sqlx::Error::RowNotFound) => {
-            tracing::info!(
-                "no existing user {}, creating new account",
-                request.username
-            );
-
-            if request.password.len() < 8 {
-                tracing::info!("password chars < 8");
-                Err(InvalidPassword(TooFewChars))
-            } else if request.password.len() > 64 {
-                tracing::info!("password chars > 64");
-                Err(InvalidPassword(TooManyChars))
-            } else {
-                let transaction = db
-                    .begin()
-                    .await
-                    .map_err(|e| DBError(OtherError(e.to_string())));
+        Err(InvalidCredentials) => {
+            throttle::store_failure(repo, &attempt_keys, now)
+                .await
+                .map_err(|err| DBError(InsertError(err.to_string())))?;
+            Err(InvalidCredentials)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Answer a login challenge from [`try_login`] with a totp or recovery code, returning the user's session.
+///
+/// Wrong codes fail with [`AuthError::InvalidCredentials`], and count as failed logins for the user and `client_ip`.
+/// Recovery codes can only be used once.
+pub(crate) async fn try_login_totp(
+    repo: &(
+         impl UserRepository + SessionRepository + AttemptRepository + TotpRepository + DeviceRepository
+     ),
+    request: &TotpLoginRequest,
+    client_ip: Option<IpAddr>,
+) -> AuthResult {
+    use AuthError::{
+        DBError, InternalError, InvalidChallenge, InvalidCredentials, TooManyAttempts,
+    };
+
+    let now = Utc::now().timestamp();
 
-                let mut transaction = match transaction {
-                    Ok(t) => t,
-                    Err(err) => return Json(Err(err)),
-                };
+    let challenge = match repo.fetch_challenge(&request.challenge).await {
+        Ok(challenge) => challenge,
+        Err(sqlx::Error::RowNotFound) => return Err(InvalidChallenge),
+        Err(err) => return Err(DBError(SelectError(err.to_string()))),
+    };
+    if now - challenge.created_at > CHALLENGE_TIMEOUT {
+        tracing::info!("login challenge timed out");
+        repo.delete_challenge(&challenge.id)
+            .await
+            .map_err(|err| DBError(DeleteError(err.to_string())))?;
+        return Err(InvalidChallenge);
+    }
+
+    let user = repo
+        .fetch_user_by_id(challenge.user_id)
+        .await
+        .map_err(|err| DBError(SelectError(err.to_string())))?;
+
+    let attempt_keys = throttle::attempt_keys(&user.username, client_ip);
+    let attempts = throttle::fetch_attempts(repo, &attempt_keys)
+        .await
+        .map_err(|err| DBError(SelectError(err.to_string())))?;
+
+    if let Some(retry_after) = throttle::locked_out(&attempts, now) {
+        tracing::info!(
+            "totp login for user {} locked out for {retry_after}s",
+            user.id
+        );
+        return Err(TooManyAttempts { retry_after });
+    }
 
-                // get the last-inserted id in db, or 0 if not found.
-                let max_id = sqlx::query_as("SELECT MAX(id) FROM users")
-                    .fetch_one(&mut *transaction)
+    let mut stored = match repo.fetch_totp(user.id).await {
+        Ok(stored) if stored.confirmed => stored,
+        Ok(_) | Err(sqlx::Error::RowNotFound) => return Err(InvalidChallenge),
+        Err(err) => return Err(DBError(SelectError(err.to_string()))),
+    };
+
+    let code = request.code.trim();
+    let verified = if totp::is_totp_code(code) {
+        let totp = totp::totp(&stored, &user.username).map_err(InternalError)?;
+        match totp::verify_code(&totp, code, stored.last_step, now.unsigned_abs()) {
+            Some(step) => {
+                stored.last_step = step;
+                repo.store_totp(&stored)
                     .await
-                    // unwrap the tuple
-                    .map(|v: (u32,)| v.0);
-                let max_id = match max_id {
-                    Ok(id) => id,
-                    Err(err) => {
-                        if matches!(err, sqlx::Error::RowNotFound) {
-                            0
-                        } else {
-                            tracing::error!("got err {err:?} trying to get last user id");
-                            return Json(Err(DBError(OtherError(err.to_string()))));
-                        }
-                    }
-                };
-
-                // we add 1 to get the next id.
-                let new_user = DBUser::new(max_id + 1, req_username, &request.password);
-
-                let sesh = match new_user {
-                    Ok(new_user) => {
-                        // store the user in db
-
-                        if let Err(err) = new_user.store(&mut *transaction).await {
-                            tracing::error!("failed to store user: {err:?}");
-                            Err(DBError(InsertError(err.to_string())))
-                        } else {
-                            // create and store the session
-                            generate_store_session(&mut *transaction, new_user.id)
-                                .await
-                                .map_err(|err| DBError(InsertError(err.to_string())))
-                        }
-                    }
-                    Err(err) => {
-                        tracing::error!("got err {err} trying to create a new user");
-                        Err(HashError(err))
-                    }
-                };
-
-                if let Err(err) = transaction.commit().await {
-                    return Json(Err(AuthError::DBError(OtherError(err.to_string()))));
-                }
-                sesh
+                    .map_err(|err| DBError(InsertError(err.to_string())))?;
+                true
             }
+            None => false,
+        }
+    } else {
+        let used = repo
+            .use_recovery_code(user.id, &totp::hash_recovery_code(code))
+            .await
+            .map_err(|err| DBError(DeleteError(err.to_string())))?;
+        if used {
+            tracing::info!("user {} used a recovery code", user.id);
+        }
+        used
+    };
+
+    if !verified {
+        tracing::info!("wrong totp code for user {}", user.id);
+        throttle::store_failure(repo, &attempt_keys, now)
+            .await
+            .map_err(|err| DBError(InsertError(err.to_string())))?;
+        return Err(InvalidCredentials);
+    }
+
+    repo.delete_challenge(&challenge.id)
+        .await
+        .map_err(|err| DBError(DeleteError(err.to_string())))?;
+    repo.clear_attempts(&attempt_keys[0])
+        .await
+        .map_err(|err| DBError(InsertError(err.to_string())))?;
+
+    complete_login(repo, &user, request.device.as_ref()).await
+}
+
+/// Return `user`'s session, generating a new one if they have none or it timed out, and register `device`.
+///
+/// Restores the user if they asked to be deleted, see [`delete_account`](crate::routes::delete_account).
+async fn complete_login(
+    repo: &(impl UserRepository + SessionRepository + DeviceRepository),
+    user: &DBUser,
+    device: Option<&DeviceInfo>,
+) -> AuthResult {
+    if user.deletion_requested_at.is_some() {
+        repo.mark_for_deletion(user.id, None)
+            .await
+            .map_err(|err| AuthError::DBError(InsertError(err.to_string())))?;
+        tracing::info!("restored user {} marked for deletion", user.id);
+    }
+
+    // lets now provide them a session id.
+    tracing::info!("getting session from db for user {}", user.id);
+
+    let last_set = repo.fetch_user_session(user.id).await;
+
+    let mut session = validate_session(repo, last_set, user)
+        .await
+        .map_err(|err| AuthError::DBError(InsertError(err.to_string())))?;
+    if let Some(device) = device {
+        session.device_id = devices::register(repo, user.id, device).await;
+    }
+    Ok(session)
+}
+
+/// Return the user `username` if `password` is theirs.
+async fn verify_login(
+    repo: &impl UserRepository,
+    hash_config: &HashConfig,
+    username: &str,
+    password: &str,
+) -> Result<DBUser, AuthError> {
+    use AuthError::{DBError, HashError, InvalidCredentials};
+    use data::public::HashErrorKind::CreateError;
+
+    // check the database for a user with the same username requested.
+    let existing_user = match repo.fetch_user_by_name(username).await {
+        Ok(user) => user,
+        // the requested user doesnt exist.
+        Err(sqlx::Error::RowNotFound) => {
+            tracing::info!("got login request for unknown user {username}");
+
+            // still verify a password, so this takes as long as a wrong password would.
+            let hasher = hash_config
+                .hasher()
+                .map_err(|err| HashError(CreateError(err.to_string())))?;
+            let _ = verify_password(dummy_hash(&hasher)?, password);
+            return Err(InvalidCredentials);
         }
         // an error occurred while querying database
+        Err(err) => {
+            tracing::error!("got err {err:?} trying to query db for user {username}.");
+            return Err(DBError(SelectError(err.to_string())));
+        }
+    };
+
+    tracing::info!("got login request for existing user {username}.");
+
+    if verify_password(&existing_user.password_hash, password)? {
+        rehash_if_weaker(repo, hash_config, &existing_user, password).await;
+        Ok(existing_user)
+    } else {
+        tracing::info!("mismatched password");
+        Err(InvalidCredentials)
+    }
+}
+
+/// Replace `user`'s password hash if it is weaker than `hash_config`, see [`HashConfig::needs_rehash`].
+///
+/// `password` must already be verified. Failing to re-hash does not fail the login.
+async fn rehash_if_weaker(
+    repo: &impl UserRepository,
+    hash_config: &HashConfig,
+    user: &DBUser,
+    password: &str,
+) {
+    let needs_rehash = PasswordHash::new(&user.password_hash)
+        .is_ok_and(|stored_hash| hash_config.needs_rehash(&stored_hash));
+    if !needs_rehash {
+        return;
+    }
+
+    tracing::info!("re-hashing password of user {}", user.id);
+
+    let new_hash = match hash_password(password, hash_config) {
+        Ok(hash) => hash,
+        Err(err) => {
+            tracing::warn!("failed to re-hash password: {err:?}");
+            return;
+        }
+    };
+    if let Err(err) = repo.update_password_hash(user.id, &new_hash).await {
+        tracing::warn!("failed to store re-hashed password: {err:?}");
+    }
+}
+
+/// Create the user requested, returning their new session.
+///
+/// The username is normalized and must be valid, see [`validate_username`], and not recently changed away from by someone.
+/// The password must satisfy `password_policy`. The requested device is registered, as when logging in.
+pub(crate) async fn try_signup(
+    repo: &(
+         impl UserRepository
+         + SessionRepository
+         + DeviceRepository
+         + OutboxRepository
+         + WebhookRepository
+     ),
+    hash_config: &HashConfig,
+    password_policy: &PasswordPolicy,
+    request: &AuthRequest,
+) -> AuthResult {
+    use AuthError::{DBError, EmptyUsername, HashError, InvalidPassword, UsernameTaken};
+
+    if request.username.trim().is_empty() {
+        return Err(EmptyUsername);
+    }
+
+    let req_username = &validate_username(&request.username).inspect_err(|kind| {
+        tracing::info!("username was invalid: {kind}");
+    })?;
+
+    match repo.fetch_user_by_name(req_username).await {
+        Ok(_) => {
+            tracing::info!("user {req_username} already exists");
+            return Err(UsernameTaken);
+        }
+        Err(sqlx::Error::RowNotFound) => {
+            tracing::info!("no existing user {req_username}, creating new account");
+        }
         Err(err) => {
             tracing::error!("got err {err:?} trying to query db for user {req_username}.");
-            Err(DBError(SelectError(err.to_string())))
+            return Err(DBError(SelectError(err.to_string())));
+        }
+    }
+
+    let released_after = Utc::now().timestamp() - USERNAME_HOLD;
+    match repo
+        .fetch_username_holder(req_username, released_after)
+        .await
+    {
+        Ok(None) => {}
+        Ok(Some(_)) => {
+            tracing::info!("username {req_username} is held by its previous owner");
+            return Err(UsernameTaken);
+        }
+        Err(err) => return Err(DBError(SelectError(err.to_string()))),
+    }
+
+    if let Err(kind) = check_password(password_policy, req_username, &request.password) {
+        tracing::info!("password was invalid: {kind}");
+        return Err(InvalidPassword(kind));
+    }
+
+    let password_hash = hash_password(&request.password, hash_config).map_err(|err| {
+        tracing::error!("got err {err} trying to create a new user");
+        HashError(err)
+    })?;
+
+    // store the user in db, with their session.
+    let (new_user, session) = match repo.insert_user(req_username, &password_hash).await {
+        Ok(stored) => stored,
+        // someone else signed up with the same username since we checked.
+        Err(sqlx::Error::Database(err)) if err.is_unique_violation() => {
+            tracing::info!("user {req_username} already exists");
+            return Err(UsernameTaken);
+        }
+        Err(err) => {
+            tracing::error!("failed to store user: {err:?}");
+            return Err(DBError(InsertError(err.to_string())));
         }
     };
-    tracing::info!("created with: {:?}", session.as_ref().map(|a| a.user_id));
+    tracing::info!("created user {}", new_user.public_id);
+    notify::emit_logged(
+        repo,
+        new_user.id,
+        &Event::AccountCreated,
+        Utc::now().timestamp(),
+    )
+    .await;
 
-    tracing::debug!(
-        "json response: {}",
-        json::to_pretty_string(&session).unwrap()
-    );
-    Json(session)
+    let mut session = UserSession::new(&new_user, session);
+    if let Some(device) = &request.device {
+        session.device_id = devices::register(repo, new_user.id, device).await;
+    }
+    Ok(session)
+}
+
+/// Sign up a random user with the default config, returning them and their session.
+#[cfg(test)]
+pub(crate) async fn signed_up(
+    repo: &(
+         impl UserRepository
+         + SessionRepository
+         + DeviceRepository
+         + OutboxRepository
+         + WebhookRepository
+     ),
+) -> (DBUser, UserSession) {
+    signed_up_as(repo, &AuthRequest::random_valid()).await
+}
+
+/// Sign up with `request` and the default config, returning the user and their session.
+#[cfg(test)]
+pub(crate) async fn signed_up_as(
+    repo: &(
+         impl UserRepository
+         + SessionRepository
+         + DeviceRepository
+         + OutboxRepository
+         + WebhookRepository
+     ),
+    request: &AuthRequest,
+) -> (DBUser, UserSession) {
+    let session = try_signup(
+        repo,
+        &HashConfig::default(),
+        &PasswordPolicy::default(),
+        request,
+    )
+    .await
+    .unwrap();
+    let user_id = repo.fetch_session(&session.id).await.unwrap().user_id;
+    (repo.fetch_user_by_id(user_id).await.unwrap(), session)
+}
+
+/// Return whether `password` matches the stored `password_hash`.
+///
+/// # Errors
+///
+/// Errors if `password_hash` could not be parsed, or verifying failed for any reason other than a mismatch.
+pub(crate) fn verify_password(password_hash: &str, password: &str) -> Result<bool, AuthError> {
+    use AuthError::{HashError, InternalError};
+    use data::public::HashErrorKind::ParseError;
+
+    let parsed_hash = PasswordHash::new(password_hash).map_err(|err| {
+        tracing::error!("got error {err:?} when parsing stored password");
+        HashError(ParseError(err.to_string()))
+    })?;
+
+    match Argon2::default().verify_password(password.as_bytes(), &parsed_hash) {
+        Ok(()) => Ok(true),
+        Err(password_hash::Error::Password) => Ok(false),
+        Err(err) => {
+            tracing::error!("got error {err:?} when validating password");
+            Err(InternalError(err.to_string()))
+        }
+    }
 }
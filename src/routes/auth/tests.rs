@@ -5,9 +5,11 @@ use rocket::{
 };
 use uuid::Uuid;
 
+use crate::config::{HashConfig, PasswordPolicy};
+
 use super::{
-    AuthResult,
-    data::public::{AuthError, AuthRequest},
+    AuthResult, LoginResult,
+    data::public::{AuthError, AuthRequest, LoginResponse},
 };
 
 #[macros::rocket_test]
@@ -17,12 +19,13 @@ fn not_enough_chars() {
     let client = Client::tracked(crate::test_rocket("not_enough_chars")).unwrap();
 
     let req = AuthRequest {
-        username: Uuid::new_v4().to_string(),
+        username: AuthRequest::random_username(),
         password: "123".to_string(),
+        device: None,
     };
 
     let resp = client
-        .post("/auth")
+        .post("/auth/signup")
         .header(ContentType::JSON)
         .body(json::to_string(&req).unwrap())
         .dispatch();
@@ -42,11 +45,12 @@ fn too_many_chars() {
     let client = Client::tracked(crate::test_rocket("too_many_chars")).unwrap();
 
     let req = AuthRequest {
-        username: Uuid::new_v4().to_string(),
+        username: AuthRequest::random_username(),
         password: "1".repeat(65),
+        device: None,
     };
 
-    let resp = client.post("/auth").json(&req).dispatch();
+    let resp = client.post("/auth/signup").json(&req).dispatch();
 
     assert_eq!(resp.status(), Status::Ok);
     let resp_json: AuthResult = resp.into_json().unwrap();
@@ -63,7 +67,7 @@ fn login() {
     let req = AuthRequest::random_valid();
 
     let resp1 = client
-        .post("/auth")
+        .post("/auth/signup")
         .json(&req)
         .dispatch()
         .into_json::<AuthResult>()
@@ -72,14 +76,303 @@ fn login() {
     let session1 = resp1.unwrap();
 
     let resp2 = client
-        .post("/auth")
+        .post("/auth/login")
         .header(ContentType::JSON)
         .json(&req)
         .dispatch()
-        .into_json::<AuthResult>()
+        .into_json::<LoginResult>()
         .unwrap();
 
     let session2 = resp2.unwrap();
 
-    assert_eq!(session1, session2);
+    assert_eq!(LoginResponse::Session(session1), session2);
+}
+
+#[rocket::async_test]
+async fn login_in_memory() {
+    use crate::repo::memory::MemoryRepository;
+
+    let repo = MemoryRepository::default();
+    let req = AuthRequest::random_valid();
+
+    let created = super::try_signup(
+        &repo,
+        &HashConfig::default(),
+        &PasswordPolicy::default(),
+        &req,
+    )
+    .await
+    .unwrap();
+    let logged_in = super::try_login(&repo, &HashConfig::default(), &req, None)
+        .await
+        .unwrap();
+    // the public id is not the internal numeric id.
+    assert!(Uuid::parse_str(&created.user_id).is_ok());
+    assert_eq!(LoginResponse::Session(created), logged_in);
+
+    assert!(matches!(
+        super::try_signup(
+            &repo,
+            &HashConfig::default(),
+            &PasswordPolicy::default(),
+            &req
+        )
+        .await
+        .unwrap_err(),
+        AuthError::UsernameTaken
+    ));
+}
+
+#[rocket::async_test]
+async fn invalid_credentials() {
+    use crate::repo::memory::MemoryRepository;
+
+    let repo = MemoryRepository::default();
+    let req = AuthRequest::random_valid();
+    super::try_signup(
+        &repo,
+        &HashConfig::default(),
+        &PasswordPolicy::default(),
+        &req,
+    )
+    .await
+    .unwrap();
+
+    let wrong_password = AuthRequest {
+        username: req.username,
+        password: "87654321".to_string(),
+        device: None,
+    };
+    let unknown_user = AuthRequest::random_valid();
+
+    // both fail the same way, so usernames cannot be enumerated.
+    assert!(matches!(
+        super::try_login(&repo, &HashConfig::default(), &wrong_password, None)
+            .await
+            .unwrap_err(),
+        AuthError::InvalidCredentials
+    ));
+    assert!(matches!(
+        super::try_login(&repo, &HashConfig::default(), &unknown_user, None)
+            .await
+            .unwrap_err(),
+        AuthError::InvalidCredentials
+    ));
+
+    // an invalid config fails the request, it does not panic.
+    let invalid = HashConfig {
+        memory_cost: 0,
+        ..HashConfig::default()
+    };
+    assert!(matches!(
+        super::try_login(&repo, &invalid, &unknown_user, None)
+            .await
+            .unwrap_err(),
+        AuthError::HashError(_)
+    ));
+}
+
+#[test]
+fn concurrent_signups() {
+    use std::collections::HashSet;
+
+    use rocket::{futures::future::join_all, local::asynchronous::Client, tokio::runtime};
+
+    const SIGNUPS: usize = 16;
+
+    let rocket = crate::test_rocket("concurrent_signups");
+
+    let runtime = runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let user_ids: HashSet<String> = runtime.block_on(async {
+        let client = Client::tracked(rocket).await.unwrap();
+
+        let requests: Vec<AuthRequest> =
+            (0..SIGNUPS).map(|_| AuthRequest::random_valid()).collect();
+        let signups = requests.iter().map(|req| async {
+            client
+                .post("/auth/signup")
+                .json(req)
+                .dispatch()
+                .await
+                .into_json::<AuthResult>()
+                .await
+                .unwrap()
+        });
+
+        join_all(signups)
+            .await
+            .into_iter()
+            // every signup succeeded..
+            .map(|session| session.unwrap().user_id)
+            .collect()
+    });
+
+    // ..with its own id.
+    assert_eq!(user_ids.len(), SIGNUPS);
+}
+
+#[rocket::async_test]
+async fn too_many_attempts() {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use crate::{repo::memory::MemoryRepository, util::throttle::FREE_ATTEMPTS};
+
+    let repo = MemoryRepository::default();
+    let req = AuthRequest::random_valid();
+    super::try_signup(
+        &repo,
+        &HashConfig::default(),
+        &PasswordPolicy::default(),
+        &req,
+    )
+    .await
+    .unwrap();
+
+    let wrong_password = AuthRequest {
+        username: req.username.clone(),
+        password: "87654321".to_string(),
+        device: None,
+    };
+    let ip = Some(IpAddr::V4(Ipv4Addr::LOCALHOST));
+
+    for _ in 0..FREE_ATTEMPTS {
+        assert!(matches!(
+            super::try_login(&repo, &HashConfig::default(), &wrong_password, ip)
+                .await
+                .unwrap_err(),
+            AuthError::InvalidCredentials
+        ));
+    }
+
+    // locked out, even with the right password..
+    assert!(matches!(
+        super::try_login(&repo, &HashConfig::default(), &req, None).await.unwrap_err(),
+        AuthError::TooManyAttempts { retry_after } if retry_after > 0
+    ));
+    // ..and for other usernames from the same address.
+    assert!(matches!(
+        super::try_login(
+            &repo,
+            &HashConfig::default(),
+            &AuthRequest::random_valid(),
+            ip
+        )
+        .await
+        .unwrap_err(),
+        AuthError::TooManyAttempts { .. }
+    ));
+}
+
+#[rocket::async_test]
+async fn rehash_on_login() {
+    use argon2::PasswordHash;
+
+    use crate::repo::{UserRepository, memory::MemoryRepository};
+
+    let repo = MemoryRepository::default();
+    let req = AuthRequest::random_valid();
+
+    let weak = HashConfig {
+        time_cost: 1,
+        ..HashConfig::default()
+    };
+    let strong = HashConfig::default();
+
+    super::try_signup(&repo, &weak, &PasswordPolicy::default(), &req)
+        .await
+        .unwrap();
+    let stored_hash = repo
+        .fetch_user_by_name(&req.username)
+        .await
+        .unwrap()
+        .password_hash;
+    assert!(strong.needs_rehash(&PasswordHash::new(&stored_hash).unwrap()));
+
+    // logging in with the stronger config re-hashes..
+    super::try_login(&repo, &strong, &req, None).await.unwrap();
+    let rehashed = repo
+        .fetch_user_by_name(&req.username)
+        .await
+        .unwrap()
+        .password_hash;
+    assert!(!strong.needs_rehash(&PasswordHash::new(&rehashed).unwrap()));
+
+    // ..and the password still works.
+    super::try_login(&repo, &strong, &req, None).await.unwrap();
+}
+
+#[macros::rocket_test]
+fn common_password() {
+    use super::data::public::InvalidPasswordKind::Common;
+
+    let req = AuthRequest {
+        username: AuthRequest::random_username(),
+        password: "password123".to_string(),
+        device: None,
+    };
+
+    let resp = client.post("/auth/signup").json(&req).dispatch();
+
+    let resp_json: AuthResult = resp.into_json().unwrap();
+    assert!(matches!(
+        resp_json.unwrap_err(),
+        AuthError::InvalidPassword(Common)
+    ));
+}
+
+#[rocket::async_test]
+async fn usernames_case_insensitive() {
+    use crate::repo::memory::MemoryRepository;
+
+    let repo = MemoryRepository::default();
+    let hash_config = HashConfig::default();
+    let policy = PasswordPolicy::default();
+
+    let req = AuthRequest::random_valid();
+    let created = super::try_signup(&repo, &hash_config, &policy, &req)
+        .await
+        .unwrap();
+
+    let shouting = AuthRequest {
+        username: format!("  {}  ", req.username.to_uppercase()),
+        password: req.password.clone(),
+        device: None,
+    };
+    assert!(matches!(
+        super::try_signup(&repo, &hash_config, &policy, &shouting)
+            .await
+            .unwrap_err(),
+        AuthError::UsernameTaken
+    ));
+
+    // the same user logs in with either.
+    let logged_in = super::try_login(&repo, &hash_config, &shouting, None)
+        .await
+        .unwrap();
+    assert_eq!(LoginResponse::Session(created), logged_in);
+}
+
+#[macros::rocket_test]
+fn invalid_username() {
+    use super::data::public::InvalidUsernameKind::InvalidChar;
+
+    let req = AuthRequest {
+        username: "zero\u{200B}width".to_string(),
+        password: "correct horse battery staple".to_string(),
+        device: None,
+    };
+
+    let resp_json: AuthResult = client
+        .post("/auth/signup")
+        .json(&req)
+        .dispatch()
+        .into_json()
+        .unwrap();
+    assert!(matches!(
+        resp_json.unwrap_err(),
+        AuthError::InvalidUsername(InvalidChar('\u{200B}'))
+    ));
 }
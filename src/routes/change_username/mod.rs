@@ -0,0 +1,116 @@
+#[cfg(test)]
+mod tests;
+
+use std::net::IpAddr;
+
+use chrono::Utc;
+use pcupback::DBErrorKind::{InsertError, SelectError};
+use rocket::{State, put, serde::json::Json};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite};
+use tracing::instrument;
+
+use crate::{
+    repo::{AttemptRepository, SessionRepository, UserRepository},
+    util::{
+        db::PoolStateExt,
+        throttle,
+        username::{USERNAME_HOLD, username_key, validate_username},
+    },
+};
+
+use super::auth::{data::public::AuthError, verify_password};
+
+/// A request to change the username of a session's owner.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChangeUsernameRequest {
+    pub new_username: String,
+    /// The user's current password, re-entered.
+    pub password: String,
+}
+
+/// The normalized new username if ok.
+pub type ChangeUsernameResult = Result<String, AuthError>;
+
+#[instrument(skip_all)]
+#[put("/auth/change_username/<session_id>", data = "<request>")]
+pub async fn change_username(
+    state: &State<Pool<Sqlite>>,
+    session_id: &str,
+    client_ip: Option<IpAddr>,
+    request: Json<ChangeUsernameRequest>,
+) -> Json<ChangeUsernameResult> {
+    Json(change(state.to_db(), session_id, &request, client_ip).await)
+}
+
+/// Change the username of the owner of `session_id`, if the request's password is theirs.
+///
+/// The new username must be valid, and not taken or held, see [`USERNAME_HOLD`].
+/// The old one is held for the user, who can change back to it.
+///
+/// Wrong passwords count as failed logins for the user and `client_ip`, see [`throttle`].
+pub(crate) async fn change(
+    repo: &(impl SessionRepository + UserRepository + AttemptRepository),
+    session_id: &str,
+    request: &ChangeUsernameRequest,
+    client_ip: Option<IpAddr>,
+) -> ChangeUsernameResult {
+    use AuthError::{DBError, InvalidCredentials, InvalidSession, TooManyAttempts, UsernameTaken};
+
+    let Ok(session) = repo.fetch_session(session_id).await else {
+        tracing::info!("session was invalid");
+        return Err(InvalidSession);
+    };
+    let user = repo
+        .fetch_user_by_id(session.user_id)
+        .await
+        .map_err(|err| DBError(SelectError(err.to_string())))?;
+
+    let now = Utc::now().timestamp();
+
+    let attempt_keys = throttle::attempt_keys(&user.username, client_ip);
+    let attempts = throttle::fetch_attempts(repo, &attempt_keys)
+        .await
+        .map_err(|err| DBError(SelectError(err.to_string())))?;
+    if let Some(retry_after) = throttle::locked_out(&attempts, now) {
+        return Err(TooManyAttempts { retry_after });
+    }
+
+    if !verify_password(&user.password_hash, &request.password)? {
+        tracing::info!("mismatched password changing username of user {}", user.id);
+        throttle::store_failure(repo, &attempt_keys, now)
+            .await
+            .map_err(|err| DBError(InsertError(err.to_string())))?;
+        return Err(InvalidCredentials);
+    }
+
+    let new_username = validate_username(&request.new_username)?;
+
+    // changing only the case keeps the same key, so it cannot be taken.
+    if username_key(&new_username) != user.username_key {
+        match repo.fetch_user_by_name(&new_username).await {
+            Ok(_) => return Err(UsernameTaken),
+            Err(sqlx::Error::RowNotFound) => {}
+            Err(err) => return Err(DBError(SelectError(err.to_string()))),
+        }
+
+        let holder = repo
+            .fetch_username_holder(&new_username, now - USERNAME_HOLD)
+            .await
+            .map_err(|err| DBError(SelectError(err.to_string())))?;
+        if holder.is_some_and(|holder| holder != user.id) {
+            tracing::info!("username {new_username} is held by its previous owner");
+            return Err(UsernameTaken);
+        }
+    }
+
+    match repo.rename_user(user.id, &new_username, now).await {
+        Ok(()) => {}
+        // someone else took it since we checked.
+        Err(sqlx::Error::Database(err)) if err.is_unique_violation() => return Err(UsernameTaken),
+        Err(err) => return Err(DBError(InsertError(err.to_string()))),
+    }
+    tracing::info!("user {} changed their username", user.id);
+
+    Ok(new_username)
+}
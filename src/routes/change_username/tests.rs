@@ -0,0 +1,154 @@
+use crate::{
+    config::{HashConfig, PasswordPolicy},
+    repo::memory::MemoryRepository,
+    routes::auth::{
+        AuthResult, LoginResult,
+        data::public::{AuthError, AuthRequest, LoginResponse},
+        try_login, try_signup,
+    },
+};
+
+use super::{ChangeUsernameRequest, ChangeUsernameResult};
+
+#[rocket::async_test]
+async fn old_username_held() {
+    let repo = MemoryRepository::default();
+    let hash_config = HashConfig::default();
+    let policy = PasswordPolicy::default();
+
+    let req = AuthRequest::random_valid();
+    let session = try_signup(&repo, &hash_config, &policy, &req)
+        .await
+        .unwrap();
+
+    let new_username = AuthRequest::random_username();
+    let wrong_password = ChangeUsernameRequest {
+        new_username: new_username.clone(),
+        password: "not the password".to_string(),
+    };
+    assert!(matches!(
+        super::change(&repo, &session.id, &wrong_password, None)
+            .await
+            .unwrap_err(),
+        AuthError::InvalidCredentials
+    ));
+
+    let rename = ChangeUsernameRequest {
+        new_username: new_username.clone(),
+        password: req.password.clone(),
+    };
+    super::change(&repo, &session.id, &rename, None)
+        .await
+        .unwrap();
+
+    // the new username logs in..
+    let renamed = AuthRequest {
+        username: new_username,
+        password: req.password.clone(),
+        device: None,
+    };
+    assert_eq!(
+        try_login(&repo, &hash_config, &renamed, None)
+            .await
+            .unwrap(),
+        LoginResponse::Session(session)
+    );
+    // ..and nobody can take the old one.
+    assert!(matches!(
+        try_signup(&repo, &hash_config, &policy, &req)
+            .await
+            .unwrap_err(),
+        AuthError::UsernameTaken
+    ));
+
+    // except its previous owner.
+    let session = match try_login(&repo, &hash_config, &renamed, None)
+        .await
+        .unwrap()
+    {
+        LoginResponse::Session(session) => session,
+        response => panic!("expected a session, got {response:?}"),
+    };
+    let change_back = ChangeUsernameRequest {
+        new_username: req.username.clone(),
+        password: req.password.clone(),
+    };
+    super::change(&repo, &session.id, &change_back, None)
+        .await
+        .unwrap();
+}
+
+#[rocket::async_test]
+async fn username_taken() {
+    let repo = MemoryRepository::default();
+    let hash_config = HashConfig::default();
+    let policy = PasswordPolicy::default();
+
+    let req = AuthRequest::random_valid();
+    let session = try_signup(&repo, &hash_config, &policy, &req)
+        .await
+        .unwrap();
+    let other = AuthRequest::random_valid();
+    try_signup(&repo, &hash_config, &policy, &other)
+        .await
+        .unwrap();
+
+    let rename = ChangeUsernameRequest {
+        new_username: other.username.to_uppercase(),
+        password: req.password.clone(),
+    };
+    assert!(matches!(
+        super::change(&repo, &session.id, &rename, None)
+            .await
+            .unwrap_err(),
+        AuthError::UsernameTaken
+    ));
+
+    // changing the case of your own is fine.
+    let recase = ChangeUsernameRequest {
+        new_username: req.username.to_uppercase(),
+        password: req.password.clone(),
+    };
+    assert_eq!(
+        super::change(&repo, &session.id, &recase, None)
+            .await
+            .unwrap(),
+        req.username.to_uppercase()
+    );
+}
+
+#[macros::rocket_test]
+fn change_username() {
+    let req = AuthRequest::random_valid();
+    let session = client
+        .post("/auth/signup")
+        .json(&req)
+        .dispatch()
+        .into_json::<AuthResult>()
+        .unwrap()
+        .unwrap();
+
+    let rename = ChangeUsernameRequest {
+        new_username: AuthRequest::random_username(),
+        password: req.password.clone(),
+    };
+    let new_username = client
+        .put(format!("/auth/change_username/{}", session.id))
+        .json(&rename)
+        .dispatch()
+        .into_json::<ChangeUsernameResult>()
+        .unwrap()
+        .unwrap();
+
+    let logged_in = client
+        .post("/auth/login")
+        .json(&AuthRequest {
+            username: new_username,
+            password: req.password,
+            device: None,
+        })
+        .dispatch()
+        .into_json::<LoginResult>()
+        .unwrap();
+    assert_eq!(logged_in.unwrap(), LoginResponse::Session(session));
+}
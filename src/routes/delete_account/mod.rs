@@ -1,59 +1,158 @@
 #[cfg(test)]
 mod tests;
 
-use pcupback::{DBErrorKind, Fetchable};
+use std::net::IpAddr;
+
+use chrono::Utc;
+use pcupback::DBErrorKind;
 use rocket::{State, put, serde::json::Json};
 use serde::{Deserialize, Serialize};
 use sqlx::{Pool, Sqlite};
 use thiserror::Error;
 use tracing::instrument;
 
-use crate::{routes::auth::data::private::DBUserSession, util::db::PoolStateExt};
+use crate::{
+    config::DeletionConfig,
+    repo::{AttemptRepository, SessionRepository, UserRepository},
+    util::{db::PoolStateExt, throttle},
+};
+
+use super::auth::verify_password;
+
+/// A request to delete a session's owner.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteAccountRequest {
+    /// The user's password, re-entered.
+    pub password: String,
+}
+
+/// When an account asked to be deleted will be purged.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DeletionScheduled {
+    /// Seconds since the unix epoch. Logging in before then restores the account.
+    pub purge_at: i64,
+}
 
 #[derive(Debug, Error, Deserialize, Serialize)]
-enum DeleteAccountError {
+pub enum DeleteAccountError {
     #[error("InvalidSession")]
     InvalidSession,
+    #[error("InvalidCredentials")]
+    InvalidCredentials,
+    /// Too many wrong passwords for the user or from the client's address.
+    #[error("TooManyAttempts")]
+    TooManyAttempts {
+        /// Seconds until another try is allowed.
+        retry_after: i64,
+    },
+    #[error("InternalError")]
+    InternalError(String),
     #[error("DBError")]
     DBError(DBErrorKind),
 }
 
-type DeleteAccountResult = Result<(), DeleteAccountError>;
+type DeleteAccountResult = Result<DeletionScheduled, DeleteAccountError>;
 
 #[instrument(skip_all)]
-#[put("/auth/delete_account/<session_id>")]
+#[put("/auth/delete_account/<session_id>", data = "<request>")]
 pub async fn delete_account(
     state: &State<Pool<Sqlite>>,
+    deletion_config: &State<DeletionConfig>,
     session_id: &str,
+    client_ip: Option<IpAddr>,
+    request: Json<DeleteAccountRequest>,
 ) -> Json<DeleteAccountResult> {
-    use self::DeleteAccountError::{DBError, InvalidSession};
-    use DBErrorKind::DeleteError;
+    Json(
+        delete(
+            state.to_db(),
+            deletion_config,
+            session_id,
+            &request,
+            client_ip,
+        )
+        .await,
+    )
+}
 
-    let db = state.to_db();
+/// Mark the owner of `session_id` for deletion, if the request's password is theirs.
+///
+/// They are logged out, and purged once `deletion_config`'s grace period passes, see [`purge`].
+/// Logging in before then restores the account.
+///
+/// Wrong passwords count as failed logins for the user and `client_ip`, see [`throttle`].
+pub(crate) async fn delete(
+    repo: &(impl SessionRepository + UserRepository + AttemptRepository),
+    deletion_config: &DeletionConfig,
+    session_id: &str,
+    request: &DeleteAccountRequest,
+    client_ip: Option<IpAddr>,
+) -> DeleteAccountResult {
+    use self::DeleteAccountError::{
+        DBError, InternalError, InvalidCredentials, InvalidSession, TooManyAttempts,
+    };
+    use DBErrorKind::{InsertError, SelectError};
 
-    let session = DBUserSession::fetch_one(session_id, db).await;
+    let session = repo.fetch_session(session_id).await;
 
     let Ok(session) = session else {
         // no such session.
         tracing::info!("session was invalid");
-        return Json(Err(InvalidSession));
+        return Err(InvalidSession);
     };
 
-    let user_id = session.user_id;
+    let user = repo
+        .fetch_user_by_id(session.user_id)
+        .await
+        .map_err(|err| DBError(SelectError(err.to_string())))?;
 
-    let delete_op = sqlx::query!("DELETE FROM users WHERE id = ?", user_id)
-        .execute(db)
+    let now = Utc::now().timestamp();
+
+    let attempt_keys = throttle::attempt_keys(&user.username, client_ip);
+    let attempts = throttle::fetch_attempts(repo, &attempt_keys)
         .await
-        .map_err(|err| {
-            tracing::error!("got err {err:?} trying to delete user {user_id}");
-            DBError(DeleteError(err.to_string()))
-        });
+        .map_err(|err| DBError(SelectError(err.to_string())))?;
+    if let Some(retry_after) = throttle::locked_out(&attempts, now) {
+        return Err(TooManyAttempts { retry_after });
+    }
 
-    if let Err(err) = delete_op {
-        return Json(Err(err));
+    let verified = verify_password(&user.password_hash, &request.password)
+        .map_err(|err| InternalError(err.to_string()))?;
+    if !verified {
+        tracing::info!("mismatched password deleting user {}", user.id);
+        throttle::store_failure(repo, &attempt_keys, now)
+            .await
+            .map_err(|err| DBError(InsertError(err.to_string())))?;
+        return Err(InvalidCredentials);
     }
 
-    tracing::info!("successfully deleted user {user_id}");
+    repo.mark_for_deletion(user.id, Some(now))
+        .await
+        .map_err(|err| {
+            tracing::error!(
+                "got err {err:?} trying to mark user {} for deletion",
+                user.id
+            );
+            DBError(InsertError(err.to_string()))
+        })?;
 
-    Json(Ok(()))
+    tracing::info!("marked user {} for deletion", user.id);
+
+    Ok(DeletionScheduled {
+        purge_at: now + deletion_config.grace_period,
+    })
+}
+
+/// Delete every user whose deletion grace period passed at `now`.
+///
+/// Returns how many were deleted.
+pub(crate) async fn purge(
+    repo: &impl UserRepository,
+    deletion_config: &DeletionConfig,
+    now: i64,
+) -> Result<u64, sqlx::Error> {
+    let purged = repo.purge_users(now - deletion_config.grace_period).await?;
+    if purged > 0 {
+        tracing::info!("purged {purged} deleted users");
+    }
+    Ok(purged)
 }
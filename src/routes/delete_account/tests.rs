@@ -1,22 +1,28 @@
+use chrono::Utc;
 use rocket::{http::ContentType, serde::json};
 
-use crate::routes::{
-    auth::{AuthResult, data::public::AuthRequest},
-    sync::SyncResult,
+use crate::{
+    config::{DeletionConfig, HashConfig},
+    repo::{SessionRepository, UserRepository, memory::MemoryRepository},
+    routes::{
+        auth::{
+            AuthResult, LoginResult,
+            data::public::{AuthRequest, LoginResponse},
+            signed_up_as, try_login,
+        },
+        sync::SyncResult,
+    },
 };
 
-use super::DeleteAccountResult;
+use super::{DeleteAccountError, DeleteAccountRequest, DeleteAccountResult};
 
 #[macros::rocket_test]
 fn create_and_delete() {
-    let user = AuthRequest {
-        username: "xddddd".to_string(),
-        password: "12345678".to_string(),
-    };
+    let user = AuthRequest::random_valid();
 
     // create the user
     let create = client
-        .post("/auth")
+        .post("/auth/signup")
         .header(ContentType::JSON)
         .body(json::to_string(&user).unwrap())
         .dispatch()
@@ -28,12 +34,16 @@ fn create_and_delete() {
     // delete that user
     let delete = client
         .put(format!("/auth/delete_account/{session_id}"))
+        .json(&DeleteAccountRequest {
+            password: user.password.clone(),
+        })
         .dispatch()
         .into_json::<DeleteAccountResult>()
         .unwrap();
-    delete.unwrap();
+    let scheduled = delete.unwrap();
+    assert!(scheduled.purge_at > Utc::now().timestamp());
 
-    // verify it's deleted
+    // verify it's logged out
     let verify = client
         .post(format!("/sync/{session_id}"))
         .header(ContentType::JSON)
@@ -41,6 +51,93 @@ fn create_and_delete() {
         .dispatch()
         .into_json::<SyncResult>()
         .unwrap();
-    // should fail since user doesnt exist anymore, so session doesnt exist anymore.
+    // should fail since the user's session was deleted.
     verify.unwrap_err();
+
+    // logging in restores the user.
+    let login = client
+        .post("/auth/login")
+        .json(&user)
+        .dispatch()
+        .into_json::<LoginResult>()
+        .unwrap();
+    let LoginResponse::Session(session) = login.unwrap() else {
+        panic!("expected a session");
+    };
+    client
+        .post(format!("/sync/{}", session.id))
+        .header(ContentType::JSON)
+        .body("null")
+        .dispatch()
+        .into_json::<SyncResult>()
+        .unwrap()
+        .unwrap();
+}
+
+#[rocket::async_test]
+async fn create_and_delete_in_memory() {
+    let repo = MemoryRepository::default();
+    let config = DeletionConfig::default();
+    let req = AuthRequest::random_valid();
+    let (_, session) = signed_up_as(&repo, &req).await;
+
+    let wrong_password = DeleteAccountRequest {
+        password: "not the password".to_string(),
+    };
+    assert!(matches!(
+        super::delete(&repo, &config, &session.id, &wrong_password, None)
+            .await
+            .unwrap_err(),
+        DeleteAccountError::InvalidCredentials
+    ));
+
+    let request = DeleteAccountRequest {
+        password: req.password.clone(),
+    };
+    let scheduled = super::delete(&repo, &config, &session.id, &request, None)
+        .await
+        .unwrap();
+
+    // the session was deleted, the user is kept until the grace period passes.
+    assert!(repo.fetch_session(&session.id).await.is_err());
+    let now = Utc::now().timestamp();
+    assert_eq!(super::purge(&repo, &config, now).await.unwrap(), 0);
+    repo.fetch_user_by_name(&req.username).await.unwrap();
+
+    assert_eq!(
+        super::purge(&repo, &config, scheduled.purge_at)
+            .await
+            .unwrap(),
+        1
+    );
+    assert!(repo.fetch_user_by_name(&req.username).await.is_err());
+}
+
+#[rocket::async_test]
+async fn login_restores() {
+    let repo = MemoryRepository::default();
+    let config = DeletionConfig::default();
+    let req = AuthRequest::random_valid();
+    let (_, session) = signed_up_as(&repo, &req).await;
+
+    let request = DeleteAccountRequest {
+        password: req.password.clone(),
+    };
+    let scheduled = super::delete(&repo, &config, &session.id, &request, None)
+        .await
+        .unwrap();
+
+    try_login(&repo, &HashConfig::default(), &req, None)
+        .await
+        .unwrap();
+
+    // restored users are not purged.
+    assert_eq!(
+        super::purge(&repo, &config, scheduled.purge_at)
+            .await
+            .unwrap(),
+        0
+    );
+    let user = repo.fetch_user_by_name(&req.username).await.unwrap();
+    assert_eq!(user.deletion_requested_at, None);
 }
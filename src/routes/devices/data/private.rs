@@ -0,0 +1,28 @@
+use pcupback::FetchableAll;
+use sqlx::{Executor, FromRow, Sqlite};
+
+/// A device, see the `devices` table.
+#[derive(Debug, FromRow, PartialEq, Eq, Clone)]
+pub struct DBDevice {
+    pub id: u32,
+    pub user_id: u32,
+    pub name: String,
+    pub platform: String,
+    /// Stored as seconds since the unix epoch.
+    pub last_seen: i64,
+}
+
+impl<'a> FetchableAll<'a, u32> for DBDevice {
+    type DB = Sqlite;
+
+    /// user id filter
+    async fn fetch_all<E>(filter: u32, executor: E) -> Result<Vec<Self>, sqlx::Error>
+    where
+        E: Executor<'a, Database = Self::DB>,
+    {
+        sqlx::query_as("SELECT * FROM devices WHERE user_id = ? ORDER BY name")
+            .bind(filter)
+            .fetch_all(executor)
+            .await
+    }
+}
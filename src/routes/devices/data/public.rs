@@ -0,0 +1,51 @@
+use pcupback::DBErrorKind;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::private::DBDevice;
+
+/// The device a client runs on, sent when logging in.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct DeviceInfo {
+    /// Unique among the user's devices, logging in with the same name is the same device.
+    pub name: String,
+    /// e.g. `windows` or `android`.
+    pub platform: String,
+}
+
+/// A device, as listed to its owner.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct Device {
+    /// The id usage is synced from the device with.
+    pub id: u32,
+    pub name: String,
+    pub platform: String,
+    /// The last login or sync. Seconds since the unix epoch.
+    pub last_seen: i64,
+}
+
+impl From<DBDevice> for Device {
+    fn from(value: DBDevice) -> Self {
+        Self {
+            id: value.id,
+            name: value.name,
+            platform: value.platform,
+            last_seen: value.last_seen,
+        }
+    }
+}
+
+#[derive(Error, Debug, Serialize, Deserialize)]
+pub enum DeviceError {
+    /// No such session or api token.
+    #[error("InvalidSession")]
+    InvalidSession,
+    /// The api token's scope does not allow the request.
+    #[error("InsufficientScope")]
+    InsufficientScope,
+    /// The user has no device with the id.
+    #[error("NotFound")]
+    NotFound,
+    #[error("DBError")]
+    DBError(#[from] DBErrorKind),
+}
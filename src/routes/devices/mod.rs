@@ -0,0 +1,159 @@
+/// Data structs regarding devices shared in requests
+pub mod data;
+
+#[cfg(test)]
+mod tests;
+
+use chrono::Utc;
+use data::public::{Device, DeviceError, DeviceInfo};
+use pcupback::DBErrorKind::{DeleteError, SelectError};
+use rocket::{State, delete, get, serde::json::Json};
+use sqlx::{Pool, Sqlite};
+use tracing::instrument;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    repo::{DeviceRepository, SessionRepository, TokenRepository},
+    util::{
+        auth::{CredentialError, authenticate},
+        db::PoolStateExt,
+    },
+};
+
+use super::tokens::data::public::TokenScope;
+
+/// How long, in graphemes, device names and platforms can be.
+const MAX_DEVICE_NAME_LENGTH: usize = 64;
+
+/// The most devices a user can have.
+pub(crate) const MAX_DEVICES: usize = 32;
+
+type ListDevicesResult = Result<Vec<Device>, DeviceError>;
+type DeleteDeviceResult = Result<(), DeviceError>;
+
+/// The devices of the owner of `credential`, a session id or an api token.
+#[instrument(skip_all)]
+#[get("/devices/<credential>")]
+pub async fn list_devices(
+    state: &State<Pool<Sqlite>>,
+    credential: &str,
+) -> Json<ListDevicesResult> {
+    Json(list(state.to_db(), credential).await)
+}
+
+/// Delete the device with the id `device_id` of the owner of `credential`, a session id or an api token that can
+/// sync.
+///
+/// Its usage is kept as synced without a device, unless `keep_usage` is `false`.
+#[instrument(skip_all)]
+#[delete("/devices/<credential>/<device_id>?<keep_usage>")]
+pub async fn delete_device(
+    state: &State<Pool<Sqlite>>,
+    credential: &str,
+    device_id: u32,
+    keep_usage: Option<bool>,
+) -> Json<DeleteDeviceResult> {
+    Json(
+        delete(
+            state.to_db(),
+            credential,
+            device_id,
+            keep_usage.unwrap_or(true),
+        )
+        .await,
+    )
+}
+
+/// List the devices of the owner of `credential`, by name.
+pub(crate) async fn list(
+    repo: &(impl SessionRepository + TokenRepository + DeviceRepository),
+    credential: &str,
+) -> ListDevicesResult {
+    let user_id = match authenticate(repo, credential, TokenScope::ReadUsage).await {
+        Ok(user_id) => user_id,
+        Err(CredentialError::Invalid) => {
+            tracing::info!("session was invalid");
+            return Err(DeviceError::InvalidSession);
+        }
+        Err(CredentialError::InsufficientScope) => return Err(DeviceError::InsufficientScope),
+    };
+
+    let devices = repo
+        .fetch_devices(user_id)
+        .await
+        .map_err(|err| DeviceError::DBError(SelectError(err.to_string())))?;
+    Ok(devices.into_iter().map(Into::into).collect())
+}
+
+/// Delete the device with the id `device_id` of the owner of `credential`, see [`delete_device`].
+pub(crate) async fn delete(
+    repo: &(impl SessionRepository + TokenRepository + DeviceRepository),
+    credential: &str,
+    device_id: u32,
+    keep_usage: bool,
+) -> DeleteDeviceResult {
+    let user_id = match authenticate(repo, credential, TokenScope::Sync).await {
+        Ok(user_id) => user_id,
+        Err(CredentialError::Invalid) => {
+            tracing::info!("session was invalid");
+            return Err(DeviceError::InvalidSession);
+        }
+        Err(CredentialError::InsufficientScope) => return Err(DeviceError::InsufficientScope),
+    };
+
+    let deleted = repo
+        .delete_device(user_id, device_id, keep_usage)
+        .await
+        .map_err(|err| DeviceError::DBError(DeleteError(err.to_string())))?;
+    if !deleted {
+        return Err(DeviceError::NotFound);
+    }
+    tracing::info!("user {user_id} deleted device {device_id}");
+
+    Ok(())
+}
+
+/// Register `device` as one of `user_id`'s, or mark it seen if it already is, returning its id.
+///
+/// Devices with an empty or too long name or platform are not registered, nor new ones of users with
+/// [`MAX_DEVICES`] already. Failing to register does not fail the login.
+pub(crate) async fn register(
+    repo: &impl DeviceRepository,
+    user_id: u32,
+    device: &DeviceInfo,
+) -> Option<u32> {
+    let name = device.name.trim();
+    let platform = device.platform.trim();
+    let valid =
+        |field: &str| !field.is_empty() && field.graphemes(true).count() <= MAX_DEVICE_NAME_LENGTH;
+    if !valid(name) || !valid(platform) {
+        tracing::info!("not registering an invalid device {device:?}");
+        return None;
+    }
+
+    match repo.fetch_devices(user_id).await {
+        Ok(devices) if devices.len() >= MAX_DEVICES && devices.iter().all(|d| d.name != name) => {
+            tracing::info!("user {user_id} has too many devices to register another");
+            return None;
+        }
+        Ok(_) => {}
+        Err(err) => {
+            tracing::warn!("failed to fetch devices: {err:?}");
+            return None;
+        }
+    }
+
+    match repo
+        .register_device(user_id, name, platform, Utc::now().timestamp())
+        .await
+    {
+        Ok(device) => {
+            tracing::info!("user {user_id} logged in from device {}", device.id);
+            Some(device.id)
+        }
+        Err(err) => {
+            tracing::warn!("failed to register device: {err:?}");
+            None
+        }
+    }
+}
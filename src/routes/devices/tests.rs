@@ -0,0 +1,302 @@
+use chrono::Utc;
+
+use crate::{
+    config::HashConfig,
+    repo::{UsageRepository, memory::MemoryRepository},
+    routes::{
+        auth::{
+            AuthResult, LoginResult,
+            data::public::{AuthRequest, LoginResponse},
+            signed_up, signed_up_as, try_login,
+        },
+        sync::{
+            SyncResult,
+            data::public::{AppInfo, SyncError, UserData},
+            events::SyncEvents,
+            sync_data,
+        },
+        tokens::{
+            self,
+            data::public::{NewTokenRequest, TokenScope},
+        },
+        usage::data::public::{UsageError, UsageStats},
+    },
+};
+
+use super::{
+    MAX_DEVICES,
+    data::public::{Device, DeviceError, DeviceInfo},
+};
+
+fn device(name: &str, platform: &str) -> Option<DeviceInfo> {
+    Some(DeviceInfo {
+        name: name.to_string(),
+        platform: platform.to_string(),
+    })
+}
+
+#[rocket::async_test]
+async fn register_on_login() {
+    let repo = MemoryRepository::default();
+    let req = AuthRequest {
+        device: device("laptop", "windows"),
+        ..AuthRequest::random_valid()
+    };
+    let (_, session) = signed_up_as(&repo, &req).await;
+    let laptop = session.device_id.unwrap();
+
+    let login = |device| {
+        let req = AuthRequest {
+            username: req.username.clone(),
+            password: req.password.clone(),
+            device,
+        };
+        let repo = &repo;
+        async move {
+            match try_login(repo, &HashConfig::default(), &req, None)
+                .await
+                .unwrap()
+            {
+                LoginResponse::Session(session) => session.device_id,
+                LoginResponse::TotpRequired { .. } => unreachable!(),
+            }
+        }
+    };
+    // the same name is the same device, even on another platform.
+    assert_eq!(login(device(" laptop ", "linux")).await, Some(laptop));
+    let phone = login(device("phone", "android")).await.unwrap();
+    assert_ne!(phone, laptop);
+    assert_eq!(login(None).await, None);
+    assert_eq!(login(device("", "android")).await, None);
+
+    let devices = super::list(&repo, &session.id).await.unwrap();
+    let listed: Vec<_> = devices
+        .iter()
+        .map(|d| (d.id, d.name.as_str(), d.platform.as_str()))
+        .collect();
+    assert_eq!(
+        listed,
+        [(laptop, "laptop", "linux"), (phone, "phone", "android")]
+    );
+
+    // syncing from another user's, or no, device fails.
+    let (_, other) = signed_up(&repo).await;
+    assert!(matches!(
+        sync_data(&repo, &SyncEvents::default(), &other.id, Some(phone), None)
+            .await
+            .unwrap_err(),
+        SyncError::UnknownDevice
+    ));
+    sync_data(
+        &repo,
+        &SyncEvents::default(),
+        &session.id,
+        Some(phone),
+        None,
+    )
+    .await
+    .unwrap();
+
+    assert!(matches!(
+        super::list(&repo, "not-a-session").await.unwrap_err(),
+        DeviceError::InvalidSession
+    ));
+}
+
+#[rocket::async_test]
+async fn delete_and_cap() {
+    let repo = MemoryRepository::default();
+    let (user, session) = signed_up_as(
+        &repo,
+        &AuthRequest {
+            device: device("laptop", "windows"),
+            ..AuthRequest::random_valid()
+        },
+    )
+    .await;
+    let user_id = user.id;
+    let laptop = session.device_id.unwrap();
+
+    let register = |name: String| {
+        let repo = &repo;
+        async move { super::register(repo, user_id, &device(&name, "linux").unwrap()).await }
+    };
+    let mut phone = None;
+    for i in 1..MAX_DEVICES {
+        phone = register(format!("device {i}")).await;
+        assert!(phone.is_some());
+    }
+    let phone = phone.unwrap();
+    assert_eq!(register("one too many".to_string()).await, None);
+    // devices already registered can still log in.
+    assert_eq!(register("laptop".to_string()).await, Some(laptop));
+
+    let sync = |device, usage| {
+        let data = UserData {
+            app_usage: vec![AppInfo::new("io1", usage, 0)],
+            ..Default::default()
+        };
+        let (repo, session) = (&repo, &session);
+        async move {
+            sync_data(
+                repo,
+                &SyncEvents::default(),
+                &session.id,
+                device,
+                Some(data),
+            )
+            .await
+            .unwrap();
+        }
+    };
+    sync(Some(laptop), 60).await;
+    sync(Some(phone), 30).await;
+    sync(None, 10).await;
+    let usage = |device| {
+        let repo = &repo;
+        async move {
+            repo.fetch_usage_history(user_id, Some(device), None, None)
+                .await
+                .unwrap()
+                .iter()
+                .map(|u| u.app_usage)
+                .sum::<u32>()
+        }
+    };
+
+    // the phone's usage adds up with the usage synced without a device..
+    super::delete(&repo, &session.id, phone, true)
+        .await
+        .unwrap();
+    assert_eq!((usage(phone).await, usage(0).await), (0, 40));
+    // ..and the laptop's is dropped.
+    super::delete(&repo, &session.id, laptop, false)
+        .await
+        .unwrap();
+    assert_eq!((usage(laptop).await, usage(0).await), (0, 40));
+    assert!(register("one too many".to_string()).await.is_some());
+
+    assert!(matches!(
+        super::delete(&repo, &session.id, laptop, true)
+            .await
+            .unwrap_err(),
+        DeviceError::NotFound
+    ));
+    assert!(matches!(
+        super::delete(&repo, "not-a-session", phone, true)
+            .await
+            .unwrap_err(),
+        DeviceError::InvalidSession
+    ));
+    // read only tokens can list devices, but not delete them.
+    let read_only = tokens::create(
+        &repo,
+        &session.id,
+        &NewTokenRequest {
+            name: "dashboard".to_string(),
+            scope: TokenScope::ReadUsage,
+        },
+    )
+    .await
+    .unwrap();
+    assert!(super::list(&repo, &read_only.token).await.is_ok());
+    assert!(matches!(
+        super::delete(&repo, &read_only.token, phone, true)
+            .await
+            .unwrap_err(),
+        DeviceError::InsufficientScope
+    ));
+}
+
+#[macros::rocket_test]
+fn usage_by_device() {
+    let req = AuthRequest {
+        device: device("laptop", "windows"),
+        ..AuthRequest::random_valid()
+    };
+    let laptop = client
+        .post("/auth/signup")
+        .json(&req)
+        .dispatch()
+        .into_json::<AuthResult>()
+        .unwrap()
+        .unwrap();
+    let phone = client
+        .post("/auth/login")
+        .json(&AuthRequest {
+            username: req.username.clone(),
+            password: req.password.clone(),
+            device: device("phone", "android"),
+        })
+        .dispatch()
+        .into_json::<LoginResult>()
+        .unwrap()
+        .unwrap();
+    let LoginResponse::Session(phone) = phone else {
+        panic!("no totp was enabled");
+    };
+    let (laptop_id, phone_id) = (laptop.device_id.unwrap(), phone.device_id.unwrap());
+
+    let sync = |device_id: u32, usage| {
+        let data = UserData {
+            app_usage: vec![AppInfo::new("io1", usage, 0)],
+            ..Default::default()
+        };
+        client
+            .post(format!("/sync/{}?device={device_id}", laptop.id))
+            .json(&Some(data))
+            .dispatch()
+            .into_json::<SyncResult>()
+            .unwrap()
+            .unwrap();
+    };
+    sync(laptop_id, 60);
+    sync(phone_id, 30);
+
+    let stats = |query: &str| {
+        client
+            .get(format!("/usage/{}/stats?{query}", laptop.id))
+            .dispatch()
+            .into_json::<Result<UsageStats, UsageError>>()
+            .unwrap()
+            .unwrap()
+            .average_daily_usage
+    };
+    // devices are combined..
+    assert_eq!(stats(""), 90);
+    // ..or broken down.
+    assert_eq!(stats(&format!("device={laptop_id}")), 60);
+    assert_eq!(stats(&format!("device={phone_id}")), 30);
+
+    let devices = client
+        .get(format!("/devices/{}", laptop.id))
+        .dispatch()
+        .into_json::<Result<Vec<Device>, DeviceError>>()
+        .unwrap()
+        .unwrap();
+    assert_eq!(devices.len(), 2);
+    assert!(
+        devices
+            .iter()
+            .all(|d| d.last_seen <= Utc::now().timestamp())
+    );
+
+    let delete = |query: String| {
+        client
+            .delete(format!("/devices/{}/{query}", laptop.id))
+            .dispatch()
+            .into_json::<Result<(), DeviceError>>()
+            .unwrap()
+    };
+    // the phone's usage is kept, without a device..
+    delete(phone_id.to_string()).unwrap();
+    assert_eq!(stats(""), 90);
+    assert_eq!(stats("device=0"), 30);
+    // ..but not the laptop's.
+    delete(format!("{laptop_id}?keep_usage=false")).unwrap();
+    assert_eq!(stats(""), 30);
+    assert!(matches!(
+        delete(laptop_id.to_string()).unwrap_err(),
+        DeviceError::NotFound
+    ));
+}
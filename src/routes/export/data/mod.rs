@@ -0,0 +1,2 @@
+/// Structs used by api consumers. They implement `Serialize` and `Deserialize`.
+pub mod public;
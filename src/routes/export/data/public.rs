@@ -0,0 +1,87 @@
+use pcupback::DBErrorKind;
+use rocket::FromFormField;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::routes::{
+    devices::data::public::Device, sync::data::public::UserData, tokens::data::public::ApiToken,
+    usage::data::public::UsageDay, webhooks::data::public::Webhook,
+};
+
+/// The version of [`UserExport`]'s schema.
+///
+/// Bumped on any change that older importers could not read.
+///
+/// # Versions
+///
+/// 1. The first version, with every field of [`UserExport`].
+pub const EXPORT_SCHEMA_VERSION: u32 = 1;
+
+/// Everything stored for a user.
+///
+/// All times are seconds since the unix epoch.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct UserExport {
+    /// See [`EXPORT_SCHEMA_VERSION`].
+    pub schema_version: u32,
+    pub exported_at: i64,
+    pub account: AccountExport,
+    /// Usernames the user changed away from, oldest first.
+    pub username_history: Vec<UsernameChange>,
+    /// The user's session, without its id.
+    pub session: Option<SessionExport>,
+    /// The user's api tokens, without the tokens.
+    pub api_tokens: Vec<ApiToken>,
+    /// The devices the user logged in from.
+    pub devices: Vec<Device>,
+    /// Usage per app, per day, per device, oldest first.
+    pub usage_history: Vec<UsageDay>,
+    /// The user's webhooks, without their secrets.
+    pub webhooks: Vec<Webhook>,
+    pub data: UserData,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct AccountExport {
+    /// The user's public id.
+    pub user_id: String,
+    pub username: String,
+    pub totp_enabled: bool,
+    /// When the user asked to delete their account, if they did.
+    pub deletion_requested_at: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct UsernameChange {
+    /// The old username, normalized and lowercased.
+    pub username: String,
+    pub released_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct SessionExport {
+    pub last_set: i64,
+}
+
+/// How an export is downloaded.
+#[derive(Debug, Default, FromFormField, PartialEq, Eq, Clone, Copy)]
+pub enum ExportFormat {
+    /// `export.json`.
+    #[default]
+    Json,
+    /// A zip archive, containing `export.json`.
+    Zip,
+}
+
+#[derive(Error, Debug, Serialize, Deserialize)]
+pub enum ExportError {
+    /// No such session or api token.
+    #[error("InvalidSession")]
+    InvalidSession,
+    #[error("InsufficientScope")]
+    InsufficientScope,
+    #[error("InternalError")]
+    InternalError(String),
+    #[error("DBError")]
+    DBError(#[from] DBErrorKind),
+}
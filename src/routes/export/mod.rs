@@ -0,0 +1,200 @@
+/// Data structs regarding exports shared in requests
+pub mod data;
+
+#[cfg(test)]
+mod tests;
+
+use std::io::{Cursor, Write};
+
+use chrono::Utc;
+use data::public::{
+    AccountExport, EXPORT_SCHEMA_VERSION, ExportError, ExportFormat, SessionExport, UserExport,
+    UsernameChange,
+};
+use pcupback::DBErrorKind::SelectError;
+use rocket::{
+    Request, Response, State, get,
+    http::{ContentType, Header},
+    response::{self, Responder},
+    serde::json::{self, Json},
+};
+use sqlx::{Pool, Sqlite};
+use tracing::instrument;
+use zip::{ZipWriter, write::SimpleFileOptions};
+
+use crate::{
+    repo::{
+        DeviceRepository, SessionRepository, TokenRepository, TotpRepository, UsageRepository,
+        UserRepository, WebhookRepository,
+    },
+    util::{
+        auth::{CredentialError, authenticate},
+        db::PoolStateExt,
+    },
+};
+
+use super::tokens::data::public::TokenScope;
+
+/// The name of the export inside a zip archive.
+pub(crate) const EXPORT_FILE_NAME: &str = "export.json";
+
+type ExportResult = Result<UserExport, ExportError>;
+
+/// A downloadable export, see [`ExportFormat`].
+pub struct ExportFile {
+    format: ExportFormat,
+    body: Vec<u8>,
+}
+
+impl<'r> Responder<'r, 'static> for ExportFile {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        let (content_type, extension) = match self.format {
+            ExportFormat::Json => (ContentType::JSON, "json"),
+            ExportFormat::Zip => (ContentType::ZIP, "zip"),
+        };
+
+        Response::build()
+            .header(content_type)
+            .header(Header::new(
+                "Content-Disposition",
+                format!("attachment; filename=\"pcupback-export.{extension}\""),
+            ))
+            .sized_body(self.body.len(), Cursor::new(self.body))
+            .ok()
+    }
+}
+
+/// Download everything stored for the owner of `credential`, a session id or an api token.
+///
+/// `format` defaults to [`ExportFormat::Json`].
+#[instrument(skip_all)]
+#[get("/export/<credential>?<format>")]
+pub async fn export_data(
+    state: &State<Pool<Sqlite>>,
+    credential: &str,
+    format: Option<ExportFormat>,
+) -> Result<ExportFile, Json<ExportError>> {
+    let format = format.unwrap_or_default();
+    let export = export(state.to_db(), credential).await.map_err(Json)?;
+
+    let body = match format {
+        ExportFormat::Json => json::to_string(&export)
+            .map(String::into_bytes)
+            .map_err(Into::into),
+        ExportFormat::Zip => to_zip(&export),
+    }
+    .map_err(|err| Json(ExportError::InternalError(err.to_string())))?;
+    tracing::info!("exported {} bytes as {format:?}", body.len());
+
+    Ok(ExportFile { format, body })
+}
+
+/// Collect everything stored for the owner of `credential`.
+///
+/// Secrets are left out: password and token hashes, session ids, totp secrets, and webhook secrets.
+pub(crate) async fn export(
+    repo: &(
+         impl SessionRepository
+         + TokenRepository
+         + UserRepository
+         + TotpRepository
+         + UsageRepository
+         + DeviceRepository
+         + WebhookRepository
+     ),
+    credential: &str,
+) -> ExportResult {
+    use ExportError::{DBError, InsufficientScope, InvalidSession};
+
+    let user_id = match authenticate(repo, credential, TokenScope::ReadUsage).await {
+        Ok(user_id) => user_id,
+        Err(CredentialError::Invalid) => {
+            tracing::info!("session was invalid");
+            return Err(InvalidSession);
+        }
+        Err(CredentialError::InsufficientScope) => return Err(InsufficientScope),
+    };
+    let select_error = |err: sqlx::Error| DBError(SelectError(err.to_string()));
+
+    let user = repo.fetch_user_by_id(user_id).await.map_err(select_error)?;
+    let totp_enabled = match repo.fetch_totp(user_id).await {
+        Ok(stored) => stored.confirmed,
+        Err(sqlx::Error::RowNotFound) => false,
+        Err(err) => return Err(select_error(err)),
+    };
+    let session = match repo.fetch_user_session(user_id).await {
+        Ok(session) => Some(SessionExport {
+            last_set: session.last_set,
+        }),
+        Err(sqlx::Error::RowNotFound) => None,
+        Err(err) => return Err(select_error(err)),
+    };
+
+    let username_history = repo
+        .fetch_username_history(user_id)
+        .await
+        .map_err(select_error)?
+        .into_iter()
+        .map(|change| UsernameChange {
+            username: change.username_key,
+            released_at: change.released_at,
+        })
+        .collect();
+    let api_tokens = repo
+        .fetch_tokens(user_id)
+        .await
+        .map_err(select_error)?
+        .into_iter()
+        .map(Into::into)
+        .collect();
+    let devices = repo
+        .fetch_devices(user_id)
+        .await
+        .map_err(select_error)?
+        .into_iter()
+        .map(Into::into)
+        .collect();
+    let usage_history = repo
+        .fetch_usage_history(user_id, None, None, None)
+        .await
+        .map_err(select_error)?
+        .into_iter()
+        .map(Into::into)
+        .collect();
+    let webhooks = repo
+        .fetch_webhooks(user_id)
+        .await
+        .map_err(select_error)?
+        .into_iter()
+        .map(Into::into)
+        .collect();
+    let data = repo.fetch_user_data(user_id).await.map_err(select_error)?;
+
+    tracing::info!("exporting user {user_id}");
+    Ok(UserExport {
+        schema_version: EXPORT_SCHEMA_VERSION,
+        exported_at: Utc::now().timestamp(),
+        account: AccountExport {
+            user_id: user.public_id,
+            username: user.username,
+            totp_enabled,
+            deletion_requested_at: user.deletion_requested_at,
+        },
+        username_history,
+        session,
+        api_tokens,
+        devices,
+        usage_history,
+        webhooks,
+        data,
+    })
+}
+
+/// Pack `export` into a zip archive, as [`EXPORT_FILE_NAME`].
+fn to_zip(export: &UserExport) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    zip.start_file(EXPORT_FILE_NAME, SimpleFileOptions::default())?;
+    zip.write_all(json::to_pretty_string(export)?.as_bytes())?;
+
+    Ok(zip.finish()?.into_inner())
+}
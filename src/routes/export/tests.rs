@@ -0,0 +1,149 @@
+use std::io::{Cursor, Read};
+
+use rocket::{
+    http::{ContentType, Status},
+    serde::json,
+};
+use zip::ZipArchive;
+
+use crate::{
+    repo::memory::MemoryRepository,
+    routes::{
+        auth::{AuthResult, data::public::AuthRequest, signed_up_as},
+        change_username::{ChangeUsernameRequest, change},
+        sync::{
+            data::public::{AppInfo, UserData, UserDebug},
+            events::SyncEvents,
+            sync_data,
+        },
+        tokens::{
+            self,
+            data::public::{NewTokenRequest, TokenScope},
+        },
+    },
+};
+
+use super::{
+    EXPORT_FILE_NAME,
+    data::public::{EXPORT_SCHEMA_VERSION, ExportError, UserExport},
+};
+
+#[rocket::async_test]
+async fn export_everything() {
+    let repo = MemoryRepository::default();
+    let req = AuthRequest::random_valid();
+    let (_, session) = signed_up_as(&repo, &req).await;
+
+    let data = UserData {
+        app_usage: vec![AppInfo::new("io1", 2, 0)],
+        debug: vec![UserDebug {
+            stored: "debug1".to_string(),
+        }],
+        ..Default::default()
+    };
+    sync_data(
+        &repo,
+        &SyncEvents::default(),
+        &session.id,
+        None,
+        Some(data.clone()),
+    )
+    .await
+    .unwrap();
+
+    let new_username = AuthRequest::random_valid().username;
+    change(
+        &repo,
+        &session.id,
+        &ChangeUsernameRequest {
+            new_username: new_username.clone(),
+            password: req.password.clone(),
+        },
+        None,
+    )
+    .await
+    .unwrap();
+
+    let token = tokens::create(
+        &repo,
+        &session.id,
+        &NewTokenRequest {
+            name: "dashboard".to_string(),
+            scope: TokenScope::ReadUsage,
+        },
+    )
+    .await
+    .unwrap();
+
+    // read only tokens can export.
+    let export = super::export(&repo, &token.token).await.unwrap();
+    assert_eq!(export.schema_version, EXPORT_SCHEMA_VERSION);
+    assert_eq!(export.account.user_id, session.user_id);
+    assert_eq!(export.account.username, new_username);
+    assert!(!export.account.totp_enabled);
+    assert_eq!(export.username_history.len(), 1);
+    assert_eq!(
+        export.username_history[0].username,
+        req.username.to_lowercase()
+    );
+    assert!(export.session.is_some());
+    assert_eq!(export.api_tokens.len(), 1);
+    assert_eq!(export.api_tokens[0].name, "dashboard");
+    assert_eq!(export.usage_history.len(), 1);
+    assert_eq!(export.usage_history[0].app_name, "io1");
+    assert_eq!(export.usage_history[0].device_id, 0);
+    assert_eq!(export.data, data);
+
+    // no secrets.
+    let exported = json::to_string(&export).unwrap();
+    assert!(!exported.contains(&session.id));
+    assert!(!exported.contains(&token.token));
+
+    assert!(matches!(
+        super::export(&repo, "not-a-session").await.unwrap_err(),
+        ExportError::InvalidSession
+    ));
+}
+
+#[macros::rocket_test]
+fn export_formats() {
+    let session = client
+        .post("/auth/signup")
+        .json(&AuthRequest::random_valid())
+        .dispatch()
+        .into_json::<AuthResult>()
+        .unwrap()
+        .unwrap();
+
+    // json by default.
+    let response = client.get(format!("/export/{}", session.id)).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::JSON));
+    assert_eq!(
+        response.headers().get_one("Content-Disposition"),
+        Some("attachment; filename=\"pcupback-export.json\"")
+    );
+    let from_json = response.into_json::<UserExport>().unwrap();
+    assert_eq!(from_json.account.user_id, session.user_id);
+
+    let response = client
+        .get(format!("/export/{}?format=zip", session.id))
+        .dispatch();
+    assert_eq!(response.content_type(), Some(ContentType::ZIP));
+    let mut archive = ZipArchive::new(Cursor::new(response.into_bytes().unwrap())).unwrap();
+    let mut contents = String::new();
+    archive
+        .by_name(EXPORT_FILE_NAME)
+        .unwrap()
+        .read_to_string(&mut contents)
+        .unwrap();
+    let from_zip: UserExport = json::from_str(&contents).unwrap();
+    assert_eq!(from_zip.account, from_json.account);
+
+    let invalid = client
+        .get("/export/not-a-session")
+        .dispatch()
+        .into_json::<ExportError>()
+        .unwrap();
+    assert!(matches!(invalid, ExportError::InvalidSession));
+}
@@ -0,0 +1,50 @@
+use pcupback::DBErrorKind;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::routes::{sync::SyncSummary, webhooks::data::public::CreatedWebhook};
+
+/// What an import stored.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct ImportSummary {
+    #[serde(flatten)]
+    pub sync: SyncSummary,
+    /// The webhooks registered from the export, with their new secrets, as the old ones are not exported.
+    ///
+    /// Only imports with a session register webhooks, see [`create_webhook`](crate::routes::webhooks::create_webhook).
+    #[serde(default)]
+    pub webhooks: Vec<CreatedWebhook>,
+}
+
+#[derive(Error, Debug, Serialize, Deserialize)]
+pub enum ImportError {
+    /// No such session or api token.
+    #[error("InvalidSession")]
+    InvalidSession,
+    /// The api token is read only.
+    #[error("InsufficientScope")]
+    InsufficientScope,
+    /// No such user, when importing from the command line.
+    #[error("UserNotFound")]
+    UserNotFound,
+    /// The archive is larger than the `import` limit, or its export is too large once extracted.
+    #[error("TooLarge")]
+    TooLarge,
+    /// The archive is not an export, as JSON or ZIP.
+    #[error("InvalidArchive")]
+    InvalidArchive(String),
+    /// A day of the export's usage history was not `YYYY-MM-DD`.
+    #[error("InvalidDate")]
+    InvalidDate(String),
+    /// The export is from a newer, or unknown, version of the schema.
+    #[error("UnsupportedSchemaVersion")]
+    UnsupportedSchemaVersion {
+        found: u32,
+        /// The newest version this server reads.
+        supported: u32,
+    },
+    #[error("InternalError")]
+    InternalError(String),
+    #[error("DBError")]
+    DBError(#[from] DBErrorKind),
+}
@@ -0,0 +1,332 @@
+/// Data structs regarding imports shared in requests
+pub mod data;
+
+#[cfg(test)]
+mod tests;
+
+use std::{
+    collections::HashMap,
+    io::{Cursor, Read},
+};
+
+use chrono::NaiveDate;
+use data::public::{ImportError, ImportSummary};
+use pcupback::DBErrorKind::{InsertError, SelectError};
+use rocket::{
+    Data, State,
+    data::{ByteUnit, Limits},
+    post,
+    serde::json::{self, Json},
+};
+use serde::Deserialize;
+use sqlx::{Pool, Sqlite};
+use tracing::instrument;
+use zip::ZipArchive;
+
+use crate::{
+    notify::sink::HttpClient,
+    repo::{
+        DeviceRepository, OutboxRepository, SessionRepository, TokenRepository, UsageRepository,
+        UserRepository, WebhookRepository,
+    },
+    util::{
+        auth::{CredentialError, authenticate},
+        db::PoolStateExt,
+        token::is_token,
+    },
+};
+
+use super::{
+    devices::MAX_DEVICES,
+    export::{
+        EXPORT_FILE_NAME,
+        data::public::{EXPORT_SCHEMA_VERSION, UserExport},
+    },
+    sync::{SyncSummary, data::public::SyncError, merge},
+    tokens::data::public::TokenScope,
+    usage::data::private::{DAY_FORMAT, DBUsageDay},
+    webhooks::{
+        data::public::{CreatedWebhook, NewWebhookRequest, WebhookError},
+        register,
+    },
+};
+
+/// How large an archive is accepted, if Rocket's `import` limit is not set.
+const DEFAULT_IMPORT_LIMIT: ByteUnit = ByteUnit::Mebibyte(8);
+
+/// How large the export in a zip archive can be once extracted, in bytes. The `import` limit only caps the
+/// compressed size.
+const MAX_EXTRACTED_SIZE: u64 = 32 * 1024 * 1024;
+
+/// What zip archives start with.
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+pub type ImportResult = Result<ImportSummary, ImportError>;
+
+/// Only the schema version, read before the rest of an export.
+#[derive(Deserialize)]
+struct SchemaVersion {
+    schema_version: u32,
+}
+
+/// Load an export, as JSON or ZIP, into the account of the owner of `credential`, a session id or an api token.
+#[instrument(skip_all)]
+#[post("/import/<credential>", data = "<archive>")]
+pub async fn import_data(
+    state: &State<Pool<Sqlite>>,
+    client: &State<HttpClient>,
+    limits: &Limits,
+    credential: &str,
+    archive: Data<'_>,
+) -> Json<ImportResult> {
+    let limit = limits.get("import").unwrap_or(DEFAULT_IMPORT_LIMIT);
+    let archive = match archive.open(limit).into_bytes().await {
+        Ok(archive) if archive.is_complete() => archive.into_inner(),
+        Ok(_) => return Json(Err(ImportError::TooLarge)),
+        Err(err) => return Json(Err(ImportError::InternalError(err.to_string()))),
+    };
+    tracing::info!("got {} bytes to import", archive.len());
+
+    Json(import(state.to_db(), client, credential, &archive).await)
+}
+
+/// Merge the data in `archive` into the data stored for the owner of `credential`.
+///
+/// Requires [`TokenScope::Sync`] of api tokens, as with [`sync`](super::sync::sync). Webhooks are only registered
+/// with a session, as api tokens cannot manage them.
+pub(crate) async fn import(
+    repo: &(
+         impl SessionRepository
+         + TokenRepository
+         + UsageRepository
+         + DeviceRepository
+         + WebhookRepository
+         + OutboxRepository
+         + UserRepository
+     ),
+    client: &HttpClient,
+    credential: &str,
+    archive: &[u8],
+) -> ImportResult {
+    use ImportError::{InsufficientScope, InvalidSession};
+
+    let user_id = match authenticate(repo, credential, TokenScope::Sync).await {
+        Ok(user_id) => user_id,
+        Err(CredentialError::Invalid) => {
+            tracing::info!("session was invalid");
+            return Err(InvalidSession);
+        }
+        Err(CredentialError::InsufficientScope) => return Err(InsufficientScope),
+    };
+
+    let export = parse_archive(archive)?;
+    let sync = import_into(repo, user_id, &export).await?;
+    let webhooks = if is_token(credential) {
+        Vec::new()
+    } else {
+        import_webhooks(repo, client, user_id, &export).await?
+    };
+
+    Ok(ImportSummary { sync, webhooks })
+}
+
+/// Merge the data in `archive` into the data stored for `username`, without a credential.
+///
+/// For server operators, see `pcupback import --help`. Webhooks are not registered, as their new secrets would
+/// only be shown to the operator.
+pub(crate) async fn import_for_username(
+    repo: &(impl UserRepository + UsageRepository + DeviceRepository),
+    username: &str,
+    archive: &[u8],
+) -> ImportResult {
+    let user = match repo.fetch_user_by_name(username).await {
+        Ok(user) => user,
+        Err(sqlx::Error::RowNotFound) => return Err(ImportError::UserNotFound),
+        Err(err) => return Err(ImportError::DBError(SelectError(err.to_string()))),
+    };
+
+    let sync = import_into(repo, user.id, &parse_archive(archive)?).await?;
+    Ok(ImportSummary {
+        sync,
+        webhooks: Vec::new(),
+    })
+}
+
+/// Merge `export`'s data into `user_id`'s, with the same rules as [`sync`](super::sync::sync).
+///
+/// Usage history is merged as it is synced, keeping the larger usage of each app on each day. Its devices are
+/// matched to `user_id`'s by name, and added if they have none with that name.
+///
+/// The rest of an export is not imported here. Sessions and api tokens are only valid on the server that made them,
+/// and the account is the one imported into.
+async fn import_into(
+    repo: &(impl UsageRepository + DeviceRepository),
+    user_id: u32,
+    export: &UserExport,
+) -> Result<SyncSummary, ImportError> {
+    // checked before anything is stored.
+    let days = usage_days(export)?;
+    let summary = merge(repo, user_id, Some(export.data.clone()))
+        .await
+        .map_err(|err| match err {
+            SyncError::DBError(err) => ImportError::DBError(err),
+            err => ImportError::InternalError(err.to_string()),
+        })?;
+    import_usage_history(repo, user_id, export, &days)
+        .await
+        .map_err(|err| ImportError::DBError(InsertError(err.to_string())))?;
+    tracing::info!(
+        "imported an export of user {} into user {user_id}, failed: {}",
+        export.account.user_id,
+        summary.failed
+    );
+
+    Ok(summary)
+}
+
+/// Parse the days of `export`'s usage history, in [`DAY_FORMAT`].
+///
+/// # Errors
+///
+/// Errors with the first day that is not a date.
+fn usage_days(export: &UserExport) -> Result<Vec<NaiveDate>, ImportError> {
+    export
+        .usage_history
+        .iter()
+        .map(|usage| {
+            NaiveDate::parse_from_str(&usage.day, DAY_FORMAT)
+                .map_err(|_| ImportError::InvalidDate(usage.day.clone()))
+        })
+        .collect()
+}
+
+/// Record `export`'s usage history for `user_id` on `days`, its parsed days, on their devices with the same names
+/// as the exported ones.
+///
+/// Usage of devices missing from `export`, or that would go over [`MAX_DEVICES`], is recorded as synced without a
+/// device.
+async fn import_usage_history(
+    repo: &(impl UsageRepository + DeviceRepository),
+    user_id: u32,
+    export: &UserExport,
+    days: &[NaiveDate],
+) -> Result<(), sqlx::Error> {
+    let mut existing: HashMap<String, u32> = repo
+        .fetch_devices(user_id)
+        .await?
+        .into_iter()
+        .map(|device| (device.name, device.id))
+        .collect();
+
+    let mut device_ids = HashMap::new();
+    for device in &export.devices {
+        let id = match existing.get(&device.name) {
+            Some(&id) => id,
+            None if existing.len() >= MAX_DEVICES => continue,
+            None => {
+                let id = repo
+                    .register_device(user_id, &device.name, &device.platform, device.last_seen)
+                    .await?
+                    .id;
+                existing.insert(device.name.clone(), id);
+                id
+            }
+        };
+        device_ids.insert(device.id, id);
+    }
+
+    for (usage, day) in export.usage_history.iter().zip(days) {
+        repo.record_usage(&DBUsageDay {
+            user_id,
+            device_id: device_ids.get(&usage.device_id).copied().unwrap_or(0),
+            app_name: usage.app_name.clone(),
+            day: day.format(DAY_FORMAT).to_string(),
+            app_usage: usage.app_usage,
+            app_limit: usage.app_limit,
+        })
+        .await?;
+    }
+    Ok(())
+}
+
+/// Register `export`'s webhooks for `user_id`, with new secrets, returning them.
+///
+/// Webhooks the user already has, with the same url and events, are skipped. So are ones that cannot be registered,
+/// as their url is not allowed by `client`, or the user has too many.
+async fn import_webhooks(
+    repo: &(impl WebhookRepository + OutboxRepository + UserRepository),
+    client: &HttpClient,
+    user_id: u32,
+    export: &UserExport,
+) -> Result<Vec<CreatedWebhook>, ImportError> {
+    let existing = repo
+        .fetch_webhooks(user_id)
+        .await
+        .map_err(|err| ImportError::DBError(SelectError(err.to_string())))?;
+
+    let mut created = Vec::new();
+    for webhook in &export.webhooks {
+        let mut events = webhook.events.clone();
+        events.sort();
+        events.dedup();
+        if existing
+            .iter()
+            .any(|e| e.url == webhook.url && e.event_kinds() == events)
+        {
+            continue;
+        }
+
+        let request = NewWebhookRequest {
+            url: webhook.url.clone(),
+            events,
+        };
+        match register(repo, client, user_id, &request).await {
+            Ok(webhook) => created.push(webhook),
+            Err(WebhookError::DBError(err)) => return Err(ImportError::DBError(err)),
+            Err(err) => tracing::info!("skipped importing webhook {}: {err}", webhook.id),
+        }
+    }
+    Ok(created)
+}
+
+/// Read an export from `archive`, either the JSON itself, or a ZIP containing it.
+///
+/// The schema version is checked before reading the rest.
+fn parse_archive(archive: &[u8]) -> Result<UserExport, ImportError> {
+    use ImportError::{InvalidArchive, TooLarge, UnsupportedSchemaVersion};
+
+    let contents = if archive.starts_with(ZIP_MAGIC) {
+        let invalid = |err: zip::result::ZipError| InvalidArchive(err.to_string());
+        let mut zip = ZipArchive::new(Cursor::new(archive)).map_err(invalid)?;
+        let entry = zip.by_name(EXPORT_FILE_NAME).map_err(invalid)?;
+        if entry.size() > MAX_EXTRACTED_SIZE {
+            tracing::info!("zipped export is {} bytes extracted", entry.size());
+            return Err(TooLarge);
+        }
+
+        // the size in the archive can lie, so read no more than the limit either way.
+        let mut contents = String::new();
+        entry
+            .take(MAX_EXTRACTED_SIZE + 1)
+            .read_to_string(&mut contents)
+            .map_err(|err| InvalidArchive(err.to_string()))?;
+        if contents.len() as u64 > MAX_EXTRACTED_SIZE {
+            return Err(TooLarge);
+        }
+        contents
+    } else {
+        String::from_utf8(archive.to_vec()).map_err(|err| InvalidArchive(err.to_string()))?
+    };
+
+    let SchemaVersion { schema_version } =
+        json::from_str(&contents).map_err(|err| InvalidArchive(err.to_string()))?;
+    if schema_version == 0 || schema_version > EXPORT_SCHEMA_VERSION {
+        tracing::info!("unsupported export schema version {schema_version}");
+        return Err(UnsupportedSchemaVersion {
+            found: schema_version,
+            supported: EXPORT_SCHEMA_VERSION,
+        });
+    }
+
+    json::from_str(&contents).map_err(|err| InvalidArchive(err.to_string()))
+}
@@ -0,0 +1,316 @@
+use std::io::Write;
+
+use rocket::{http::ContentType, serde::json};
+use zip::{ZipWriter, write::SimpleFileOptions};
+
+use crate::{
+    notify::sink::http_client,
+    repo::memory::MemoryRepository,
+    routes::{
+        auth::{AuthResult, data::public::AuthRequest, signed_up, signed_up_as},
+        devices::data::public::DeviceInfo,
+        export::{
+            EXPORT_FILE_NAME,
+            data::public::{EXPORT_SCHEMA_VERSION, UserExport},
+            export,
+        },
+        sync::{
+            data::public::{AppInfo, UserData, UserDebug},
+            events::SyncEvents,
+            sync_data,
+        },
+        tokens::{
+            self,
+            data::public::{NewTokenRequest, TokenScope},
+        },
+        usage::data::public::UsageDay,
+        webhooks::data::public::{CreatedWebhook, NewWebhookRequest, Webhook, WebhookError},
+    },
+};
+
+use super::{ImportResult, data::public::ImportError};
+
+fn zipped(contents: &str) -> Vec<u8> {
+    let mut zip = ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    zip.start_file(EXPORT_FILE_NAME, SimpleFileOptions::default())
+        .unwrap();
+    zip.write_all(contents.as_bytes()).unwrap();
+    zip.finish().unwrap().into_inner()
+}
+
+#[rocket::async_test]
+async fn import_merges() {
+    // the server moved from.
+    let old = MemoryRepository::default();
+    let (_, old_session) = signed_up(&old).await;
+    let data = UserData {
+        app_usage: vec![AppInfo::new("io1", 2, 0), AppInfo::new("io2", 5, 10)],
+        debug: vec![UserDebug {
+            stored: "debug1".to_string(),
+        }],
+        ..Default::default()
+    };
+    sync_data(
+        &old,
+        &SyncEvents::default(),
+        &old_session.id,
+        None,
+        Some(data.clone()),
+    )
+    .await
+    .unwrap();
+    let exported = json::to_string(&export(&old, &old_session.id).await.unwrap()).unwrap();
+
+    // the server moved to, with some data already.
+    let new = MemoryRepository::default();
+    let req = AuthRequest::random_valid();
+    let (_, session) = signed_up_as(&new, &req).await;
+    let existing = UserData {
+        app_usage: vec![AppInfo::new("io1", 2, 0), AppInfo::new("io3", 1, 0)],
+        debug: vec![],
+        ..Default::default()
+    };
+    sync_data(
+        &new,
+        &SyncEvents::default(),
+        &session.id,
+        None,
+        Some(existing),
+    )
+    .await
+    .unwrap();
+
+    let summary = super::import(&new, &http_client(true), &session.id, exported.as_bytes())
+        .await
+        .unwrap();
+    assert_eq!(summary.sync.failed, 0);
+    // equal entries are not duplicated.
+    assert_eq!(summary.sync.data.app_usage.len(), 3);
+    assert_eq!(summary.sync.data.debug, data.debug);
+
+    // importing again changes nothing, and zips work too.
+    let again = super::import(&new, &http_client(true), &session.id, &zipped(&exported))
+        .await
+        .unwrap();
+    assert_eq!(again.sync.data, summary.sync.data);
+
+    // from the command line, by username.
+    let by_name = super::import_for_username(&new, &req.username, exported.as_bytes())
+        .await
+        .unwrap();
+    assert_eq!(by_name.sync.data, summary.sync.data);
+    assert!(matches!(
+        super::import_for_username(&new, "nobody-here", exported.as_bytes())
+            .await
+            .unwrap_err(),
+        ImportError::UserNotFound
+    ));
+}
+
+#[rocket::async_test]
+async fn import_rejects() {
+    let repo = MemoryRepository::default();
+    let (_, session) = signed_up(&repo).await;
+    let exported = export(&repo, &session.id).await.unwrap();
+
+    // newer, or unknown, schema versions.
+    for schema_version in [0, EXPORT_SCHEMA_VERSION + 1] {
+        let mut export = exported.clone();
+        export.schema_version = schema_version;
+        let archive = json::to_string(&export).unwrap();
+
+        let err = super::import(&repo, &http_client(true), &session.id, archive.as_bytes())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            ImportError::UnsupportedSchemaVersion { found, supported }
+                if found == schema_version && supported == EXPORT_SCHEMA_VERSION
+        ));
+    }
+
+    // not an export.
+    for archive in [&b"not json"[..], b"{}", &zipped("{}"), b"PK\x03\x04broken"] {
+        assert!(matches!(
+            super::import(&repo, &http_client(true), &session.id, archive)
+                .await
+                .unwrap_err(),
+            ImportError::InvalidArchive(_)
+        ));
+    }
+
+    // usage on days that are not dates, before anything is stored.
+    let mut invalid = exported.clone();
+    invalid.data.app_usage = vec![AppInfo::new("io1", 60, 0)];
+    invalid.usage_history = vec![UsageDay {
+        device_id: 0,
+        app_name: "io1".to_string(),
+        day: "yesterday".to_string(),
+        app_usage: 60,
+        app_limit: 0,
+    }];
+    let archive = json::to_string(&invalid).unwrap();
+    assert!(matches!(
+        super::import(&repo, &http_client(true), &session.id, archive.as_bytes())
+            .await
+            .unwrap_err(),
+        ImportError::InvalidDate(day) if day == "yesterday"
+    ));
+    assert!(
+        export(&repo, &session.id)
+            .await
+            .unwrap()
+            .data
+            .app_usage
+            .is_empty()
+    );
+
+    // zip bombs are not extracted.
+    let bomb = zipped(&" ".repeat(usize::try_from(super::MAX_EXTRACTED_SIZE).unwrap() + 1));
+    assert!(bomb.len() < 1024 * 1024);
+    assert!(matches!(
+        super::import(&repo, &http_client(true), &session.id, &bomb)
+            .await
+            .unwrap_err(),
+        ImportError::TooLarge
+    ));
+
+    // read only tokens cannot import.
+    let archive = json::to_string(&exported).unwrap();
+    let read_only = tokens::create(
+        &repo,
+        &session.id,
+        &NewTokenRequest {
+            name: "dashboard".to_string(),
+            scope: TokenScope::ReadUsage,
+        },
+    )
+    .await
+    .unwrap();
+    assert!(matches!(
+        super::import(
+            &repo,
+            &http_client(true),
+            &read_only.token,
+            archive.as_bytes()
+        )
+        .await
+        .unwrap_err(),
+        ImportError::InsufficientScope
+    ));
+    assert!(matches!(
+        super::import(
+            &repo,
+            &http_client(true),
+            "not-a-session",
+            archive.as_bytes()
+        )
+        .await
+        .unwrap_err(),
+        ImportError::InvalidSession
+    ));
+}
+
+#[macros::rocket_test]
+fn export_then_import() {
+    let signup = |device| {
+        client
+            .post("/auth/signup")
+            .json(&AuthRequest {
+                device,
+                ..AuthRequest::random_valid()
+            })
+            .dispatch()
+            .into_json::<AuthResult>()
+            .unwrap()
+            .unwrap()
+    };
+    let from = signup(Some(DeviceInfo {
+        name: "laptop".to_string(),
+        platform: "windows".to_string(),
+    }));
+    let to = signup(None);
+
+    let data = UserData {
+        app_usage: vec![AppInfo::new("io1", 2, 0)],
+        debug: vec![],
+        ..Default::default()
+    };
+    client
+        .post(format!(
+            "/sync/{}?device={}",
+            from.id,
+            from.device_id.unwrap()
+        ))
+        .json(&Some(data.clone()))
+        .dispatch();
+
+    let webhook = client
+        .post(format!("/webhooks/{}", from.id))
+        .json(&NewWebhookRequest {
+            url: "https://1.1.1.1/hook".to_string(),
+            events: vec![],
+        })
+        .dispatch()
+        .into_json::<Result<CreatedWebhook, WebhookError>>()
+        .unwrap()
+        .unwrap();
+
+    let export = |session: &str| {
+        client
+            .get(format!("/export/{session}"))
+            .dispatch()
+            .into_json::<UserExport>()
+            .unwrap()
+    };
+    let archive = client
+        .get(format!("/export/{}?format=zip", from.id))
+        .dispatch()
+        .into_bytes()
+        .unwrap();
+    let import = || {
+        client
+            .post(format!("/import/{}", to.id))
+            .header(ContentType::ZIP)
+            .body(&archive)
+            .dispatch()
+            .into_json::<ImportResult>()
+            .unwrap()
+            .unwrap()
+    };
+    let summary = import();
+    assert_eq!(summary.sync.data, data);
+
+    // webhooks are registered again, with a new secret.
+    assert_eq!(summary.webhooks.len(), 1);
+    assert_eq!(summary.webhooks[0].info.url, webhook.info.url);
+    assert_ne!(summary.webhooks[0].secret, webhook.secret);
+    assert!(import().webhooks.is_empty());
+
+    // usage keeps its device, which is added by name.
+    let (exported, imported) = (export(&from.id), export(&to.id));
+    assert!(
+        !json::to_string(&exported)
+            .unwrap()
+            .contains(&webhook.secret)
+    );
+    assert_eq!(
+        imported.webhooks,
+        vec![Webhook {
+            id: summary.webhooks[0].info.id.clone(),
+            created_at: imported.webhooks[0].created_at,
+            ..webhook.info
+        }]
+    );
+    assert_eq!(imported.devices.len(), 1);
+    assert_eq!(imported.devices[0].name, "laptop");
+    assert_eq!(imported.usage_history.len(), 1);
+    assert_eq!(
+        imported.usage_history[0],
+        UsageDay {
+            device_id: imported.devices[0].id,
+            ..exported.usage_history[0].clone()
+        }
+    );
+    assert_eq!(imported.usage_history[0].app_usage, 2);
+}
@@ -1,4 +1,4 @@
-/// The authentication endpoint.
+/// The authentication endpoints, `/auth/login` and `/auth/signup`.
 ///
 /// # Receives:
 /// An username and password. Or, a [`AuthRequest`].
@@ -7,11 +7,99 @@
 /// In Json, the requested user's session if ok, else an [`AuthError`]. Or, a [`Json<Result<UserSession, AuthError>>`]
 pub mod auth;
 
-// TODO: docs
+/// The username change endpoint, `/auth/change_username/<session_id>`.
+///
+/// # Receives:
+/// The new username and the user's password. Or, a [`ChangeUsernameRequest`](change_username::ChangeUsernameRequest).
+///
+/// # Returns:
+/// In Json, the normalized new username if ok, else an [`AuthError`](auth::data::public::AuthError).
+pub mod change_username;
+
+/// The account deletion endpoint, `/auth/delete_account/<session_id>`.
+///
+/// # Receives:
+/// The user's password. Or, a [`DeleteAccountRequest`](delete_account::DeleteAccountRequest).
+///
+/// # Returns:
+/// In Json, when the account will be purged if ok, else a [`DeleteAccountError`](delete_account::DeleteAccountError).
+/// Logging in before then restores it.
 pub mod delete_account;
 
+/// The device endpoints, `/devices/<session_id>` to list and `/devices/<session_id>/<device_id>` to delete.
+///
+/// Devices are registered when logging in with a [`DeviceInfo`](devices::data::public::DeviceInfo), and usage
+/// is synced from one with its id, see [`sync`]. A deleted device's usage is kept as synced without a device,
+/// unless `?keep_usage=false`.
+///
+/// # Returns:
+/// In Json, the user's [`Device`](devices::data::public::Device)s when listing if ok, else a
+/// [`DeviceError`](devices::data::public::DeviceError).
+pub mod devices;
+
+/// The personal data export endpoint, `/export/<session_id>`.
+///
+/// # Receives:
+/// The `session_id` of the requested user, or an api token, and an optional `format`, `json` or `zip`.
+///
+/// # Returns:
+/// A [`UserExport`](export::data::public::UserExport) file to download if ok, else, in Json, an
+/// [`ExportError`](export::data::public::ExportError).
+pub mod export;
+
+/// The data import endpoint, `/import/<session_id>`.
+///
+/// # Receives:
+/// The `session_id` of the requested user, or an api token, and a [`UserExport`](export::data::public::UserExport),
+/// as JSON or ZIP.
+///
+/// # Returns:
+/// In Json, an [`ImportSummary`](import::data::public::ImportSummary) if ok, the final stored user data as with
+/// [`sync`] and the webhooks registered, else an [`ImportError`](import::data::public::ImportError).
+pub mod import;
+
+/// The totp endpoints, `/auth/totp/enroll/<session_id>` and `/auth/totp/confirm/<session_id>`.
+///
+/// Once confirmed, logging in returns a challenge to be answered with a code at `/auth/login/totp`.
+pub mod totp;
+
+/// The due reminders endpoint, `/reminders/<session_id>/due`.
+///
+/// Reminders are synced as part of [`UserData`](sync::data::public::UserData), see
+/// [`Reminder`](sync::data::public::Reminder).
+///
+/// # Receives:
+/// The `session_id` of the requested user, or an api token, and an optional `device` id.
+///
+/// # Returns:
+/// In Json, the [`DueReminder`](reminders::data::public::DueReminder)s if ok, else a
+/// [`ReminderError`](reminders::data::public::ReminderError).
+pub mod reminders;
+
 pub mod reset_session;
 
+/// The api token endpoints, under `/auth/tokens/<session_id>`.
+///
+/// Tokens are named and scoped, see [`TokenScope`](tokens::data::public::TokenScope),
+/// and are accepted in place of a session id by [`sync`].
+pub mod tokens;
+
+/// The usage history endpoints, `/usage/<session_id>/csv`, `/usage/<session_id>/stats`
+/// and `/usage/<session_id>/limits`, and the app catalog, `/usage/catalog`.
+///
+/// Usage is recorded by day as it is synced, see [`sync`], and reported by canonical app, see
+/// [`AppAlias`](sync::data::public::AppAlias).
+///
+/// # Receives:
+/// The `session_id` of the requested user, or an api token, and optional `from` and `to` dates, as `YYYY-MM-DD`.
+///
+/// # Returns:
+/// A CSV file of `app,date,usage_seconds,limit_seconds` lines to download, or, in Json,
+/// [`UsageStats`](usage::data::public::UsageStats) or today's
+/// [`LimitEvaluation`](usage::data::public::LimitEvaluation) if ok. Else, in Json, an
+/// [`UsageError`](usage::data::public::UsageError).
+pub mod usage;
+
 pub mod validate_session;
 
 // TODO: reset password
@@ -20,10 +108,21 @@ pub mod validate_session;
 ///
 /// # Receives:
 /// The `session_id` of the requested user **and** the client's optional local [`UserData`]. (a [`Option<UserData>`])
+/// Optionally, the `device` id synced from.
 ///
 /// # Returns:
 /// In Json, the final stored user data if ok, else an [`SyncError`]. Or, a [`Json<Result<UserData, SyncError>>`]
+///
+/// Devices can listen at `/sync/<credential>/events?<device>` to be told, as server-sent events, when another device's
+/// sync changed the stored data, see [`DataChanged`](sync::data::public::DataChanged).
 pub mod sync;
 
 #[cfg(test)]
-pub mod sql;
\ No newline at end of file
+pub mod sql;
+
+/// The webhook endpoints, under `/webhooks/<session_id>`.
+///
+/// Webhooks receive the user's events as a [`Notification`](crate::notify::data::public::Notification),
+/// signed with their secret, see [`CreatedWebhook`](webhooks::data::public::CreatedWebhook). Failed deliveries
+/// are retried, and logged at `/webhooks/<session_id>/<webhook_id>/deliveries`.
+pub mod webhooks;
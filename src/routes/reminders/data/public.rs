@@ -0,0 +1,32 @@
+use pcupback::DBErrorKind;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// A [`Reminder`](crate::routes::sync::data::public::Reminder) that is due.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct DueReminder {
+    /// The reminder's name.
+    pub name: String,
+    /// The app or category it is due for. [`None`] for a break from all apps.
+    pub target: Option<String>,
+    /// The target's usage today, in seconds.
+    pub usage: u32,
+    /// How many times it has been due today. Breaks are due again every interval, clients remind again
+    /// when this grows.
+    pub occurrence: u32,
+}
+
+#[derive(Error, Debug, Serialize, Deserialize)]
+pub enum ReminderError {
+    /// No such session or api token.
+    #[error("InvalidSession")]
+    InvalidSession,
+    /// The api token's scope does not allow the request.
+    #[error("InsufficientScope")]
+    InsufficientScope,
+    /// The user has no device with the id.
+    #[error("UnknownDevice")]
+    UnknownDevice,
+    #[error("DBError")]
+    DBError(#[from] DBErrorKind),
+}
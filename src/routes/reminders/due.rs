@@ -0,0 +1,195 @@
+use crate::routes::{
+    sync::data::private::{DBCategoryApp, DBReminder, ReminderKind},
+    usage::data::{
+        private::DBUsageDay,
+        public::{LimitEvaluation, LimitState},
+    },
+};
+
+use super::data::public::DueReminder;
+
+/// The reminders of `reminders` that are due, by name.
+///
+/// Limits are as evaluated in `limits`, across devices. Breaks count `today`'s usage, on the device reminded,
+/// with apps in their categories as in `category_apps`. All by canonical app.
+pub(crate) fn due(
+    reminders: &[DBReminder],
+    limits: &LimitEvaluation,
+    today: &[DBUsageDay],
+    category_apps: &[DBCategoryApp],
+) -> Vec<DueReminder> {
+    let mut due = Vec::new();
+
+    for reminder in reminders {
+        let target = reminder.target.as_deref();
+        match reminder.kind {
+            ReminderKind::LimitPercent => {
+                // `(target, limit, usage)`, of the apps with their own limit, and the limited categories.
+                let apps = limits
+                    .apps
+                    .iter()
+                    .filter(|a| a.limit > 0 && a.state != LimitState::Blocked)
+                    .map(|a| (a.app.as_str(), a.limit, a.usage));
+                let categories = limits
+                    .categories
+                    .iter()
+                    .map(|c| (c.name.as_str(), c.limit, c.usage));
+
+                due.extend(
+                    categories
+                        .chain(apps)
+                        .filter(|(name, ..)| target.is_none_or(|target| target == *name))
+                        .filter(|(_, limit, usage)| {
+                            u64::from(*usage) * 100
+                                >= u64::from(*limit) * u64::from(reminder.threshold)
+                        })
+                        .map(|(name, _, usage)| DueReminder {
+                            name: reminder.name.clone(),
+                            target: Some(name.to_string()),
+                            usage,
+                            occurrence: 1,
+                        }),
+                );
+            }
+            ReminderKind::Break => {
+                let in_target = |app: &str| {
+                    target.is_none_or(|target| {
+                        app == target
+                            || category_apps
+                                .iter()
+                                .any(|a| a.category == target && a.app_name == app)
+                    })
+                };
+                let usage = today
+                    .iter()
+                    .filter(|u| in_target(&u.app_name))
+                    .fold(0u32, |sum, u| sum.saturating_add(u.app_usage));
+
+                let occurrence = usage / reminder.threshold.saturating_mul(60).max(1);
+                if occurrence > 0 {
+                    due.push(DueReminder {
+                        name: reminder.name.clone(),
+                        target: reminder.target.clone(),
+                        usage,
+                        occurrence,
+                    });
+                }
+            }
+        }
+    }
+
+    due
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::routes::{
+        sync::data::private::{DBCategoryApp, DBReminder, ReminderKind},
+        usage::data::{
+            private::DBUsageDay,
+            public::{CategoryStatus, LimitEvaluation, LimitState, LimitStatus},
+        },
+    };
+
+    use super::super::data::public::DueReminder;
+
+    fn reminder(
+        name: &str,
+        kind: ReminderKind,
+        target: Option<&str>,
+        threshold: u32,
+    ) -> DBReminder {
+        DBReminder {
+            user_id: 1,
+            name: name.to_string(),
+            kind,
+            target: target.map(ToString::to_string),
+            threshold,
+        }
+    }
+
+    fn usage(app: &str, app_usage: u32) -> DBUsageDay {
+        DBUsageDay {
+            user_id: 1,
+            device_id: 1,
+            app_name: app.to_string(),
+            day: "2025-05-01".to_string(),
+            app_usage,
+            app_limit: 0,
+        }
+    }
+
+    #[test]
+    fn due() {
+        let status = |app: &str, limit, usage| LimitStatus {
+            app: app.to_string(),
+            category: None,
+            limit,
+            usage,
+            remaining: limit.saturating_sub(usage),
+            state: LimitState::Ok,
+        };
+        let limits = LimitEvaluation {
+            resets_at: 0,
+            apps: vec![
+                status("chat", 1000, 850),
+                status("games", 1000, 100),
+                // limited by its category only.
+                status("editor", 0, 3000),
+            ],
+            categories: vec![CategoryStatus {
+                name: "work".to_string(),
+                limit: 3600,
+                usage: 3000,
+                remaining: 600,
+                state: LimitState::Ok,
+            }],
+        };
+        let today = [
+            usage("editor", 3000),
+            usage("terminal", 1000),
+            usage("chat", 200),
+        ];
+        let work = [
+            DBCategoryApp {
+                user_id: 1,
+                category: "work".to_string(),
+                app_name: "terminal".to_string(),
+            },
+            DBCategoryApp {
+                user_id: 1,
+                category: "work".to_string(),
+                app_name: "editor".to_string(),
+            },
+        ];
+        let reminders = [
+            reminder("any at 80%", ReminderKind::LimitPercent, None, 80),
+            reminder(
+                "games at 50%",
+                ReminderKind::LimitPercent,
+                Some("games"),
+                50,
+            ),
+            reminder("work break", ReminderKind::Break, Some("work"), 50),
+            reminder("screen break", ReminderKind::Break, None, 60),
+            reminder("chat break", ReminderKind::Break, Some("chat"), 10),
+        ];
+
+        let due = |name: &str, target: Option<&str>, usage, occurrence| DueReminder {
+            name: name.to_string(),
+            target: target.map(ToString::to_string),
+            usage,
+            occurrence,
+        };
+        assert_eq!(
+            super::due(&reminders, &limits, &today, &work),
+            [
+                due("any at 80%", Some("work"), 3000, 1),
+                due("any at 80%", Some("chat"), 850, 1),
+                // 4000s is 1h 6m of work, a break every 50m.
+                due("work break", Some("work"), 4000, 1),
+                due("screen break", None, 4200, 1),
+            ]
+        );
+    }
+}
@@ -0,0 +1,116 @@
+/// Data structs regarding reminders shared in requests
+pub mod data;
+/// Evaluating which reminders are due.
+mod due;
+
+#[cfg(test)]
+mod tests;
+
+use chrono::{DateTime, Utc};
+use data::public::{DueReminder, ReminderError};
+use pcupback::DBErrorKind::SelectError;
+use rocket::{State, get, serde::json::Json};
+use sqlx::{Pool, Sqlite};
+use tracing::instrument;
+
+use crate::{
+    config::LimitConfig,
+    repo::{DeviceRepository, SessionRepository, TokenRepository, UsageRepository},
+    util::{
+        auth::{CredentialError, authenticate},
+        db::PoolStateExt,
+    },
+};
+
+use super::{
+    tokens::data::public::TokenScope,
+    usage::{self, aliases::Aliases},
+};
+
+type DueRemindersResult = Result<Vec<DueReminder>, ReminderError>;
+
+/// The reminders of the owner of `credential`, a session id or an api token, due now.
+///
+/// Breaks count the usage of `device`, as synced from it, or of all devices if not given.
+#[instrument(skip_all)]
+#[get("/reminders/<credential>/due?<device>")]
+pub async fn due_reminders(
+    state: &State<Pool<Sqlite>>,
+    limit_config: &State<LimitConfig>,
+    credential: &str,
+    device: Option<u32>,
+) -> Json<DueRemindersResult> {
+    Json(due(state.to_db(), limit_config, credential, device, Utc::now()).await)
+}
+
+/// Evaluate which reminders of the owner of `credential` are due at `now`, on `device`.
+pub(crate) async fn due(
+    repo: &(impl SessionRepository + TokenRepository + UsageRepository + DeviceRepository),
+    config: &LimitConfig,
+    credential: &str,
+    device: Option<u32>,
+    now: DateTime<Utc>,
+) -> DueRemindersResult {
+    use ReminderError::{DBError, InsufficientScope, InvalidSession, UnknownDevice};
+
+    let user_id = match authenticate(repo, credential, TokenScope::ReadUsage).await {
+        Ok(user_id) => user_id,
+        Err(CredentialError::Invalid) => {
+            tracing::info!("session was invalid");
+            return Err(InvalidSession);
+        }
+        Err(CredentialError::InsufficientScope) => return Err(InsufficientScope),
+    };
+    let select_error = |err: sqlx::Error| DBError(SelectError(err.to_string()));
+
+    if let Some(device) = device {
+        let devices = repo.fetch_devices(user_id).await.map_err(select_error)?;
+        if !devices.iter().any(|d| d.id == device) {
+            tracing::info!("user {user_id} has no device {device}");
+            return Err(UnknownDevice);
+        }
+    }
+
+    let mut reminders = repo.fetch_reminders(user_id).await.map_err(select_error)?;
+    if reminders.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let limits = usage::limits_of(repo, config, user_id, now)
+        .await
+        .map_err(select_error)?;
+    let aliases = Aliases::new(
+        &repo
+            .fetch_app_aliases(user_id)
+            .await
+            .map_err(select_error)?,
+    );
+    let timezone = usage::timezone_of(repo, user_id)
+        .await
+        .map_err(select_error)?;
+    let day = Some(usage::local_time(now, timezone).date_naive());
+    let today = aliases.aggregate(
+        repo.fetch_usage_history(user_id, device, day, day)
+            .await
+            .map_err(select_error)?,
+    );
+    let categories = repo.fetch_categories(user_id).await.map_err(select_error)?;
+    let mut category_apps = repo
+        .fetch_category_apps(user_id)
+        .await
+        .map_err(select_error)?;
+    category_apps
+        .iter_mut()
+        .for_each(|a| aliases.rename(&mut a.app_name));
+
+    // targets are apps by any of their names, or categories.
+    for target in reminders.iter_mut().filter_map(|r| r.target.as_mut()) {
+        if !categories.iter().any(|c| &c.name == target) {
+            aliases.rename(target);
+        }
+    }
+
+    let due = due::due(&reminders, &limits, &today, &category_apps);
+    tracing::info!("{} reminders due for user {user_id}", due.len());
+    Ok(due)
+}
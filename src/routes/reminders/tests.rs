@@ -0,0 +1,102 @@
+use crate::routes::{
+    auth::{AuthResult, data::public::AuthRequest},
+    devices::data::public::DeviceInfo,
+    sync::{
+        SyncResult,
+        data::public::{AppInfo, Reminder, ReminderRule, UserData},
+    },
+};
+
+use super::data::public::{DueReminder, ReminderError};
+
+fn reminder(name: &str, target: Option<&str>, rule: ReminderRule) -> Reminder {
+    Reminder {
+        name: name.to_string(),
+        target: target.map(str::to_string),
+        rule,
+    }
+}
+
+#[macros::rocket_test]
+fn due_reminders() {
+    let session = client
+        .post("/auth/signup")
+        .json(&AuthRequest {
+            device: Some(DeviceInfo {
+                name: "laptop".to_string(),
+                platform: "linux".to_string(),
+            }),
+            ..AuthRequest::random_valid()
+        })
+        .dispatch()
+        .into_json::<AuthResult>()
+        .unwrap()
+        .unwrap();
+    let laptop = session.device_id.unwrap();
+
+    let reminders = vec![
+        reminder(
+            "almost done",
+            Some("io1"),
+            ReminderRule::LimitPercent { percent: 80 },
+        ),
+        reminder("stretch", None, ReminderRule::Break { every_minutes: 20 }),
+        reminder(
+            "not yet",
+            Some("io2"),
+            ReminderRule::LimitPercent { percent: 80 },
+        ),
+        // invalid, counted as failed.
+        reminder("", None, ReminderRule::Break { every_minutes: 20 }),
+        reminder("never", None, ReminderRule::LimitPercent { percent: 0 }),
+    ];
+    let summary = client
+        .post(format!("/sync/{}?device={laptop}", session.id))
+        .json(&Some(UserData {
+            app_usage: vec![
+                AppInfo::new("io1", 2700, 3000),
+                AppInfo::new("io2", 60, 3000),
+            ],
+            reminders,
+            ..Default::default()
+        }))
+        .dispatch()
+        .into_json::<SyncResult>()
+        .unwrap()
+        .unwrap();
+    assert_eq!(summary.failed, 2);
+    assert_eq!(summary.data.reminders.len(), 3);
+
+    let due = |query: &str| {
+        client
+            .get(format!("/reminders/{}/due?{query}", session.id))
+            .dispatch()
+            .into_json::<Result<Vec<DueReminder>, ReminderError>>()
+            .unwrap()
+    };
+    let mut due_names: Vec<_> = due(&format!("device={laptop}"))
+        .unwrap()
+        .into_iter()
+        .map(|d| (d.name, d.occurrence))
+        .collect();
+    due_names.sort();
+    // 2760s of use is 2 breaks of 20 minutes.
+    assert_eq!(
+        due_names,
+        [("almost done".to_string(), 1), ("stretch".to_string(), 2)]
+    );
+
+    assert!(matches!(
+        due("device=12345").unwrap_err(),
+        ReminderError::UnknownDevice
+    ));
+    assert!(matches!(
+        client
+            .get("/reminders/not-a-session/due")
+            .dispatch()
+            .into_json::<Result<Vec<DueReminder>, ReminderError>>()
+            .unwrap()
+            .unwrap_err(),
+        ReminderError::InvalidSession
+    ));
+}
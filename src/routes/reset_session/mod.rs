@@ -1,11 +1,11 @@
-use pcupback::{DBErrorKind, Fetchable};
+use pcupback::DBErrorKind;
 use rocket::{State, put, serde::json::Json};
 use serde::{Deserialize, Serialize};
 use sqlx::{Pool, Sqlite};
 use thiserror::Error;
 
 use crate::{
-    routes::auth::data::private::DBUserSession,
+    repo::{SessionRepository, UserRepository},
     util::{auth::generate_store_session, db::PoolStateExt},
 };
 
@@ -30,24 +30,31 @@ pub async fn reset_session(
     state: &State<Pool<Sqlite>>,
     session_id: &str,
 ) -> Json<ResetSessionResult> {
-    use DBErrorKind::InsertError;
-    use ResetSessionError::{DBError, InvalidSession};
+    Json(reset(state.to_db(), session_id).await)
+}
 
-    let db = state.to_db();
+/// Replace `session_id` with a newly generated session.
+pub(crate) async fn reset(
+    repo: &(impl SessionRepository + UserRepository),
+    session_id: &str,
+) -> ResetSessionResult {
+    use DBErrorKind::{InsertError, SelectError};
+    use ResetSessionError::{DBError, InvalidSession};
 
-    let session = DBUserSession::fetch_one(session_id, db).await;
+    let session = repo.fetch_session(session_id).await;
 
     let Ok(session) = session else {
         // no such session.
         tracing::info!("session was invalid");
-        return Json(Err(InvalidSession));
+        return Err(InvalidSession);
     };
 
-    let user_id = session.user_id;
-
-    let new_session = generate_store_session(db, user_id)
+    let user = repo
+        .fetch_user_by_id(session.user_id)
         .await
-        .map_err(|err| DBError(InsertError(err.to_string())));
+        .map_err(|err| DBError(SelectError(err.to_string())))?;
 
-    Json(new_session)
+    generate_store_session(repo, &user)
+        .await
+        .map_err(|err| DBError(InsertError(err.to_string())))
 }
@@ -9,14 +9,11 @@ use super::ResetSessionResult;
 
 #[macros::rocket_test]
 fn reset_session() {
-    let user = AuthRequest {
-        username: "ppkxddddd".to_string(),
-        password: "12345678".to_string(),
-    };
+    let user = AuthRequest::random_valid();
 
     // create the user
     let create = client
-        .post("/auth")
+        .post("/auth/signup")
         .json(&user)
         .dispatch()
         .into_json::<AuthResult>()
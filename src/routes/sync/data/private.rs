@@ -1,9 +1,12 @@
-use pcupback::{Fetchable, Storable};
+use pcupback::{FetchableAll, Storable};
 use sqlx::{Executor, FromRow, Sqlite, sqlite::SqliteQueryResult};
 
-use super::public::{AppInfo, UserDebug};
+use super::public::{
+    AppAlias, AppInfo, BlockedWindow, LimitOverride, Reminder, ReminderRule, UserDebug, Weekday,
+    WeekdayLimit,
+};
 
-#[derive(Debug, FromRow, PartialEq, Eq)]
+#[derive(Debug, FromRow, PartialEq, Eq, Clone)]
 pub struct DBAppInfo {
     pub user_id: u32,
     pub app_name: String,
@@ -11,7 +14,7 @@ pub struct DBAppInfo {
     pub app_limit: u32,
 }
 
-#[derive(Debug, FromRow, PartialEq, Eq)]
+#[derive(Debug, FromRow, PartialEq, Eq, Clone)]
 pub struct DBUserDebug {
     pub user_id: u32,
     pub stored: String,
@@ -97,42 +100,420 @@ impl<'a> Storable<'a> for DBAppInfo {
     }
 }
 
-impl<'a> Fetchable<'a, u32> for DBAppInfo {
+impl<'a> FetchableAll<'a, u32> for DBAppInfo {
     type DB = Sqlite;
 
-    async fn fetch_one<E>(_filter: u32, _executor: E) -> Result<Self, sqlx::Error>
+    async fn fetch_all<E>(filter: u32, executor: E) -> Result<Vec<Self>, sqlx::Error>
     where
         E: Executor<'a, Database = Self::DB>,
     {
-        unimplemented!()
+        sqlx::query_as("SELECT * FROM app_info WHERE user_id = ?")
+            .bind(filter)
+            .fetch_all(executor)
+            .await
     }
+}
+
+impl<'a> FetchableAll<'a, u32> for DBUserDebug {
+    type DB = Sqlite;
 
     async fn fetch_all<E>(filter: u32, executor: E) -> Result<Vec<Self>, sqlx::Error>
     where
         E: Executor<'a, Database = Self::DB>,
     {
-        sqlx::query_as("SELECT * FROM app_info WHERE user_id = ?")
+        sqlx::query_as("SELECT * FROM user_debug WHERE user_id = ?")
             .bind(filter)
             .fetch_all(executor)
             .await
     }
 }
 
-impl<'a> Fetchable<'a, u32> for DBUserDebug {
+#[derive(Debug, FromRow, PartialEq, Eq, Clone)]
+pub struct DBWeekdayLimit {
+    pub user_id: u32,
+    pub app_name: String,
+    /// See [`Weekday::index`].
+    pub weekday: u8,
+    pub app_limit: u32,
+}
+
+impl DBWeekdayLimit {
+    /// Create a [`DBWeekdayLimit`] from a [`WeekdayLimit`] by supplying a `user_id`.
+    #[must_use]
+    pub fn with_weekday_limit(user_id: u32, limit: WeekdayLimit) -> Self {
+        Self {
+            user_id,
+            app_name: limit.app,
+            weekday: limit.weekday.index(),
+            app_limit: limit.limit,
+        }
+    }
+
+    /// The stored weekday. The schema only allows valid ones.
+    #[must_use]
+    pub fn weekday(&self) -> Weekday {
+        Weekday::from_index(self.weekday).unwrap_or(Weekday::Monday)
+    }
+}
+
+#[derive(Debug, FromRow, PartialEq, Eq, Clone)]
+pub struct DBBlockedWindow {
+    pub user_id: u32,
+    pub app_name: String,
+    pub start_minute: u32,
+    pub end_minute: u32,
+    /// Bit `n` is the day `n` days after monday, see [`Weekday::index`].
+    pub weekdays: u8,
+}
+
+impl DBBlockedWindow {
+    /// Create a [`DBBlockedWindow`] from a [`BlockedWindow`] by supplying a `user_id`.
+    #[must_use]
+    pub fn with_blocked_window(user_id: u32, window: BlockedWindow) -> Self {
+        Self {
+            user_id,
+            app_name: window.app,
+            start_minute: window.start,
+            end_minute: window.end,
+            weekdays: window
+                .weekdays
+                .iter()
+                .fold(0, |days, day| days | 1 << day.index()),
+        }
+    }
+
+    /// The days the window starts on, monday first.
+    #[must_use]
+    pub fn weekdays(&self) -> Vec<Weekday> {
+        Weekday::ALL
+            .into_iter()
+            .filter(|day| self.starts_on(*day))
+            .collect()
+    }
+
+    /// Return `true` if the window starts on `day`.
+    #[must_use]
+    pub fn starts_on(&self, day: Weekday) -> bool {
+        self.weekdays & 1 << day.index() != 0
+    }
+
+    /// Return `true` if the window covers `minute` after midnight on `day`.
+    #[must_use]
+    pub fn covers(&self, day: Weekday, minute: u32) -> bool {
+        if self.start_minute < self.end_minute {
+            return self.starts_on(day) && (self.start_minute..self.end_minute).contains(&minute);
+        }
+
+        // wraps past midnight, the morning belongs to the day before.
+        let yesterday = Weekday::from_index((day.index() + 6) % 7).unwrap_or(day);
+        (self.starts_on(day) && minute >= self.start_minute)
+            || (self.starts_on(yesterday) && minute < self.end_minute)
+    }
+}
+
+#[derive(Debug, FromRow, PartialEq, Eq, Clone)]
+pub struct DBLimitOverride {
+    pub user_id: u32,
+    pub app_name: String,
+    pub app_limit: u32,
+    /// Stored as seconds since the unix epoch.
+    pub expires_at: i64,
+}
+
+impl DBLimitOverride {
+    /// Create a [`DBLimitOverride`] from a [`LimitOverride`] by supplying a `user_id`.
+    #[must_use]
+    pub fn with_limit_override(user_id: u32, limit: LimitOverride) -> Self {
+        Self {
+            user_id,
+            app_name: limit.app,
+            app_limit: limit.limit,
+            expires_at: limit.expires_at,
+        }
+    }
+
+    /// Return `true` if the override has not expired at `now`, seconds since the unix epoch.
+    #[must_use]
+    pub fn is_active(&self, now: i64) -> bool {
+        self.expires_at > now
+    }
+}
+
+#[derive(Debug, FromRow, PartialEq, Eq, Clone)]
+pub struct DBCategory {
+    pub user_id: u32,
+    pub name: String,
+    /// Stored as seconds, across all the category's apps.
+    pub category_limit: u32,
+}
+
+/// An app in a [`DBCategory`].
+#[derive(Debug, FromRow, PartialEq, Eq, Clone)]
+pub struct DBCategoryApp {
+    pub user_id: u32,
+    /// The [`DBCategory::name`].
+    pub category: String,
+    pub app_name: String,
+}
+
+#[derive(Debug, FromRow, PartialEq, Eq, Clone)]
+pub struct DBAppAlias {
+    pub user_id: u32,
+    pub alias: String,
+    /// The canonical name.
+    pub app_name: String,
+}
+
+impl DBAppAlias {
+    /// Create a [`DBAppAlias`] from an [`AppAlias`] by supplying a `user_id`.
+    #[must_use]
+    pub fn with_app_alias(user_id: u32, alias: AppAlias) -> Self {
+        Self {
+            user_id,
+            alias: alias.alias,
+            app_name: alias.app,
+        }
+    }
+}
+
+/// The kind of a [`DBReminder`], see [`ReminderRule`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy, sqlx::Type)]
+#[sqlx(rename_all = "snake_case")]
+pub enum ReminderKind {
+    LimitPercent,
+    Break,
+}
+
+#[derive(Debug, FromRow, PartialEq, Eq, Clone)]
+pub struct DBReminder {
+    pub user_id: u32,
+    pub name: String,
+    pub kind: ReminderKind,
+    pub target: Option<String>,
+    /// The percent of a [`ReminderKind::LimitPercent`], the minutes of a [`ReminderKind::Break`].
+    pub threshold: u32,
+}
+
+impl DBReminder {
+    /// Create a [`DBReminder`] from a [`Reminder`] by supplying a `user_id`.
+    #[must_use]
+    pub fn with_reminder(user_id: u32, reminder: Reminder) -> Self {
+        let (kind, threshold) = match reminder.rule {
+            ReminderRule::LimitPercent { percent } => (ReminderKind::LimitPercent, percent),
+            ReminderRule::Break { every_minutes } => (ReminderKind::Break, every_minutes),
+        };
+        Self {
+            user_id,
+            name: reminder.name,
+            kind,
+            target: reminder.target,
+            threshold,
+        }
+    }
+}
+
+impl<'a> Storable<'a> for DBWeekdayLimit {
     type DB = Sqlite;
 
-    async fn fetch_one<E>(_filter: u32, _executor: E) -> Result<Self, sqlx::Error>
+    /// Replaces the app's limit on the same weekday.
+    async fn store<E>(&self, executor: E) -> Result<SqliteQueryResult, sqlx::Error>
     where
         E: Executor<'a, Database = Self::DB>,
     {
-        unimplemented!()
+        sqlx::query!(
+            "INSERT INTO weekday_limits(user_id, app_name, weekday, app_limit) VALUES(?, ?, ?, ?)
+            ON CONFLICT(user_id, app_name, weekday) DO UPDATE SET app_limit = excluded.app_limit",
+            self.user_id,
+            self.app_name,
+            self.weekday,
+            self.app_limit
+        )
+        .execute(executor)
+        .await
     }
+}
+
+impl<'a> Storable<'a> for DBBlockedWindow {
+    type DB = Sqlite;
+
+    /// Replaces the weekdays of the app's window with the same times.
+    async fn store<E>(&self, executor: E) -> Result<SqliteQueryResult, sqlx::Error>
+    where
+        E: Executor<'a, Database = Self::DB>,
+    {
+        sqlx::query!(
+            "INSERT INTO blocked_windows(user_id, app_name, start_minute, end_minute, weekdays) VALUES(?, ?, ?, ?, ?)
+            ON CONFLICT(user_id, app_name, start_minute, end_minute) DO UPDATE SET weekdays = excluded.weekdays",
+            self.user_id,
+            self.app_name,
+            self.start_minute,
+            self.end_minute,
+            self.weekdays
+        )
+        .execute(executor)
+        .await
+    }
+}
+
+impl<'a> Storable<'a> for DBLimitOverride {
+    type DB = Sqlite;
+
+    /// Replaces the app's override.
+    async fn store<E>(&self, executor: E) -> Result<SqliteQueryResult, sqlx::Error>
+    where
+        E: Executor<'a, Database = Self::DB>,
+    {
+        sqlx::query!(
+            "INSERT INTO limit_overrides(user_id, app_name, app_limit, expires_at) VALUES(?, ?, ?, ?)
+            ON CONFLICT(user_id, app_name) DO UPDATE
+            SET app_limit = excluded.app_limit, expires_at = excluded.expires_at",
+            self.user_id,
+            self.app_name,
+            self.app_limit,
+            self.expires_at
+        )
+        .execute(executor)
+        .await
+    }
+}
+
+impl<'a> FetchableAll<'a, u32> for DBWeekdayLimit {
+    type DB = Sqlite;
 
     async fn fetch_all<E>(filter: u32, executor: E) -> Result<Vec<Self>, sqlx::Error>
     where
         E: Executor<'a, Database = Self::DB>,
     {
-        sqlx::query_as("SELECT * FROM user_debug WHERE user_id = ?")
+        sqlx::query_as("SELECT * FROM weekday_limits WHERE user_id = ? ORDER BY app_name, weekday")
+            .bind(filter)
+            .fetch_all(executor)
+            .await
+    }
+}
+
+impl<'a> FetchableAll<'a, u32> for DBBlockedWindow {
+    type DB = Sqlite;
+
+    async fn fetch_all<E>(filter: u32, executor: E) -> Result<Vec<Self>, sqlx::Error>
+    where
+        E: Executor<'a, Database = Self::DB>,
+    {
+        sqlx::query_as(
+            "SELECT * FROM blocked_windows WHERE user_id = ? ORDER BY app_name, start_minute, end_minute",
+        )
+        .bind(filter)
+        .fetch_all(executor)
+        .await
+    }
+}
+
+impl<'a> FetchableAll<'a, u32> for DBLimitOverride {
+    type DB = Sqlite;
+
+    async fn fetch_all<E>(filter: u32, executor: E) -> Result<Vec<Self>, sqlx::Error>
+    where
+        E: Executor<'a, Database = Self::DB>,
+    {
+        sqlx::query_as("SELECT * FROM limit_overrides WHERE user_id = ? ORDER BY app_name")
+            .bind(filter)
+            .fetch_all(executor)
+            .await
+    }
+}
+
+impl<'a> FetchableAll<'a, u32> for DBCategory {
+    type DB = Sqlite;
+
+    async fn fetch_all<E>(filter: u32, executor: E) -> Result<Vec<Self>, sqlx::Error>
+    where
+        E: Executor<'a, Database = Self::DB>,
+    {
+        sqlx::query_as("SELECT * FROM categories WHERE user_id = ? ORDER BY name")
+            .bind(filter)
+            .fetch_all(executor)
+            .await
+    }
+}
+
+impl<'a> FetchableAll<'a, u32> for DBCategoryApp {
+    type DB = Sqlite;
+
+    async fn fetch_all<E>(filter: u32, executor: E) -> Result<Vec<Self>, sqlx::Error>
+    where
+        E: Executor<'a, Database = Self::DB>,
+    {
+        sqlx::query_as("SELECT * FROM category_apps WHERE user_id = ? ORDER BY category, app_name")
+            .bind(filter)
+            .fetch_all(executor)
+            .await
+    }
+}
+
+impl<'a> Storable<'a> for DBAppAlias {
+    type DB = Sqlite;
+
+    /// Replaces the alias' app.
+    async fn store<E>(&self, executor: E) -> Result<SqliteQueryResult, sqlx::Error>
+    where
+        E: Executor<'a, Database = Self::DB>,
+    {
+        sqlx::query!(
+            "INSERT INTO app_aliases(user_id, alias, app_name) VALUES(?, ?, ?)
+            ON CONFLICT(user_id, alias) DO UPDATE SET app_name = excluded.app_name",
+            self.user_id,
+            self.alias,
+            self.app_name
+        )
+        .execute(executor)
+        .await
+    }
+}
+
+impl<'a> FetchableAll<'a, u32> for DBAppAlias {
+    type DB = Sqlite;
+
+    async fn fetch_all<E>(filter: u32, executor: E) -> Result<Vec<Self>, sqlx::Error>
+    where
+        E: Executor<'a, Database = Self::DB>,
+    {
+        sqlx::query_as("SELECT * FROM app_aliases WHERE user_id = ? ORDER BY alias")
+            .bind(filter)
+            .fetch_all(executor)
+            .await
+    }
+}
+
+impl<'a> Storable<'a> for DBReminder {
+    type DB = Sqlite;
+
+    /// Replaces the reminder with the same name.
+    async fn store<E>(&self, executor: E) -> Result<SqliteQueryResult, sqlx::Error>
+    where
+        E: Executor<'a, Database = Self::DB>,
+    {
+        sqlx::query!(
+            "INSERT INTO reminders(user_id, name, kind, target, threshold) VALUES(?, ?, ?, ?, ?)
+            ON CONFLICT(user_id, name) DO UPDATE
+            SET kind = excluded.kind, target = excluded.target, threshold = excluded.threshold",
+            self.user_id,
+            self.name,
+            self.kind,
+            self.target,
+            self.threshold
+        )
+        .execute(executor)
+        .await
+    }
+}
+
+impl<'a> FetchableAll<'a, u32> for DBReminder {
+    type DB = Sqlite;
+
+    async fn fetch_all<E>(filter: u32, executor: E) -> Result<Vec<Self>, sqlx::Error>
+    where
+        E: Executor<'a, Database = Self::DB>,
+    {
+        sqlx::query_as("SELECT * FROM reminders WHERE user_id = ? ORDER BY name")
             .bind(filter)
             .fetch_all(executor)
             .await
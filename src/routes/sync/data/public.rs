@@ -1,40 +1,41 @@
-use pcupback::{DBErrorKind, Fetchable};
+use pcupback::DBErrorKind;
 use serde::{Deserialize, Serialize};
-use sqlx::{Executor, Sqlite};
 use thiserror::Error;
 
-use super::private::{DBAppInfo, DBUserDebug};
+use super::private::{
+    DBAppAlias, DBAppInfo, DBBlockedWindow, DBCategory, DBCategoryApp, DBLimitOverride, DBReminder,
+    DBUserDebug, DBWeekdayLimit, ReminderKind,
+};
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Clone)]
 pub struct UserData {
     pub app_usage: Vec<AppInfo>,
     pub debug: Vec<UserDebug>,
+    /// Limits by day of the week, see [`WeekdayLimit`].
+    #[serde(default)]
+    pub weekday_limits: Vec<WeekdayLimit>,
+    #[serde(default)]
+    pub blocked_windows: Vec<BlockedWindow>,
+    /// Only the ones not expired.
+    #[serde(default)]
+    pub limit_overrides: Vec<LimitOverride>,
+    #[serde(default)]
+    pub categories: Vec<Category>,
+    /// The user's own, see [`AppAlias`].
+    #[serde(default)]
+    pub aliases: Vec<AppAlias>,
+    #[serde(default)]
+    pub reminders: Vec<Reminder>,
+    /// The IANA name of the timezone weekday limits and blocked windows are in, e.g. `Europe/Berlin`. Utc if
+    /// [`None`], syncing [`None`] keeps the stored one.
+    #[serde(default)]
+    pub timezone: Option<String>,
+    /// Entries to delete, by key, before the received ones are stored. Never sent back.
+    #[serde(default)]
+    pub removed: Vec<ScheduleKey>,
 }
 
-impl<'a> Fetchable<'a, u32> for UserData {
-    type DB = Sqlite;
-
-    /// Aggregates associated data in a [`UserData`], converts from in-db types to non-db types.
-    async fn fetch_one<E>(filter: u32, executor: E) -> Result<Self, sqlx::Error>
-    where
-        E: Executor<'a, Database = Self::DB> + Copy,
-    {
-        let app_usage: Vec<AppInfo> = DBAppInfo::fetch_all(filter, executor)
-            .await?
-            .into_iter()
-            .map(Into::into)
-            .collect();
-        let debug: Vec<UserDebug> = DBUserDebug::fetch_all(filter, executor)
-            .await?
-            .into_iter()
-            .map(Into::into)
-            .collect();
-
-        Ok(Self { app_usage, debug })
-    }
-}
-
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct UserDebug {
     pub stored: String,
 }
@@ -75,22 +76,303 @@ impl From<DBAppInfo> for AppInfo {
     }
 }
 
+/// A day of the week.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl Weekday {
+    pub const ALL: [Self; 7] = [
+        Self::Monday,
+        Self::Tuesday,
+        Self::Wednesday,
+        Self::Thursday,
+        Self::Friday,
+        Self::Saturday,
+        Self::Sunday,
+    ];
+
+    /// Days after monday, how weekdays are stored.
+    #[must_use]
+    pub fn index(self) -> u8 {
+        self as u8
+    }
+
+    /// The day `index` days after monday, [`None`] if there is no such day.
+    #[must_use]
+    pub fn from_index(index: u8) -> Option<Self> {
+        Self::ALL.get(usize::from(index)).copied()
+    }
+}
+
+impl From<chrono::Weekday> for Weekday {
+    fn from(value: chrono::Weekday) -> Self {
+        Self::ALL[value.num_days_from_monday() as usize]
+    }
+}
+
+/// An app's limit on a day of the week, replacing its [`AppInfo`] limit that day.
+///
+/// Unique per app and weekday, syncing another replaces it.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct WeekdayLimit {
+    pub app: String,
+    pub weekday: Weekday,
+    /// In seconds, `0` for no limit.
+    pub limit: u32,
+}
+
+/// A time of day an app is not allowed, e.g. no games from 09:00 to 17:00 on weekdays.
+///
+/// Times are minutes after midnight, in the user's [`UserData::timezone`]. An `end` before the `start` wraps past
+/// midnight, the time after midnight belonging to the day the window started. Unique per app, `start` and `end`,
+/// syncing another replaces its `weekdays`, none disables it.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct BlockedWindow {
+    pub app: String,
+    /// The days the window starts on.
+    pub weekdays: Vec<Weekday>,
+    /// From 0 to 1439.
+    pub start: u32,
+    /// From 0 to 1439, different from `start`.
+    pub end: u32,
+}
+
+impl BlockedWindow {
+    /// Return `true` if `start` and `end` are times of day, and different.
+    #[must_use]
+    pub fn is_valid(&self) -> bool {
+        self.start < MINUTES_PER_DAY && self.end < MINUTES_PER_DAY && self.start != self.end
+    }
+}
+
+pub(crate) const MINUTES_PER_DAY: u32 = 24 * 60;
+
+/// A limit replacing all of an app's others until it expires.
+///
+/// Unique per app, syncing another replaces it.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct LimitOverride {
+    pub app: String,
+    /// In seconds, `0` for no limit.
+    pub limit: u32,
+    /// Seconds since the unix epoch.
+    pub expires_at: i64,
+}
+
+/// A group of apps sharing a limit, e.g. "Games".
+///
+/// Unique by name, syncing another replaces it and its apps. An app is in at most one category,
+/// listing it in another moves it.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct Category {
+    pub name: String,
+    /// In seconds, across all the category's apps, `0` for no limit.
+    pub limit: u32,
+    /// The names of the apps, as in [`AppInfo`].
+    pub apps: Vec<String>,
+}
+
+impl Category {
+    /// Group stored `apps` into their stored `categories`, ordered by name.
+    #[must_use]
+    pub fn collect(categories: Vec<DBCategory>, apps: Vec<DBCategoryApp>) -> Vec<Self> {
+        let mut categories: Vec<Self> = categories
+            .into_iter()
+            .map(|category| Self {
+                name: category.name,
+                limit: category.category_limit,
+                apps: Vec::new(),
+            })
+            .collect();
+        categories.sort_by(|a, b| a.name.cmp(&b.name));
+
+        for app in apps {
+            if let Some(category) = categories.iter_mut().find(|c| c.name == app.category) {
+                category.apps.push(app.app_name);
+            }
+        }
+        for category in &mut categories {
+            category.apps.sort();
+        }
+        categories
+    }
+}
+
+/// Another name of an app, e.g. `chrome.exe` on one device and `com.android.chrome` on another are both
+/// `Google Chrome`.
+///
+/// Usage is reported by the app, see [`usage`](crate::routes::usage). They take precedence over the bundled
+/// catalog, and can rename its apps. Unique per alias, syncing another replaces it.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct AppAlias {
+    /// As in [`AppInfo`].
+    pub alias: String,
+    /// The canonical name.
+    pub app: String,
+}
+
+/// A rule of when clients remind the user, e.g. at 80% of a limit, or to take a break every 50 minutes of
+/// work apps. See [`reminders`](crate::routes::reminders) for the ones due.
+///
+/// Unique by name, syncing another replaces it.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct Reminder {
+    pub name: String,
+    /// An app, or a [`Category`]. [`None`] for all of them.
+    pub target: Option<String>,
+    #[serde(flatten)]
+    pub rule: ReminderRule,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ReminderRule {
+    /// Once `percent` of the target's limit is used today. Targets without a limit are never due.
+    LimitPercent { percent: u32 },
+    /// Every `every_minutes` of the target's usage today, on the device reminded.
+    Break { every_minutes: u32 },
+}
+
+impl Reminder {
+    /// Return `true` if it has a name, and its rule can be due: a `percent` from 1 to 100, or `every_minutes`
+    /// in a day.
+    #[must_use]
+    pub fn is_valid(&self) -> bool {
+        let threshold = match self.rule {
+            ReminderRule::LimitPercent { percent } => (1..=100).contains(&percent),
+            ReminderRule::Break { every_minutes } => (1..MINUTES_PER_DAY).contains(&every_minutes),
+        };
+        !self.name.is_empty() && threshold
+    }
+}
+
+/// The key of an entry of [`UserData`] that is unique by key, to delete it.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ScheduleKey {
+    WeekdayLimit {
+        app: String,
+        weekday: Weekday,
+    },
+    BlockedWindow {
+        app: String,
+        start: u32,
+        end: u32,
+    },
+    LimitOverride {
+        app: String,
+    },
+    /// Its apps are left without a category.
+    Category {
+        name: String,
+    },
+    AppAlias {
+        alias: String,
+    },
+    Reminder {
+        name: String,
+    },
+}
+
+impl From<DBWeekdayLimit> for WeekdayLimit {
+    fn from(value: DBWeekdayLimit) -> Self {
+        Self {
+            weekday: value.weekday(),
+            app: value.app_name,
+            limit: value.app_limit,
+        }
+    }
+}
+
+impl From<DBBlockedWindow> for BlockedWindow {
+    fn from(value: DBBlockedWindow) -> Self {
+        Self {
+            weekdays: value.weekdays(),
+            app: value.app_name,
+            start: value.start_minute,
+            end: value.end_minute,
+        }
+    }
+}
+
+impl From<DBLimitOverride> for LimitOverride {
+    fn from(value: DBLimitOverride) -> Self {
+        Self {
+            app: value.app_name,
+            limit: value.app_limit,
+            expires_at: value.expires_at,
+        }
+    }
+}
+
+impl From<DBAppAlias> for AppAlias {
+    fn from(value: DBAppAlias) -> Self {
+        Self {
+            alias: value.alias,
+            app: value.app_name,
+        }
+    }
+}
+
+impl From<DBReminder> for Reminder {
+    fn from(value: DBReminder) -> Self {
+        Self {
+            name: value.name,
+            target: value.target,
+            rule: match value.kind {
+                ReminderKind::LimitPercent => ReminderRule::LimitPercent {
+                    percent: value.threshold,
+                },
+                ReminderKind::Break => ReminderRule::Break {
+                    every_minutes: value.threshold,
+                },
+            },
+        }
+    }
+}
+
+/// Sent to a user's connected devices when another device's sync changed their stored data, see
+/// [`sync_events`](crate::routes::sync::sync_events).
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DataChanged {
+    /// The device synced from, if it was given.
+    pub device: Option<u32>,
+    /// How many received entries were stored. `0` if some changes were missed, the data should be synced anyway.
+    pub added: u32,
+}
+
 #[derive(Error, Debug, Serialize, Deserialize)]
 pub enum SyncError {
+    /// No such session or api token.
     #[error("InvalidSession")]
     InvalidSession,
+    /// The api token is read only, and the request had data to store.
+    #[error("InsufficientScope")]
+    InsufficientScope,
+    /// The user has no device with the id synced from.
+    #[error("UnknownDevice")]
+    UnknownDevice,
     #[error("DBError")]
     DBError(#[from] DBErrorKind),
 }
 
 #[cfg(test)]
 mod tests {
-    use pcupback::{Fetchable, Storable};
+    use pcupback::Storable;
     use sqlx::{Pool, Sqlite};
 
-    use crate::routes::{
-        auth::data::private::DBUser,
-        sync::data::{private::DBAppInfo, public::UserData},
+    use crate::{
+        repo::UsageRepository,
+        routes::{auth::data::private::DBUser, sync::data::private::DBAppInfo},
     };
 
     #[sqlx::test]
@@ -100,7 +382,7 @@ mod tests {
         let app_info = DBAppInfo::new_raw(1, "xddapp", 1, 0);
         app_info.store(&db).await.unwrap();
 
-        let data = UserData::fetch_one(1, &db).await.unwrap();
+        let data = db.fetch_user_data(1).await.unwrap();
         assert_eq!(data.app_usage.len(), 1);
         assert_eq!(data.app_usage[0].name, app_info.app_name);
         assert_eq!(data.app_usage[0].usage, app_info.app_usage);
@@ -0,0 +1,182 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::pin,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use rocket::{
+    futures::{Stream, StreamExt},
+    response::stream::stream,
+    tokio::{
+        select,
+        sync::broadcast::{self, Receiver, Sender, error::RecvError},
+        time::{MissedTickBehavior, interval},
+    },
+};
+
+use super::data::public::DataChanged;
+
+/// How many changes are kept for a user's devices that are slow to receive them.
+const CAPACITY: usize = 16;
+
+/// Tells the devices listening on [`sync_events`](super::sync_events) when their owner's data was changed by a sync.
+///
+/// Each user listened for has their own channel, so devices only wake for their owner's changes. It is dropped once
+/// their last device stops listening.
+#[derive(Debug, Clone, Default)]
+pub struct SyncEvents {
+    channels: Arc<Mutex<HashMap<u32, Sender<DataChanged>>>>,
+}
+
+impl SyncEvents {
+    /// Tell the devices of the user with the id `user_id` that their data changed.
+    pub fn publish(&self, user_id: u32, change: DataChanged) {
+        if let Some(sender) = self.channels().get(&user_id) {
+            // errors if no device is listening anymore, which is fine.
+            let _ = sender.send(change);
+        }
+    }
+
+    /// Listen for changes to the data of the user with the id `user_id`, made from other devices than `device`.
+    ///
+    /// Without a `device`, every change is received.
+    pub fn changes(
+        &self,
+        user_id: u32,
+        device: Option<u32>,
+    ) -> impl Stream<Item = DataChanged> + use<> {
+        let receiver = self
+            .channels()
+            .entry(user_id)
+            .or_insert_with(|| broadcast::channel(CAPACITY).0)
+            .subscribe();
+        let subscription = Subscription {
+            events: self.clone(),
+            user_id,
+            receiver: Some(receiver),
+        };
+        listen(subscription, device)
+    }
+
+    fn channels(&self) -> MutexGuard<'_, HashMap<u32, Sender<DataChanged>>> {
+        // the map is left consistent if a thread panics holding the lock.
+        self.channels
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+/// A device listening for its owner's changes, dropping their channel if it was the last one.
+struct Subscription {
+    events: SyncEvents,
+    user_id: u32,
+    /// Only [`None`] while dropped.
+    receiver: Option<Receiver<DataChanged>>,
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        let mut channels = self.events.channels();
+        drop(self.receiver.take());
+        // subscribing holds the lock, so no device can be joining.
+        if channels
+            .get(&self.user_id)
+            .is_some_and(|sender| sender.receiver_count() == 0)
+        {
+            channels.remove(&self.user_id);
+        }
+    }
+}
+
+fn listen(mut subscription: Subscription, device: Option<u32>) -> impl Stream<Item = DataChanged> {
+    stream! {
+        let user_id = subscription.user_id;
+        let Some(receiver) = subscription.receiver.as_mut() else {
+            return;
+        };
+        loop {
+            match receiver.recv().await {
+                Ok(change) => {
+                    if device.is_none() || change.device != device {
+                        yield change;
+                    }
+                }
+                Err(RecvError::Lagged(missed)) => {
+                    tracing::info!("user {user_id}'s device missed {missed} changes");
+                    yield DataChanged { device: None, added: 0 };
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    }
+}
+
+/// Pass on `changes` until `valid` resolves to `false`, checked every `period`.
+///
+/// Ends streams of credentials that were invalidated while listening, e.g. by logging out or deleting a token.
+pub(crate) fn while_valid<T, F>(
+    changes: impl Stream<Item = T>,
+    period: Duration,
+    mut valid: impl FnMut() -> F,
+) -> impl Stream<Item = T>
+where
+    F: Future<Output = bool>,
+{
+    stream! {
+        let mut changes = pin!(changes);
+        let mut checks = interval(period);
+        checks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        // the first tick is right away, the credential was just checked.
+        checks.tick().await;
+        loop {
+            select! {
+                change = changes.next() => match change {
+                    Some(change) => yield change,
+                    None => break,
+                },
+                _ = checks.tick() => {
+                    if !valid().await {
+                        tracing::info!("credential was invalidated while listening");
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::futures::{FutureExt, StreamExt};
+
+    use super::SyncEvents;
+    use crate::routes::sync::data::public::DataChanged;
+
+    #[rocket::async_test]
+    async fn channels_per_user() {
+        let events = SyncEvents::default();
+        let change = DataChanged {
+            device: None,
+            added: 1,
+        };
+        // no one is listening, no channel is kept.
+        events.publish(1, change);
+        assert!(events.channels().is_empty());
+
+        let mut first = Box::pin(events.changes(1, None));
+        let second = Box::pin(events.changes(1, None));
+        let mut other = Box::pin(events.changes(2, None));
+        assert_eq!(events.channels().len(), 2);
+
+        events.publish(1, change);
+        assert_eq!(first.next().await, Some(change));
+        assert!(other.next().now_or_never().is_none());
+
+        drop(first);
+        assert_eq!(events.channels().len(), 2);
+        drop((second, other));
+        assert!(events.channels().is_empty());
+    }
+}
@@ -1,24 +1,56 @@
 pub mod data;
 
+/// The changes pushed to connected devices.
+pub mod events;
+
 #[cfg(test)]
 mod tests;
 
+use std::time::Duration;
+
+use chrono::Utc;
+use chrono_tz::Tz;
 use data::{
-    private::{DBAppInfo, DBUserDebug},
-    public::{SyncError, UserData},
+    private::{
+        DBAppAlias, DBAppInfo, DBBlockedWindow, DBCategory, DBLimitOverride, DBReminder,
+        DBUserDebug, DBWeekdayLimit,
+    },
+    public::{Category, DataChanged, SyncError, UserData},
+};
+use events::SyncEvents;
+use rocket::{
+    Shutdown, State,
+    futures::{StreamExt, stream::BoxStream},
+    get, post,
+    response::stream::{Event, EventStream},
+    serde::json::Json,
 };
-use pcupback::{Fetchable, Storable};
-use rocket::{State, post, serde::json::Json};
 use serde::{Deserialize, Serialize};
 use sqlx::{Pool, Sqlite};
 use tracing::instrument;
 
-use crate::{routes::auth::data::private::DBUserSession, util::db::PoolStateExt};
+use crate::{
+    notify,
+    repo::{
+        DeviceRepository, OutboxRepository, SessionRepository, TokenRepository, UsageRepository,
+        UserRepository, WebhookRepository,
+    },
+    util::{
+        auth::{CredentialError, authenticate},
+        db::PoolStateExt,
+    },
+};
+
+use super::{tokens::data::public::TokenScope, usage};
 
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub struct SyncSummary {
-    data: UserData,
-    failed: u32,
+    /// The final, combined data.
+    pub data: UserData,
+    /// How many received entries were stored, or deleted, see [`UserData::removed`].
+    pub added: u32,
+    /// How many received entries could not be stored.
+    pub failed: u32,
 }
 
 pub type SyncResult = Result<SyncSummary, SyncError>;
@@ -26,31 +58,174 @@ pub type SyncResult = Result<SyncSummary, SyncError>;
 /// We want to receive the client's state,
 /// find the diff of the client state and stored state,
 /// and return the final, combined state.  
+///
+/// `credential` is a session id, or an api token. Read only tokens can only send no data.
+///
+/// `device` is the id of the device synced from, as returned when logging in.
 #[instrument(skip_all)]
-#[post("/sync/<session_id>", data = "<request_user_data>")]
+#[post("/sync/<credential>?<device>", data = "<request_user_data>")]
 pub async fn sync(
     state: &State<Pool<Sqlite>>,
-    session_id: &str,
+    events: &State<SyncEvents>,
+    credential: &str,
+    device: Option<u32>,
     request_user_data: Json<Option<UserData>>,
 ) -> Json<SyncResult> {
-    use data::public::SyncError::{DBError, InvalidSession};
-    use pcupback::DBErrorKind::SelectError;
-
     tracing::info!("got data sync request");
 
-    let db = state.to_db();
+    Json(
+        sync_data(
+            state.to_db(),
+            events,
+            credential,
+            device,
+            request_user_data.into_inner(),
+        )
+        .await,
+    )
+}
+
+/// How often [`sync_events`] checks its credential is still valid.
+const CREDENTIAL_CHECK: Duration = Duration::from_secs(60);
+
+/// Listen for changes to the data of the owner of `credential` made by other devices' syncs, as server-sent
+/// [`DataChanged`] events named `changed`, until Rocket shuts down, or `credential` is invalidated.
+///
+/// `device` is the id of the listening device, its own syncs are not sent back to it.
+#[instrument(skip_all)]
+#[get("/sync/<credential>/events?<device>")]
+pub async fn sync_events(
+    state: &State<Pool<Sqlite>>,
+    events: &State<SyncEvents>,
+    credential: &str,
+    device: Option<u32>,
+    shutdown: Shutdown,
+) -> Result<EventStream<BoxStream<'static, Event>>, Json<SyncError>> {
+    let user_id = authorize(state.to_db(), credential, TokenScope::ReadUsage, device)
+        .await
+        .map_err(Json)?;
+    tracing::info!("user {user_id} is listening for changes");
+
+    let (repo, credential) = (state.inner().clone(), credential.to_string());
+    let valid = move || {
+        let (repo, credential) = (repo.clone(), credential.clone());
+        async move { authenticate(&repo, &credential, TokenScope::ReadUsage).await == Ok(user_id) }
+    };
+    let changes = events::while_valid(events.changes(user_id, device), CREDENTIAL_CHECK, valid)
+        .take_until(shutdown)
+        .map(|change| Event::json(&change).event("changed"))
+        .boxed();
+    Ok(EventStream::from(changes))
+}
+
+/// Fetch the id of the owner of `credential`, checking it has the `required` scope and, if given, the owner has
+/// `device`, which is marked as seen.
+async fn authorize(
+    repo: &(impl SessionRepository + TokenRepository + DeviceRepository),
+    credential: &str,
+    required: TokenScope,
+    device: Option<u32>,
+) -> Result<u32, SyncError> {
+    use data::public::SyncError::{DBError, InsufficientScope, InvalidSession, UnknownDevice};
+    use pcupback::DBErrorKind::InsertError;
+
+    let user_id = match authenticate(repo, credential, required).await {
+        Ok(user_id) => user_id,
+        Err(CredentialError::Invalid) => {
+            // no such session.
+            tracing::info!("session was invalid");
+            return Err(InvalidSession);
+        }
+        Err(CredentialError::InsufficientScope) => return Err(InsufficientScope),
+    };
 
-    let session = DBUserSession::fetch_one(session_id, db).await;
+    if let Some(device) = device {
+        let known = repo
+            .touch_device(user_id, device, Utc::now().timestamp())
+            .await
+            .map_err(|e| DBError(InsertError(e.to_string())))?;
+        if !known {
+            tracing::info!("user {user_id} has no device {device}");
+            return Err(UnknownDevice);
+        }
+    }
 
-    let Ok(session) = session else {
-        // no such session.
-        tracing::info!("session was invalid");
-        return Json(Err(InvalidSession));
+    Ok(user_id)
+}
+
+/// Merge `request_user_data` into the data stored for the owner of `credential`.
+///
+/// Usage is recorded as the owner's `device`'s, if given, on the day it is in their timezone. If anything was
+/// stored, the owner's other devices are told through `events`.
+pub(crate) async fn sync_data(
+    repo: &(
+         impl SessionRepository
+         + TokenRepository
+         + UsageRepository
+         + DeviceRepository
+         + OutboxRepository
+         + WebhookRepository
+         + UserRepository
+     ),
+    events: &SyncEvents,
+    credential: &str,
+    device: Option<u32>,
+    request_user_data: Option<UserData>,
+) -> SyncResult {
+    let required = if request_user_data.is_some() {
+        TokenScope::Sync
+    } else {
+        TokenScope::ReadUsage
     };
+    let user_id = authorize(repo, credential, required, device).await?;
+
+    let app_usage = request_user_data.as_ref().map(|d| d.app_usage.clone());
+    let summary = merge(repo, user_id, request_user_data).await?;
+    let now = Utc::now();
+    if let Some(app_usage) = &app_usage {
+        // on the user's day, in the timezone just merged.
+        let timezone = usage::timezone_of(repo, user_id)
+            .await
+            .unwrap_or_else(|err| {
+                tracing::warn!("failed to fetch the timezone of user {user_id}: {err:?}");
+                None
+            });
+        let day = usage::local_time(now, timezone).date_naive();
+        usage::record(repo, user_id, device.unwrap_or(0), app_usage, day).await;
+    }
+
+    if summary.added > 0 {
+        events.publish(
+            user_id,
+            DataChanged {
+                device,
+                added: summary.added,
+            },
+        );
+    }
+    if app_usage.is_some() {
+        // with the limits just merged.
+        notify::emit_limits_exceeded(repo, user_id, now).await;
+        notify::emit_daily_summary(repo, user_id, now).await;
+    }
+    Ok(summary)
+}
 
-    let user_id = session.user_id;
+/// Store what `request_user_data` has that `user_id`'s stored data does not, returning the combined data.
+///
+/// Usage and debug entries are only ever added. An entry equal to a stored one is skipped. Schedules, categories,
+/// aliases and reminders are also deleted by their [`ScheduleKey`](data::public::ScheduleKey) in
+/// [`UserData::removed`], see `merge_schedule`.
+pub(crate) async fn merge(
+    repo: &impl UsageRepository,
+    user_id: u32,
+    request_user_data: Option<UserData>,
+) -> SyncResult {
+    use data::public::SyncError::DBError;
+    use pcupback::DBErrorKind::SelectError;
 
-    let stored_app_info = DBAppInfo::fetch_all(user_id, db)
+    let stored_app_info = repo
+        .fetch_app_info(user_id)
         .await
         .map_err(|e| DBError(SelectError(e.to_string())));
 
@@ -58,13 +233,14 @@ pub async fn sync(
         Ok(info) => info,
         Err(err) => {
             tracing::info!("failed to fetch stored app info");
-            return Json(Err(err));
+            return Err(err);
         }
     };
 
     // TODO: make a macro/function to make new ones
-    
-    let stored_debug = DBUserDebug::fetch_all(user_id, db)
+
+    let stored_debug = repo
+        .fetch_debug(user_id)
         .await
         .map_err(|e| DBError(SelectError(e.to_string())));
 
@@ -72,7 +248,7 @@ pub async fn sync(
         Ok(info) => info,
         Err(err) => {
             tracing::info!("failed to fetch stored app info");
-            return Json(Err(err));
+            return Err(err);
         }
     };
 
@@ -80,7 +256,7 @@ pub async fn sync(
     let mut failed = 0;
 
     // check for differences between the request's userdata and our stored one.
-    if let Json(Some(user_data)) = request_user_data {
+    if let Some(user_data) = request_user_data {
         // check for `app`s that arent in `stored_app_info`.
         for app in &user_data.app_usage {
             if stored_app_info.iter().any(|s| s.eq(app)) {
@@ -90,7 +266,7 @@ pub async fn sync(
 
             // `app`, from the request, was not found in the `stored_app_info`.
             let new_in_db = DBAppInfo::with_app_info(user_id, app.clone());
-            if let Err(err) = new_in_db.store(db).await {
+            if let Err(err) = repo.store_app_info(&new_in_db).await {
                 tracing::warn!("failed to store received data: {err:?}");
                 failed += 1;
                 continue;
@@ -98,25 +274,33 @@ pub async fn sync(
             added += 1;
         }
 
-        for debug in user_data.debug {
-            if stored_debug.iter().any(|s| s.eq(&debug)) {
+        for debug in &user_data.debug {
+            if stored_debug.iter().any(|s| s.eq(debug)) {
                 // `stored_app_info` conatins `app`
                 continue;
             }
 
             // `app`, from the request, was not found in the `stored_app_info`.
-            let new_in_db = DBUserDebug { user_id, stored: debug.stored };
-            if let Err(err) = new_in_db.store(db).await {
+            let new_in_db = DBUserDebug {
+                user_id,
+                stored: debug.stored.clone(),
+            };
+            if let Err(err) = repo.store_debug(&new_in_db).await {
                 tracing::warn!("failed to store received data: {err:?}");
                 failed += 1;
                 continue;
             }
             added += 1;
         }
+
+        let (schedule_added, schedule_failed) = merge_schedule(repo, user_id, &user_data).await?;
+        added += schedule_added;
+        failed += schedule_failed;
     }
 
     // the final, combined user data.
-    let stored_data = UserData::fetch_one(user_id, db)
+    let stored_data = repo
+        .fetch_user_data(user_id)
         .await
         .map_err(|e| DBError(SelectError(e.to_string())));
 
@@ -124,16 +308,179 @@ pub async fn sync(
         "sync'd => incoming: {added}, outgoing: {}, failed: {failed}",
         stored_data
             .as_ref()
-            .map(|d| d.app_usage.len() + d.debug.len() - added)
+            .map(|d| {
+                (d.app_usage.len()
+                    + d.debug.len()
+                    + d.weekday_limits.len()
+                    + d.blocked_windows.len()
+                    + d.limit_overrides.len()
+                    + d.categories.len()
+                    + d.aliases.len()
+                    + d.reminders.len())
+                .saturating_sub(added)
+            })
             .unwrap_or(0)
     );
 
-    match stored_data {
-        Ok(data) => {
-            let summary = SyncSummary { data, failed };
+    stored_data.map(|data| SyncSummary {
+        data,
+        added: u32::try_from(added).unwrap_or(u32::MAX),
+        failed,
+    })
+}
+
+/// Store the limit schedules, categories, app aliases and reminders in `user_data` that differ from `user_id`'s stored
+/// ones, returning `(added, failed)`.
+///
+/// Unlike other entries, schedules are unique by key, see [`WeekdayLimit`](data::public::WeekdayLimit),
+/// [`BlockedWindow`](data::public::BlockedWindow), [`LimitOverride`](data::public::LimitOverride), [`Category`],
+/// [`AppAlias`](data::public::AppAlias) and [`Reminder`](data::public::Reminder), and replace the stored ones with the
+/// same key. Expired overrides are skipped.
+///
+/// A [`UserData::timezone`] replaces the stored one, if it is a known IANA name.
+///
+/// The entries with the keys in [`UserData::removed`] are deleted first, counting as added if there was one.
+async fn merge_schedule(
+    repo: &impl UsageRepository,
+    user_id: u32,
+    user_data: &UserData,
+) -> Result<(usize, u32), SyncError> {
+    use data::public::SyncError::DBError;
+    use pcupback::DBErrorKind::SelectError;
 
-            Json(Ok(summary))
+    let mut results = Vec::new();
+
+    let mut removed = 0;
+    for key in &user_data.removed {
+        match repo.delete_schedule(user_id, key).await {
+            Ok(deleted) => removed += usize::from(deleted),
+            Err(err) => {
+                tracing::warn!("failed to delete {key:?}: {err:?}");
+                results.push(false);
+            }
+        }
+    }
+
+    let select_error = |e: sqlx::Error| DBError(SelectError(e.to_string()));
+    let stored_limits = repo
+        .fetch_weekday_limits(user_id)
+        .await
+        .map_err(select_error)?;
+    let stored_windows = repo
+        .fetch_blocked_windows(user_id)
+        .await
+        .map_err(select_error)?;
+    let stored_overrides = repo
+        .fetch_limit_overrides(user_id)
+        .await
+        .map_err(select_error)?;
+    let stored_categories = Category::collect(
+        repo.fetch_categories(user_id).await.map_err(select_error)?,
+        repo.fetch_category_apps(user_id)
+            .await
+            .map_err(select_error)?,
+    );
+    let stored_aliases = repo
+        .fetch_app_aliases(user_id)
+        .await
+        .map_err(select_error)?;
+    let stored_reminders = repo.fetch_reminders(user_id).await.map_err(select_error)?;
+    let stored_timezone = repo.fetch_timezone(user_id).await.map_err(select_error)?;
+
+    if let Some(timezone) = &user_data.timezone
+        && stored_timezone.as_ref() != Some(timezone)
+    {
+        if timezone.parse::<Tz>().is_ok() {
+            results.push(repo.store_timezone(user_id, timezone).await.is_ok());
+        } else {
+            tracing::info!("received an unknown timezone {timezone:?}");
+            results.push(false);
+        }
+    }
+
+    for limit in &user_data.weekday_limits {
+        let new_in_db = DBWeekdayLimit::with_weekday_limit(user_id, limit.clone());
+        if !stored_limits.contains(&new_in_db) {
+            results.push(repo.store_weekday_limit(&new_in_db).await.is_ok());
+        }
+    }
+
+    for window in &user_data.blocked_windows {
+        if !window.is_valid() {
+            tracing::info!("received an invalid blocked window {window:?}");
+            results.push(false);
+            continue;
+        }
+        let new_in_db = DBBlockedWindow::with_blocked_window(user_id, window.clone());
+        if !stored_windows.contains(&new_in_db) {
+            results.push(repo.store_blocked_window(&new_in_db).await.is_ok());
         }
-        Err(err) => Json(Err(err)),
     }
+
+    let now = Utc::now().timestamp();
+    for limit in &user_data.limit_overrides {
+        let new_in_db = DBLimitOverride::with_limit_override(user_id, limit.clone());
+        if new_in_db.is_active(now) && !stored_overrides.contains(&new_in_db) {
+            results.push(repo.store_limit_override(&new_in_db).await.is_ok());
+        }
+    }
+
+    for category in &user_data.categories {
+        if category.name.is_empty() {
+            tracing::info!("received a category without a name");
+            results.push(false);
+            continue;
+        }
+        let mut apps = category.apps.clone();
+        apps.sort();
+        apps.dedup();
+        let new_category = Category {
+            apps,
+            ..category.clone()
+        };
+        if !stored_categories.contains(&new_category) {
+            let new_in_db = DBCategory {
+                user_id,
+                name: new_category.name.clone(),
+                category_limit: new_category.limit,
+            };
+            results.push(
+                repo.replace_category(&new_in_db, &new_category.apps)
+                    .await
+                    .is_ok(),
+            );
+        }
+    }
+
+    for alias in &user_data.aliases {
+        if alias.alias.is_empty() || alias.app.is_empty() {
+            tracing::info!("received an incomplete app alias {alias:?}");
+            results.push(false);
+            continue;
+        }
+        let new_in_db = DBAppAlias::with_app_alias(user_id, alias.clone());
+        if !stored_aliases.contains(&new_in_db) {
+            results.push(repo.store_app_alias(&new_in_db).await.is_ok());
+        }
+    }
+
+    for reminder in &user_data.reminders {
+        if !reminder.is_valid() {
+            tracing::info!("received an invalid reminder {reminder:?}");
+            results.push(false);
+            continue;
+        }
+        let new_in_db = DBReminder::with_reminder(user_id, reminder.clone());
+        if !stored_reminders.contains(&new_in_db) {
+            results.push(repo.store_reminder(&new_in_db).await.is_ok());
+        }
+    }
+
+    let stored = results.iter().filter(|stored| **stored).count();
+    let failed = results.len() - stored;
+    let added = stored + removed;
+    if failed > 0 {
+        tracing::warn!("failed to store {failed} received schedules");
+    }
+    Ok((added, u32::try_from(failed).unwrap_or(u32::MAX)))
 }
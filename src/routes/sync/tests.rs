@@ -1,19 +1,48 @@
-use rocket::{http::ContentType, serde::json};
-use uuid::Uuid;
+use std::{pin::pin, time::Duration};
 
-use crate::routes::{
-    auth::{AuthResult, data::public::AuthRequest},
-    sync::SyncResult,
+use chrono::Utc;
+use chrono_tz::Tz;
+use rocket::{
+    futures::{FutureExt, StreamExt},
+    http::ContentType,
+    serde::json,
+    tokio::time::timeout,
 };
 
-use super::data::public::{AppInfo, UserData};
+use crate::{
+    config::{HashConfig, LimitConfig},
+    repo::{TokenRepository, UsageRepository, memory::MemoryRepository},
+    routes::{
+        auth::{
+            AuthResult,
+            data::public::{AuthRequest, LoginResponse},
+            signed_up, signed_up_as, try_login,
+        },
+        devices::data::public::DeviceInfo,
+        sync::SyncResult,
+        tokens::{
+            self,
+            data::public::{NewTokenRequest, TokenScope},
+        },
+        usage::{self, data::private::DAY_FORMAT},
+    },
+    util::auth::authenticate,
+};
+
+use super::{
+    data::public::{
+        AppAlias, AppInfo, BlockedWindow, Category, DataChanged, LimitOverride, Reminder,
+        ReminderRule, ScheduleKey, SyncError, UserData, Weekday, WeekdayLimit,
+    },
+    events::{self, SyncEvents},
+};
 
 #[macros::rocket_test]
 fn dry_sync() {
     let user = AuthRequest::random_valid();
 
     let session = client
-        .post("/auth")
+        .post("/auth/signup")
         .header(ContentType::JSON)
         .body(json::to_string(&user).unwrap())
         .dispatch()
@@ -42,7 +71,7 @@ fn sync_store() {
     let user = AuthRequest::random_valid();
 
     let session = client
-        .post("/auth")
+        .post("/auth/signup")
         .header(ContentType::JSON)
         .body(json::to_string(&user).unwrap())
         .dispatch()
@@ -54,6 +83,7 @@ fn sync_store() {
     let my_data = UserData {
         app_usage: vec![AppInfo::new("io1", 2, 0), AppInfo::new("io2", 10, 10)],
         debug: vec![],
+        ..Default::default()
     };
 
     let store = client
@@ -70,12 +100,13 @@ fn sync_store() {
 #[macros::rocket_test]
 fn sync_multi_client() {
     let user = AuthRequest {
-        username: Uuid::new_v4().to_string(),
-        password: "12345678".to_string(),
+        username: AuthRequest::random_username(),
+        password: "correct horse battery staple".to_string(),
+        device: None,
     };
 
     let session = client
-        .post("/auth")
+        .post("/auth/signup")
         .json(&user)
         .dispatch()
         .into_json::<AuthResult>()
@@ -86,6 +117,7 @@ fn sync_multi_client() {
     let my_data = UserData {
         app_usage: vec![AppInfo::new("io1", 2, 0), AppInfo::new("io2", 10, 10)],
         debug: vec![],
+        ..Default::default()
     };
 
     let url = format!("/sync/{session_id}");
@@ -110,5 +142,447 @@ fn sync_multi_client() {
     let another_client_data = another_client.unwrap();
 
     assert_eq!(&my_data, &first_client_sync.data);
-    assert_eq!(first_client_sync, another_client_data);
+    assert_eq!(first_client_sync.data, another_client_data.data);
+    assert_eq!((first_client_sync.added, another_client_data.added), (2, 0));
+}
+
+#[rocket::async_test]
+async fn sync_in_memory() {
+    let repo = MemoryRepository::default();
+    let (_, session) = signed_up(&repo).await;
+
+    let my_data = UserData {
+        app_usage: vec![AppInfo::new("io1", 2, 0), AppInfo::new("io2", 10, 10)],
+        debug: vec![],
+        ..Default::default()
+    };
+
+    let stored = super::sync_data(
+        &repo,
+        &SyncEvents::default(),
+        &session.id,
+        None,
+        Some(my_data),
+    )
+    .await
+    .unwrap();
+    // syncing the same data again stores nothing new.
+    let synced_again = super::sync_data(
+        &repo,
+        &SyncEvents::default(),
+        &session.id,
+        None,
+        Some(stored.data.clone()),
+    )
+    .await
+    .unwrap();
+    assert_eq!(stored.data, synced_again.data);
+    assert_eq!((stored.added, synced_again.added), (2, 0));
+
+    assert!(
+        super::sync_data(&repo, &SyncEvents::default(), "xdd", None, None)
+            .await
+            .is_err()
+    );
+}
+
+#[macros::rocket_test]
+fn sync_schedules() {
+    let session = client
+        .post("/auth/signup")
+        .json(&AuthRequest::random_valid())
+        .dispatch()
+        .into_json::<AuthResult>()
+        .unwrap()
+        .unwrap();
+    let sync = |data: &UserData| {
+        client
+            .post(format!("/sync/{}", session.id))
+            .json(&Some(data))
+            .dispatch()
+            .into_json::<SyncResult>()
+            .unwrap()
+            .unwrap()
+    };
+
+    let tomorrow = Utc::now().timestamp() + 24 * 60 * 60;
+    let mut data = UserData {
+        weekday_limits: vec![WeekdayLimit {
+            app: "games".to_string(),
+            weekday: Weekday::Saturday,
+            limit: 7200,
+        }],
+        blocked_windows: vec![
+            BlockedWindow {
+                app: "games".to_string(),
+                weekdays: Weekday::ALL[..5].to_vec(),
+                start: 9 * 60,
+                end: 17 * 60,
+            },
+            // not a time of day.
+            BlockedWindow {
+                app: "games".to_string(),
+                weekdays: vec![Weekday::Sunday],
+                start: 9 * 60,
+                end: 24 * 60,
+            },
+        ],
+        limit_overrides: vec![
+            LimitOverride {
+                app: "chat".to_string(),
+                limit: 600,
+                expires_at: tomorrow,
+            },
+            // already expired.
+            LimitOverride {
+                app: "video".to_string(),
+                limit: 600,
+                expires_at: 0,
+            },
+        ],
+        ..Default::default()
+    };
+    let summary = sync(&data);
+    assert_eq!(summary.failed, 1);
+    assert_eq!(summary.data.weekday_limits, data.weekday_limits);
+    assert_eq!(summary.data.blocked_windows, data.blocked_windows[..1]);
+    assert_eq!(summary.data.limit_overrides, data.limit_overrides[..1]);
+
+    // entries with the same key are replaced.
+    data.weekday_limits[0].limit = 3600;
+    data.blocked_windows.truncate(1);
+    data.blocked_windows[0].weekdays = vec![];
+    data.limit_overrides.truncate(1);
+    data.limit_overrides[0].limit = 1200;
+    let summary = sync(&data);
+    assert_eq!(summary.failed, 0);
+    assert_eq!(summary.data.weekday_limits, data.weekday_limits);
+    assert_eq!(summary.data.blocked_windows, data.blocked_windows);
+    assert_eq!(summary.data.limit_overrides, data.limit_overrides);
+
+    // removed by key, keys that are not stored are skipped.
+    let removed = UserData {
+        removed: vec![
+            ScheduleKey::WeekdayLimit {
+                app: "games".to_string(),
+                weekday: Weekday::Saturday,
+            },
+            ScheduleKey::BlockedWindow {
+                app: "games".to_string(),
+                start: 9 * 60,
+                end: 17 * 60,
+            },
+            ScheduleKey::LimitOverride {
+                app: "chat".to_string(),
+            },
+            ScheduleKey::Reminder {
+                name: "not stored".to_string(),
+            },
+        ],
+        ..Default::default()
+    };
+    let summary = sync(&removed);
+    assert_eq!((summary.added, summary.failed), (3, 0));
+    assert!(summary.data.weekday_limits.is_empty());
+    assert!(summary.data.blocked_windows.is_empty());
+    assert!(summary.data.limit_overrides.is_empty());
+    assert!(summary.data.removed.is_empty());
+
+    // and can be added again in the same sync.
+    let summary = sync(&UserData {
+        weekday_limits: data.weekday_limits.clone(),
+        ..removed
+    });
+    assert_eq!(summary.data.weekday_limits, data.weekday_limits);
+
+    // timezones are checked, and kept if none is sent.
+    let timezone = |timezone: &str| UserData {
+        timezone: Some(timezone.to_string()),
+        ..Default::default()
+    };
+    let summary = sync(&timezone("Mars/Olympus_Mons"));
+    assert_eq!((summary.failed, summary.data.timezone), (1, None));
+    let summary = sync(&timezone("Europe/Berlin"));
+    assert_eq!(summary.added, 1);
+    assert_eq!(
+        sync(&UserData::default()).data.timezone,
+        summary.data.timezone
+    );
+    assert_eq!(summary.data.timezone.as_deref(), Some("Europe/Berlin"));
+}
+
+#[macros::rocket_test]
+fn sync_categories() {
+    let session = client
+        .post("/auth/signup")
+        .json(&AuthRequest::random_valid())
+        .dispatch()
+        .into_json::<AuthResult>()
+        .unwrap()
+        .unwrap();
+    let sync = |data: &UserData| {
+        client
+            .post(format!("/sync/{}", session.id))
+            .json(&Some(data))
+            .dispatch()
+            .into_json::<SyncResult>()
+            .unwrap()
+            .unwrap()
+    };
+    let category = |name: &str, limit, apps: &[&str]| Category {
+        name: name.to_string(),
+        limit,
+        apps: apps.iter().map(ToString::to_string).collect(),
+    };
+
+    let data = UserData {
+        categories: vec![
+            category("games", 3600, &["io2", "io1", "io1"]),
+            category("social", 1800, &["chat"]),
+            category("", 60, &["io3"]),
+        ],
+        ..Default::default()
+    };
+    let summary = sync(&data);
+    assert_eq!(summary.failed, 1);
+    // apps are sorted, without duplicates.
+    assert_eq!(
+        summary.data.categories,
+        [
+            category("games", 3600, &["io1", "io2"]),
+            category("social", 1800, &["chat"]),
+        ]
+    );
+
+    // replaced by name, and `io2` moves to `social`.
+    let data = UserData {
+        categories: vec![category("social", 600, &["chat", "io2"])],
+        ..Default::default()
+    };
+    let summary = sync(&data);
+    assert_eq!(summary.failed, 0);
+    assert_eq!(
+        summary.data.categories,
+        [
+            category("games", 3600, &["io1"]),
+            category("social", 600, &["chat", "io2"]),
+        ]
+    );
+
+    // its apps are left without a category.
+    let summary = sync(&UserData {
+        removed: vec![ScheduleKey::Category {
+            name: "social".to_string(),
+        }],
+        ..Default::default()
+    });
+    assert_eq!(summary.added, 1);
+    assert_eq!(summary.data.categories, [category("games", 3600, &["io1"])]);
+}
+
+#[rocket::async_test]
+async fn remove_in_memory() {
+    let repo = MemoryRepository::default();
+    let (user, session) = signed_up(&repo).await;
+    let sync = async |data| {
+        super::sync_data(&repo, &SyncEvents::default(), &session.id, None, Some(data))
+            .await
+            .unwrap()
+    };
+
+    let data = UserData {
+        categories: vec![Category {
+            name: "games".to_string(),
+            limit: 3600,
+            apps: vec!["io1".to_string()],
+        }],
+        aliases: vec![AppAlias {
+            alias: "chrome.exe".to_string(),
+            app: "Google Chrome".to_string(),
+        }],
+        reminders: vec![Reminder {
+            name: "break".to_string(),
+            target: None,
+            rule: ReminderRule::Break { every_minutes: 50 },
+        }],
+        ..Default::default()
+    };
+    assert_eq!(sync(data.clone()).await.added, 3);
+
+    let summary = sync(UserData {
+        removed: vec![
+            ScheduleKey::Category {
+                name: "games".to_string(),
+            },
+            ScheduleKey::AppAlias {
+                alias: "chrome.exe".to_string(),
+            },
+        ],
+        ..Default::default()
+    })
+    .await;
+    assert_eq!(summary.added, 2);
+    assert!(summary.data.categories.is_empty());
+    assert!(summary.data.aliases.is_empty());
+    assert_eq!(summary.data.reminders, data.reminders);
+    assert!(repo.fetch_category_apps(user.id).await.unwrap().is_empty());
+}
+
+#[rocket::async_test]
+async fn record_on_local_day() {
+    let repo = MemoryRepository::default();
+    let (user, session) = signed_up(&repo).await;
+    let user_id = user.id;
+    let sync = async |timezone: Tz, usage| {
+        let data = UserData {
+            app_usage: vec![AppInfo::new("io1", usage, 600)],
+            timezone: Some(timezone.name().to_string()),
+            ..Default::default()
+        };
+        super::sync_data(&repo, &SyncEvents::default(), &session.id, None, Some(data))
+            .await
+            .unwrap();
+        Utc::now().with_timezone(&timezone).date_naive()
+    };
+
+    // 25 hours apart, so always on different days.
+    let early = sync(Tz::Pacific__Kiritimati, 60).await;
+    let late = sync(Tz::Pacific__Pago_Pago, 30).await;
+    let days: Vec<_> = repo
+        .fetch_usage_history(user_id, None, None, None)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|u| (u.day, u.app_usage))
+        .collect();
+    assert_eq!(
+        days,
+        [
+            (late.format(DAY_FORMAT).to_string(), 30),
+            (early.format(DAY_FORMAT).to_string(), 60),
+        ]
+    );
+
+    // limits are checked against the usage of the user's day.
+    let limits = usage::limits_of(&repo, &LimitConfig::default(), user_id, Utc::now())
+        .await
+        .unwrap();
+    assert_eq!(limits.apps[0].usage, 30);
+}
+
+#[rocket::async_test]
+async fn push_changes() {
+    let repo = MemoryRepository::default();
+    let req = AuthRequest {
+        device: Some(DeviceInfo {
+            name: "laptop".to_string(),
+            platform: "linux".to_string(),
+        }),
+        ..AuthRequest::random_valid()
+    };
+    let (user, session) = signed_up_as(&repo, &req).await;
+    let laptop = session.device_id.unwrap();
+    let login = AuthRequest {
+        device: Some(DeviceInfo {
+            name: "phone".to_string(),
+            platform: "android".to_string(),
+        }),
+        ..req
+    };
+    let LoginResponse::Session(phone_session) =
+        try_login(&repo, &HashConfig::default(), &login, None)
+            .await
+            .unwrap()
+    else {
+        unreachable!()
+    };
+    let phone = phone_session.device_id.unwrap();
+    let user_id = user.id;
+    let (_, other) = signed_up(&repo).await;
+
+    let events = SyncEvents::default();
+    let mut laptop_changes = pin!(events.changes(user_id, Some(laptop)));
+    let mut phone_changes = pin!(events.changes(user_id, Some(phone)));
+
+    let data = |app| UserData {
+        app_usage: vec![AppInfo::new(app, 60, 0)],
+        ..Default::default()
+    };
+    let sync = async |credential, device, app| {
+        super::sync_data(&repo, &events, credential, device, Some(data(app)))
+            .await
+            .unwrap()
+    };
+    assert_eq!(sync(&session.id, Some(laptop), "io1").await.added, 1);
+    // nothing new, nothing to tell.
+    assert_eq!(sync(&session.id, Some(laptop), "io1").await.added, 0);
+    sync(&other.id, None, "io1").await;
+    sync(&session.id, Some(phone), "io2").await;
+
+    assert_eq!(
+        phone_changes.next().await,
+        Some(DataChanged {
+            device: Some(laptop),
+            added: 1
+        })
+    );
+    // devices are not told about their own syncs.
+    assert_eq!(
+        laptop_changes.next().await,
+        Some(DataChanged {
+            device: Some(phone),
+            added: 1
+        })
+    );
+    assert!(phone_changes.next().now_or_never().is_none());
+}
+
+#[rocket::async_test]
+async fn revoked_listeners() {
+    let repo = MemoryRepository::default();
+    let (user, session) = signed_up(&repo).await;
+    let user_id = user.id;
+    let token = tokens::create(
+        &repo,
+        &session.id,
+        &NewTokenRequest {
+            name: "dashboard".to_string(),
+            scope: TokenScope::ReadUsage,
+        },
+    )
+    .await
+    .unwrap();
+
+    let events = SyncEvents::default();
+    let valid =
+        || async { authenticate(&repo, &token.token, TokenScope::ReadUsage).await == Ok(user_id) };
+    let mut changes = pin!(events::while_valid(
+        events.changes(user_id, None),
+        Duration::from_millis(10),
+        valid
+    ));
+
+    let change = DataChanged {
+        device: None,
+        added: 1,
+    };
+    events.publish(user_id, change);
+    assert_eq!(changes.next().await, Some(change));
+
+    // the stream ends once the token is revoked, without another change.
+    assert!(repo.delete_token(user_id, &token.info.id).await.unwrap());
+    assert_eq!(
+        timeout(Duration::from_secs(5), changes.next()).await,
+        Ok(None)
+    );
+}
+
+#[macros::rocket_test]
+fn sync_events_unauthorized() {
+    let error = client
+        .get("/sync/xdd/events")
+        .dispatch()
+        .into_json::<SyncError>()
+        .unwrap();
+    assert!(matches!(error, SyncError::InvalidSession));
 }
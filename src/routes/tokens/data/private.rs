@@ -0,0 +1,136 @@
+use chrono::Utc;
+use pcupback::{Fetchable, FetchableAll, Storable};
+use sqlx::{Executor, FromRow, Sqlite, sqlite::SqliteQueryResult};
+use uuid::Uuid;
+
+use crate::util::token::hash_token;
+
+use super::public::TokenScope;
+
+/// An api token, see the `api_tokens` table.
+#[derive(Debug, FromRow, PartialEq, Eq, Clone)]
+pub struct DBApiToken {
+    pub id: String,
+    pub user_id: u32,
+    pub name: String,
+    pub scope: TokenScope,
+    /// See [`hash_token`].
+    pub token_hash: String,
+    /// Stored as seconds since the unix epoch.
+    pub created_at: i64,
+    /// Stored as seconds since the unix epoch.
+    pub last_used: Option<i64>,
+}
+
+impl DBApiToken {
+    /// Create the stored form of `token`.
+    ///
+    /// `created_at` is [`Utc::now`]. `id` is [`Uuid::new_v4`]
+    #[must_use]
+    pub fn new(user_id: u32, name: impl Into<String>, scope: TokenScope, token: &str) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            user_id,
+            name: name.into(),
+            scope,
+            token_hash: hash_token(token),
+            created_at: Utc::now().timestamp(),
+            last_used: None,
+        }
+    }
+}
+
+impl<'a> Fetchable<'a, &'a str> for DBApiToken {
+    type DB = Sqlite;
+
+    /// token hash filter
+    ///
+    /// Special behaviour: tokens of users marked for deletion are not found.
+    async fn fetch_one<E>(filter: &'a str, executor: E) -> Result<Self, sqlx::Error>
+    where
+        E: Executor<'a, Database = Self::DB>,
+    {
+        sqlx::query_as(
+            "SELECT api_tokens.* FROM api_tokens JOIN users ON users.id = api_tokens.user_id WHERE token_hash = ? AND users.deletion_requested_at IS NULL",
+        )
+            .bind(filter)
+            .fetch_one(executor)
+            .await
+    }
+}
+
+impl<'a> FetchableAll<'a, u32> for DBApiToken {
+    type DB = Sqlite;
+
+    /// user id filter
+    async fn fetch_all<E>(filter: u32, executor: E) -> Result<Vec<Self>, sqlx::Error>
+    where
+        E: Executor<'a, Database = Self::DB>,
+    {
+        sqlx::query_as("SELECT * FROM api_tokens WHERE user_id = ? ORDER BY created_at")
+            .bind(filter)
+            .fetch_all(executor)
+            .await
+    }
+}
+
+impl<'a> Storable<'a> for DBApiToken {
+    type DB = Sqlite;
+
+    async fn store<E>(&self, executor: E) -> Result<SqliteQueryResult, sqlx::Error>
+    where
+        E: Executor<'a, Database = Self::DB>,
+    {
+        sqlx::query!(
+            "INSERT INTO api_tokens(id, user_id, name, scope, token_hash, created_at, last_used) VALUES(?, ?, ?, ?, ?, ?, ?)",
+            self.id,
+            self.user_id,
+            self.name,
+            self.scope,
+            self.token_hash,
+            self.created_at,
+            self.last_used
+        )
+        .execute(executor)
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pcupback::{Fetchable, FetchableAll, Storable};
+    use sqlx::{Pool, Sqlite};
+
+    use crate::routes::{auth::data::private::DBUser, tokens::data::public::TokenScope};
+
+    use super::DBApiToken;
+
+    #[sqlx::test]
+    async fn store_token(db: Pool<Sqlite>) {
+        // no such user id `2`
+        DBApiToken::new(2, "orphan", TokenScope::ReadUsage, "pcup_orphan")
+            .store(&db)
+            .await
+            .unwrap_err();
+
+        DBUser::new_raw(1, "test", "xd").store(&db).await.unwrap();
+        let token = DBApiToken::new(1, "export", TokenScope::ReadUsage, "pcup_test");
+        token.store(&db).await.unwrap();
+        // names are unique per user.
+        DBApiToken::new(1, "export", TokenScope::Sync, "pcup_other")
+            .store(&db)
+            .await
+            .unwrap_err();
+
+        let fetched = DBApiToken::fetch_one(token.token_hash.as_str(), &db)
+            .await
+            .unwrap();
+        assert_eq!(fetched, token);
+        assert_eq!(
+            <DBApiToken as FetchableAll<u32>>::fetch_all(1, &db)
+                .await
+                .unwrap(),
+            vec![token]
+        );
+    }
+}
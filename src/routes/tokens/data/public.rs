@@ -0,0 +1,85 @@
+use pcupback::DBErrorKind;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::private::DBApiToken;
+
+/// What an api token is allowed to do. Sessions are allowed everything.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy, sqlx::Type)]
+#[sqlx(rename_all = "snake_case")]
+pub enum TokenScope {
+    /// Read the user's data, without changing it.
+    ReadUsage,
+    /// Read and sync the user's data.
+    Sync,
+}
+
+impl TokenScope {
+    /// Return `true` if a token with this scope can do what `required` allows.
+    #[must_use]
+    pub fn allows(self, required: Self) -> bool {
+        self == Self::Sync || required == Self::ReadUsage
+    }
+}
+
+/// A request to create an api token.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NewTokenRequest {
+    /// Unique among the user's tokens.
+    pub name: String,
+    pub scope: TokenScope,
+}
+
+/// An api token, as listed to its owner. The token itself is only shown once, see [`CreatedToken`].
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct ApiToken {
+    /// The id the token is revoked by.
+    pub id: String,
+    pub name: String,
+    pub scope: TokenScope,
+    /// Seconds since the unix epoch.
+    pub created_at: i64,
+    /// Seconds since the unix epoch, [`None`] if never used.
+    pub last_used: Option<i64>,
+}
+
+impl From<DBApiToken> for ApiToken {
+    fn from(value: DBApiToken) -> Self {
+        Self {
+            id: value.id,
+            name: value.name,
+            scope: value.scope,
+            created_at: value.created_at,
+            last_used: value.last_used,
+        }
+    }
+}
+
+/// A newly created api token. Only its hash is stored, so `token` cannot be shown again.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CreatedToken {
+    /// Used in place of a session id.
+    pub token: String,
+    #[serde(flatten)]
+    pub info: ApiToken,
+}
+
+#[derive(Error, Debug, Serialize, Deserialize)]
+pub enum TokenError {
+    #[error("InvalidSession")]
+    InvalidSession,
+    #[error("EmptyName")]
+    EmptyName,
+    #[error("NameTooLong")]
+    NameTooLong,
+    /// The user already has a token with the name.
+    #[error("NameTaken")]
+    NameTaken,
+    #[error("TooManyTokens")]
+    TooManyTokens,
+    /// The user has no token with the id.
+    #[error("NotFound")]
+    NotFound,
+    #[error("DBError")]
+    DBError(#[from] DBErrorKind),
+}
@@ -0,0 +1,142 @@
+/// Data structs regarding api tokens shared in requests
+pub mod data;
+
+#[cfg(test)]
+mod tests;
+
+use data::{
+    private::DBApiToken,
+    public::{ApiToken, CreatedToken, NewTokenRequest, TokenError},
+};
+use pcupback::DBErrorKind::{DeleteError, InsertError, SelectError};
+use rocket::{State, delete, get, post, serde::json::Json};
+use sqlx::{Pool, Sqlite};
+use tracing::instrument;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    repo::{SessionRepository, TokenRepository},
+    util::{
+        db::PoolStateExt,
+        token::{MAX_NAME_LENGTH, MAX_TOKENS, generate_token},
+    },
+};
+
+type CreateTokenResult = Result<CreatedToken, TokenError>;
+type ListTokensResult = Result<Vec<ApiToken>, TokenError>;
+type RevokeTokenResult = Result<(), TokenError>;
+
+#[instrument(skip_all)]
+#[post("/auth/tokens/<session_id>", data = "<request>")]
+pub async fn create_token(
+    state: &State<Pool<Sqlite>>,
+    session_id: &str,
+    request: Json<NewTokenRequest>,
+) -> Json<CreateTokenResult> {
+    Json(create(state.to_db(), session_id, &request).await)
+}
+
+#[instrument(skip_all)]
+#[get("/auth/tokens/<session_id>")]
+pub async fn list_tokens(state: &State<Pool<Sqlite>>, session_id: &str) -> Json<ListTokensResult> {
+    Json(list(state.to_db(), session_id).await)
+}
+
+#[instrument(skip_all)]
+#[delete("/auth/tokens/<session_id>/<token_id>")]
+pub async fn revoke_token(
+    state: &State<Pool<Sqlite>>,
+    session_id: &str,
+    token_id: &str,
+) -> Json<RevokeTokenResult> {
+    Json(revoke(state.to_db(), session_id, token_id).await)
+}
+
+/// Create an api token for the owner of `session_id`.
+///
+/// Tokens cannot create tokens, only sessions can.
+pub(crate) async fn create(
+    repo: &(impl SessionRepository + TokenRepository),
+    session_id: &str,
+    request: &NewTokenRequest,
+) -> CreateTokenResult {
+    use TokenError::{DBError, EmptyName, NameTaken, NameTooLong, TooManyTokens};
+
+    let user_id = session_user_id(repo, session_id).await?;
+
+    let name = request.name.trim();
+    if name.is_empty() {
+        return Err(EmptyName);
+    }
+    if name.graphemes(true).count() > MAX_NAME_LENGTH {
+        return Err(NameTooLong);
+    }
+
+    let existing = repo
+        .fetch_tokens(user_id)
+        .await
+        .map_err(|err| DBError(SelectError(err.to_string())))?;
+    if existing.len() >= MAX_TOKENS {
+        return Err(TooManyTokens);
+    }
+
+    let token = generate_token();
+    let stored = DBApiToken::new(user_id, name, request.scope, &token);
+    match repo.insert_token(&stored).await {
+        Ok(()) => {}
+        Err(sqlx::Error::Database(err)) if err.is_unique_violation() => return Err(NameTaken),
+        Err(err) => return Err(DBError(InsertError(err.to_string()))),
+    }
+    tracing::info!("user {user_id} created api token {}", stored.id);
+
+    Ok(CreatedToken {
+        token,
+        info: stored.into(),
+    })
+}
+
+/// List the api tokens of the owner of `session_id`.
+pub(crate) async fn list(
+    repo: &(impl SessionRepository + TokenRepository),
+    session_id: &str,
+) -> ListTokensResult {
+    let user_id = session_user_id(repo, session_id).await?;
+
+    let tokens = repo
+        .fetch_tokens(user_id)
+        .await
+        .map_err(|err| TokenError::DBError(SelectError(err.to_string())))?;
+    Ok(tokens.into_iter().map(Into::into).collect())
+}
+
+/// Revoke the api token `token_id` of the owner of `session_id`.
+pub(crate) async fn revoke(
+    repo: &(impl SessionRepository + TokenRepository),
+    session_id: &str,
+    token_id: &str,
+) -> RevokeTokenResult {
+    let user_id = session_user_id(repo, session_id).await?;
+
+    let deleted = repo
+        .delete_token(user_id, token_id)
+        .await
+        .map_err(|err| TokenError::DBError(DeleteError(err.to_string())))?;
+    if !deleted {
+        return Err(TokenError::NotFound);
+    }
+    tracing::info!("user {user_id} revoked api token {token_id}");
+
+    Ok(())
+}
+
+/// Fetch the id of the owner of `session_id`.
+async fn session_user_id(
+    repo: &impl SessionRepository,
+    session_id: &str,
+) -> Result<u32, TokenError> {
+    let Ok(session) = repo.fetch_session(session_id).await else {
+        tracing::info!("session was invalid");
+        return Err(TokenError::InvalidSession);
+    };
+    Ok(session.user_id)
+}
@@ -0,0 +1,157 @@
+use crate::{
+    repo::memory::MemoryRepository,
+    routes::{
+        auth::{AuthResult, data::public::AuthRequest, signed_up},
+        sync::{
+            SyncResult,
+            data::public::{AppInfo, SyncError, UserData},
+            events::SyncEvents,
+            sync_data,
+        },
+    },
+};
+
+use super::{
+    CreateTokenResult,
+    data::public::{NewTokenRequest, TokenError, TokenScope},
+};
+
+fn request(name: &str, scope: TokenScope) -> NewTokenRequest {
+    NewTokenRequest {
+        name: name.to_string(),
+        scope,
+    }
+}
+
+#[rocket::async_test]
+async fn scopes() {
+    let repo = MemoryRepository::default();
+    let (_, session) = signed_up(&repo).await;
+
+    let read_only = super::create(
+        &repo,
+        &session.id,
+        &request("dashboard", TokenScope::ReadUsage),
+    )
+    .await
+    .unwrap();
+    let full = super::create(&repo, &session.id, &request("backup", TokenScope::Sync))
+        .await
+        .unwrap();
+
+    let data = UserData {
+        app_usage: vec![AppInfo::new("io1", 2, 0)],
+        debug: vec![],
+        ..Default::default()
+    };
+
+    // read only tokens can read..
+    sync_data(&repo, &SyncEvents::default(), &read_only.token, None, None)
+        .await
+        .unwrap();
+    // ..but not store.
+    assert!(matches!(
+        sync_data(
+            &repo,
+            &SyncEvents::default(),
+            &read_only.token,
+            None,
+            Some(data.clone())
+        )
+        .await
+        .unwrap_err(),
+        SyncError::InsufficientScope
+    ));
+    let synced = sync_data(&repo, &SyncEvents::default(), &full.token, None, Some(data))
+        .await
+        .unwrap();
+    assert_eq!(
+        synced.data,
+        sync_data(&repo, &SyncEvents::default(), &session.id, None, None)
+            .await
+            .unwrap()
+            .data
+    );
+
+    let listed = super::list(&repo, &session.id).await.unwrap();
+    assert_eq!(listed.len(), 2);
+    // the tokens used were recorded.
+    assert!(listed.iter().all(|t| t.last_used.is_some()));
+
+    // revoked tokens stop working.
+    super::revoke(&repo, &session.id, &read_only.info.id)
+        .await
+        .unwrap();
+    assert!(matches!(
+        sync_data(&repo, &SyncEvents::default(), &read_only.token, None, None)
+            .await
+            .unwrap_err(),
+        SyncError::InvalidSession
+    ));
+    assert!(matches!(
+        super::revoke(&repo, &session.id, &read_only.info.id)
+            .await
+            .unwrap_err(),
+        TokenError::NotFound
+    ));
+}
+
+#[rocket::async_test]
+async fn tokens_cannot_manage_tokens() {
+    let repo = MemoryRepository::default();
+    let (_, session) = signed_up(&repo).await;
+
+    let token = super::create(&repo, &session.id, &request("backup", TokenScope::Sync))
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        super::create(&repo, &token.token, &request("other", TokenScope::Sync))
+            .await
+            .unwrap_err(),
+        TokenError::InvalidSession
+    ));
+    assert!(matches!(
+        super::create(
+            &repo,
+            &session.id,
+            &request(" backup ", TokenScope::ReadUsage)
+        )
+        .await
+        .unwrap_err(),
+        TokenError::NameTaken
+    ));
+    assert!(matches!(
+        super::create(&repo, &session.id, &request("  ", TokenScope::ReadUsage))
+            .await
+            .unwrap_err(),
+        TokenError::EmptyName
+    ));
+}
+
+#[macros::rocket_test]
+fn sync_with_token() {
+    let session = client
+        .post("/auth/signup")
+        .json(&AuthRequest::random_valid())
+        .dispatch()
+        .into_json::<AuthResult>()
+        .unwrap()
+        .unwrap();
+
+    let created = client
+        .post(format!("/auth/tokens/{}", session.id))
+        .json(&request("export", TokenScope::ReadUsage))
+        .dispatch()
+        .into_json::<CreateTokenResult>()
+        .unwrap()
+        .unwrap();
+
+    let synced = client
+        .post(format!("/sync/{}", created.token))
+        .json(&None::<UserData>)
+        .dispatch()
+        .into_json::<SyncResult>()
+        .unwrap();
+    synced.unwrap();
+}
@@ -0,0 +1,155 @@
+use chrono::Utc;
+use pcupback::{Fetchable, Storable};
+use sqlx::{Executor, FromRow, Sqlite, sqlite::SqliteQueryResult};
+use totp_rs::Secret;
+use uuid::Uuid;
+
+/// A user's totp secret, see the `totp` table.
+#[derive(Debug, FromRow, PartialEq, Eq, Clone)]
+pub struct DBTotp {
+    pub user_id: u32,
+    /// Base32 encoded, without padding.
+    pub secret: String,
+    pub confirmed: bool,
+    /// The last time step a code was accepted for.
+    pub last_step: i64,
+}
+
+impl DBTotp {
+    /// Generate a new, unconfirmed totp secret for `user_id`.
+    #[must_use]
+    pub fn generate(user_id: u32) -> Self {
+        let Secret::Encoded(secret) = Secret::generate_secret().to_encoded() else {
+            unreachable!("`to_encoded` always returns `Secret::Encoded`")
+        };
+
+        Self {
+            user_id,
+            secret,
+            confirmed: false,
+            last_step: 0,
+        }
+    }
+}
+
+impl<'a> Fetchable<'a, u32> for DBTotp {
+    type DB = Sqlite;
+
+    /// user id filter
+    async fn fetch_one<E>(filter: u32, executor: E) -> Result<Self, sqlx::Error>
+    where
+        E: Executor<'a, Database = Self::DB>,
+    {
+        sqlx::query_as("SELECT * FROM totp WHERE user_id = ?")
+            .bind(filter)
+            .fetch_one(executor)
+            .await
+    }
+}
+
+impl<'a> Storable<'a> for DBTotp {
+    type DB = Sqlite;
+
+    /// Special behaviour: replaces if there is an existing secret, does not error.
+    async fn store<E>(&self, executor: E) -> Result<SqliteQueryResult, sqlx::Error>
+    where
+        E: Executor<'a, Database = Self::DB>,
+    {
+        sqlx::query!(
+            "INSERT OR REPLACE INTO totp(user_id, secret, confirmed, last_step) VALUES(?, ?, ?, ?)",
+            self.user_id,
+            self.secret,
+            self.confirmed,
+            self.last_step
+        )
+        .execute(executor)
+        .await
+    }
+}
+
+/// A login waiting on a second factor, see the `login_challenges` table.
+#[derive(Debug, FromRow, PartialEq, Eq, Clone)]
+pub struct DBLoginChallenge {
+    pub id: String,
+    pub user_id: u32,
+    /// Stored as seconds since the unix epoch.
+    pub created_at: i64,
+}
+
+impl DBLoginChallenge {
+    /// Generate a new challenge for `user_id`.
+    ///
+    /// `created_at` is [`Utc::now`]. `id` is [`Uuid::new_v4`]
+    #[must_use]
+    pub fn generate(user_id: u32) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            user_id,
+            created_at: Utc::now().timestamp(),
+        }
+    }
+}
+
+impl<'a> Fetchable<'a, &'a str> for DBLoginChallenge {
+    type DB = Sqlite;
+
+    async fn fetch_one<E>(filter: &'a str, executor: E) -> Result<Self, sqlx::Error>
+    where
+        E: Executor<'a, Database = Self::DB>,
+    {
+        sqlx::query_as("SELECT * FROM login_challenges WHERE id = ?")
+            .bind(filter)
+            .fetch_one(executor)
+            .await
+    }
+}
+
+impl<'a> Storable<'a> for DBLoginChallenge {
+    type DB = Sqlite;
+
+    async fn store<E>(&self, executor: E) -> Result<SqliteQueryResult, sqlx::Error>
+    where
+        E: Executor<'a, Database = Self::DB>,
+    {
+        sqlx::query!(
+            "INSERT INTO login_challenges(id, user_id, created_at) VALUES(?, ?, ?)",
+            self.id,
+            self.user_id,
+            self.created_at
+        )
+        .execute(executor)
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pcupback::{Fetchable, Storable};
+    use sqlx::{Pool, Sqlite};
+
+    use crate::routes::auth::data::private::DBUser;
+
+    use super::{DBLoginChallenge, DBTotp};
+
+    #[sqlx::test]
+    async fn store_totp(db: Pool<Sqlite>) {
+        // no such user id `1`
+        DBTotp::generate(1).store(&db).await.unwrap_err();
+
+        DBUser::new_raw(1, "test", "xd").store(&db).await.unwrap();
+        let stored = DBTotp::generate(1);
+        stored.store(&db).await.unwrap();
+
+        assert_eq!(DBTotp::fetch_one(1, &db).await.unwrap(), stored);
+    }
+
+    #[sqlx::test]
+    async fn store_challenge(db: Pool<Sqlite>) {
+        DBUser::new_raw(1, "test", "xd").store(&db).await.unwrap();
+        let stored = DBLoginChallenge::generate(1);
+        stored.store(&db).await.unwrap();
+
+        let fetched = DBLoginChallenge::fetch_one(&stored.id, &db).await.unwrap();
+        assert_eq!(fetched, stored);
+    }
+}
@@ -0,0 +1,55 @@
+use pcupback::DBErrorKind;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::routes::devices::data::public::DeviceInfo;
+
+/// A new totp secret, to be added to an authenticator app.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TotpEnrollment {
+    /// Base32 encoded, for entering by hand.
+    pub secret: String,
+    /// The `otpauth://` uri, usually shown as a qr code.
+    pub otpauth_uri: String,
+}
+
+/// A code from the user's authenticator app, or one of their recovery codes.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TotpCode {
+    pub code: String,
+}
+
+/// One-time recovery codes, shown once after enrolling. Each logs in once in place of a totp code.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RecoveryCodes {
+    pub codes: Vec<String>,
+}
+
+/// The second step of logging in, for users with totp enabled.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TotpLoginRequest {
+    /// The challenge returned from the first step.
+    pub challenge: String,
+    /// A totp code, or a recovery code.
+    pub code: String,
+    /// As in [`AuthRequest`](crate::routes::auth::data::public::AuthRequest).
+    #[serde(default)]
+    pub device: Option<DeviceInfo>,
+}
+
+#[derive(Error, Debug, Serialize, Deserialize)]
+pub enum TotpError {
+    #[error("InvalidSession")]
+    InvalidSession,
+    #[error("AlreadyEnabled")]
+    AlreadyEnabled,
+    /// Confirming without enrolling first.
+    #[error("NotEnrolled")]
+    NotEnrolled,
+    #[error("InvalidCode")]
+    InvalidCode,
+    #[error("InternalError")]
+    InternalError(String),
+    #[error("DBError")]
+    DBError(#[from] DBErrorKind),
+}
@@ -0,0 +1,136 @@
+/// Data structs regarding totp shared in requests
+pub mod data;
+
+#[cfg(test)]
+mod tests;
+
+use chrono::Utc;
+use data::{
+    private::DBTotp,
+    public::{RecoveryCodes, TotpCode, TotpEnrollment, TotpError},
+};
+use pcupback::DBErrorKind::{InsertError, SelectError};
+use rocket::{State, post, serde::json::Json};
+use sqlx::{Pool, Sqlite};
+use tracing::instrument;
+
+use crate::{
+    repo::{SessionRepository, TotpRepository, UserRepository},
+    util::{
+        db::PoolStateExt,
+        totp::{generate_recovery_codes, hash_recovery_code, totp, verify_code},
+    },
+};
+
+use super::auth::data::private::DBUser;
+
+type EnrollResult = Result<TotpEnrollment, TotpError>;
+type ConfirmResult = Result<RecoveryCodes, TotpError>;
+
+#[instrument(skip_all)]
+#[post("/auth/totp/enroll/<session_id>")]
+pub async fn enroll_totp(state: &State<Pool<Sqlite>>, session_id: &str) -> Json<EnrollResult> {
+    Json(enroll(state.to_db(), session_id).await)
+}
+
+#[instrument(skip_all)]
+#[post("/auth/totp/confirm/<session_id>", data = "<request>")]
+pub async fn confirm_totp(
+    state: &State<Pool<Sqlite>>,
+    session_id: &str,
+    request: Json<TotpCode>,
+) -> Json<ConfirmResult> {
+    Json(confirm(state.to_db(), session_id, &request).await)
+}
+
+/// Generate a new, unconfirmed totp secret for the owner of `session_id`.
+///
+/// Enrolling again before confirming replaces the secret.
+pub(crate) async fn enroll(
+    repo: &(impl SessionRepository + UserRepository + TotpRepository),
+    session_id: &str,
+) -> EnrollResult {
+    use TotpError::{AlreadyEnabled, DBError, InternalError};
+
+    let user = session_user(repo, session_id).await?;
+
+    match repo.fetch_totp(user.id).await {
+        Ok(existing) if existing.confirmed => return Err(AlreadyEnabled),
+        Ok(_) | Err(sqlx::Error::RowNotFound) => {}
+        Err(err) => return Err(DBError(SelectError(err.to_string()))),
+    }
+
+    let stored = DBTotp::generate(user.id);
+    let otpauth_uri = totp(&stored, &user.username)
+        .map_err(InternalError)?
+        .get_url();
+
+    repo.store_totp(&stored)
+        .await
+        .map_err(|err| DBError(InsertError(err.to_string())))?;
+    tracing::info!("user {} enrolling in totp", user.id);
+
+    Ok(TotpEnrollment {
+        secret: stored.secret,
+        otpauth_uri,
+    })
+}
+
+/// Enable totp for the owner of `session_id`, if `request` has a valid code for their enrolled secret.
+///
+/// Returns new recovery codes. Only their hashes are stored, so they cannot be shown again.
+pub(crate) async fn confirm(
+    repo: &(impl SessionRepository + UserRepository + TotpRepository),
+    session_id: &str,
+    request: &TotpCode,
+) -> ConfirmResult {
+    use TotpError::{AlreadyEnabled, DBError, InternalError, InvalidCode, NotEnrolled};
+
+    let user = session_user(repo, session_id).await?;
+
+    let mut stored = match repo.fetch_totp(user.id).await {
+        Ok(stored) if stored.confirmed => return Err(AlreadyEnabled),
+        Ok(stored) => stored,
+        Err(sqlx::Error::RowNotFound) => return Err(NotEnrolled),
+        Err(err) => return Err(DBError(SelectError(err.to_string()))),
+    };
+
+    let totp = totp(&stored, &user.username).map_err(InternalError)?;
+    let now = Utc::now().timestamp().unsigned_abs();
+    let Some(step) = verify_code(&totp, request.code.trim(), stored.last_step, now) else {
+        tracing::info!("wrong totp code confirming user {}", user.id);
+        return Err(InvalidCode);
+    };
+
+    let codes = generate_recovery_codes();
+    let code_hashes: Vec<String> = codes.iter().map(|code| hash_recovery_code(code)).collect();
+    repo.replace_recovery_codes(user.id, &code_hashes)
+        .await
+        .map_err(|err| DBError(InsertError(err.to_string())))?;
+
+    stored.confirmed = true;
+    stored.last_step = step;
+    repo.store_totp(&stored)
+        .await
+        .map_err(|err| DBError(InsertError(err.to_string())))?;
+    tracing::info!("user {} enabled totp", user.id);
+
+    Ok(RecoveryCodes { codes })
+}
+
+/// Fetch the owner of `session_id`.
+async fn session_user(
+    repo: &(impl SessionRepository + UserRepository),
+    session_id: &str,
+) -> Result<DBUser, TotpError> {
+    use TotpError::{DBError, InvalidSession};
+
+    let Ok(session) = repo.fetch_session(session_id).await else {
+        tracing::info!("session was invalid");
+        return Err(InvalidSession);
+    };
+
+    repo.fetch_user_by_id(session.user_id)
+        .await
+        .map_err(|err| DBError(SelectError(err.to_string())))
+}
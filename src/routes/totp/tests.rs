@@ -0,0 +1,190 @@
+use chrono::Utc;
+
+use crate::{
+    config::HashConfig,
+    repo::{TotpRepository, memory::MemoryRepository},
+    routes::auth::{
+        self, AuthResult,
+        data::public::{AuthError, AuthRequest, LoginResponse},
+    },
+    util::totp::{STEP, totp},
+};
+
+use super::{
+    EnrollResult,
+    data::public::{TotpCode, TotpError, TotpLoginRequest},
+};
+
+/// Sign up a new user with totp enabled, returning their login request and recovery codes.
+async fn signup_with_totp(repo: &MemoryRepository) -> (AuthRequest, Vec<String>) {
+    let req = AuthRequest::random_valid();
+    let (_, session) = auth::signed_up_as(repo, &req).await;
+
+    super::enroll(repo, &session.id).await.unwrap();
+
+    let stored = repo.fetch_totp(user_id(repo, &req).await).await.unwrap();
+    let code = totp(&stored, &req.username)
+        .unwrap()
+        .generate(Utc::now().timestamp().unsigned_abs());
+
+    let codes = super::confirm(repo, &session.id, &TotpCode { code })
+        .await
+        .unwrap()
+        .codes;
+    (req, codes)
+}
+
+async fn user_id(repo: &MemoryRepository, req: &AuthRequest) -> u32 {
+    use crate::repo::UserRepository;
+
+    repo.fetch_user_by_name(&req.username).await.unwrap().id
+}
+
+/// Log in with a password, returning the totp challenge.
+async fn login_challenge(repo: &MemoryRepository, req: &AuthRequest) -> String {
+    let response = auth::try_login(repo, &HashConfig::default(), req, None)
+        .await
+        .unwrap();
+    let LoginResponse::TotpRequired { challenge } = response else {
+        panic!("expected a totp challenge, got {response:?}");
+    };
+    challenge
+}
+
+#[rocket::async_test]
+async fn login_with_totp() {
+    let repo = MemoryRepository::default();
+    let (req, _) = signup_with_totp(&repo).await;
+
+    // the password alone does not give a session.
+    let challenge = login_challenge(&repo, &req).await;
+
+    let stored = repo.fetch_totp(user_id(&repo, &req).await).await.unwrap();
+    let totp = totp(&stored, &req.username).unwrap();
+    // the confirming code was for the current step, so use the next.
+    let code = totp.generate(Utc::now().timestamp().unsigned_abs() + STEP);
+
+    // a wrong code fails.
+    assert!(matches!(
+        auth::try_login_totp(
+            &repo,
+            &TotpLoginRequest {
+                challenge: challenge.clone(),
+                code: "000000".to_string(),
+                device: None,
+            },
+            None,
+        )
+        .await
+        .unwrap_err(),
+        AuthError::InvalidCredentials
+    ));
+
+    let request = TotpLoginRequest {
+        challenge,
+        code,
+        device: None,
+    };
+    auth::try_login_totp(&repo, &request, None).await.unwrap();
+
+    // the challenge is used up..
+    assert!(matches!(
+        auth::try_login_totp(&repo, &request, None)
+            .await
+            .unwrap_err(),
+        AuthError::InvalidChallenge
+    ));
+    // ..and so is the code.
+    let again = TotpLoginRequest {
+        challenge: login_challenge(&repo, &req).await,
+        code: request.code,
+        device: None,
+    };
+    assert!(matches!(
+        auth::try_login_totp(&repo, &again, None).await.unwrap_err(),
+        AuthError::InvalidCredentials
+    ));
+}
+
+#[rocket::async_test]
+async fn login_with_recovery_code() {
+    let repo = MemoryRepository::default();
+    let (req, codes) = signup_with_totp(&repo).await;
+
+    let request = TotpLoginRequest {
+        challenge: login_challenge(&repo, &req).await,
+        code: codes[0].to_lowercase(),
+        device: None,
+    };
+    auth::try_login_totp(&repo, &request, None).await.unwrap();
+
+    // each recovery code works once.
+    let again = TotpLoginRequest {
+        challenge: login_challenge(&repo, &req).await,
+        code: codes[0].clone(),
+        device: None,
+    };
+    assert!(matches!(
+        auth::try_login_totp(&repo, &again, None).await.unwrap_err(),
+        AuthError::InvalidCredentials
+    ));
+}
+
+#[rocket::async_test]
+async fn confirm_wrong_code() {
+    let repo = MemoryRepository::default();
+    let req = AuthRequest::random_valid();
+    let (_, session) = auth::signed_up_as(&repo, &req).await;
+
+    let wrong = TotpCode {
+        code: "000000".to_string(),
+    };
+    assert!(matches!(
+        super::confirm(&repo, &session.id, &wrong)
+            .await
+            .unwrap_err(),
+        TotpError::NotEnrolled
+    ));
+
+    super::enroll(&repo, &session.id).await.unwrap();
+    assert!(matches!(
+        super::confirm(&repo, &session.id, &wrong)
+            .await
+            .unwrap_err(),
+        TotpError::InvalidCode
+    ));
+
+    // unconfirmed totp is not required to log in.
+    let response = auth::try_login(&repo, &HashConfig::default(), &req, None)
+        .await
+        .unwrap();
+    assert!(matches!(response, LoginResponse::Session(_)));
+}
+
+#[macros::rocket_test]
+fn enroll_totp() {
+    let req = AuthRequest::random_valid();
+    let session = client
+        .post("/auth/signup")
+        .json(&req)
+        .dispatch()
+        .into_json::<AuthResult>()
+        .unwrap()
+        .unwrap();
+
+    let enrollment = client
+        .post(format!("/auth/totp/enroll/{}", session.id))
+        .dispatch()
+        .into_json::<EnrollResult>()
+        .unwrap()
+        .unwrap();
+    assert!(enrollment.otpauth_uri.starts_with("otpauth://totp/"));
+    assert!(enrollment.otpauth_uri.contains(&enrollment.secret));
+
+    let invalid = client
+        .post("/auth/totp/enroll/not-a-session")
+        .dispatch()
+        .into_json::<EnrollResult>()
+        .unwrap();
+    assert!(matches!(invalid.unwrap_err(), TotpError::InvalidSession));
+}
@@ -0,0 +1,214 @@
+use std::collections::{BTreeMap, HashMap, btree_map::Entry};
+
+use crate::routes::sync::data::private::DBAppAlias;
+
+use super::data::{private::DBUsageDay, public::CatalogApp};
+
+/// Common apps, by canonical name, and their names on each platform.
+///
+/// Windows executables, macOS bundle ids, Android packages and names clients are known to report.
+const CATALOG: &[(&str, &[&str])] = &[
+    (
+        "Discord",
+        &["discord.exe", "com.hnc.Discord", "com.discord"],
+    ),
+    (
+        "Firefox",
+        &["firefox.exe", "org.mozilla.firefox", "Mozilla Firefox"],
+    ),
+    (
+        "Google Chrome",
+        &[
+            "chrome.exe",
+            "com.google.Chrome",
+            "com.android.chrome",
+            "Chrome",
+        ],
+    ),
+    (
+        "Microsoft Edge",
+        &["msedge.exe", "com.microsoft.edgemac", "com.microsoft.emmx"],
+    ),
+    ("Minecraft", &["minecraft.exe", "com.mojang.minecraftpe"]),
+    (
+        "Slack",
+        &["slack.exe", "com.tinyspeck.slackmacgap", "com.Slack"],
+    ),
+    (
+        "Spotify",
+        &["spotify.exe", "com.spotify.client", "com.spotify.music"],
+    ),
+    ("Steam", &["steam.exe", "com.valvesoftware.steam"]),
+    (
+        "Telegram",
+        &[
+            "telegram.exe",
+            "ru.keepcoder.Telegram",
+            "org.telegram.messenger",
+        ],
+    ),
+    (
+        "WhatsApp",
+        &["whatsapp.exe", "net.whatsapp.WhatsApp", "com.whatsapp"],
+    ),
+    (
+        "YouTube",
+        &["com.google.android.youtube", "com.google.ios.youtube"],
+    ),
+];
+
+/// The bundled catalog, ordered by name.
+pub(crate) fn catalog() -> Vec<CatalogApp> {
+    CATALOG
+        .iter()
+        .map(|(name, identifiers)| CatalogApp {
+            name: (*name).to_string(),
+            identifiers: identifiers.iter().map(ToString::to_string).collect(),
+        })
+        .collect()
+}
+
+/// Resolves app names to canonical apps, with a user's aliases and the bundled catalog.
+///
+/// Names are matched ignoring ascii case.
+#[derive(Debug, Default)]
+pub(crate) struct Aliases {
+    /// By lowercase alias.
+    user: HashMap<String, String>,
+}
+
+impl Aliases {
+    #[must_use]
+    pub fn new(user_aliases: &[DBAppAlias]) -> Self {
+        Self {
+            user: user_aliases
+                .iter()
+                .map(|a| (a.alias.to_ascii_lowercase(), a.app_name.clone()))
+                .collect(),
+        }
+    }
+
+    /// The canonical name of `app`. First the user's alias of it, then the user's alias of its catalog app,
+    /// then its catalog app, or else `app` itself.
+    #[must_use]
+    pub fn resolve<'a>(&'a self, app: &'a str) -> &'a str {
+        let user = |name: &str| {
+            self.user
+                .get(&name.to_ascii_lowercase())
+                .map(String::as_str)
+        };
+        let catalog = CATALOG
+            .iter()
+            .find(|(name, identifiers)| {
+                name.eq_ignore_ascii_case(app)
+                    || identifiers.iter().any(|i| i.eq_ignore_ascii_case(app))
+            })
+            .map(|(name, _)| *name);
+
+        user(app)
+            .or_else(|| catalog.and_then(user))
+            .or(catalog)
+            .unwrap_or(app)
+    }
+
+    /// Replace `app` with its canonical name.
+    pub fn rename(&self, app: &mut String) {
+        let canonical = self.resolve(app).to_string();
+        *app = canonical;
+    }
+
+    /// Combine the usage in `history` of apps with the same canonical name, by day, across devices.
+    ///
+    /// Usage is summed, and the strictest limit kept. Ordered by day, then app. Combined usage of several
+    /// devices has no device.
+    #[must_use]
+    pub fn aggregate(&self, history: Vec<DBUsageDay>) -> Vec<DBUsageDay> {
+        let mut days: BTreeMap<(String, String), DBUsageDay> = BTreeMap::new();
+        for mut usage in history {
+            self.rename(&mut usage.app_name);
+            match days.entry((usage.day.clone(), usage.app_name.clone())) {
+                Entry::Vacant(entry) => {
+                    entry.insert(usage);
+                }
+                Entry::Occupied(mut entry) => {
+                    let day = entry.get_mut();
+                    day.app_usage = day.app_usage.saturating_add(usage.app_usage);
+                    day.app_limit = stricter(day.app_limit, usage.app_limit);
+                    if day.device_id != usage.device_id {
+                        day.device_id = 0;
+                    }
+                }
+            }
+        }
+        days.into_values().collect()
+    }
+}
+
+/// The stricter of two limits, where `0` is no limit.
+pub(crate) fn stricter(a: u32, b: u32) -> u32 {
+    match (a, b) {
+        (0, limit) | (limit, 0) => limit,
+        (a, b) => a.min(b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::routes::{sync::data::private::DBAppAlias, usage::data::private::DBUsageDay};
+
+    use super::Aliases;
+
+    fn alias(alias: &str, app: &str) -> DBAppAlias {
+        DBAppAlias {
+            user_id: 1,
+            alias: alias.to_string(),
+            app_name: app.to_string(),
+        }
+    }
+
+    #[test]
+    fn resolve() {
+        let aliases = Aliases::new(&[
+            alias("my-editor", "Editor"),
+            // renames a catalog app.
+            alias("Firefox", "Browser"),
+            // takes precedence over the catalog.
+            alias("chrome.exe", "Work browser"),
+        ]);
+
+        assert_eq!(aliases.resolve("com.android.chrome"), "Google Chrome");
+        assert_eq!(aliases.resolve("CHROME.EXE"), "Work browser");
+        assert_eq!(aliases.resolve("org.mozilla.firefox"), "Browser");
+        assert_eq!(aliases.resolve("My-Editor"), "Editor");
+        assert_eq!(aliases.resolve("unknown"), "unknown");
+        assert_eq!(Aliases::default().resolve("google chrome"), "Google Chrome");
+    }
+
+    #[test]
+    fn aggregate() {
+        let usage = |app: &str, day: &str, app_usage, app_limit| DBUsageDay {
+            user_id: 1,
+            device_id: 0,
+            app_name: app.to_string(),
+            day: day.to_string(),
+            app_usage,
+            app_limit,
+        };
+
+        let history = vec![
+            usage("chrome.exe", "2025-05-01", 60, 0),
+            usage("com.android.chrome", "2025-05-01", 30, 600),
+            usage("io1", "2025-05-01", 10, 0),
+            usage("com.google.Chrome", "2025-05-02", 5, 900),
+            usage("Chrome", "2025-05-02", 5, 300),
+        ];
+        assert_eq!(
+            Aliases::default().aggregate(history),
+            [
+                usage("Google Chrome", "2025-05-01", 90, 600),
+                usage("io1", "2025-05-01", 10, 0),
+                usage("Google Chrome", "2025-05-02", 10, 300),
+            ]
+        );
+    }
+}
@@ -0,0 +1,39 @@
+use chrono::NaiveDate;
+use sqlx::FromRow;
+
+use crate::routes::sync::data::private::DBAppInfo;
+
+/// The format `day` is stored in, `YYYY-MM-DD`. It sorts by date.
+pub const DAY_FORMAT: &str = "%Y-%m-%d";
+
+/// An app's usage on a day on a device, as synced by its user.
+#[derive(Debug, FromRow, PartialEq, Eq, Clone)]
+pub struct DBUsageDay {
+    pub user_id: u32,
+    /// The device synced from, `0` if none, see
+    /// [`DeviceInfo`](crate::routes::devices::data::public::DeviceInfo).
+    pub device_id: u32,
+    pub app_name: String,
+    /// The date synced on in the user's timezone, see
+    /// [`UserData::timezone`](crate::routes::sync::data::public::UserData::timezone). In [`DAY_FORMAT`].
+    pub day: String,
+    /// Stored as seconds.
+    pub app_usage: u32,
+    /// Stored as seconds.
+    pub app_limit: u32,
+}
+
+impl DBUsageDay {
+    /// Record `app_info`'s usage as of `day`, synced from the device with the id `device_id`.
+    #[must_use]
+    pub fn with_app_info(app_info: &DBAppInfo, device_id: u32, day: NaiveDate) -> Self {
+        Self {
+            user_id: app_info.user_id,
+            device_id,
+            app_name: app_info.app_name.clone(),
+            day: day.format(DAY_FORMAT).to_string(),
+            app_usage: app_info.app_usage,
+            app_limit: app_info.app_limit,
+        }
+    }
+}
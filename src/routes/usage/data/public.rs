@@ -0,0 +1,169 @@
+use pcupback::DBErrorKind;
+use rocket::FromFormField;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::private::DBUsageDay;
+
+#[derive(Error, Debug, Serialize, Deserialize)]
+pub enum UsageError {
+    /// No such session or api token.
+    #[error("InvalidSession")]
+    InvalidSession,
+    /// The api token's scope does not allow the request.
+    #[error("InsufficientScope")]
+    InsufficientScope,
+    /// A date was not `YYYY-MM-DD`.
+    #[error("InvalidDate")]
+    InvalidDate(String),
+    /// `from` is after `to`.
+    #[error("InvalidRange")]
+    InvalidRange,
+    #[error("DBError")]
+    DBError(#[from] DBErrorKind),
+}
+
+/// What [`UsageStats::totals`] are grouped by.
+#[derive(Debug, Default, FromFormField, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum StatsPeriod {
+    #[default]
+    Day,
+    /// Weeks start on monday.
+    Week,
+    Month,
+}
+
+/// Aggregated usage, from the days recorded in a range.
+///
+/// All usage is in seconds. Apps with a limit of `0` have no limit.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct UsageStats {
+    pub period: StatsPeriod,
+    /// Each app's usage per period, ordered by period then app.
+    pub totals: Vec<PeriodTotal>,
+    /// The most used apps over the whole range, most used first.
+    pub top_apps: Vec<AppTotal>,
+    /// The usage of all apps, averaged over the days with any usage recorded.
+    pub average_daily_usage: u64,
+    /// For each app with a limit, how often it was exceeded. Ordered by app.
+    pub limits_exceeded: Vec<LimitExceeded>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct PeriodTotal {
+    /// The first day of the period, as `YYYY-MM-DD`.
+    pub period_start: String,
+    pub app: String,
+    pub usage: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct AppTotal {
+    pub app: String,
+    pub usage: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct LimitExceeded {
+    pub app: String,
+    /// The days the app was used with a limit set.
+    pub days: u32,
+    /// The days the app was used more than its limit.
+    pub days_exceeded: u32,
+    /// `days_exceeded` out of `days`, from 0 to 100.
+    pub percentage: f64,
+}
+
+/// Each limited app's time remaining today, see [`LimitConfig`](crate::config::LimitConfig).
+///
+/// Days are in utc, as usage is recorded, while weekday limits and blocked windows are in the user's
+/// [`timezone`](crate::routes::sync::data::public::UserData::timezone). Apps with a limit of `0` have no limit, and
+/// are left out unless blocked.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct LimitEvaluation {
+    /// When usage resets, at the start of the next day. Seconds since the unix epoch.
+    pub resets_at: i64,
+    /// Ordered by app.
+    pub apps: Vec<LimitStatus>,
+    /// Each limited category, ordered by name, see
+    /// [`Category`](crate::routes::sync::data::public::Category).
+    pub categories: Vec<CategoryStatus>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct LimitStatus {
+    pub app: String,
+    /// The name of the app's category, if any.
+    pub category: Option<String>,
+    /// In seconds, after applying the user's limit schedules. `0` if only its category is limited.
+    pub limit: u32,
+    /// Used today, in seconds.
+    pub usage: u32,
+    /// In seconds, `0` once exceeded. The least of the app's and its category's.
+    pub remaining: u32,
+    /// The worst of the app's and its category's.
+    pub state: LimitState,
+}
+
+/// A category's limit, shared by its apps.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct CategoryStatus {
+    pub name: String,
+    /// In seconds.
+    pub limit: u32,
+    /// Used today by all the category's apps, in seconds.
+    pub usage: u32,
+    /// In seconds, `0` once exceeded.
+    pub remaining: u32,
+    pub state: LimitState,
+}
+
+/// Ordered from least to most restrictive.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitState {
+    Ok,
+    /// At least the configured percentage of the limit is used.
+    Warning,
+    /// No time remains.
+    Exceeded,
+    /// The app is in one of its blocked windows, see
+    /// [`BlockedWindow`](crate::routes::sync::data::public::BlockedWindow).
+    Blocked,
+}
+
+/// An app of the bundled catalog, see [`AppAlias`](crate::routes::sync::data::public::AppAlias).
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct CatalogApp {
+    /// The canonical name.
+    pub name: String,
+    /// Its names on each platform.
+    pub identifiers: Vec<String>,
+}
+
+/// An app's usage on a day on a device, as exported.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct UsageDay {
+    /// The [`Device`](crate::routes::devices::data::public::Device) synced from, `0` if none.
+    pub device_id: u32,
+    pub app_name: String,
+    /// The date in the user's timezone, `YYYY-MM-DD`.
+    pub day: String,
+    /// In seconds.
+    pub app_usage: u32,
+    /// In seconds, `0` if the app has no limit.
+    pub app_limit: u32,
+}
+
+impl From<DBUsageDay> for UsageDay {
+    fn from(value: DBUsageDay) -> Self {
+        Self {
+            device_id: value.device_id,
+            app_name: value.app_name,
+            day: value.day,
+            app_usage: value.app_usage,
+            app_limit: value.app_limit,
+        }
+    }
+}
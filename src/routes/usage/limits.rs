@@ -0,0 +1,493 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Datelike, Days, Timelike, Utc};
+use chrono_tz::Tz;
+
+use crate::{
+    config::LimitConfig,
+    routes::sync::data::{
+        private::{
+            DBAppInfo, DBBlockedWindow, DBCategory, DBCategoryApp, DBLimitOverride, DBWeekdayLimit,
+        },
+        public::Weekday,
+    },
+};
+
+use super::{
+    aliases::{Aliases, stricter},
+    data::{
+        private::DBUsageDay,
+        public::{CategoryStatus, LimitEvaluation, LimitState, LimitStatus},
+    },
+};
+
+/// A user's limit schedules and categories, see [`UserData`](crate::routes::sync::data::public::UserData).
+#[derive(Debug, Default)]
+pub(crate) struct LimitRules {
+    pub weekday_limits: Vec<DBWeekdayLimit>,
+    pub blocked_windows: Vec<DBBlockedWindow>,
+    pub limit_overrides: Vec<DBLimitOverride>,
+    pub categories: Vec<DBCategory>,
+    pub category_apps: Vec<DBCategoryApp>,
+    /// What weekdays, blocked windows and days are evaluated in, utc if [`None`].
+    pub timezone: Option<Tz>,
+}
+
+impl LimitRules {
+    /// Replace the app names of the rules with their canonical names.
+    pub fn rename_apps(&mut self, aliases: &Aliases) {
+        let names = self
+            .weekday_limits
+            .iter_mut()
+            .map(|l| &mut l.app_name)
+            .chain(self.blocked_windows.iter_mut().map(|w| &mut w.app_name))
+            .chain(self.limit_overrides.iter_mut().map(|o| &mut o.app_name))
+            .chain(self.category_apps.iter_mut().map(|a| &mut a.app_name));
+        for name in names {
+            aliases.rename(name);
+        }
+    }
+}
+
+/// Evaluate each app's limit against its usage `today`, as of `now`.
+///
+/// An app's limit is, first to last, its override that has not expired, its limit for `now`'s weekday,
+/// the last synced today, or the last stored in `app_info`. Weekdays, blocked windows and the next midnight are in
+/// the rules' timezone.
+///
+/// A category's usage is the sum of its apps', and its apps are limited by both.
+pub(crate) fn evaluate(
+    app_info: &[DBAppInfo],
+    today: &[DBUsageDay],
+    rules: &LimitRules,
+    config: &LimitConfig,
+    now: DateTime<Utc>,
+) -> LimitEvaluation {
+    let local = super::local_time(now, rules.timezone);
+    let weekday = Weekday::from(local.weekday());
+    let minute = local.hour() * 60 + local.minute();
+
+    // `(limit, usage)`, later entries replace earlier ones. Entries of the same app, by alias, are combined
+    // as in `Aliases::aggregate`.
+    let mut apps: BTreeMap<&str, (u32, u32)> = BTreeMap::new();
+    for info in app_info {
+        let (limit, _) = apps.entry(&info.app_name).or_insert((info.app_limit, 0));
+        *limit = stricter(*limit, info.app_limit);
+    }
+    let mut synced: BTreeMap<&str, (u32, u32)> = BTreeMap::new();
+    for usage in today {
+        let (limit, app_usage) = synced
+            .entry(&usage.app_name)
+            .or_insert((usage.app_limit, 0));
+        *limit = stricter(*limit, usage.app_limit);
+        *app_usage = app_usage.saturating_add(usage.app_usage);
+    }
+    apps.extend(synced);
+    let weekday_limits = rules
+        .weekday_limits
+        .iter()
+        .filter(|l| l.weekday() == weekday)
+        .map(|l| (l.app_name.as_str(), l.app_limit));
+    let overrides = rules
+        .limit_overrides
+        .iter()
+        .filter(|o| o.is_active(now.timestamp()))
+        .map(|o| (o.app_name.as_str(), o.app_limit));
+    for (app, limit) in weekday_limits.chain(overrides) {
+        apps.entry(app).or_default().0 = limit;
+    }
+    for window in &rules.blocked_windows {
+        apps.entry(&window.app_name).or_default();
+    }
+
+    let categories: BTreeMap<&str, CategoryStatus> = rules
+        .categories
+        .iter()
+        .filter(|c| c.category_limit > 0)
+        .map(|c| {
+            let usage = rules
+                .category_apps
+                .iter()
+                .filter(|a| a.category == c.name)
+                .filter_map(|a| apps.get(a.app_name.as_str()))
+                .fold(0u32, |sum, (_, usage)| sum.saturating_add(*usage));
+            let status = CategoryStatus {
+                name: c.name.clone(),
+                limit: c.category_limit,
+                usage,
+                remaining: c.category_limit.saturating_sub(usage),
+                state: state(c.category_limit, usage, config),
+            };
+            (c.name.as_str(), status)
+        })
+        .collect();
+    for app in &rules.category_apps {
+        if categories.contains_key(app.category.as_str()) {
+            apps.entry(&app.app_name).or_default();
+        }
+    }
+
+    let tomorrow = local.date_naive() + Days::new(1);
+    // clocks changing can skip midnight, the day then starts at the first hour that exists.
+    let resets_at = (0..24)
+        .find_map(|hour| {
+            tomorrow
+                .and_hms_opt(hour, 0, 0)?
+                .and_local_timezone(local.timezone())
+                .earliest()
+        })
+        .map_or(i64::MAX, |midnight| midnight.timestamp());
+
+    LimitEvaluation {
+        resets_at,
+        apps: apps
+            .into_iter()
+            .filter_map(|(app, (limit, usage))| {
+                let blocked = rules
+                    .blocked_windows
+                    .iter()
+                    .any(|w| w.app_name == app && w.covers(weekday, minute));
+                let category = rules
+                    .category_apps
+                    .iter()
+                    .find(|a| a.app_name == app)
+                    .map(|a| a.category.as_str());
+                let category_status = category.and_then(|c| categories.get(c));
+                if limit == 0 && category_status.is_none() && !blocked {
+                    return None;
+                }
+
+                let (mut remaining, mut app_state) = if limit == 0 {
+                    (u32::MAX, LimitState::Ok)
+                } else {
+                    (limit.saturating_sub(usage), state(limit, usage, config))
+                };
+                if let Some(status) = category_status {
+                    remaining = remaining.min(status.remaining);
+                    app_state = app_state.max(status.state);
+                }
+                if blocked {
+                    app_state = LimitState::Blocked;
+                }
+
+                Some(LimitStatus {
+                    app: app.to_string(),
+                    category: category.map(str::to_string),
+                    limit,
+                    usage,
+                    // only blocked, without a limit.
+                    remaining: if remaining == u32::MAX { 0 } else { remaining },
+                    state: app_state,
+                })
+            })
+            .collect(),
+        categories: categories.into_values().collect(),
+    }
+}
+
+fn state(limit: u32, usage: u32, config: &LimitConfig) -> LimitState {
+    if super::limit_exceeded(limit, usage) {
+        LimitState::Exceeded
+    } else if u64::from(usage) * 100 >= u64::from(limit) * u64::from(config.warning_percent) {
+        LimitState::Warning
+    } else {
+        LimitState::Ok
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use crate::{
+        config::LimitConfig,
+        routes::{
+            sync::data::{
+                private::{
+                    DBAppInfo, DBBlockedWindow, DBCategory, DBCategoryApp, DBLimitOverride,
+                    DBWeekdayLimit,
+                },
+                public::{BlockedWindow, Weekday},
+            },
+            usage::data::{
+                private::DBUsageDay,
+                public::{CategoryStatus, LimitState, LimitStatus},
+            },
+        },
+    };
+
+    use super::LimitRules;
+
+    fn today(app: &str, app_usage: u32, app_limit: u32) -> DBUsageDay {
+        DBUsageDay {
+            user_id: 1,
+            device_id: 0,
+            app_name: app.to_string(),
+            day: "2025-05-01".to_string(),
+            app_usage,
+            app_limit,
+        }
+    }
+
+    #[test]
+    fn evaluate() {
+        let app_info = [
+            DBAppInfo::new_raw(1, "unused", 0, 600),
+            DBAppInfo::new_raw(1, "no_limit", 0, 0),
+            DBAppInfo::new_raw(1, "io1", 0, 100),
+        ];
+        let usage = [
+            today("io1", 50, 100),
+            today("near", 95, 100),
+            // synced today with a new limit.
+            today("over", 120, 60),
+            today("no_limit", 500, 0),
+        ];
+        let now = Utc.with_ymd_and_hms(2025, 5, 1, 15, 30, 0).unwrap();
+
+        let evaluation = super::evaluate(
+            &app_info,
+            &usage,
+            &LimitRules::default(),
+            &LimitConfig::default(),
+            now,
+        );
+        assert_eq!(
+            evaluation.resets_at,
+            Utc.with_ymd_and_hms(2025, 5, 2, 0, 0, 0)
+                .unwrap()
+                .timestamp()
+        );
+
+        let status = |app: &str, limit, usage, remaining, state| LimitStatus {
+            app: app.to_string(),
+            category: None,
+            limit,
+            usage,
+            remaining,
+            state,
+        };
+        assert_eq!(
+            evaluation.apps,
+            [
+                status("io1", 100, 50, 50, LimitState::Ok),
+                status("near", 100, 95, 5, LimitState::Warning),
+                status("over", 60, 120, 0, LimitState::Exceeded),
+                status("unused", 600, 0, 600, LimitState::Ok),
+            ]
+        );
+    }
+
+    #[test]
+    fn evaluate_schedule() {
+        let app_info = [
+            DBAppInfo::new_raw(1, "games", 0, 3600),
+            DBAppInfo::new_raw(1, "video", 0, 3600),
+            DBAppInfo::new_raw(1, "chat", 0, 3600),
+        ];
+        let usage = [today("games", 600, 3600), today("video", 600, 3600)];
+        let window = |start, end, weekdays| {
+            DBBlockedWindow::with_blocked_window(
+                1,
+                BlockedWindow {
+                    app: "games".to_string(),
+                    weekdays,
+                    start,
+                    end,
+                },
+            )
+        };
+        let rules = LimitRules {
+            weekday_limits: vec![
+                DBWeekdayLimit {
+                    user_id: 1,
+                    app_name: "video".to_string(),
+                    weekday: Weekday::Thursday.index(),
+                    app_limit: 1200,
+                },
+                DBWeekdayLimit {
+                    user_id: 1,
+                    app_name: "chat".to_string(),
+                    weekday: Weekday::Friday.index(),
+                    app_limit: 60,
+                },
+            ],
+            // no games from 09:00 to 17:00 on weekdays.
+            blocked_windows: vec![window(9 * 60, 17 * 60, Weekday::ALL[..5].to_vec())],
+            limit_overrides: vec![DBLimitOverride {
+                user_id: 1,
+                app_name: "chat".to_string(),
+                app_limit: 7200,
+                expires_at: Utc
+                    .with_ymd_and_hms(2025, 5, 1, 16, 0, 0)
+                    .unwrap()
+                    .timestamp(),
+            }],
+            ..Default::default()
+        };
+        let evaluate = |hour| {
+            // 2025-05-01 is a thursday.
+            let now = Utc.with_ymd_and_hms(2025, 5, 1, hour, 0, 0).unwrap();
+            super::evaluate(&app_info, &usage, &rules, &LimitConfig::default(), now)
+                .apps
+                .into_iter()
+                .map(|s| (s.app, s.limit, s.state))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            evaluate(12),
+            [
+                // the override has not expired.
+                ("chat".to_string(), 7200, LimitState::Ok),
+                ("games".to_string(), 3600, LimitState::Blocked),
+                // thursday's limit.
+                ("video".to_string(), 1200, LimitState::Ok),
+            ]
+        );
+        assert_eq!(
+            evaluate(18),
+            [
+                ("chat".to_string(), 3600, LimitState::Ok),
+                ("games".to_string(), 3600, LimitState::Ok),
+                ("video".to_string(), 1200, LimitState::Ok),
+            ]
+        );
+
+        // in tokyo, 02:00 utc is 11:00 on thursday, and 18:00 utc is 03:00 on friday.
+        let rules = LimitRules {
+            timezone: Some(chrono_tz::Asia::Tokyo),
+            ..rules
+        };
+        let evaluate = |hour| {
+            let now = Utc.with_ymd_and_hms(2025, 5, 1, hour, 0, 0).unwrap();
+            super::evaluate(&app_info, &usage, &rules, &LimitConfig::default(), now)
+                .apps
+                .into_iter()
+                .map(|s| (s.app, s.limit, s.state))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            evaluate(2)[1],
+            ("games".to_string(), 3600, LimitState::Blocked)
+        );
+        // at tokyo's midnight, 15:00 utc.
+        let now = Utc.with_ymd_and_hms(2025, 5, 1, 18, 0, 0).unwrap();
+        assert_eq!(
+            super::evaluate(&app_info, &usage, &rules, &LimitConfig::default(), now).resets_at,
+            Utc.with_ymd_and_hms(2025, 5, 2, 15, 0, 0)
+                .unwrap()
+                .timestamp()
+        );
+        assert_eq!(
+            evaluate(18),
+            [
+                // friday's limit.
+                ("chat".to_string(), 60, LimitState::Ok),
+                ("games".to_string(), 3600, LimitState::Ok),
+                ("video".to_string(), 3600, LimitState::Ok),
+            ]
+        );
+    }
+
+    #[test]
+    fn evaluate_categories() {
+        let app_info = [
+            DBAppInfo::new_raw(1, "io1", 0, 0),
+            DBAppInfo::new_raw(1, "io2", 0, 600),
+            DBAppInfo::new_raw(1, "chat", 0, 0),
+        ];
+        let usage = [today("io1", 2000, 0), today("io2", 300, 600)];
+        let member = |category: &str, app: &str| DBCategoryApp {
+            user_id: 1,
+            category: category.to_string(),
+            app_name: app.to_string(),
+        };
+        let rules = LimitRules {
+            categories: vec![
+                DBCategory {
+                    user_id: 1,
+                    name: "games".to_string(),
+                    category_limit: 2400,
+                },
+                // no limit.
+                DBCategory {
+                    user_id: 1,
+                    name: "social".to_string(),
+                    category_limit: 0,
+                },
+            ],
+            category_apps: vec![
+                member("games", "io1"),
+                member("games", "io2"),
+                member("social", "chat"),
+            ],
+            ..Default::default()
+        };
+        let now = Utc.with_ymd_and_hms(2025, 5, 1, 12, 0, 0).unwrap();
+
+        let evaluation = super::evaluate(&app_info, &usage, &rules, &LimitConfig::default(), now);
+        assert_eq!(
+            evaluation.categories,
+            [CategoryStatus {
+                name: "games".to_string(),
+                limit: 2400,
+                usage: 2300,
+                remaining: 100,
+                state: LimitState::Warning,
+            }]
+        );
+        assert_eq!(
+            evaluation.apps,
+            [
+                // limited by its category only.
+                LimitStatus {
+                    app: "io1".to_string(),
+                    category: Some("games".to_string()),
+                    limit: 0,
+                    usage: 2000,
+                    remaining: 100,
+                    state: LimitState::Warning,
+                },
+                // its own limit leaves more than its category's.
+                LimitStatus {
+                    app: "io2".to_string(),
+                    category: Some("games".to_string()),
+                    limit: 600,
+                    usage: 300,
+                    remaining: 100,
+                    state: LimitState::Warning,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn window_covers() {
+        let window = |start, end, weekdays: &[Weekday]| {
+            DBBlockedWindow::with_blocked_window(
+                1,
+                BlockedWindow {
+                    app: "games".to_string(),
+                    weekdays: weekdays.to_vec(),
+                    start,
+                    end,
+                },
+            )
+        };
+
+        let day = window(9 * 60, 17 * 60, &[Weekday::Monday]);
+        assert!(day.covers(Weekday::Monday, 9 * 60));
+        assert!(!day.covers(Weekday::Monday, 17 * 60));
+        assert!(!day.covers(Weekday::Tuesday, 12 * 60));
+
+        // 22:00 sunday to 06:00 monday.
+        let night = window(22 * 60, 6 * 60, &[Weekday::Sunday]);
+        assert!(night.covers(Weekday::Sunday, 23 * 60));
+        assert!(night.covers(Weekday::Monday, 5 * 60));
+        assert!(!night.covers(Weekday::Sunday, 5 * 60));
+        assert!(!night.covers(Weekday::Monday, 23 * 60));
+
+        assert!(!window(9 * 60, 17 * 60, &[]).covers(Weekday::Monday, 12 * 60));
+    }
+}
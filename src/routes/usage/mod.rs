@@ -0,0 +1,310 @@
+/// Resolving app names to canonical apps.
+pub(crate) mod aliases;
+/// Data structs regarding usage history shared in requests
+pub mod data;
+/// Evaluating app limits into a [`LimitEvaluation`].
+mod limits;
+/// Aggregating usage history into [`UsageStats`].
+mod stats;
+
+use aliases::Aliases;
+use limits::LimitRules;
+
+#[cfg(test)]
+mod tests;
+
+use std::io::Cursor;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::Tz;
+use data::{
+    private::{DAY_FORMAT, DBUsageDay},
+    public::{CatalogApp, LimitEvaluation, StatsPeriod, UsageError, UsageStats},
+};
+use pcupback::DBErrorKind::SelectError;
+use rocket::{
+    Request, Response, State, get,
+    http::{ContentType, Header, Status},
+    response::{self, Responder},
+    serde::json::Json,
+};
+use sqlx::{Pool, Sqlite};
+use tracing::instrument;
+
+use crate::{
+    config::LimitConfig,
+    repo::{SessionRepository, TokenRepository, UsageRepository},
+    util::{
+        auth::{CredentialError, authenticate},
+        db::PoolStateExt,
+    },
+};
+
+use super::{
+    sync::data::{private::DBAppInfo, public::AppInfo},
+    tokens::data::public::TokenScope,
+};
+
+/// The first line of [`UsageCsv`].
+const CSV_HEADER: [&str; 4] = ["app", "date", "usage_seconds", "limit_seconds"];
+
+/// A user's usage history as a CSV download, one line per app, per day.
+///
+/// The file is encoded whole before it is sent, so it has a known length.
+pub struct UsageCsv(Vec<DBUsageDay>);
+
+impl<'r> Responder<'r, 'static> for UsageCsv {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        let body = to_csv(self.0).map_err(|err| {
+            tracing::error!("could not encode csv: {err}");
+            Status::InternalServerError
+        })?;
+
+        Response::build()
+            .header(ContentType::CSV)
+            .header(Header::new(
+                "Content-Disposition",
+                "attachment; filename=\"pcupback-usage.csv\"",
+            ))
+            .sized_body(body.len(), Cursor::new(body))
+            .ok()
+    }
+}
+
+/// Encode `history` as CSV, after [`CSV_HEADER`], quoting fields as needed.
+fn to_csv(history: Vec<DBUsageDay>) -> Result<Vec<u8>, csv::Error> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(CSV_HEADER)?;
+    for usage in history {
+        writer.write_record([
+            usage.app_name,
+            usage.day,
+            usage.app_usage.to_string(),
+            usage.app_limit.to_string(),
+        ])?;
+    }
+    writer
+        .into_inner()
+        .map_err(|err| csv::Error::from(err.into_error()))
+}
+
+/// The bundled catalog of apps, that app names are resolved with, along with the user's own aliases.
+#[get("/usage/catalog")]
+pub fn usage_catalog() -> Json<Vec<CatalogApp>> {
+    Json(aliases::catalog())
+}
+
+/// Download the usage history of the owner of `credential`, a session id or an api token, as CSV.
+///
+/// Apps are combined by canonical name, see [`AppAlias`](crate::routes::sync::data::public::AppAlias).
+///
+/// `from` and `to` are inclusive `YYYY-MM-DD` dates, in the user's timezone. Either can be left out. Devices are
+/// combined, unless one is given by its `device` id.
+#[instrument(skip_all)]
+#[get("/usage/<credential>/csv?<from>&<to>&<device>")]
+pub async fn usage_csv(
+    state: &State<Pool<Sqlite>>,
+    credential: &str,
+    from: Option<&str>,
+    to: Option<&str>,
+    device: Option<u32>,
+) -> Result<UsageCsv, Json<UsageError>> {
+    history(state.to_db(), credential, device, from, to)
+        .await
+        .map(UsageCsv)
+        .map_err(Json)
+}
+
+/// How many apps [`UsageStats::top_apps`] has, if not given.
+const DEFAULT_TOP_APPS: usize = 10;
+
+/// Aggregated usage of the owner of `credential`, a session id or an api token.
+///
+/// `from`, `to` and `device` are as in [`usage_csv`]. `period` defaults to [`StatsPeriod::Day`],
+/// `top` to [`DEFAULT_TOP_APPS`].
+#[instrument(skip_all)]
+#[get("/usage/<credential>/stats?<period>&<from>&<to>&<device>&<top>")]
+pub async fn usage_stats(
+    state: &State<Pool<Sqlite>>,
+    credential: &str,
+    period: Option<StatsPeriod>,
+    from: Option<&str>,
+    to: Option<&str>,
+    device: Option<u32>,
+    top: Option<usize>,
+) -> Json<Result<UsageStats, UsageError>> {
+    Json(
+        history(state.to_db(), credential, device, from, to)
+            .await
+            .map(|history| {
+                stats::compute(
+                    &history,
+                    period.unwrap_or_default(),
+                    top.unwrap_or(DEFAULT_TOP_APPS),
+                )
+            }),
+    )
+}
+
+/// The time remaining today for each limited app and category of the owner of `credential`, a session id or an api token.
+#[instrument(skip_all)]
+#[get("/usage/<credential>/limits")]
+pub async fn usage_limits(
+    state: &State<Pool<Sqlite>>,
+    limit_config: &State<LimitConfig>,
+    credential: &str,
+) -> Json<Result<LimitEvaluation, UsageError>> {
+    Json(evaluate_limits(state.to_db(), limit_config, credential, Utc::now()).await)
+}
+
+/// Evaluate the limits of the owner of `credential` against their usage on `now`'s day.
+pub(crate) async fn evaluate_limits(
+    repo: &(impl SessionRepository + TokenRepository + UsageRepository),
+    config: &LimitConfig,
+    credential: &str,
+    now: DateTime<Utc>,
+) -> Result<LimitEvaluation, UsageError> {
+    use UsageError::{DBError, InsufficientScope, InvalidSession};
+
+    let user_id = match authenticate(repo, credential, TokenScope::ReadUsage).await {
+        Ok(user_id) => user_id,
+        Err(CredentialError::Invalid) => {
+            tracing::info!("session was invalid");
+            return Err(InvalidSession);
+        }
+        Err(CredentialError::InsufficientScope) => return Err(InsufficientScope),
+    };
+
+    limits_of(repo, config, user_id, now)
+        .await
+        .map_err(|err| DBError(SelectError(err.to_string())))
+}
+
+/// Evaluate the limits of `user_id` against their usage on `now`'s day, by canonical app.
+pub(crate) async fn limits_of(
+    repo: &impl UsageRepository,
+    config: &LimitConfig,
+    user_id: u32,
+    now: DateTime<Utc>,
+) -> Result<LimitEvaluation, sqlx::Error> {
+    let timezone = timezone_of(repo, user_id).await?;
+    let mut app_info = repo.fetch_app_info(user_id).await?;
+    let day = Some(local_time(now, timezone).date_naive());
+    let mut today = repo.fetch_usage_history(user_id, None, day, day).await?;
+
+    let mut rules = LimitRules {
+        weekday_limits: repo.fetch_weekday_limits(user_id).await?,
+        blocked_windows: repo.fetch_blocked_windows(user_id).await?,
+        limit_overrides: repo.fetch_limit_overrides(user_id).await?,
+        categories: repo.fetch_categories(user_id).await?,
+        category_apps: repo.fetch_category_apps(user_id).await?,
+        timezone,
+    };
+
+    let aliases = Aliases::new(&repo.fetch_app_aliases(user_id).await?);
+    app_info
+        .iter_mut()
+        .for_each(|a| aliases.rename(&mut a.app_name));
+    today
+        .iter_mut()
+        .for_each(|u| aliases.rename(&mut u.app_name));
+    rules.rename_apps(&aliases);
+
+    Ok(limits::evaluate(&app_info, &today, &rules, config, now))
+}
+
+/// Fetch the timezone of the user with the id `user_id`, see
+/// [`UserData::timezone`](crate::routes::sync::data::public::UserData::timezone).
+pub(crate) async fn timezone_of(
+    repo: &impl UsageRepository,
+    user_id: u32,
+) -> Result<Option<Tz>, sqlx::Error> {
+    // timezones are checked before being stored.
+    Ok(repo
+        .fetch_timezone(user_id)
+        .await?
+        .and_then(|timezone| timezone.parse().ok()))
+}
+
+/// `now` in `timezone`, utc if [`None`]. Its date is the day usage is recorded on and limits are evaluated for.
+pub(crate) fn local_time(now: DateTime<Utc>, timezone: Option<Tz>) -> DateTime<Tz> {
+    now.with_timezone(&timezone.unwrap_or(Tz::UTC))
+}
+
+/// Whether `usage` reached `limit`. A `limit` of `0` is no limit and is never exceeded.
+pub(crate) fn limit_exceeded(limit: u32, usage: u32) -> bool {
+    limit > 0 && usage >= limit
+}
+
+/// Fetch the usage history of the owner of `credential`, from `from` to `to`, by canonical app.
+///
+/// Only `device`'s, if given, else all devices' combined.
+pub(crate) async fn history(
+    repo: &(impl SessionRepository + TokenRepository + UsageRepository),
+    credential: &str,
+    device: Option<u32>,
+    from: Option<&str>,
+    to: Option<&str>,
+) -> Result<Vec<DBUsageDay>, UsageError> {
+    use UsageError::{DBError, InsufficientScope, InvalidRange, InvalidSession};
+
+    // every api token is allowed to read.
+    let user_id = match authenticate(repo, credential, TokenScope::ReadUsage).await {
+        Ok(user_id) => user_id,
+        Err(CredentialError::Invalid) => {
+            tracing::info!("session was invalid");
+            return Err(InvalidSession);
+        }
+        Err(CredentialError::InsufficientScope) => return Err(InsufficientScope),
+    };
+
+    let from = from.map(parse_day).transpose()?;
+    let to = to.map(parse_day).transpose()?;
+    if let (Some(from), Some(to)) = (from, to)
+        && from > to
+    {
+        return Err(InvalidRange);
+    }
+
+    let select_error = |err: sqlx::Error| DBError(SelectError(err.to_string()));
+    let aliases = Aliases::new(
+        &repo
+            .fetch_app_aliases(user_id)
+            .await
+            .map_err(select_error)?,
+    );
+    let history = aliases.aggregate(
+        repo.fetch_usage_history(user_id, device, from, to)
+            .await
+            .map_err(select_error)?,
+    );
+    tracing::info!("got {} days of app usage", history.len());
+    Ok(history)
+}
+
+fn parse_day(day: &str) -> Result<NaiveDate, UsageError> {
+    NaiveDate::parse_from_str(day, DAY_FORMAT).map_err(|_| UsageError::InvalidDate(day.to_string()))
+}
+
+/// Record `apps`' usage as of `day` for `user_id`, from the device with the id `device_id`, see
+/// [`UsageRepository::record_usage`].
+///
+/// Failing to record does not fail the sync.
+pub(crate) async fn record(
+    repo: &impl UsageRepository,
+    user_id: u32,
+    device_id: u32,
+    apps: &[AppInfo],
+    day: NaiveDate,
+) {
+    for app in apps {
+        let usage = DBUsageDay::with_app_info(
+            &DBAppInfo::with_app_info(user_id, app.clone()),
+            device_id,
+            day,
+        );
+        if let Err(err) = repo.record_usage(&usage).await {
+            tracing::warn!("failed to record app usage: {err:?}");
+        }
+    }
+}
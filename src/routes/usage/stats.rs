@@ -0,0 +1,192 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::{Datelike, Days, NaiveDate};
+
+use super::data::{
+    private::{DAY_FORMAT, DBUsageDay},
+    public::{AppTotal, LimitExceeded, PeriodTotal, StatsPeriod, UsageStats},
+};
+
+/// Aggregate `history` into [`UsageStats`], keeping the `top` most used apps.
+pub(crate) fn compute(history: &[DBUsageDay], period: StatsPeriod, top: usize) -> UsageStats {
+    let mut totals: BTreeMap<(NaiveDate, &str), u64> = BTreeMap::new();
+    let mut app_totals: BTreeMap<&str, u64> = BTreeMap::new();
+    let mut days = BTreeSet::new();
+    // `(days, days_exceeded)`
+    let mut limits: BTreeMap<&str, (u32, u32)> = BTreeMap::new();
+
+    for usage in history {
+        let Ok(day) = NaiveDate::parse_from_str(&usage.day, DAY_FORMAT) else {
+            tracing::warn!("skipping usage on invalid day {:?}", usage.day);
+            continue;
+        };
+        let app = usage.app_name.as_str();
+
+        *totals.entry((period_start(day, period), app)).or_default() += u64::from(usage.app_usage);
+        *app_totals.entry(app).or_default() += u64::from(usage.app_usage);
+        days.insert(day);
+
+        if usage.app_limit > 0 {
+            let (days, days_exceeded) = limits.entry(app).or_default();
+            *days += 1;
+            if super::limit_exceeded(usage.app_limit, usage.app_usage) {
+                *days_exceeded += 1;
+            }
+        }
+    }
+
+    let mut top_apps: Vec<AppTotal> = app_totals
+        .iter()
+        .map(|(app, usage)| AppTotal {
+            app: (*app).to_string(),
+            usage: *usage,
+        })
+        .collect();
+    // most used first, then by name. the sort is stable.
+    top_apps.sort_by_key(|a| std::cmp::Reverse(a.usage));
+    top_apps.truncate(top);
+
+    let average_daily_usage = match u64::try_from(days.len()) {
+        Ok(0) | Err(_) => 0,
+        Ok(days) => app_totals.values().sum::<u64>() / days,
+    };
+
+    UsageStats {
+        period,
+        totals: totals
+            .into_iter()
+            .map(|((start, app), usage)| PeriodTotal {
+                period_start: start.format(DAY_FORMAT).to_string(),
+                app: app.to_string(),
+                usage,
+            })
+            .collect(),
+        top_apps,
+        average_daily_usage,
+        limits_exceeded: limits
+            .into_iter()
+            .map(|(app, (days, days_exceeded))| LimitExceeded {
+                app: app.to_string(),
+                days,
+                days_exceeded,
+                percentage: f64::from(days_exceeded) * 100.0 / f64::from(days),
+            })
+            .collect(),
+    }
+}
+
+/// The first day of the `period` `day` is in.
+fn period_start(day: NaiveDate, period: StatsPeriod) -> NaiveDate {
+    match period {
+        StatsPeriod::Day => day,
+        StatsPeriod::Week => day - Days::new(day.weekday().num_days_from_monday().into()),
+        StatsPeriod::Month => day.with_day(1).unwrap_or(day),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::routes::usage::data::{
+        private::DBUsageDay,
+        public::{AppTotal, LimitExceeded, PeriodTotal, StatsPeriod},
+    };
+
+    fn usage(app: &str, day: &str, app_usage: u32, app_limit: u32) -> DBUsageDay {
+        DBUsageDay {
+            user_id: 1,
+            device_id: 0,
+            app_name: app.to_string(),
+            day: day.to_string(),
+            app_usage,
+            app_limit,
+        }
+    }
+
+    fn totals(history: &[DBUsageDay], period: StatsPeriod) -> Vec<(String, String, u64)> {
+        super::compute(history, period, 10)
+            .totals
+            .into_iter()
+            .map(
+                |PeriodTotal {
+                     period_start,
+                     app,
+                     usage,
+                 }| (period_start, app, usage),
+            )
+            .collect()
+    }
+
+    #[test]
+    fn periods() {
+        // 2025-05-04 is a sunday, 2025-05-05 a monday.
+        let history = [
+            usage("io1", "2025-04-30", 10, 0),
+            usage("io1", "2025-05-04", 20, 0),
+            usage("io1", "2025-05-05", 40, 0),
+            usage("io2", "2025-05-05", 5, 0),
+        ];
+
+        let day = |start: &str, app: &str, usage| (start.to_string(), app.to_string(), usage);
+        assert_eq!(
+            totals(&history, StatsPeriod::Day),
+            [
+                day("2025-04-30", "io1", 10),
+                day("2025-05-04", "io1", 20),
+                day("2025-05-05", "io1", 40),
+                day("2025-05-05", "io2", 5),
+            ]
+        );
+        assert_eq!(
+            totals(&history, StatsPeriod::Week),
+            [
+                day("2025-04-28", "io1", 30),
+                day("2025-05-05", "io1", 40),
+                day("2025-05-05", "io2", 5),
+            ]
+        );
+        assert_eq!(
+            totals(&history, StatsPeriod::Month),
+            [
+                day("2025-04-01", "io1", 10),
+                day("2025-05-01", "io1", 60),
+                day("2025-05-01", "io2", 5),
+            ]
+        );
+    }
+
+    #[test]
+    fn top_apps_average_and_limits() {
+        let history = [
+            usage("io1", "2025-05-01", 100, 60),
+            usage("io2", "2025-05-01", 20, 0),
+            usage("io3", "2025-05-01", 20, 0),
+            usage("io1", "2025-05-02", 30, 60),
+            usage("io1", "2025-05-03", 70, 60),
+            usage("io1", "2025-05-04", 60, 60),
+            usage("io2", "2025-05-04", 60, 0),
+        ];
+
+        let stats = super::compute(&history, StatsPeriod::Day, 2);
+        let app = |app: &str, usage| AppTotal {
+            app: app.to_string(),
+            usage,
+        };
+        assert_eq!(stats.top_apps, [app("io1", 260), app("io2", 80)]);
+        // 360 seconds over 4 days.
+        assert_eq!(stats.average_daily_usage, 90);
+        assert_eq!(
+            stats.limits_exceeded,
+            [LimitExceeded {
+                app: "io1".to_string(),
+                days: 4,
+                // the limit is reached on the last day.
+                days_exceeded: 3,
+                percentage: 75.0,
+            }]
+        );
+
+        let empty = super::compute(&[], StatsPeriod::Day, 2);
+        assert_eq!(empty.average_daily_usage, 0);
+        assert!(empty.top_apps.is_empty());
+    }
+}
@@ -0,0 +1,291 @@
+use chrono::{NaiveDate, Utc};
+use rocket::http::{ContentType, Status};
+
+use crate::{
+    repo::memory::MemoryRepository,
+    routes::{
+        auth::{AuthResult, data::public::AuthRequest, signed_up},
+        sync::data::public::{AppAlias, AppInfo, UserData},
+    },
+};
+
+use super::{
+    data::public::{CatalogApp, LimitEvaluation, LimitState, StatsPeriod, UsageError, UsageStats},
+    history, record,
+};
+
+fn day(day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(2025, 5, day).unwrap()
+}
+
+#[rocket::async_test]
+async fn record_and_filter() {
+    let repo = MemoryRepository::default();
+    let (user, session) = signed_up(&repo).await;
+    let user_id = { user.id };
+
+    record(&repo, user_id, 0, &[AppInfo::new("io1", 60, 0)], day(1)).await;
+    record(
+        &repo,
+        user_id,
+        0,
+        &[AppInfo::new("io1", 120, 600), AppInfo::new("io2", 5, 0)],
+        day(2),
+    )
+    .await;
+    // a later sync with less usage keeps the day's most, and takes the new limit.
+    record(&repo, user_id, 0, &[AppInfo::new("io1", 30, 300)], day(2)).await;
+
+    let all = history(&repo, &session.id, None, None, None).await.unwrap();
+    let rows: Vec<_> = all
+        .iter()
+        .map(|u| {
+            (
+                u.app_name.as_str(),
+                u.day.as_str(),
+                u.app_usage,
+                u.app_limit,
+            )
+        })
+        .collect();
+    assert_eq!(
+        rows,
+        [
+            ("io1", "2025-05-01", 60, 0),
+            ("io1", "2025-05-02", 120, 300),
+            ("io2", "2025-05-02", 5, 0),
+        ]
+    );
+
+    // both ends are inclusive.
+    let second = history(
+        &repo,
+        &session.id,
+        None,
+        Some("2025-05-02"),
+        Some("2025-05-02"),
+    )
+    .await
+    .unwrap();
+    assert_eq!(second.len(), 2);
+    let first = history(&repo, &session.id, None, None, Some("2025-05-01"))
+        .await
+        .unwrap();
+    assert_eq!(first.len(), 1);
+
+    assert!(matches!(
+        history(&repo, &session.id, None, Some("05/01/2025"), None)
+            .await
+            .unwrap_err(),
+        UsageError::InvalidDate(_)
+    ));
+    assert!(matches!(
+        history(
+            &repo,
+            &session.id,
+            None,
+            Some("2025-05-02"),
+            Some("2025-05-01")
+        )
+        .await
+        .unwrap_err(),
+        UsageError::InvalidRange
+    ));
+    assert!(matches!(
+        history(&repo, "not-a-session", None, None, None)
+            .await
+            .unwrap_err(),
+        UsageError::InvalidSession
+    ));
+}
+
+#[macros::rocket_test]
+fn usage_csv() {
+    let session = client
+        .post("/auth/signup")
+        .json(&AuthRequest::random_valid())
+        .dispatch()
+        .into_json::<AuthResult>()
+        .unwrap()
+        .unwrap();
+
+    let data = UserData {
+        app_usage: vec![
+            AppInfo::new("io1", 60, 0),
+            AppInfo::new("app, with comma", 5, 10),
+        ],
+        debug: vec![],
+        ..Default::default()
+    };
+    client
+        .post(format!("/sync/{}", session.id))
+        .json(&Some(data))
+        .dispatch();
+
+    let today = Utc::now().date_naive().format("%Y-%m-%d").to_string();
+    let response = client
+        .get(format!("/usage/{}/csv?from={today}", session.id))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::CSV));
+    assert_eq!(
+        response.into_string().unwrap(),
+        format!(
+            "app,date,usage_seconds,limit_seconds\n\
+             \"app, with comma\",{today},5,10\n\
+             io1,{today},60,0\n"
+        )
+    );
+
+    // nothing before today.
+    let response = client
+        .get(format!("/usage/{}/csv?to=2000-01-01", session.id))
+        .dispatch();
+    assert_eq!(
+        response.into_string().unwrap(),
+        "app,date,usage_seconds,limit_seconds\n"
+    );
+
+    let invalid = client
+        .get(format!("/usage/{}/csv?from=yesterday", session.id))
+        .dispatch()
+        .into_json::<UsageError>()
+        .unwrap();
+    assert!(matches!(invalid, UsageError::InvalidDate(_)));
+}
+
+#[macros::rocket_test]
+fn usage_stats() {
+    let session = client
+        .post("/auth/signup")
+        .json(&AuthRequest::random_valid())
+        .dispatch()
+        .into_json::<AuthResult>()
+        .unwrap()
+        .unwrap();
+
+    let data = UserData {
+        app_usage: vec![AppInfo::new("io1", 90, 60), AppInfo::new("io2", 30, 0)],
+        debug: vec![],
+        ..Default::default()
+    };
+    client
+        .post(format!("/sync/{}", session.id))
+        .json(&Some(data))
+        .dispatch();
+
+    let stats = client
+        .get(format!("/usage/{}/stats?period=month&top=1", session.id))
+        .dispatch()
+        .into_json::<Result<UsageStats, UsageError>>()
+        .unwrap()
+        .unwrap();
+    assert_eq!(stats.period, StatsPeriod::Month);
+    assert_eq!(stats.totals.len(), 2);
+    assert_eq!(stats.top_apps.len(), 1);
+    assert_eq!(stats.top_apps[0].app, "io1");
+    assert_eq!(stats.average_daily_usage, 120);
+    assert_eq!(stats.limits_exceeded.len(), 1);
+    assert!((stats.limits_exceeded[0].percentage - 100.0).abs() < f64::EPSILON);
+}
+
+#[macros::rocket_test]
+fn usage_limits() {
+    let session = client
+        .post("/auth/signup")
+        .json(&AuthRequest::random_valid())
+        .dispatch()
+        .into_json::<AuthResult>()
+        .unwrap()
+        .unwrap();
+
+    let data = UserData {
+        app_usage: vec![AppInfo::new("io1", 30, 60), AppInfo::new("io2", 30, 0)],
+        debug: vec![],
+        ..Default::default()
+    };
+    client
+        .post(format!("/sync/{}", session.id))
+        .json(&Some(data))
+        .dispatch();
+
+    let evaluation = client
+        .get(format!("/usage/{}/limits", session.id))
+        .dispatch()
+        .into_json::<Result<LimitEvaluation, UsageError>>()
+        .unwrap()
+        .unwrap();
+    assert!(evaluation.resets_at > Utc::now().timestamp());
+    // `io2` has no limit.
+    assert_eq!(evaluation.apps.len(), 1);
+    assert_eq!(evaluation.apps[0].remaining, 30);
+    assert_eq!(evaluation.apps[0].state, LimitState::Ok);
+}
+
+#[macros::rocket_test]
+fn usage_by_alias() {
+    let session = client
+        .post("/auth/signup")
+        .json(&AuthRequest::random_valid())
+        .dispatch()
+        .into_json::<AuthResult>()
+        .unwrap()
+        .unwrap();
+
+    // two devices, and an alias of the user's own.
+    let data = UserData {
+        app_usage: vec![
+            AppInfo::new("chrome.exe", 60, 0),
+            AppInfo::new("com.android.chrome", 30, 120),
+            AppInfo::new("code.exe", 10, 0),
+        ],
+        aliases: vec![AppAlias {
+            alias: "code.exe".to_string(),
+            app: "Editor".to_string(),
+        }],
+        ..Default::default()
+    };
+    let summary = client
+        .post(format!("/sync/{}", session.id))
+        .json(&Some(data.clone()))
+        .dispatch()
+        .into_json::<crate::routes::sync::SyncResult>()
+        .unwrap()
+        .unwrap();
+    assert_eq!(summary.data.aliases, data.aliases);
+
+    let today = Utc::now().date_naive().format("%Y-%m-%d").to_string();
+    let csv = client
+        .get(format!("/usage/{}/csv", session.id))
+        .dispatch()
+        .into_string()
+        .unwrap();
+    assert_eq!(
+        csv,
+        format!(
+            "app,date,usage_seconds,limit_seconds\n\
+             Editor,{today},10,0\n\
+             Google Chrome,{today},90,120\n"
+        )
+    );
+
+    let evaluation = client
+        .get(format!("/usage/{}/limits", session.id))
+        .dispatch()
+        .into_json::<Result<LimitEvaluation, UsageError>>()
+        .unwrap()
+        .unwrap();
+    assert_eq!(evaluation.apps.len(), 1);
+    assert_eq!(evaluation.apps[0].app, "Google Chrome");
+    assert_eq!(evaluation.apps[0].remaining, 30);
+
+    let catalog = client
+        .get("/usage/catalog")
+        .dispatch()
+        .into_json::<Vec<CatalogApp>>()
+        .unwrap();
+    assert!(
+        catalog.iter().any(|app| app.name == "Google Chrome"
+            && app.identifiers.contains(&"chrome.exe".to_string()))
+    );
+}
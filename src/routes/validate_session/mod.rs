@@ -2,13 +2,13 @@
 mod tests;
 
 use chrono::{DateTime, Utc};
-use pcupback::Fetchable;
 use rocket::{State, get, serde::json::Json};
 use sqlx::{Pool, Sqlite};
 
-use crate::util::{auth::session_timeout, db::PoolStateExt};
-
-use super::auth::data::private::DBUserSession;
+use crate::{
+    repo::SessionRepository,
+    util::{auth::session_timeout, db::PoolStateExt},
+};
 
 #[inline]
 fn not_timed_out(dt: DateTime<Utc>) -> bool {
@@ -17,10 +17,12 @@ fn not_timed_out(dt: DateTime<Utc>) -> bool {
 
 #[get("/auth/validate_session/<session_id>")]
 pub async fn validate_session(state: &State<Pool<Sqlite>>, session_id: &str) -> Json<bool> {
-    let db = state.to_db();
+    Json(is_valid(state.to_db(), session_id).await)
+}
 
-    let valid = DBUserSession::fetch_one(session_id, db)
+/// Return `true` if `session_id` exists and has not timed out.
+pub(crate) async fn is_valid(repo: &impl SessionRepository, session_id: &str) -> bool {
+    repo.fetch_session(session_id)
         .await
-        .is_ok_and(|f| f.id == session_id && f.last_set_datetime().is_some_and(not_timed_out));
-    Json(valid)
+        .is_ok_and(|f| f.id == session_id && f.last_set_datetime().is_some_and(not_timed_out))
 }
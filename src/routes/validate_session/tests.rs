@@ -14,7 +14,7 @@ fn validate_session() {
 
     // get the session
     let session = client
-        .post("/auth")
+        .post("/auth/signup")
         .json(&user)
         .dispatch()
         .into_json::<AuthResult>()
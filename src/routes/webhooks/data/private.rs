@@ -0,0 +1,151 @@
+use chrono::Utc;
+use pcupback::{Fetchable, FetchableAll, Storable};
+use sqlx::{Executor, FromRow, Sqlite, sqlite::SqliteQueryResult};
+use uuid::Uuid;
+
+use crate::{notify::data::public::EventKind, util::token::generate_secret};
+
+/// A webhook, see the `webhooks` table.
+#[derive(Debug, FromRow, PartialEq, Eq, Clone)]
+pub struct DBWebhook {
+    pub id: String,
+    pub user_id: u32,
+    pub url: String,
+    /// What payloads are signed with.
+    pub secret: String,
+    /// [`EventKind::name`]s, comma separated. Empty for all.
+    pub events: String,
+    /// Stored as seconds since the unix epoch.
+    pub created_at: i64,
+}
+
+impl DBWebhook {
+    /// Create a webhook posting `events` to `url`, all of them if empty.
+    ///
+    /// `created_at` is [`Utc::now`]. `id` is [`Uuid::new_v4`], `secret` is random.
+    #[must_use]
+    pub fn new(user_id: u32, url: impl Into<String>, events: &[EventKind]) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            user_id,
+            url: url.into(),
+            secret: generate_secret(),
+            events: events
+                .iter()
+                .map(|e| e.name())
+                .collect::<Vec<_>>()
+                .join(","),
+            created_at: Utc::now().timestamp(),
+        }
+    }
+
+    /// The kinds of events delivered, empty for all.
+    #[must_use]
+    pub fn event_kinds(&self) -> Vec<EventKind> {
+        self.events
+            .split(',')
+            .filter_map(EventKind::from_name)
+            .collect()
+    }
+
+    /// Return `true` if events of `kind` are delivered.
+    #[must_use]
+    pub fn receives(&self, kind: EventKind) -> bool {
+        self.events.is_empty() || self.event_kinds().contains(&kind)
+    }
+}
+
+impl<'a> Fetchable<'a, &'a str> for DBWebhook {
+    type DB = Sqlite;
+
+    /// id filter
+    async fn fetch_one<E>(filter: &'a str, executor: E) -> Result<Self, sqlx::Error>
+    where
+        E: Executor<'a, Database = Self::DB>,
+    {
+        sqlx::query_as("SELECT * FROM webhooks WHERE id = ?")
+            .bind(filter)
+            .fetch_one(executor)
+            .await
+    }
+}
+
+impl<'a> FetchableAll<'a, u32> for DBWebhook {
+    type DB = Sqlite;
+
+    /// user id filter
+    async fn fetch_all<E>(filter: u32, executor: E) -> Result<Vec<Self>, sqlx::Error>
+    where
+        E: Executor<'a, Database = Self::DB>,
+    {
+        sqlx::query_as("SELECT * FROM webhooks WHERE user_id = ? ORDER BY created_at, id")
+            .bind(filter)
+            .fetch_all(executor)
+            .await
+    }
+}
+
+impl<'a> Storable<'a> for DBWebhook {
+    type DB = Sqlite;
+
+    async fn store<E>(&self, executor: E) -> Result<SqliteQueryResult, sqlx::Error>
+    where
+        E: Executor<'a, Database = Self::DB>,
+    {
+        sqlx::query!(
+            "INSERT INTO webhooks(id, user_id, url, secret, events, created_at) VALUES(?, ?, ?, ?, ?, ?)",
+            self.id,
+            self.user_id,
+            self.url,
+            self.secret,
+            self.events,
+            self.created_at
+        )
+        .execute(executor)
+        .await
+    }
+}
+
+/// A delivery of an event to a webhook, see the `webhook_deliveries` table.
+#[derive(Debug, FromRow, PartialEq, Eq, Clone)]
+pub struct DBWebhookDelivery {
+    pub id: u32,
+    pub webhook_id: String,
+    /// The event's kind, e.g. `app_limit_exceeded`.
+    pub event: String,
+    /// The [`Notification`](crate::notify::data::public::Notification), as json.
+    pub payload: String,
+    /// Stored as seconds since the unix epoch.
+    pub created: i64,
+    /// Failed deliveries so far.
+    pub attempts: u32,
+    /// When to next try delivering, [`None`] once delivered or given up on.
+    pub next_attempt: Option<i64>,
+    pub delivered: Option<i64>,
+    /// The status of the last response, [`None`] if there was none.
+    pub status: Option<u16>,
+    pub last_error: Option<String>,
+}
+
+impl<'a> Storable<'a> for DBWebhookDelivery {
+    type DB = Sqlite;
+
+    /// Updates the delivery with the same id.
+    async fn store<E>(&self, executor: E) -> Result<SqliteQueryResult, sqlx::Error>
+    where
+        E: Executor<'a, Database = Self::DB>,
+    {
+        sqlx::query!(
+            "UPDATE webhook_deliveries SET attempts = ?, next_attempt = ?, delivered = ?, status = ?, last_error = ?
+            WHERE id = ?",
+            self.attempts,
+            self.next_attempt,
+            self.delivered,
+            self.status,
+            self.last_error,
+            self.id
+        )
+        .execute(executor)
+        .await
+    }
+}
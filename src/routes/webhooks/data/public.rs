@@ -0,0 +1,101 @@
+use pcupback::DBErrorKind;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::notify::data::public::EventKind;
+
+use super::private::{DBWebhook, DBWebhookDelivery};
+
+/// A request to register a webhook.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NewWebhookRequest {
+    /// An `http` or `https` url events are posted to.
+    pub url: String,
+    /// The kinds of events posted, all of them if empty.
+    #[serde(default)]
+    pub events: Vec<EventKind>,
+}
+
+/// A webhook, as listed to its owner. Its secret is only shown once, see [`CreatedWebhook`].
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct Webhook {
+    /// The id the webhook is deleted by.
+    pub id: String,
+    pub url: String,
+    /// Empty for all.
+    pub events: Vec<EventKind>,
+    /// Seconds since the unix epoch.
+    pub created_at: i64,
+}
+
+impl From<DBWebhook> for Webhook {
+    fn from(value: DBWebhook) -> Self {
+        Self {
+            events: value.event_kinds(),
+            id: value.id,
+            url: value.url,
+            created_at: value.created_at,
+        }
+    }
+}
+
+/// A newly registered webhook.
+///
+/// Each delivery is signed with `secret`: its `X-Pcup-Signature` header is `sha256=` and the hex encoded
+/// hmac-sha256 of the `X-Pcup-Timestamp` header, a `.` and the body.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CreatedWebhook {
+    pub secret: String,
+    #[serde(flatten)]
+    pub info: Webhook,
+}
+
+/// A delivery of an event to a webhook, as logged to its owner.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct WebhookDelivery {
+    pub id: u32,
+    /// The event's kind, or `webhook_test`.
+    pub event: String,
+    /// Seconds since the unix epoch.
+    pub created: i64,
+    /// Failed deliveries so far.
+    pub attempts: u32,
+    /// When it is retried, [`None`] once delivered or given up on. Seconds since the unix epoch.
+    pub next_attempt: Option<i64>,
+    /// Seconds since the unix epoch.
+    pub delivered: Option<i64>,
+    /// The status of the last response.
+    pub status: Option<u16>,
+    pub last_error: Option<String>,
+}
+
+impl From<DBWebhookDelivery> for WebhookDelivery {
+    fn from(value: DBWebhookDelivery) -> Self {
+        Self {
+            id: value.id,
+            event: value.event,
+            created: value.created,
+            attempts: value.attempts,
+            next_attempt: value.next_attempt,
+            delivered: value.delivered,
+            status: value.status,
+            last_error: value.last_error,
+        }
+    }
+}
+
+#[derive(Error, Debug, Serialize, Deserialize)]
+pub enum WebhookError {
+    #[error("InvalidSession")]
+    InvalidSession,
+    /// Not an `http` or `https` url, or its host does not resolve to public addresses.
+    #[error("InvalidUrl")]
+    InvalidUrl,
+    #[error("TooManyWebhooks")]
+    TooManyWebhooks,
+    /// The user has no webhook with the id.
+    #[error("NotFound")]
+    NotFound,
+    #[error("DBError")]
+    DBError(#[from] DBErrorKind),
+}
@@ -0,0 +1,269 @@
+/// Data structs regarding webhooks shared in requests
+pub mod data;
+
+#[cfg(test)]
+mod tests;
+
+use chrono::Utc;
+use data::{
+    private::DBWebhook,
+    public::{CreatedWebhook, NewWebhookRequest, Webhook, WebhookDelivery, WebhookError},
+};
+use pcupback::DBErrorKind::{DeleteError, InsertError, SelectError};
+use rocket::{State, delete, get, post, serde::json::Json};
+use sqlx::{Pool, Sqlite};
+use tracing::instrument;
+
+use crate::{
+    notify::{
+        data::public::{Event, Notification},
+        net::check_destination,
+        sink::{HttpClient, parse_url},
+        webhooks,
+    },
+    repo::{OutboxRepository, SessionRepository, UserRepository, WebhookRepository},
+    util::db::PoolStateExt,
+};
+
+/// The most webhooks a user can have.
+const MAX_WEBHOOKS: usize = 10;
+
+/// The most deliveries listed per webhook.
+const MAX_LISTED_DELIVERIES: u32 = 100;
+
+/// The kind of test deliveries, as in their log.
+const TEST_EVENT: &str = "webhook_test";
+
+type CreateWebhookResult = Result<CreatedWebhook, WebhookError>;
+type ListWebhooksResult = Result<Vec<Webhook>, WebhookError>;
+type DeleteWebhookResult = Result<(), WebhookError>;
+type DeliveriesResult = Result<Vec<WebhookDelivery>, WebhookError>;
+type TestWebhookResult = Result<WebhookDelivery, WebhookError>;
+
+#[instrument(skip_all)]
+#[post("/webhooks/<session_id>", data = "<request>")]
+pub async fn create_webhook(
+    state: &State<Pool<Sqlite>>,
+    client: &State<HttpClient>,
+    session_id: &str,
+    request: Json<NewWebhookRequest>,
+) -> Json<CreateWebhookResult> {
+    Json(create(state.to_db(), client, session_id, &request).await)
+}
+
+#[instrument(skip_all)]
+#[get("/webhooks/<session_id>")]
+pub async fn list_webhooks(
+    state: &State<Pool<Sqlite>>,
+    session_id: &str,
+) -> Json<ListWebhooksResult> {
+    Json(list(state.to_db(), session_id).await)
+}
+
+#[instrument(skip_all)]
+#[delete("/webhooks/<session_id>/<webhook_id>")]
+pub async fn delete_webhook(
+    state: &State<Pool<Sqlite>>,
+    session_id: &str,
+    webhook_id: &str,
+) -> Json<DeleteWebhookResult> {
+    Json(delete(state.to_db(), session_id, webhook_id).await)
+}
+
+#[instrument(skip_all)]
+#[get("/webhooks/<session_id>/<webhook_id>/deliveries")]
+pub async fn webhook_deliveries(
+    state: &State<Pool<Sqlite>>,
+    session_id: &str,
+    webhook_id: &str,
+) -> Json<DeliveriesResult> {
+    Json(deliveries(state.to_db(), session_id, webhook_id).await)
+}
+
+#[instrument(skip_all)]
+#[post("/webhooks/<session_id>/<webhook_id>/test")]
+pub async fn test_webhook(
+    state: &State<Pool<Sqlite>>,
+    client: &State<HttpClient>,
+    session_id: &str,
+    webhook_id: &str,
+) -> Json<TestWebhookResult> {
+    Json(test(state.to_db(), client, session_id, webhook_id).await)
+}
+
+/// Register a webhook for the owner of `session_id`.
+///
+/// Only sessions can manage webhooks, api tokens cannot. The url must resolve to global addresses, unless `client`
+/// allows local ones.
+///
+/// If it receives [`EventKind::AccountCreated`](crate::notify::data::public::EventKind::AccountCreated), it is
+/// sent the user's `AccountCreated` event again, see [`webhooks::replay_account_created`].
+pub(crate) async fn create(
+    repo: &(impl SessionRepository + WebhookRepository + OutboxRepository + UserRepository),
+    client: &HttpClient,
+    session_id: &str,
+    request: &NewWebhookRequest,
+) -> CreateWebhookResult {
+    let user_id = session_user_id(repo, session_id).await?;
+    register(repo, client, user_id, request).await
+}
+
+/// Register a webhook for the user with the id `user_id`, see [`create`].
+pub(crate) async fn register(
+    repo: &(impl WebhookRepository + OutboxRepository + UserRepository),
+    client: &HttpClient,
+    user_id: u32,
+    request: &NewWebhookRequest,
+) -> CreateWebhookResult {
+    use WebhookError::{DBError, InvalidUrl, TooManyWebhooks};
+
+    let url = request.url.trim();
+    let Ok(parsed) = parse_url(url) else {
+        return Err(InvalidUrl);
+    };
+    if let Err(err) = check_destination(&parsed, client.allows_local()).await {
+        tracing::info!("user {user_id} gave an unusable webhook url: {err}");
+        return Err(InvalidUrl);
+    }
+
+    let existing = repo
+        .fetch_webhooks(user_id)
+        .await
+        .map_err(|err| DBError(SelectError(err.to_string())))?;
+    if existing.len() >= MAX_WEBHOOKS {
+        return Err(TooManyWebhooks);
+    }
+
+    let mut events = request.events.clone();
+    events.sort();
+    events.dedup();
+    let webhook = DBWebhook::new(user_id, url, &events);
+    repo.insert_webhook(&webhook)
+        .await
+        .map_err(|err| DBError(InsertError(err.to_string())))?;
+    tracing::info!("user {user_id} created webhook {}", webhook.id);
+    webhooks::replay_account_created(repo, &webhook, Utc::now().timestamp())
+        .await
+        .map_err(|err| DBError(InsertError(err.to_string())))?;
+
+    Ok(CreatedWebhook {
+        secret: webhook.secret.clone(),
+        info: webhook.into(),
+    })
+}
+
+/// List the webhooks of the owner of `session_id`.
+pub(crate) async fn list(
+    repo: &(impl SessionRepository + WebhookRepository),
+    session_id: &str,
+) -> ListWebhooksResult {
+    let user_id = session_user_id(repo, session_id).await?;
+
+    let webhooks = repo
+        .fetch_webhooks(user_id)
+        .await
+        .map_err(|err| WebhookError::DBError(SelectError(err.to_string())))?;
+    Ok(webhooks.into_iter().map(Into::into).collect())
+}
+
+/// Delete the webhook `webhook_id` of the owner of `session_id`, and its deliveries.
+pub(crate) async fn delete(
+    repo: &(impl SessionRepository + WebhookRepository),
+    session_id: &str,
+    webhook_id: &str,
+) -> DeleteWebhookResult {
+    let user_id = session_user_id(repo, session_id).await?;
+
+    let deleted = repo
+        .delete_webhook(user_id, webhook_id)
+        .await
+        .map_err(|err| WebhookError::DBError(DeleteError(err.to_string())))?;
+    if !deleted {
+        return Err(WebhookError::NotFound);
+    }
+    tracing::info!("user {user_id} deleted webhook {webhook_id}");
+
+    Ok(())
+}
+
+/// List the last deliveries to the webhook `webhook_id` of the owner of `session_id`, newest first.
+pub(crate) async fn deliveries(
+    repo: &(impl SessionRepository + WebhookRepository),
+    session_id: &str,
+    webhook_id: &str,
+) -> DeliveriesResult {
+    let user_id = session_user_id(repo, session_id).await?;
+    let webhook = owned_webhook(repo, user_id, webhook_id).await?;
+
+    let deliveries = repo
+        .fetch_webhook_deliveries(&webhook.id, MAX_LISTED_DELIVERIES)
+        .await
+        .map_err(|err| WebhookError::DBError(SelectError(err.to_string())))?;
+    Ok(deliveries.into_iter().map(Into::into).collect())
+}
+
+/// Send a test event to the webhook `webhook_id` of the owner of `session_id`, returning how it went.
+///
+/// Test deliveries are logged, but not retried.
+pub(crate) async fn test(
+    repo: &(impl SessionRepository + WebhookRepository + UserRepository),
+    client: &HttpClient,
+    session_id: &str,
+    webhook_id: &str,
+) -> TestWebhookResult {
+    use WebhookError::DBError;
+
+    let user_id = session_user_id(repo, session_id).await?;
+    let webhook = owned_webhook(repo, user_id, webhook_id).await?;
+    let user = repo
+        .fetch_user_by_id(user_id)
+        .await
+        .map_err(|err| DBError(SelectError(err.to_string())))?;
+
+    let now = Utc::now().timestamp();
+    let notification = Notification {
+        id: 0,
+        user: user.public_id,
+        created: now,
+        event: Event::WebhookTest {
+            webhook: webhook.id.clone(),
+        },
+    };
+    let payload = rocket::serde::json::to_string(&notification)
+        .map_err(|err| DBError(InsertError(err.to_string())))?;
+
+    let mut delivery = repo
+        .enqueue_webhook_delivery(&webhook.id, TEST_EVENT, &payload, now)
+        .await
+        .map_err(|err| DBError(InsertError(err.to_string())))?;
+    webhooks::attempt(repo, client, &webhook, &mut delivery, None, now)
+        .await
+        .map_err(|err| DBError(InsertError(err.to_string())))?;
+
+    Ok(delivery.into())
+}
+
+/// Fetch the webhook `webhook_id`, if it is the user with the id `user_id`'s.
+async fn owned_webhook(
+    repo: &impl WebhookRepository,
+    user_id: u32,
+    webhook_id: &str,
+) -> Result<DBWebhook, WebhookError> {
+    match repo.fetch_webhook(webhook_id).await {
+        Ok(webhook) if webhook.user_id == user_id => Ok(webhook),
+        Ok(_) | Err(sqlx::Error::RowNotFound) => Err(WebhookError::NotFound),
+        Err(err) => Err(WebhookError::DBError(SelectError(err.to_string()))),
+    }
+}
+
+/// Fetch the id of the owner of `session_id`.
+async fn session_user_id(
+    repo: &impl SessionRepository,
+    session_id: &str,
+) -> Result<u32, WebhookError> {
+    let Ok(session) = repo.fetch_session(session_id).await else {
+        tracing::info!("session was invalid");
+        return Err(WebhookError::InvalidSession);
+    };
+    Ok(session.user_id)
+}
@@ -0,0 +1,388 @@
+use chrono::Utc;
+use rocket::{
+    serde::json,
+    tokio::{self, net::TcpListener, sync::Barrier},
+};
+
+use crate::{
+    config::NotificationConfig,
+    notify::{
+        self,
+        data::public::{Event, EventKind, Notification},
+        sink::http_client,
+        tests::{receive, respond, serve_once},
+        webhooks::{self, signature},
+    },
+    repo::memory::MemoryRepository,
+    routes::{
+        auth::{AuthResult, data::public::AuthRequest, signed_up},
+        sync::{
+            data::public::{AppInfo, UserData},
+            events::SyncEvents,
+            sync_data,
+        },
+    },
+};
+
+use super::{
+    CreateWebhookResult,
+    data::public::{NewWebhookRequest, Webhook, WebhookDelivery, WebhookError},
+};
+
+fn request(url: &str, events: &[EventKind]) -> NewWebhookRequest {
+    NewWebhookRequest {
+        url: url.to_string(),
+        events: events.to_vec(),
+    }
+}
+
+/// A stub server, and its url.
+async fn stub() -> (TcpListener, String) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    (listener, url)
+}
+
+#[rocket::async_test]
+async fn signed_deliveries() {
+    let repo = MemoryRepository::default();
+    let (_, session) = signed_up(&repo).await;
+
+    let client = http_client(true);
+    assert!(matches!(
+        super::create(
+            &repo,
+            &client,
+            &session.id,
+            &request("ftp://example.com", &[])
+        )
+        .await
+        .unwrap_err(),
+        WebhookError::InvalidUrl
+    ));
+
+    let (limits_listener, limits_url) = stub().await;
+    let (all_listener, all_url) = stub().await;
+    let (_summary_listener, summary_url) = stub().await;
+    let limits = super::create(
+        &repo,
+        &client,
+        &session.id,
+        &request(&limits_url, &[EventKind::AppLimitExceeded]),
+    )
+    .await
+    .unwrap();
+    let all = super::create(&repo, &client, &session.id, &request(&all_url, &[]))
+        .await
+        .unwrap();
+    let summaries = super::create(
+        &repo,
+        &client,
+        &session.id,
+        &request(&summary_url, &[EventKind::DailySummary]),
+    )
+    .await
+    .unwrap();
+    assert_eq!(limits.info.events, [EventKind::AppLimitExceeded]);
+    assert_eq!(super::list(&repo, &session.id).await.unwrap().len(), 3);
+
+    let data = UserData {
+        app_usage: vec![AppInfo::new("io1", 60, 30)],
+        ..Default::default()
+    };
+    sync_data(&repo, &SyncEvents::default(), &session.id, None, Some(data))
+        .await
+        .unwrap();
+
+    let limits_server = tokio::spawn(async move {
+        let failed = serve_once(&limits_listener, "500 Internal Server Error").await;
+        let retried = serve_once(&limits_listener, "200 OK").await;
+        (failed, retried)
+    });
+    let all_server = tokio::spawn(async move {
+        let first = serve_once(&all_listener, "204 No Content").await;
+        let second = serve_once(&all_listener, "204 No Content").await;
+        [first, second]
+    });
+
+    let config = NotificationConfig {
+        backoff: 10,
+        ..Default::default()
+    };
+    let now = Utc::now().timestamp();
+    // the webhook receiving all events is sent the account's creation too.
+    assert_eq!(
+        webhooks::deliver_due(&repo, &client, &config, now)
+            .await
+            .unwrap(),
+        2
+    );
+
+    let failed = super::deliveries(&repo, &session.id, &limits.info.id)
+        .await
+        .unwrap();
+    assert_eq!(failed.len(), 1);
+    assert_eq!(
+        (failed[0].attempts, failed[0].status, failed[0].next_attempt),
+        (1, Some(500), Some(now + 10))
+    );
+    assert_eq!(
+        webhooks::deliver_due(&repo, &client, &config, now + 10)
+            .await
+            .unwrap(),
+        1
+    );
+    let retried = super::deliveries(&repo, &session.id, &limits.info.id)
+        .await
+        .unwrap();
+    assert_eq!(
+        (retried[0].status, retried[0].delivered),
+        (Some(200), Some(now + 10))
+    );
+
+    // every attempt is signed with the webhook's secret.
+    let (_, received) = limits_server.await.unwrap();
+    let timestamp = received.header("x-pcup-timestamp").unwrap();
+    assert_eq!(
+        received.header("x-pcup-signature").unwrap(),
+        signature(&limits.secret, timestamp.parse().unwrap(), &received.body)
+    );
+    assert_eq!(received.header("x-pcup-event"), Some("app_limit_exceeded"));
+    let notification: Notification = json::from_str(&received.body).unwrap();
+    assert_eq!(notification.user, session.user_id);
+    assert!(matches!(
+        notification.event,
+        Event::AppLimitExceeded { ref app, .. } if app == "io1"
+    ));
+
+    let mut events = Vec::new();
+    for received in all_server.await.unwrap() {
+        assert_eq!(
+            received.header("x-pcup-signature").unwrap(),
+            signature(
+                &all.secret,
+                received
+                    .header("x-pcup-timestamp")
+                    .unwrap()
+                    .parse()
+                    .unwrap(),
+                &received.body
+            )
+        );
+        let notification: Notification = json::from_str(&received.body).unwrap();
+        events.push(notification.event.kind().unwrap());
+    }
+    events.sort();
+    assert_eq!(
+        events,
+        [EventKind::AppLimitExceeded, EventKind::AccountCreated]
+    );
+    // webhooks only receive what they subscribed to.
+    assert!(
+        super::deliveries(&repo, &session.id, &summaries.info.id)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[rocket::async_test]
+async fn test_fire() {
+    let repo = MemoryRepository::default();
+    let (_, session) = signed_up(&repo).await;
+    let (_, other) = signed_up(&repo).await;
+
+    let client = http_client(true);
+    let (listener, url) = stub().await;
+    let webhook = super::create(&repo, &client, &session.id, &request(&url, &[]))
+        .await
+        .unwrap();
+    let server = tokio::spawn(async move {
+        let ok = serve_once(&listener, "200 OK").await;
+        serve_once(&listener, "404 Not Found").await;
+        ok
+    });
+
+    // deliveries to local addresses are refused too, without the opt-in.
+    let refused = super::test(&repo, &http_client(false), &session.id, &webhook.info.id)
+        .await
+        .unwrap();
+    assert_eq!((refused.status, refused.delivered), (None, None));
+    assert!(refused.last_error.unwrap().contains("not a public address"));
+
+    let ok = super::test(&repo, &client, &session.id, &webhook.info.id)
+        .await
+        .unwrap();
+    assert_eq!((ok.event.as_str(), ok.status), ("webhook_test", Some(200)));
+    assert!(ok.delivered.is_some());
+    // tests are not retried.
+    let failed = super::test(&repo, &client, &session.id, &webhook.info.id)
+        .await
+        .unwrap();
+    assert_eq!(
+        (failed.status, failed.attempts, failed.next_attempt),
+        (Some(404), 1, None)
+    );
+
+    let received = server.await.unwrap();
+    let notification: Notification = json::from_str(&received.body).unwrap();
+    assert_eq!(
+        notification.event,
+        Event::WebhookTest {
+            webhook: webhook.info.id.clone()
+        }
+    );
+
+    // others' webhooks are not found.
+    assert!(matches!(
+        super::test(&repo, &client, &other.id, &webhook.info.id)
+            .await
+            .unwrap_err(),
+        WebhookError::NotFound
+    ));
+    assert!(matches!(
+        super::deliveries(&repo, &other.id, &webhook.info.id)
+            .await
+            .unwrap_err(),
+        WebhookError::NotFound
+    ));
+    assert!(matches!(
+        super::delete(&repo, &other.id, &webhook.info.id)
+            .await
+            .unwrap_err(),
+        WebhookError::NotFound
+    ));
+
+    super::delete(&repo, &session.id, &webhook.info.id)
+        .await
+        .unwrap();
+    assert!(super::list(&repo, &session.id).await.unwrap().is_empty());
+}
+
+#[rocket::async_test]
+async fn concurrent_deliveries() {
+    let repo = MemoryRepository::default();
+    let (user, session) = signed_up(&repo).await;
+    let client = http_client(true);
+
+    // each webhook only responds once both were sent their delivery, which never happens one at a time.
+    let barrier = std::sync::Arc::new(Barrier::new(2));
+    let mut servers = Vec::new();
+    for _ in 0..2 {
+        let (listener, url) = stub().await;
+        super::create(
+            &repo,
+            &client,
+            &session.id,
+            &request(&url, &[EventKind::AppLimitExceeded]),
+        )
+        .await
+        .unwrap();
+        let barrier = barrier.clone();
+        servers.push(tokio::spawn(async move {
+            let (stream, _) = receive(&listener).await;
+            barrier.wait().await;
+            respond(stream, "200 OK").await;
+        }));
+    }
+
+    let user_id = user.id;
+    let now = Utc::now().timestamp();
+    let event = Event::AppLimitExceeded {
+        app: "io1".to_string(),
+        day: "2025-07-19".to_string(),
+        usage: 60,
+        limit: 30,
+    };
+    notify::emit(&repo, user_id, &event, now).await.unwrap();
+
+    assert_eq!(
+        webhooks::deliver_due(&repo, &client, &NotificationConfig::default(), now)
+            .await
+            .unwrap(),
+        2
+    );
+    for server in servers {
+        server.await.unwrap();
+    }
+}
+
+#[macros::rocket_test]
+fn webhook_routes() {
+    let session = client
+        .post("/auth/signup")
+        .json(&AuthRequest::random_valid())
+        .dispatch()
+        .into_json::<AuthResult>()
+        .unwrap()
+        .unwrap();
+
+    // local addresses are not allowed by default.
+    for url in [
+        "http://127.0.0.1:8000/hook",
+        "http://localhost/hook",
+        "http://169.254.169.254/latest/meta-data",
+        "https://10.0.0.1/hook",
+        "http://[::1]/hook",
+    ] {
+        assert!(matches!(
+            client
+                .post(format!("/webhooks/{}", session.id))
+                .json(&request(url, &[]))
+                .dispatch()
+                .into_json::<CreateWebhookResult>()
+                .unwrap()
+                .unwrap_err(),
+            WebhookError::InvalidUrl
+        ));
+    }
+
+    let created = client
+        .post(format!("/webhooks/{}", session.id))
+        .json(&request(
+            "https://1.1.1.1/hook",
+            &[EventKind::DailySummary, EventKind::DailySummary],
+        ))
+        .dispatch()
+        .into_json::<CreateWebhookResult>()
+        .unwrap()
+        .unwrap();
+    assert_eq!(created.info.events, [EventKind::DailySummary]);
+    assert_eq!(created.secret.len(), 64);
+
+    // the secret is only shown once.
+    let listed = client
+        .get(format!("/webhooks/{}", session.id))
+        .dispatch()
+        .into_json::<Result<Vec<Webhook>, WebhookError>>()
+        .unwrap()
+        .unwrap();
+    assert_eq!(listed, std::slice::from_ref(&created.info));
+
+    let deliveries = client
+        .get(format!(
+            "/webhooks/{}/{}/deliveries",
+            session.id, created.info.id
+        ))
+        .dispatch()
+        .into_json::<Result<Vec<WebhookDelivery>, WebhookError>>()
+        .unwrap()
+        .unwrap();
+    assert!(deliveries.is_empty());
+
+    client
+        .delete(format!("/webhooks/{}/{}", session.id, created.info.id))
+        .dispatch()
+        .into_json::<Result<(), WebhookError>>()
+        .unwrap()
+        .unwrap();
+    assert!(matches!(
+        client
+            .get("/webhooks/not-a-session")
+            .dispatch()
+            .into_json::<Result<Vec<Webhook>, WebhookError>>()
+            .unwrap()
+            .unwrap_err(),
+        WebhookError::InvalidSession
+    ));
+}
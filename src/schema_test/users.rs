@@ -28,6 +28,20 @@ async fn expect_fail(db: Pool<Sqlite>) {
         .await
         .unwrap_err();
 
+    // duplicate public id
+    sqlx::query!(
+        "INSERT INTO users(id, username, password_hash, public_id) VALUES(10, 'a', 'xdd', 'same')"
+    )
+    .execute(&db)
+    .await
+    .unwrap();
+    sqlx::query!(
+        "INSERT INTO users(id, username, password_hash, public_id) VALUES(11, 'b', 'xdd', 'same')"
+    )
+    .execute(&db)
+    .await
+    .unwrap_err();
+
     // null values
     sqlx::query!("INSERT INTO users(id) VALUES(1)")
         .execute(&db)
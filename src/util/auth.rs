@@ -1,8 +1,17 @@
 use chrono::{DateTime, TimeDelta, Utc};
-use pcupback::Storable;
-use sqlx::{Executor, Sqlite};
 
-use crate::routes::auth::data::{private::DBUserSession, public::UserSession};
+use crate::{
+    repo::{SessionRepository, TokenRepository},
+    routes::{
+        auth::data::{
+            private::{DBUser, DBUserSession},
+            public::UserSession,
+        },
+        tokens::data::public::TokenScope,
+    },
+};
+
+use super::token::{hash_token, is_token};
 
 pub(crate) const SESSION_TIMEOUT: TimeDelta = TimeDelta::days(1);
 
@@ -12,10 +21,10 @@ pub(crate) fn session_timeout(dt: DateTime<Utc>) -> bool {
 }
 
 /// Check if `session` is timed out. If it is, generate and store a new one.
-pub(crate) async fn validate_session<'a>(
-    executor: impl Executor<'a, Database = Sqlite>,
+pub(crate) async fn validate_session(
+    repo: &impl SessionRepository,
     session: Result<DBUserSession, sqlx::Error>,
-    new_id: u32,
+    user: &DBUser,
 ) -> Result<UserSession, sqlx::Error> {
     if let Ok(session) = session {
         // if `last_set` was more than `SESSION_TIMEOUT` ago, we create a new session.
@@ -24,29 +33,71 @@ pub(crate) async fn validate_session<'a>(
         if let Some(session_last_set) = session_last_set {
             if session_timeout(session_last_set) {
                 tracing::info!("session timed out, generating new one");
-                generate_store_session(executor, new_id).await
+                generate_store_session(repo, user).await
             } else {
                 // session is ok, return it
-                Ok(session.into())
+                Ok(UserSession::new(user, session))
             }
         } else {
-            generate_store_session(executor, new_id).await
+            generate_store_session(repo, user).await
         }
     } else {
         tracing::warn!("no session, generating one");
-        generate_store_session(executor, new_id).await
+        generate_store_session(repo, user).await
+    }
+}
+
+/// Why a credential was rejected, see [`authenticate`].
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum CredentialError {
+    /// No such session or api token.
+    Invalid,
+    /// The api token's scope does not allow the request.
+    InsufficientScope,
+}
+
+/// Resolve `credential`, a session id or an api token, to the id of the user it belongs to.
+///
+/// Sessions are allowed everything, api tokens only what their scope allows of `required`.
+pub(crate) async fn authenticate(
+    repo: &(impl SessionRepository + TokenRepository),
+    credential: &str,
+    required: TokenScope,
+) -> Result<u32, CredentialError> {
+    if !is_token(credential) {
+        return repo
+            .fetch_session(credential)
+            .await
+            .map(|session| session.user_id)
+            .map_err(|_| CredentialError::Invalid);
     }
+
+    let Ok(token) = repo.fetch_token(&hash_token(credential)).await else {
+        tracing::info!("api token was invalid");
+        return Err(CredentialError::Invalid);
+    };
+    if !token.scope.allows(required) {
+        tracing::info!("api token {} not allowed {required:?}", token.id);
+        return Err(CredentialError::InsufficientScope);
+    }
+
+    // failing to record the use does not fail the request.
+    if let Err(err) = repo.touch_token(&token.id, Utc::now().timestamp()).await {
+        tracing::warn!("failed to record api token use: {err:?}");
+    }
+
+    Ok(token.user_id)
 }
 
 // store a session, returning Ok(session)
 pub(crate) async fn generate_store_session(
-    executor: impl Executor<'_, Database = Sqlite>,
-    user_id: u32,
+    repo: &impl SessionRepository,
+    user: &DBUser,
 ) -> Result<UserSession, sqlx::Error> {
-    let session = DBUserSession::generate(user_id);
-    match session.store(executor).await {
+    let session = DBUserSession::generate(user.id);
+    match repo.store_session(&session).await {
         // stored session successfully, return
-        Ok(_) => Ok(session.into()),
+        Ok(_) => Ok(UserSession::new(user, session)),
         Err(err) => {
             tracing::error!("failed to store session: {err:?}");
             Err(err)
@@ -64,12 +115,14 @@ mod tests {
 
     #[sqlx::test]
     async fn generate_store_session(db: Pool<Sqlite>) {
+        let user = DBUser::new_raw(1, "ppk1", "12");
+
         // no such user id `1`
-        super::generate_store_session(&db, 1).await.unwrap_err();
+        super::generate_store_session(&db, &user).await.unwrap_err();
 
-        // generate the user, now ok.
-        DBUser::new_raw(1, "ppk1", "12").store(&db).await.unwrap();
-        super::generate_store_session(&db, 1).await.unwrap();
+        // store the user, now ok.
+        user.store(&db).await.unwrap();
+        super::generate_store_session(&db, &user).await.unwrap();
     }
 
     #[test]
@@ -0,0 +1,38 @@
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+/// Hash `data` with sha-256, hex encoded.
+///
+/// Only for random secrets, like recovery codes and api tokens. Passwords are hashed with Argon2.
+pub(crate) fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Sign `data` with hmac-sha256 keyed by `key`, hex encoded.
+pub(crate) fn hmac_sha256_hex(key: &[u8], data: &[u8]) -> String {
+    // hmac accepts keys of any length.
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac accepts any key length");
+    mac.update(data);
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::hmac_sha256_hex;
+
+    #[test]
+    fn hmac() {
+        // rfc 4231, test case 2.
+        assert_eq!(
+            hmac_sha256_hex(b"Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+}
@@ -1,2 +1,8 @@
 pub(crate) mod auth;
 pub(crate) mod db;
+pub(crate) mod digest;
+pub(crate) mod password;
+pub(crate) mod throttle;
+pub(crate) mod token;
+pub(crate) mod totp;
+pub(crate) mod username;
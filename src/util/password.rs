@@ -0,0 +1,119 @@
+use std::{collections::HashSet, sync::LazyLock};
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{config::PasswordPolicy, routes::auth::data::public::InvalidPasswordKind};
+
+/// The bundled list of common passwords, one per line, lowercase.
+static COMMON_PASSWORDS: LazyLock<HashSet<&'static str>> =
+    LazyLock::new(|| include_str!("common_passwords.txt").lines().collect());
+
+/// Usernames shorter than this (in graphemes) are not searched for in passwords, only compared.
+const MIN_CONTAINED_USERNAME: usize = 3;
+
+/// Check `password` against `policy`, for the user `username`.
+///
+/// # Errors
+///
+/// Returns the first rule `password` breaks, checking length, then the username, then the common list.
+pub(crate) fn check_password(
+    policy: &PasswordPolicy,
+    username: &str,
+    password: &str,
+) -> Result<(), InvalidPasswordKind> {
+    use InvalidPasswordKind::{
+        Common, ContainsUsername, EqualsUsername, TooFewChars, TooManyChars,
+    };
+
+    let length = password.graphemes(true).count();
+    if length < policy.min_length {
+        return Err(TooFewChars);
+    }
+    if length > policy.max_length {
+        return Err(TooManyChars);
+    }
+
+    let lower_password = password.to_lowercase();
+
+    if policy.reject_username {
+        let lower_username = username.trim().to_lowercase();
+
+        if lower_password == lower_username {
+            return Err(EqualsUsername);
+        }
+        if lower_username.graphemes(true).count() >= MIN_CONTAINED_USERNAME
+            && lower_password.contains(&lower_username)
+        {
+            return Err(ContainsUsername);
+        }
+    }
+
+    if policy.reject_common && COMMON_PASSWORDS.contains(lower_password.as_str()) {
+        return Err(Common);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{config::PasswordPolicy, routes::auth::data::public::InvalidPasswordKind};
+
+    use super::check_password;
+
+    #[test]
+    fn length_in_graphemes() {
+        let policy = PasswordPolicy::default();
+
+        // 7 graphemes, but 14 bytes.
+        assert!(matches!(
+            check_password(&policy, "user", "ééééééé"),
+            Err(InvalidPasswordKind::TooFewChars)
+        ));
+        // 8 family emojis, each many code points.
+        check_password(&policy, "user", &"👨‍👩‍👧‍👦".repeat(8)).unwrap();
+        assert!(matches!(
+            check_password(&policy, "user", &"é".repeat(65)),
+            Err(InvalidPasswordKind::TooManyChars)
+        ));
+    }
+
+    #[test]
+    fn username() {
+        let policy = PasswordPolicy::default();
+
+        assert!(matches!(
+            check_password(&policy, "Trevor123", "trevor123"),
+            Err(InvalidPasswordKind::EqualsUsername)
+        ));
+        assert!(matches!(
+            check_password(&policy, "trevor", "my name is Trevor"),
+            Err(InvalidPasswordKind::ContainsUsername)
+        ));
+        // too short to look for.
+        check_password(&policy, "ab", "the absolute unit").unwrap();
+
+        let allowed = PasswordPolicy {
+            reject_username: false,
+            ..policy
+        };
+        check_password(&allowed, "trevor123", "trevor123").unwrap();
+    }
+
+    #[test]
+    fn common() {
+        let policy = PasswordPolicy::default();
+
+        assert!(matches!(
+            check_password(&policy, "user", "Password1"),
+            Err(InvalidPasswordKind::Common)
+        ));
+        check_password(&policy, "user", "correct horse battery staple").unwrap();
+
+        let allowed = PasswordPolicy {
+            reject_common: false,
+            ..policy
+        };
+        check_password(&allowed, "user", "password1").unwrap();
+    }
+}
@@ -0,0 +1,167 @@
+use std::net::IpAddr;
+
+use crate::{repo::AttemptRepository, routes::auth::data::private::DBLoginAttempts};
+
+use super::username::username_key;
+
+/// Failed attempts allowed before locking out.
+pub(crate) const FREE_ATTEMPTS: u32 = 5;
+
+/// The first lockout, in seconds. Every failure after that doubles it.
+pub(crate) const BASE_LOCKOUT: i64 = 30;
+
+/// The longest lockout, in seconds.
+pub(crate) const MAX_LOCKOUT: i64 = 60 * 60;
+
+/// Failures are forgotten after this many seconds without another one.
+pub(crate) const FAILURE_EXPIRY: i64 = 24 * 60 * 60;
+
+/// The `login_attempts` key for attempts at logging in as `username`.
+///
+/// Usernames that are the same user share attempts, see [`username_key`].
+pub(crate) fn username_attempts_key(username: &str) -> String {
+    format!("user:{}", username_key(username))
+}
+
+/// The `login_attempts` key for attempts made from `ip`.
+pub(crate) fn ip_attempts_key(ip: IpAddr) -> String {
+    format!("ip:{ip}")
+}
+
+/// The `login_attempts` keys for attempts at `username`'s password, from `client_ip` if known.
+///
+/// The username's key is first.
+pub(crate) fn attempt_keys(username: &str, client_ip: Option<IpAddr>) -> Vec<String> {
+    let mut keys = vec![username_attempts_key(username)];
+    keys.extend(client_ip.map(ip_attempts_key));
+    keys
+}
+
+/// Fetch the failed attempts for each of `keys`.
+pub(crate) async fn fetch_attempts(
+    repo: &impl AttemptRepository,
+    keys: &[String],
+) -> Result<Vec<DBLoginAttempts>, sqlx::Error> {
+    let mut attempts = Vec::with_capacity(keys.len());
+    for key in keys {
+        attempts.push(repo.fetch_attempts(key).await?);
+    }
+    Ok(attempts)
+}
+
+/// Return the seconds until all of `attempts` are allowed another try at `now`, or [`None`] if they already are.
+pub(crate) fn locked_out(attempts: &[DBLoginAttempts], now: i64) -> Option<i64> {
+    attempts.iter().filter_map(|a| retry_after(a, now)).max()
+}
+
+/// Record a failed attempt at `now` for each of `keys`.
+pub(crate) async fn store_failure(
+    repo: &impl AttemptRepository,
+    keys: &[String],
+    now: i64,
+) -> Result<(), sqlx::Error> {
+    for key in keys {
+        repo.record_failure(key, now).await?;
+    }
+    Ok(())
+}
+
+/// Return the seconds left until `attempts` is allowed another try at `now`, or [`None`] if it already is.
+pub(crate) fn retry_after(attempts: &DBLoginAttempts, now: i64) -> Option<i64> {
+    (attempts.locked_until > now).then_some(attempts.locked_until - now)
+}
+
+/// Record a failed attempt at `now`, locking out with exponential backoff after [`FREE_ATTEMPTS`].
+///
+/// The database does the same in one statement, see [`AttemptRepository::record_failure`].
+#[cfg(test)]
+pub(crate) fn record_failure(attempts: &mut DBLoginAttempts, now: i64) {
+    if now - attempts.last_failure > FAILURE_EXPIRY {
+        attempts.failures = 0;
+    }
+
+    attempts.failures += 1;
+    attempts.last_failure = now;
+
+    if attempts.failures >= FREE_ATTEMPTS {
+        let doublings = (attempts.failures - FREE_ATTEMPTS).min(32);
+        let lockout = BASE_LOCKOUT.saturating_mul(1 << doublings).min(MAX_LOCKOUT);
+        attempts.locked_until = now + lockout;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::futures::future::join_all;
+    use sqlx::{Pool, Sqlite};
+
+    use crate::{repo::AttemptRepository, routes::auth::data::private::DBLoginAttempts};
+
+    use super::{
+        BASE_LOCKOUT, FAILURE_EXPIRY, FREE_ATTEMPTS, MAX_LOCKOUT, record_failure, retry_after,
+    };
+
+    #[test]
+    fn lockout_backoff() {
+        let mut attempts = DBLoginAttempts::new("user:test");
+        let now = 1000;
+
+        for _ in 1..FREE_ATTEMPTS {
+            record_failure(&mut attempts, now);
+            assert_eq!(retry_after(&attempts, now), None);
+        }
+
+        record_failure(&mut attempts, now);
+        assert_eq!(retry_after(&attempts, now), Some(BASE_LOCKOUT));
+
+        // doubled
+        record_failure(&mut attempts, now);
+        assert_eq!(retry_after(&attempts, now), Some(BASE_LOCKOUT * 2));
+        // the lockout passes
+        assert_eq!(retry_after(&attempts, now + BASE_LOCKOUT * 2), None);
+
+        // capped
+        for _ in 0..64 {
+            record_failure(&mut attempts, now);
+        }
+        assert_eq!(retry_after(&attempts, now), Some(MAX_LOCKOUT));
+    }
+
+    #[test]
+    fn failures_expire() {
+        let mut attempts = DBLoginAttempts::new("ip:127.0.0.1");
+
+        for _ in 0..FREE_ATTEMPTS - 1 {
+            record_failure(&mut attempts, 0);
+        }
+
+        let later = FAILURE_EXPIRY + 1;
+        record_failure(&mut attempts, later);
+        assert_eq!(attempts.failures, 1);
+        assert_eq!(retry_after(&attempts, later), None);
+    }
+
+    #[sqlx::test]
+    async fn stored_failures(db: Pool<Sqlite>) {
+        // the upsert counts as `record_failure` does.
+        let mut expected = DBLoginAttempts::new("user:test");
+        for now in [0, 1, 2, 3, 4, 5, 6, FAILURE_EXPIRY + 7, FAILURE_EXPIRY + 8] {
+            record_failure(&mut expected, now);
+            let stored = db.record_failure("user:test", now).await.unwrap();
+            assert_eq!(
+                (stored.failures, stored.last_failure, stored.locked_until),
+                (
+                    expected.failures,
+                    expected.last_failure,
+                    expected.locked_until
+                )
+            );
+        }
+
+        // concurrent failures are all counted.
+        join_all((0..20).map(|_| db.record_failure("ip:127.0.0.1", 1000))).await;
+        let stored = db.fetch_attempts("ip:127.0.0.1").await.unwrap();
+        assert_eq!(stored.failures, 20);
+        assert_eq!(retry_after(&stored, 1000), Some(MAX_LOCKOUT));
+    }
+}
@@ -0,0 +1,51 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+
+use super::digest::sha256_hex;
+
+/// What every api token starts with, telling them apart from session ids.
+pub(crate) const TOKEN_PREFIX: &str = "pcup_";
+
+/// The most api tokens a user can have.
+pub(crate) const MAX_TOKENS: usize = 20;
+
+/// The most graphemes a token's name can have.
+pub(crate) const MAX_NAME_LENGTH: usize = 64;
+
+/// Return `true` if `credential` is an api token rather than a session id.
+pub(crate) fn is_token(credential: &str) -> bool {
+    credential.starts_with(TOKEN_PREFIX)
+}
+
+/// Generate a new, random api token.
+pub(crate) fn generate_token() -> String {
+    format!("{TOKEN_PREFIX}{}", generate_secret())
+}
+
+/// Generate 32 random bytes, hex encoded.
+pub(crate) fn generate_secret() -> String {
+    let mut bytes = [0; 32];
+    OsRng.fill_bytes(&mut bytes);
+
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Hash an api token to be stored, or looked up.
+pub(crate) fn hash_token(token: &str) -> String {
+    sha256_hex(token.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate_token, hash_token, is_token};
+
+    #[test]
+    fn tokens() {
+        let token = generate_token();
+        assert!(is_token(&token));
+        assert!(!is_token(&uuid::Uuid::new_v4().to_string()));
+
+        assert_ne!(token, generate_token());
+        assert_eq!(hash_token(&token), hash_token(&token));
+        assert_ne!(hash_token(&token), hash_token(&generate_token()));
+    }
+}
@@ -0,0 +1,128 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use crate::routes::totp::data::private::DBTotp;
+
+use super::digest::sha256_hex;
+
+/// The issuer shown in authenticator apps.
+pub(crate) const ISSUER: &str = "WorkReminders";
+
+/// Seconds each code is valid for.
+pub(crate) const STEP: u64 = 30;
+
+/// Seconds a login challenge can be answered in.
+pub(crate) const CHALLENGE_TIMEOUT: i64 = 5 * 60;
+
+/// How many recovery codes are generated when enrolling.
+pub(crate) const RECOVERY_CODES: usize = 10;
+
+/// Create the [`TOTP`] for `stored`, labelled with the user's `account_name`.
+///
+/// # Errors
+///
+/// Errors if the stored secret is invalid.
+pub(crate) fn totp(stored: &DBTotp, account_name: &str) -> Result<TOTP, String> {
+    let secret = Secret::Encoded(stored.secret.clone())
+        .to_bytes()
+        .map_err(|err| format!("{err:?}"))?;
+
+    TOTP::new(
+        Algorithm::SHA1,
+        6,
+        // accept the codes before and after the current one, for clock drift.
+        1,
+        STEP,
+        secret,
+        Some(ISSUER.to_string()),
+        account_name.to_string(),
+    )
+    .map_err(|err| err.to_string())
+}
+
+/// Return the time step `code` is for, if it is valid at `now` and for a later step than `last_step`.
+///
+/// Checking the step makes each code usable once.
+pub(crate) fn verify_code(totp: &TOTP, code: &str, last_step: i64, now: u64) -> Option<i64> {
+    [now.saturating_sub(STEP), now, now + STEP]
+        .into_iter()
+        .find(|time| totp.generate(*time) == code)
+        .and_then(|time| i64::try_from(time / STEP).ok())
+        .filter(|step| *step > last_step)
+}
+
+/// Return `true` if `code` looks like a totp code rather than a recovery code.
+pub(crate) fn is_totp_code(code: &str) -> bool {
+    code.len() == 6 && code.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Generate [`RECOVERY_CODES`] random recovery codes, formatted like `ABCD-EFGH-IJKL-MNOP`.
+pub(crate) fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODES)
+        .map(|_| {
+            let mut bytes = [0; 10];
+            OsRng.fill_bytes(&mut bytes);
+
+            let Secret::Encoded(encoded) = Secret::Raw(bytes.to_vec()).to_encoded() else {
+                unreachable!("`to_encoded` always returns `Secret::Encoded`")
+            };
+            encoded
+                .as_bytes()
+                .chunks(4)
+                .map(|chunk| String::from_utf8_lossy(chunk))
+                .collect::<Vec<_>>()
+                .join("-")
+        })
+        .collect()
+}
+
+/// Hash a recovery code to be stored, ignoring case, dashes and whitespace.
+pub(crate) fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| *c != '-' && !c.is_whitespace())
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+
+    sha256_hex(normalized.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::routes::totp::data::private::DBTotp;
+
+    use super::{STEP, generate_recovery_codes, hash_recovery_code, totp, verify_code};
+
+    #[test]
+    fn codes_used_once() {
+        let stored = DBTotp::generate(1);
+        let totp = totp(&stored, "test").unwrap();
+        let now = 1_000_000;
+
+        let code = totp.generate(now);
+        let step = verify_code(&totp, &code, 0, now).unwrap();
+        // the same code again is rejected.
+        assert_eq!(verify_code(&totp, &code, step, now), None);
+        // the next code is not.
+        let next = totp.generate(now + STEP);
+        assert!(verify_code(&totp, &next, step, now + STEP).is_some());
+
+        // too old.
+        let old = totp.generate(now - 2 * STEP);
+        assert_eq!(verify_code(&totp, &old, 0, now), None);
+    }
+
+    #[test]
+    fn recovery_codes() {
+        let codes = generate_recovery_codes();
+        assert_eq!(codes.len(), super::RECOVERY_CODES);
+        assert_eq!(codes[0].len(), "ABCD-EFGH-IJKL-MNOP".len());
+
+        let code = &codes[0];
+        assert_eq!(
+            hash_recovery_code(code),
+            hash_recovery_code(&code.replace('-', " ").to_lowercase())
+        );
+        assert_ne!(hash_recovery_code(code), hash_recovery_code(&codes[1]));
+    }
+}
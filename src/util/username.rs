@@ -0,0 +1,229 @@
+use std::collections::HashSet;
+
+use sqlx::{Pool, Sqlite};
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::routes::auth::data::public::InvalidUsernameKind;
+
+/// The fewest graphemes a username can have.
+pub(crate) const MIN_USERNAME_LENGTH: usize = 3;
+
+/// The most graphemes a username can have.
+pub(crate) const MAX_USERNAME_LENGTH: usize = 32;
+
+/// Seconds a username someone changed away from stays theirs, before anyone else can take it.
+pub(crate) const USERNAME_HOLD: i64 = 30 * 24 * 60 * 60;
+
+/// Usernames nobody can sign up with, compared by [`username_key`].
+const RESERVED_USERNAMES: &[&str] = &[
+    "admin",
+    "administrator",
+    "root",
+    "system",
+    "support",
+    "help",
+    "moderator",
+    "staff",
+    "official",
+    "api",
+    "auth",
+    "sync",
+    "server",
+    "pcupback",
+    "workreminders",
+    "null",
+    "undefined",
+    "anonymous",
+    "me",
+];
+
+/// Return `true` if `c` is allowed in a username: letters and digits of any script, `_`, `-` and `.`.
+///
+/// This excludes whitespace, and invisible characters like zero-width spaces.
+fn allowed_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '.')
+}
+
+/// Normalize `username` as it is displayed: trimmed, then NFKC normalized.
+#[must_use]
+pub(crate) fn normalize_username(username: &str) -> String {
+    username.trim().nfkc().collect()
+}
+
+/// The key usernames are unique by, and looked up with: the normalized username, lowercased.
+///
+/// `username` does not need to be normalized already.
+#[must_use]
+pub(crate) fn username_key(username: &str) -> String {
+    // lowercasing can denormalize, so normalize again.
+    normalize_username(username).to_lowercase().nfkc().collect()
+}
+
+/// Normalize `username`, then check it can be signed up with.
+///
+/// Returns the normalized username.
+///
+/// # Errors
+///
+/// Returns the first rule the normalized username breaks.
+pub(crate) fn validate_username(username: &str) -> Result<String, InvalidUsernameKind> {
+    use InvalidUsernameKind::{InvalidChar, Reserved, TooLong, TooShort};
+
+    let username = normalize_username(username);
+
+    let length = username.graphemes(true).count();
+    if length < MIN_USERNAME_LENGTH {
+        return Err(TooShort);
+    }
+    if length > MAX_USERNAME_LENGTH {
+        return Err(TooLong);
+    }
+
+    if let Some(c) = username.chars().find(|c| !allowed_char(*c)) {
+        return Err(InvalidChar(c));
+    }
+
+    if RESERVED_USERNAMES.contains(&username_key(&username).as_str()) {
+        return Err(Reserved);
+    }
+
+    Ok(username)
+}
+
+/// Set every user's `username_key` to their [`username_key`], as the migration adding them could only fold ascii.
+///
+/// A user whose key is another user's already keeps their stored key and is added to the `username_collisions`
+/// table, to be resolved by hand. They can still log in with their exact username.
+///
+/// # Errors
+///
+/// See [`sqlx::Error`].
+pub(crate) async fn backfill_username_keys(db: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+    let mut transaction = db.begin().await?;
+
+    let users: Vec<(u32, String, Option<String>)> =
+        sqlx::query_as("SELECT id, username, username_key FROM users ORDER BY id")
+            .fetch_all(&mut *transaction)
+            .await?;
+    let mut taken: HashSet<String> = users.iter().filter_map(|u| u.2.clone()).collect();
+
+    for (id, username, stored) in users {
+        let key = username_key(&username);
+        if stored.as_deref() == Some(key.as_str()) {
+            continue;
+        }
+
+        if taken.contains(&key) {
+            let reported = sqlx::query(
+                "INSERT OR IGNORE INTO username_collisions(user_id, username) VALUES(?, ?)",
+            )
+            .bind(id)
+            .bind(&username)
+            .execute(&mut *transaction)
+            .await?;
+            if reported.rows_affected() > 0 {
+                tracing::warn!(
+                    "user {id}'s username collides with another user's, keeping its key"
+                );
+            }
+            continue;
+        }
+
+        sqlx::query("UPDATE users SET username_key = ? WHERE id = ?")
+            .bind(&key)
+            .bind(id)
+            .execute(&mut *transaction)
+            .await?;
+        if let Some(stored) = stored {
+            taken.remove(&stored);
+        }
+        taken.insert(key);
+    }
+
+    transaction.commit().await
+}
+
+#[cfg(test)]
+mod tests {
+    use pcupback::Fetchable;
+    use sqlx::{Pool, Sqlite};
+
+    use crate::routes::auth::data::{private::DBUser, public::InvalidUsernameKind};
+
+    use super::{backfill_username_keys, username_key, validate_username};
+
+    #[test]
+    fn normalization() {
+        // fullwidth letters are NFKC normalized to ascii.
+        assert_eq!(validate_username(" Ａｌｉｃｅ ").unwrap(), "Alice");
+        assert_eq!(username_key("Alice"), username_key("ａｌｉｃｅ"));
+        assert_eq!(username_key("ÅNGSTRÖM"), username_key("ångström"));
+        assert_ne!(username_key("alice"), username_key("alicia"));
+    }
+
+    #[test]
+    fn rules() {
+        validate_username("trevor_rosa-1.0").unwrap();
+        validate_username("ユーザー名").unwrap();
+
+        assert!(matches!(
+            validate_username("ab"),
+            Err(InvalidUsernameKind::TooShort)
+        ));
+        assert!(matches!(
+            validate_username(&"a".repeat(33)),
+            Err(InvalidUsernameKind::TooLong)
+        ));
+        assert!(matches!(
+            validate_username("ali\u{200B}ce"),
+            Err(InvalidUsernameKind::InvalidChar('\u{200B}'))
+        ));
+        assert!(matches!(
+            validate_username("two words"),
+            Err(InvalidUsernameKind::InvalidChar(' '))
+        ));
+        assert!(matches!(
+            validate_username("Admin"),
+            Err(InvalidUsernameKind::Reserved)
+        ));
+    }
+
+    #[sqlx::test]
+    async fn backfill(db: Pool<Sqlite>) {
+        // keys as the migration set them, folding only ascii.
+        for (username, key) in [
+            ("ÅNGSTRÖM", "Ångström"),
+            ("ölf", "ölf"),
+            ("ÖLF", "Ölf"),
+            ("Ｂｏｂ", "ｂｏｂ"),
+        ] {
+            sqlx::query(
+                "INSERT INTO users(username, password_hash, public_id, username_key) VALUES(?, '', ?, ?)",
+            )
+            .bind(username)
+            .bind(username)
+            .bind(key)
+            .execute(&db)
+            .await
+            .unwrap();
+        }
+
+        backfill_username_keys(&db).await.unwrap();
+        // again, changing nothing.
+        backfill_username_keys(&db).await.unwrap();
+
+        let user = async |username: &str| DBUser::fetch_one(username, &db).await.unwrap().username;
+        assert_eq!(user("ångström").await, "ÅNGSTRÖM");
+        assert_eq!(user("bob").await, "Ｂｏｂ");
+        assert_eq!(user("ölf").await, "ölf");
+        // collisions keep their key, and log in with their exact username.
+        assert_eq!(user("ÖLF").await, "ÖLF");
+        let collisions: Vec<String> =
+            sqlx::query_scalar("SELECT username FROM username_collisions")
+                .fetch_all(&db)
+                .await
+                .unwrap();
+        assert_eq!(collisions, ["ÖLF"]);
+    }
+}